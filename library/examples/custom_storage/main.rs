@@ -1,12 +1,15 @@
 //! Use a custom storage layer.
 
-use std::collections::{btree_map::Entry, BTreeMap};
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
 
 use acceptxmr::{
-    storage::{HeightStorage, InvoiceStorage, OutputId, OutputKeyStorage, OutputPubKey, Storage},
-    Invoice, InvoiceId, PaymentGatewayBuilder, SubIndex,
+    storage::{
+        ArchiveStorage, EventStorage, HeightStorage, InvoiceStorage, OutputId, OutputKeyStorage,
+        OutputPubKey, Storage, SubaddressStorage,
+    },
+    Invoice, InvoiceEvent, InvoiceId, PaymentGatewayBuilder, SubIndex,
 };
-use log::{error, info, LevelFilter};
+use log::{info, LevelFilter};
 use thiserror::Error;
 
 #[tokio::main]
@@ -58,8 +61,12 @@ async fn main() {
 /// or `MySQL`, CSV files, whatever works best for your application.
 pub struct MyCustomStorage {
     invoices: BTreeMap<InvoiceId, Invoice>,
-    output_keys: BTreeMap<OutputPubKey, OutputId>,
+    output_keys: BTreeMap<OutputPubKey, (OutputId, u64)>,
     height: Option<u64>,
+    funded_subaddresses: BTreeSet<(String, SubIndex)>,
+    events: BTreeMap<u64, InvoiceEvent>,
+    next_sequence: u64,
+    archived: BTreeMap<InvoiceId, Invoice>,
 }
 
 impl MyCustomStorage {
@@ -70,6 +77,10 @@ impl MyCustomStorage {
             invoices: BTreeMap::new(),
             output_keys: BTreeMap::new(),
             height: None,
+            funded_subaddresses: BTreeSet::new(),
+            events: BTreeMap::new(),
+            next_sequence: 1,
+            archived: BTreeMap::new(),
         }
     }
 }
@@ -87,6 +98,11 @@ impl InvoiceStorage for MyCustomStorage {
         if self.invoices.contains_key(&invoice.id()) {
             return Err(MyCustomStorageError::DuplicateInvoice);
         }
+        if let Some(reference) = invoice.reference() {
+            if self.get_by_reference(reference)?.is_some() {
+                return Err(MyCustomStorageError::DuplicateReference);
+            }
+        }
         self.invoices.insert(invoice.id(), invoice);
         Ok(())
     }
@@ -106,6 +122,14 @@ impl InvoiceStorage for MyCustomStorage {
         Ok(self.invoices.get(&invoice_id).cloned())
     }
 
+    fn get_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error> {
+        Ok(self
+            .invoices
+            .values()
+            .find(|invoice| invoice.reference() == Some(reference))
+            .cloned())
+    }
+
     fn get_ids(&self) -> Result<Vec<InvoiceId>, Self::Error> {
         Ok(self.invoices.keys().copied().collect())
     }
@@ -135,16 +159,37 @@ impl InvoiceStorage for MyCustomStorage {
 impl OutputKeyStorage for MyCustomStorage {
     type Error = MyCustomStorageError;
 
-    fn insert(&mut self, key: OutputPubKey, output_id: OutputId) -> Result<(), Self::Error> {
+    fn insert(
+        &mut self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> Result<(), Self::Error> {
         if self.output_keys.contains_key(&key) {
             return Err(MyCustomStorageError::DuplicateOutputKey);
         }
-        self.output_keys.insert(key, output_id);
+        self.output_keys.insert(key, (output_id, height));
         Ok(())
     }
 
     fn get(&self, key: OutputPubKey) -> Result<Option<OutputId>, Self::Error> {
-        Ok(self.output_keys.get(&key).copied())
+        Ok(self.output_keys.get(&key).map(|(output_id, _)| *output_id))
+    }
+
+    fn try_for_each_key<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<(OutputPubKey, OutputId, u64), Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.output_keys
+            .iter()
+            .try_for_each(|(key, (output_id, height))| f(Ok((*key, *output_id, *height))))
+    }
+
+    fn prune_keys(&mut self, height: u64) -> Result<u64, Self::Error> {
+        let count_before = self.output_keys.len();
+        self.output_keys
+            .retain(|_, (_, key_height)| *key_height >= height);
+        Ok((count_before - self.output_keys.len()) as u64)
     }
 }
 
@@ -162,6 +207,94 @@ impl HeightStorage for MyCustomStorage {
     }
 }
 
+impl SubaddressStorage for MyCustomStorage {
+    type Error = MyCustomStorageError;
+
+    fn insert_funded(&mut self, wallet: &str, sub_index: SubIndex) -> Result<(), Self::Error> {
+        self.funded_subaddresses
+            .insert((wallet.to_string(), sub_index));
+        Ok(())
+    }
+
+    fn is_funded(&self, wallet: &str, sub_index: SubIndex) -> Result<bool, Self::Error> {
+        Ok(self
+            .funded_subaddresses
+            .contains(&(wallet.to_string(), sub_index)))
+    }
+
+    fn funded(&self) -> Result<Vec<(String, SubIndex)>, Self::Error> {
+        Ok(self.funded_subaddresses.iter().cloned().collect())
+    }
+}
+
+impl EventStorage for MyCustomStorage {
+    type Error = MyCustomStorageError;
+
+    fn append_event(&mut self, event: InvoiceEvent) -> Result<u64, Self::Error> {
+        let sequence = self.next_sequence;
+        self.events.insert(sequence, event);
+        self.next_sequence += 1;
+        Ok(sequence)
+    }
+
+    fn events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, Self::Error> {
+        Ok(self
+            .events
+            .range(sequence..)
+            .take(limit)
+            .map(|(sequence, event)| (*sequence, event.clone()))
+            .collect())
+    }
+
+    fn prune_events(&mut self, sequence: u64) -> Result<(), Self::Error> {
+        self.events = self.events.split_off(&sequence);
+        Ok(())
+    }
+}
+
+impl ArchiveStorage for MyCustomStorage {
+    type Error = MyCustomStorageError;
+
+    fn archive(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        let Some(invoice) = self.invoices.remove(&invoice_id) else {
+            return Ok(None);
+        };
+        self.archived.insert(invoice_id, invoice.clone());
+        Ok(Some(invoice))
+    }
+
+    fn get_archived(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        Ok(self.archived.get(&invoice_id).cloned())
+    }
+
+    fn get_archived_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error> {
+        Ok(self
+            .archived
+            .values()
+            .find(|invoice| invoice.reference() == Some(reference))
+            .cloned())
+    }
+
+    fn try_for_each_archived<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.archived
+            .values()
+            .try_for_each(move |invoice| f(Ok(invoice.clone())))
+    }
+
+    fn prune_archive(&mut self, height: u64) -> Result<(), Self::Error> {
+        self.archived
+            .retain(|_, invoice| invoice.current_height() >= height);
+        Ok(())
+    }
+}
+
 impl Storage for MyCustomStorage {
     type Error = MyCustomStorageError;
 }
@@ -173,6 +306,9 @@ pub enum MyCustomStorageError {
     /// Attempted to insert an invoice which already exists
     #[error("attempted to insert an invoice which already exists")]
     DuplicateInvoice,
+    /// Attempted to insert an invoice with the same reference as another
+    #[error("attempted to insert an invoice with the same reference as another")]
+    DuplicateReference,
     /// Attempted to insert an output public key which already exists
     #[error("attempted to insert an output public key which already exists")]
     DuplicateOutputKey,
@@ -175,8 +175,8 @@ async fn checkout(
                 "instruction": instruction,
                 "address": address,
                 "qrcode": qrcode,
-                "paid": invoice.xmr_paid(),
-                "requested": invoice.xmr_requested(),
+                "paid": invoice.amount_paid().to_string(),
+                "requested": invoice.amount_requested().to_string(),
                 "confirmations": invoice.confirmations().unwrap_or_default(),
                 "confirmations-required": invoice.confirmations_required(),
             });
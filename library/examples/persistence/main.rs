@@ -1,6 +1,9 @@
 //! Use a persistent invoice store to enable recovery from power loss.
 
-use acceptxmr::{storage::stores::Sqlite, PaymentGateway, PaymentGatewayBuilder};
+use acceptxmr::{
+    storage::stores::{Sqlite, TableNames},
+    PaymentGateway, PaymentGatewayBuilder,
+};
 use log::{error, info, LevelFilter};
 
 #[tokio::main]
@@ -20,9 +23,7 @@ async fn main() {
     // Use an Sqlite database for persistent storage.
     let store = Sqlite::new(
         "library/examples/persistence/AcceptXMR_DB",
-        "invoices",
-        "output keys",
-        "height",
+        &TableNames::default(),
     )
     .unwrap();
 
@@ -61,9 +62,7 @@ async fn main() {
     // Reconstruct the gateway...
     let store = Sqlite::new(
         "library/examples/persistence/AcceptXMR_DB",
-        "invoices",
-        "output keys",
-        "height",
+        &TableNames::default(),
     )
     .unwrap();
     let payment_gateway = PaymentGatewayBuilder::new(
@@ -0,0 +1,367 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    str::FromStr,
+};
+
+#[cfg(feature = "bincode")]
+use bincode::{Decode, Encode};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// An exact amount of monero, stored as a whole number of piconeros.
+///
+/// Unlike `f64`, an `Amount` parses and displays without rounding, in any
+/// [`Denomination`]. It (de)serializes as a plain number of piconeros.
+///
+/// # Examples
+///
+/// ```
+/// use acceptxmr::{Amount, Denomination};
+///
+/// let price: Amount = "0.0015".parse()?;
+/// assert_eq!(price.as_pico(), 1_500_000_000);
+/// assert_eq!(price.to_string_in(Denomination::Millinero), "1.5");
+///
+/// let total = price.checked_mul(3).expect("overflow");
+/// assert_eq!(total.to_string(), "0.0045");
+/// # Ok::<(), acceptxmr::ParseAmountError>(())
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+#[cfg_attr(feature = "bincode", derive(Encode, Decode))]
+pub struct Amount(u64);
+
+impl Amount {
+    /// Zero monero.
+    pub const ZERO: Amount = Amount(0);
+    /// One piconero, the smallest unit of monero.
+    pub const ONE_PICONERO: Amount = Amount(1);
+    /// One millinero.
+    pub const ONE_MILLINERO: Amount = Amount(1_000_000_000);
+    /// One monero.
+    pub const ONE_XMR: Amount = Amount(1_000_000_000_000);
+    /// The largest representable amount.
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    /// Create an amount from a number of piconeros.
+    #[must_use]
+    pub const fn from_pico(piconeros: u64) -> Amount {
+        Amount(piconeros)
+    }
+
+    /// Returns the amount as a number of piconeros.
+    #[must_use]
+    pub const fn as_pico(self) -> u64 {
+        self.0
+    }
+
+    /// Parse an amount expressed in the given denomination, such as `"1.5"`
+    /// millineros.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the string is not a non-negative decimal number,
+    /// if it is more precise than one piconero, or if it is too large to
+    /// represent.
+    pub fn from_str_in(s: &str, denomination: Denomination) -> Result<Amount, ParseAmountError> {
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(ParseAmountError::Empty);
+        }
+        if let Some(c) = whole
+            .chars()
+            .chain(fraction.chars())
+            .find(|c| !c.is_ascii_digit())
+        {
+            return Err(ParseAmountError::InvalidCharacter(c));
+        }
+
+        let decimals = denomination.decimals();
+        // Trailing zeros don't add precision.
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > decimals {
+            return Err(ParseAmountError::TooPrecise);
+        }
+
+        let mut piconeros: u64 = 0;
+        let digits = whole
+            .bytes()
+            .chain(fraction.bytes())
+            .chain(std::iter::repeat(b'0').take(decimals - fraction.len()));
+        for digit in digits {
+            piconeros = piconeros
+                .checked_mul(10)
+                .and_then(|p| p.checked_add(u64::from(digit - b'0')))
+                .ok_or(ParseAmountError::Overflow)?;
+        }
+        Ok(Amount(piconeros))
+    }
+
+    /// Format the amount in the given denomination, without trailing zeros.
+    ///
+    /// ```
+    /// use acceptxmr::{Amount, Denomination};
+    ///
+    /// let amount = Amount::from_pico(1_230_000_000_000);
+    /// assert_eq!(amount.to_string_in(Denomination::Xmr), "1.23");
+    /// assert_eq!(amount.to_string_in(Denomination::Millinero), "1230");
+    /// assert_eq!(amount.to_string_in(Denomination::Piconero), "1230000000000");
+    /// ```
+    #[must_use]
+    pub fn to_string_in(self, denomination: Denomination) -> String {
+        let decimals = denomination.decimals();
+        let scale = 10_u64.pow(u32::try_from(decimals).unwrap_or_default());
+        let whole = self.0 / scale;
+        let fraction = self.0 % scale;
+        if fraction == 0 {
+            return whole.to_string();
+        }
+        let fraction = format!("{fraction:0decimals$}");
+        format!("{whole}.{}", fraction.trim_end_matches('0'))
+    }
+
+    /// Checked addition. Returns `None` on overflow.
+    #[must_use]
+    pub const fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        match self.0.checked_add(rhs.0) {
+            Some(piconeros) => Some(Amount(piconeros)),
+            None => None,
+        }
+    }
+
+    /// Checked subtraction. Returns `None` if `rhs` is larger than `self`.
+    #[must_use]
+    pub const fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        match self.0.checked_sub(rhs.0) {
+            Some(piconeros) => Some(Amount(piconeros)),
+            None => None,
+        }
+    }
+
+    /// Checked multiplication. Returns `None` on overflow.
+    #[must_use]
+    pub const fn checked_mul(self, rhs: u64) -> Option<Amount> {
+        match self.0.checked_mul(rhs) {
+            Some(piconeros) => Some(Amount(piconeros)),
+            None => None,
+        }
+    }
+
+    /// Checked division, rounding down. Returns `None` if `rhs` is zero.
+    #[must_use]
+    pub const fn checked_div(self, rhs: u64) -> Option<Amount> {
+        match self.0.checked_div(rhs) {
+            Some(piconeros) => Some(Amount(piconeros)),
+            None => None,
+        }
+    }
+
+    /// Saturating addition. Returns [`Amount::MAX`] on overflow.
+    #[must_use]
+    pub const fn saturating_add(self, rhs: Amount) -> Amount {
+        Amount(self.0.saturating_add(rhs.0))
+    }
+
+    /// Saturating subtraction. Returns [`Amount::ZERO`] if `rhs` is larger
+    /// than `self`.
+    #[must_use]
+    pub const fn saturating_sub(self, rhs: Amount) -> Amount {
+        Amount(self.0.saturating_sub(rhs.0))
+    }
+}
+
+/// Formats the amount in XMR, without trailing zeros.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&self.to_string_in(Denomination::Xmr))
+    }
+}
+
+/// Parses an amount in XMR.
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Amount::from_str_in(s, Denomination::Xmr)
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(piconeros: u64) -> Self {
+        Amount(piconeros)
+    }
+}
+
+impl From<Amount> for u64 {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+impl PartialEq<u64> for Amount {
+    fn eq(&self, piconeros: &u64) -> bool {
+        self.0 == *piconeros
+    }
+}
+
+impl PartialOrd<u64> for Amount {
+    fn partial_cmp(&self, piconeros: &u64) -> Option<Ordering> {
+        self.0.partial_cmp(piconeros)
+    }
+}
+
+/// Behaves like addition of `u64`s: panics on overflow in debug builds, and
+/// wraps in release builds. Use [`Amount::checked_add`] or
+/// [`Amount::saturating_add`] where overflow is possible.
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, rhs: Amount) -> Amount {
+        Amount(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, rhs: Amount) {
+        *self = *self + rhs;
+    }
+}
+
+/// Behaves like subtraction of `u64`s: panics on underflow in debug builds,
+/// and wraps in release builds. Use [`Amount::checked_sub`] or
+/// [`Amount::saturating_sub`] where `rhs` may be larger than `self`.
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, rhs: Amount) -> Amount {
+        Amount(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, rhs: Amount) {
+        *self = *self - rhs;
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Amount> for Amount {
+    fn sum<I: Iterator<Item = &'a Amount>>(iter: I) -> Amount {
+        iter.copied().sum()
+    }
+}
+
+/// A unit in which an [`Amount`] can be parsed or displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Denomination {
+    /// Monero. One XMR is 10<sup>12</sup> piconeros.
+    Xmr,
+    /// One thousandth of a monero, or 10<sup>9</sup> piconeros.
+    Millinero,
+    /// The smallest unit of monero.
+    Piconero,
+}
+
+impl Denomination {
+    /// Number of decimal places a value in this denomination can have.
+    const fn decimals(self) -> usize {
+        match self {
+            Denomination::Xmr => 12,
+            Denomination::Millinero => 9,
+            Denomination::Piconero => 0,
+        }
+    }
+}
+
+/// An error parsing an [`Amount`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseAmountError {
+    /// The string contained no digits.
+    #[error("amount is empty")]
+    Empty,
+    /// The string contained a character other than digits and a decimal
+    /// point. Negative amounts are not allowed.
+    #[error("invalid character in amount: {0:?}")]
+    InvalidCharacter(char),
+    /// The amount is more precise than one piconero.
+    #[error("amount is more precise than one piconero")]
+    TooPrecise,
+    /// The amount is too large to represent.
+    #[error("amount is too large")]
+    Overflow,
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::{Amount, Denomination, ParseAmountError};
+
+    #[test_case("1", Denomination::Xmr => Ok(1_000_000_000_000); "whole xmr")]
+    #[test_case("0.000000000001", Denomination::Xmr => Ok(1); "one piconero")]
+    #[test_case(".5", Denomination::Xmr => Ok(500_000_000_000); "no whole part")]
+    #[test_case("2.", Denomination::Millinero => Ok(2_000_000_000); "no fraction")]
+    #[test_case("1.5000000000000", Denomination::Xmr => Ok(1_500_000_000_000); "trailing zeros")]
+    #[test_case("18446744.073709551615", Denomination::Xmr => Ok(u64::MAX); "max")]
+    #[test_case("18446744.073709551616", Denomination::Xmr => Err(ParseAmountError::Overflow); "overflow")]
+    #[test_case("0.0000000000001", Denomination::Xmr => Err(ParseAmountError::TooPrecise); "too precise")]
+    #[test_case("1.5", Denomination::Piconero => Err(ParseAmountError::TooPrecise); "fractional piconero")]
+    #[test_case("-1", Denomination::Xmr => Err(ParseAmountError::InvalidCharacter('-')); "negative")]
+    #[test_case("1.2.3", Denomination::Xmr => Err(ParseAmountError::InvalidCharacter('.')); "two points")]
+    #[test_case(".", Denomination::Xmr => Err(ParseAmountError::Empty); "empty")]
+    fn parse(s: &str, denomination: Denomination) -> Result<u64, ParseAmountError> {
+        Amount::from_str_in(s, denomination).map(Amount::as_pico)
+    }
+
+    #[test_case(0, Denomination::Xmr => "0"; "zero")]
+    #[test_case(1, Denomination::Xmr => "0.000000000001"; "one piconero")]
+    #[test_case(u64::MAX, Denomination::Xmr => "18446744.073709551615"; "max")]
+    #[test_case(1_500_000_000, Denomination::Millinero => "1.5"; "millinero")]
+    #[test_case(1_500_000_000, Denomination::Piconero => "1500000000"; "piconero")]
+    fn display(piconeros: u64, denomination: Denomination) -> String {
+        let amount = Amount::from_pico(piconeros);
+        let s = amount.to_string_in(denomination);
+        assert_eq!(Amount::from_str_in(&s, denomination), Ok(amount));
+        s
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Amount::from_pico(5);
+        let b = Amount::from_pico(3);
+        assert_eq!(a + b, 8);
+        assert_eq!(a - b, 2);
+        assert_eq!(b.checked_sub(a), None);
+        assert_eq!(b.saturating_sub(a), Amount::ZERO);
+        assert_eq!(Amount::MAX.checked_add(b), None);
+        assert_eq!(Amount::MAX.saturating_add(b), Amount::MAX);
+        assert_eq!(a.checked_mul(2), Some(Amount::from_pico(10)));
+        assert_eq!(a.checked_div(0), None);
+        assert_eq!([a, b].iter().sum::<Amount>(), 8);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overflow")]
+    fn add_overflow_panics_in_debug() {
+        let _ = Amount::MAX + Amount::from_pico(1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overflow")]
+    fn sub_underflow_panics_in_debug() {
+        let _ = Amount::ZERO - Amount::from_pico(1);
+    }
+}
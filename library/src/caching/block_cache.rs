@@ -1,11 +1,14 @@
 use std::{
-    cmp::max,
+    cmp::{max, min},
+    collections::VecDeque,
+    ops::Range,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
 };
 
+use futures_util::future::join_all;
 use log::{debug, trace, warn};
 use thiserror::Error;
 
@@ -17,6 +20,9 @@ pub(crate) struct BlockCache<M: MonerodClient = MonerodRpcClient> {
     height: Arc<AtomicU64>,
     daemon_height: Arc<AtomicU64>,
     blocks: Vec<Block>,
+    /// Blocks fetched ahead of the cache, in height order.
+    pending: VecDeque<Block>,
+    fetch_concurrency: usize,
     monerod_client: M,
 }
 
@@ -24,21 +30,17 @@ impl<M: MonerodClient> BlockCache<M> {
     pub(crate) async fn init(
         monerod_client: M,
         cache_size: usize,
+        fetch_concurrency: usize,
         initial_height: Arc<AtomicU64>,
         daemon_height: Arc<AtomicU64>,
     ) -> Result<BlockCache<M>, BlockCacheError> {
+        let top_height = initial_height.load(Ordering::Relaxed);
         let mut blocks = Vec::with_capacity(cache_size);
-        // TODO: Get blocks concurrently.
-        for i in 0..cache_size {
-            let height = initial_height.load(Ordering::Relaxed) - i as u64;
-            let (block_id, block) = monerod_client.block(height).await?;
-            let transactions = monerod_client.block_transactions(&block).await?;
-            blocks.push(Block {
-                hash: block_id,
-                height,
-                inner: block,
-                transactions,
-            });
+        for block in
+            join_all((0..cache_size as u64).map(|i| fetch_block(&monerod_client, top_height - i)))
+                .await
+        {
+            blocks.push(block?);
         }
 
         let mut block_cache_summary = String::new();
@@ -57,43 +59,61 @@ impl<M: MonerodClient> BlockCache<M> {
             height: initial_height,
             daemon_height,
             blocks,
+            pending: VecDeque::new(),
+            fetch_concurrency: fetch_concurrency.max(1),
             monerod_client,
         })
     }
 
-    /// Advance block cache by 1 block if new block is available and apply reorg
-    /// if one has occurred. Returns number of blocks updated.
+    /// Advance block cache by up to one cache length if new blocks are
+    /// available and apply reorg if one has occurred. Returns number of blocks
+    /// updated.
+    ///
+    /// While behind the blockchain tip, up to `fetch_concurrency` blocks are
+    /// fetched concurrently and queued, then added to the cache in height
+    /// order over as many updates as needed.
     pub(crate) async fn update(&mut self) -> Result<usize, BlockCacheError> {
         trace!("Checking for block cache updates");
         let mut updated = 0;
         let blockchain_height = self.monerod_client.daemon_height().await?;
         self.daemon_height
             .store(blockchain_height, Ordering::Relaxed);
-        if self.height.load(Ordering::Relaxed) < blockchain_height.saturating_sub(1) {
-            let (block_id, block) = self
-                .monerod_client
-                .block(self.height.load(Ordering::Relaxed) + 1)
-                .await?;
-            let transactions = self.monerod_client.block_transactions(&block).await?;
-            self.blocks.insert(
-                0,
-                Block {
-                    hash: block_id,
-                    height: self.height() + 1,
-                    inner: block,
-                    transactions,
-                },
-            );
-            self.blocks.remove(self.blocks.len().saturating_sub(1));
-            self.height.fetch_add(1, Ordering::Relaxed);
+        let top_height = blockchain_height.saturating_sub(1);
+
+        // Discard queued blocks if the chain has shrunk below them.
+        if self.pending.back().is_some_and(|b| b.height > top_height) {
+            self.pending.clear();
+        }
+
+        if self.height() < top_height {
+            if self.pending.is_empty() {
+                let first = self.height() + 1;
+                let last = min(top_height, self.height() + self.fetch_concurrency as u64);
+                self.pending = self.fetch_blocks(first..last + 1).await?;
+            }
+
+            // Never advance by more than the cache length, so every new block
+            // is still in the cache when the scanner looks at it.
+            while updated < self.blocks.len() {
+                let Some(block) = self.pending.pop_front() else {
+                    break;
+                };
+                if block.height != self.height() + 1 {
+                    self.pending.clear();
+                    break;
+                }
+                self.blocks.insert(0, block);
+                self.blocks.remove(self.blocks.len().saturating_sub(1));
+                self.height.fetch_add(1, Ordering::Relaxed);
+                updated += 1;
+            }
             debug!(
                 "Cache top block height updated to {}, blockchain top block height is {}, blockchain height is {}",
                 self.height.load(Ordering::Relaxed),
-                blockchain_height.saturating_sub(1),
+                top_height,
                 blockchain_height,
             );
             self.log_cache_summary();
-            updated += 1;
         }
         updated = max(updated, self.check_and_fix_reorg().await?);
 
@@ -119,17 +139,8 @@ impl<M: MonerodClient> BlockCache<M> {
         for i in 0..self.blocks.len() - 1 {
             if self.blocks[i].inner.header.prev_id != self.blocks[i + 1].hash {
                 warn!("Blocks in cache not consecutive! A reorg may have occurred; repairing now");
-                let (block_id, block) = self
-                    .monerod_client
-                    .block(cache_height - 1 - i as u64)
-                    .await?;
-                let transactions = self.monerod_client.block_transactions(&block).await?;
-                self.blocks[i + 1] = Block {
-                    hash: block_id,
-                    height: cache_height - 1 - i as u64,
-                    inner: block,
-                    transactions,
-                };
+                self.blocks[i + 1] =
+                    fetch_block(&self.monerod_client, cache_height - 1 - i as u64).await?;
                 updated = max(updated, 1);
                 updated += 1;
             }
@@ -155,6 +166,46 @@ impl<M: MonerodClient> BlockCache<M> {
     pub(crate) fn is_synchronized(&self) -> bool {
         self.height() >= self.daemon_height().saturating_sub(1)
     }
+
+    /// Fetch blocks at `heights` concurrently. If some fail, the blocks below
+    /// the first failure are still returned. Fails only if the lowest block
+    /// cannot be fetched.
+    async fn fetch_blocks(&self, heights: Range<u64>) -> Result<VecDeque<Block>, BlockCacheError> {
+        let mut blocks = VecDeque::with_capacity(heights.clone().count());
+        for result in
+            join_all(heights.map(|height| fetch_block(&self.monerod_client, height))).await
+        {
+            match result {
+                Ok(block) => blocks.push_back(block),
+                Err(e) if blocks.is_empty() => return Err(e),
+                Err(e) => {
+                    debug!(
+                        "Failed to fetch block {}, will retry on next update: {}",
+                        blocks.back().map_or(0, |b: &Block| b.height) + 1,
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+        trace!("Fetched {} blocks", blocks.len());
+        Ok(blocks)
+    }
+}
+
+/// Fetch a block and its transactions.
+async fn fetch_block<M: MonerodClient>(
+    monerod_client: &M,
+    height: u64,
+) -> Result<Block, BlockCacheError> {
+    let (hash, block) = monerod_client.block(height).await?;
+    let transactions = monerod_client.block_transactions(&block).await?;
+    Ok(Block {
+        hash,
+        height,
+        inner: block,
+        transactions,
+    })
 }
 
 pub(crate) struct Block {
@@ -1,165 +1,586 @@
 use std::{
     cmp,
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{
         atomic::{AtomicU32, Ordering},
         Arc, Mutex, PoisonError,
     },
 };
 
-use indexmap::{IndexMap, IndexSet};
+use indexmap::IndexMap;
 use log::{debug, error, warn};
 use monero::{cryptonote::subaddress, ViewPair};
-use rand::{Rng, SeedableRng};
-use rand_chacha::ChaCha12Rng;
 
 use crate::{
-    storage::{Client as StorageClient, Storage, StorageError},
-    SubIndex,
+    storage::{AsyncStorage, StorageError},
+    AcceptXmrError, Amount, SubIndex, SubaddressAllocator, SubaddressUsage,
 };
 
 const MIN_AVAILABLE_SUBADDRESSES: u32 = 100;
 
+/// Subaddresses available for new invoices, for each wallet and account the
+/// payment gateway uses.
+///
+/// Each wallet allocates from its own subaddress indices, so that its gap limit
+/// only counts its own subaddresses. An invoice's ID is made from its
+/// subaddress index though, so an index in use by one wallet is not allocated
+/// to another until it is released.
 pub(crate) struct SubaddressCache {
-    major_index: u32,
+    /// Subaddresses of each wallet, by primary address. Wallets which are not
+    /// tracked are kept too, so that their history is known if they are added.
+    wallets: HashMap<String, WalletSubaddresses>,
+    /// Primary address of the wallet that invoices and funded subaddresses
+    /// recorded without a wallet belong to.
+    default_wallet: String,
+    /// Subaddress indices in use by an invoice of any wallet.
+    in_use: HashSet<SubIndex>,
+    major_indices: Vec<u32>,
+    /// Highest minor index generated for any wallet and account.
     highest_minor_index: Arc<AtomicU32>,
+    /// Number of blocks past an unpaid invoice's expiration its subaddress is
+    /// reserved for.
+    reuse_delay: u64,
+    allocator: Box<dyn SubaddressAllocator>,
+    /// Maximum number of subaddresses past the highest funded one that may be
+    /// allocated, if bounded.
+    gap_limit: Option<u32>,
+}
+
+/// The subaddresses one wallet has used, and those available to it while it is
+/// tracked.
+#[derive(Default)]
+struct WalletSubaddresses {
+    /// The wallet's view pair and accounts, while payments to it are tracked.
+    tracked: Option<(ViewPair, BTreeMap<u32, AccountSubaddresses>)>,
+    /// Subaddresses in use by the wallet's invoices.
+    used: HashSet<SubIndex>,
+    /// Subaddresses which have received funds.
+    funded: HashSet<SubIndex>,
+    /// Subaddresses of invoices which stopped being tracked unpaid, and the
+    /// height from which they may be reused.
+    reserved: HashMap<SubIndex, u64>,
+}
+
+struct AccountSubaddresses {
+    highest_minor_index: u32,
+    /// Highest minor index allocated to an invoice or funded, if any.
+    highest_used_minor_index: Option<u32>,
+    /// Highest minor index which has received funds, if any.
+    highest_funded_minor_index: Option<u32>,
+    /// Ordered by ascending minor index.
     available_subaddresses: IndexMap<SubIndex, String>,
-    viewpair: ViewPair,
-    rng: ChaCha12Rng,
 }
 
 impl SubaddressCache {
-    pub(crate) async fn init<S: Storage + 'static>(
-        storage: &StorageClient<S>,
-        viewpair: monero::ViewPair,
-        major_index: u32,
+    /// Create a cache of subaddresses for the given wallets, the first of which
+    /// is the default wallet. Subaddresses used by invoices in storage, or
+    /// reserved for archived invoices, are left out.
+    pub(crate) async fn init<S: AsyncStorage>(
+        storage: &S,
+        wallets: &[(String, ViewPair)],
+        major_indices: &[u32],
         highest_minor_index: Arc<AtomicU32>,
-        seed: Option<u64>,
+        allocator: Box<dyn SubaddressAllocator>,
+        gap_limit: Option<u32>,
+        reuse_delay: u64,
     ) -> Result<SubaddressCache, StorageError> {
         // Get currently used subindexes from database, so they won't be put in the list
-        // of available subindexes.
-        let used_sub_indexes = Arc::new(Mutex::new(IndexSet::new()));
+        // of available subindexes. Note which have received funds too.
+        let used_sub_indexes = Arc::new(Mutex::new(Vec::new()));
         let cloned_sub_indexes = used_sub_indexes.clone();
         storage
             .try_for_each_invoice(move |invoice_or_err| {
-                let sub_index = invoice_or_err?.index();
+                let invoice = invoice_or_err?;
                 cloned_sub_indexes
                     .lock()
                     .unwrap_or_else(PoisonError::into_inner)
-                    .insert(sub_index);
+                    .push((
+                        invoice.wallet().to_string(),
+                        invoice.index(),
+                        invoice.amount_paid() > Amount::ZERO,
+                    ));
                 Ok(())
             })
             .await?;
+        let used_sub_indexes = std::mem::take(
+            &mut *used_sub_indexes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
 
-        // Get highest index from list of used subindexes.
-        let max_used = if let Some(max_sub_index) = used_sub_indexes
-            .lock()
-            .unwrap_or_else(PoisonError::into_inner)
-            .iter()
-            .max()
-        {
-            debug!(
-                "Highest subaddress index in the database: {}",
-                SubIndex::new(major_index, max_sub_index.minor)
-            );
-            max_sub_index.minor
-        } else {
-            debug!("Highest subaddress index in the database: N/A");
-            0
+        let mut cache = SubaddressCache {
+            wallets: HashMap::new(),
+            default_wallet: wallets
+                .first()
+                .map(|(wallet, _)| wallet.clone())
+                .unwrap_or_default(),
+            in_use: HashSet::new(),
+            major_indices: major_indices.to_vec(),
+            highest_minor_index,
+            reuse_delay,
+            allocator,
+            gap_limit,
         };
+        for (wallet, sub_index, is_funded) in used_sub_indexes {
+            cache.in_use.insert(sub_index);
+            let history = cache.history(&wallet);
+            history.used.insert(sub_index);
+            if is_funded {
+                history.funded.insert(sub_index);
+            }
+        }
 
-        // Generate enough subaddresses to cover all pending invoices.
-        highest_minor_index.store(
-            cmp::max(MIN_AVAILABLE_SUBADDRESSES - 1, max_used),
-            Ordering::Relaxed,
-        );
-        let mut available_subaddresses: IndexMap<SubIndex, String> = generate_range(
-            SubIndex::new(major_index, 0),
-            SubIndex::new(major_index, highest_minor_index.load(Ordering::Relaxed)),
-            &viewpair,
-        )
-        .into_iter()
-        .collect();
+        // Get subaddresses which received funds before from the database. Those may not
+        // be reused if the allocator forbids it.
+        for (wallet, sub_index) in storage.get_funded_subaddresses().await? {
+            cache.history(&wallet).funded.insert(sub_index);
+        }
 
-        // Remove subaddresses that are present in the database.
-        available_subaddresses.retain(|sub_index, _| {
-            !used_sub_indexes
-                .lock()
-                .unwrap_or_else(PoisonError::into_inner)
-                .contains(sub_index)
-        });
+        // Archived invoices which were never paid keep their subaddress reserved,
+        // in case a late payment arrives.
+        let reuse_funded = cache.allocator.reuse_funded();
+        for ((wallet, sub_index), until) in reserved_subaddresses(storage, reuse_delay).await? {
+            let history = cache.history(&wallet);
+            if !history.used.contains(&sub_index)
+                && (reuse_funded || !history.funded.contains(&sub_index))
+            {
+                history.reserved.insert(sub_index, until);
+            }
+        }
 
-        // If a seed is supplied, seed the random number generator with it.
-        let mut rng = ChaCha12Rng::from_entropy();
-        if let Some(s) = seed {
-            rng = ChaCha12Rng::seed_from_u64(s);
+        for (wallet, viewpair) in wallets {
+            cache.insert_wallet(wallet, *viewpair);
         }
+        Ok(cache)
+    }
 
-        Ok(SubaddressCache {
-            major_index,
-            highest_minor_index,
-            available_subaddresses,
-            viewpair,
-            rng,
-        })
+    /// Start allocating subaddresses of the given wallet. Has no effect if the
+    /// wallet's subaddresses are already being allocated.
+    pub(crate) fn insert_wallet(&mut self, wallet: &str, viewpair: ViewPair) {
+        let reuse_funded = self.allocator.reuse_funded();
+        let history = self.wallets.entry(wallet.to_string()).or_default();
+        if history.tracked.is_some() {
+            return;
+        }
+
+        let mut accounts = BTreeMap::new();
+        for &major_index in &self.major_indices {
+            // Get highest index from list of used subindexes.
+            let max_used = if let Some(max_sub_index) = history
+                .used
+                .iter()
+                .filter(|sub_index| sub_index.major == major_index)
+                .max()
+            {
+                debug!(
+                    "Highest subaddress index in the database for wallet {}: {}",
+                    wallet, max_sub_index
+                );
+                max_sub_index.minor
+            } else {
+                debug!(
+                    "Highest subaddress index in the database for wallet {}, account {}: N/A",
+                    wallet, major_index
+                );
+                0
+            };
+
+            // Generate enough subaddresses to cover all pending invoices.
+            let account_highest_minor_index = cmp::max(MIN_AVAILABLE_SUBADDRESSES - 1, max_used);
+            let available_subaddresses: IndexMap<SubIndex, String> = generate_range(
+                SubIndex::new(major_index, 0),
+                SubIndex::new(major_index, account_highest_minor_index),
+                &viewpair,
+            )
+            .into_iter()
+            .filter(|(sub_index, _)| history.may_allocate(*sub_index, &self.in_use, reuse_funded))
+            .collect();
+
+            let highest_funded_minor_index = history
+                .funded
+                .iter()
+                .filter(|sub_index| sub_index.major == major_index)
+                .map(|sub_index| sub_index.minor)
+                .max();
+            let highest_used_minor_index = history
+                .used
+                .iter()
+                .filter(|sub_index| sub_index.major == major_index)
+                .map(|sub_index| sub_index.minor)
+                .chain(highest_funded_minor_index)
+                .max();
+
+            self.highest_minor_index
+                .fetch_max(account_highest_minor_index, Ordering::Relaxed);
+            accounts.insert(
+                major_index,
+                AccountSubaddresses {
+                    highest_minor_index: account_highest_minor_index,
+                    highest_used_minor_index,
+                    highest_funded_minor_index,
+                    available_subaddresses,
+                },
+            );
+        }
+        history.tracked = Some((viewpair, accounts));
     }
 
-    pub(crate) fn remove_random(&mut self) -> (SubIndex, String) {
-        let map_index = self.rng.gen_range(0..self.available_subaddresses.len());
+    /// Stop allocating subaddresses of the given wallet. Its history is kept,
+    /// in case it is added again.
+    pub(crate) fn remove_wallet(&mut self, wallet: &str) {
+        if let Some(history) = self.wallets.get_mut(wallet) {
+            history.tracked = None;
+        }
+    }
 
-        if let Some((sub_index, subaddress)) =
-            self.available_subaddresses.shift_remove_index(map_index)
-        {
-            if self.len() <= MIN_AVAILABLE_SUBADDRESSES as usize {
-                self.extend_by(MIN_AVAILABLE_SUBADDRESSES);
+    /// Removes the subaddress of the given wallet and account chosen by the
+    /// allocator from the cache. Reserved subaddresses are returned to the
+    /// cache first if they may be reused at the given height.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AcceptXmrError::UnknownWallet`] or
+    /// [`AcceptXmrError::UnknownAccount`] error if the cache does not hold
+    /// subaddresses for that wallet or account, or an
+    /// [`AcceptXmrError::GapLimitReached`] error if none are available within
+    /// the wallet's gap limit.
+    pub(crate) fn allocate(
+        &mut self,
+        wallet: &str,
+        major_index: u32,
+        height: u64,
+    ) -> Result<(SubIndex, String), AcceptXmrError> {
+        self.release_reserved(height);
+        let gap_limit = self.gap_limit;
+        let Some(WalletSubaddresses {
+            tracked: Some((_, accounts)),
+            used,
+            ..
+        }) = self.wallets.get_mut(wallet)
+        else {
+            return Err(AcceptXmrError::UnknownWallet(wallet.to_string()));
+        };
+        let account = accounts
+            .get_mut(&major_index)
+            .ok_or(AcceptXmrError::UnknownAccount(major_index))?;
+        // Only subaddresses within the gap limit may be allocated. The available
+        // subaddresses are sorted, so those come first.
+        let available = match gap_limit {
+            Some(gap_limit) => {
+                let max_minor_index = account
+                    .highest_funded_minor_index
+                    .map_or(0, |minor| minor.saturating_add(1))
+                    .saturating_add(gap_limit.saturating_sub(1));
+                account
+                    .available_subaddresses
+                    .partition_point(|sub_index, _| sub_index.minor <= max_minor_index)
             }
-            (sub_index, subaddress)
-        } else {
+            None => account.available_subaddresses.len(),
+        };
+        if available == 0 {
+            return Err(AcceptXmrError::GapLimitReached(major_index));
+        }
+        let map_index = self.allocator.select(available).min(available - 1);
+
+        let Some((sub_index, subaddress)) =
+            account.available_subaddresses.shift_remove_index(map_index)
+        else {
             // Is this the best way to handle this error?
             error!("Failed to retrieve subaddress by index from subaddress cache; retrying");
-            self.remove_random()
+            return self.allocate(wallet, major_index, height);
+        };
+        account.highest_used_minor_index =
+            account.highest_used_minor_index.max(Some(sub_index.minor));
+        let running_low =
+            account.available_subaddresses.len() <= MIN_AVAILABLE_SUBADDRESSES as usize;
+        used.insert(sub_index);
+        self.in_use.insert(sub_index);
+
+        // The index now identifies this wallet's invoice, so other wallets can't
+        // use it until it is released.
+        for (_, accounts) in self
+            .wallets
+            .values_mut()
+            .filter_map(|history| history.tracked.as_mut())
+        {
+            if let Some(account) = accounts.get_mut(&sub_index.major) {
+                account.available_subaddresses.shift_remove(&sub_index);
+            }
+        }
+
+        if running_low {
+            self.extend_by(wallet, major_index, MIN_AVAILABLE_SUBADDRESSES);
         }
+        Ok((sub_index, subaddress))
     }
 
-    pub(crate) fn insert(&mut self, sub_index: SubIndex, address: String) -> Option<String> {
-        self.available_subaddresses.insert(sub_index, address)
+    /// Returns a subaddress no longer used by an invoice of the given wallet to
+    /// the cache. It may be allocated to the same wallet again if the allocator
+    /// allows it to be reused, and to other wallets either way.
+    pub(crate) fn release(&mut self, wallet: &str, sub_index: SubIndex, funded: bool) {
+        let wallet = self.resolve(wallet);
+        self.in_use.remove(&sub_index);
+        self.history(&wallet).used.remove(&sub_index);
+        if funded {
+            if !self.allocator.reuse_funded() {
+                debug!(
+                    "Retiring funded subaddress {} of wallet {}",
+                    sub_index, wallet
+                );
+            }
+            self.mark_funded([(wallet, sub_index)]);
+        }
+        self.make_available(sub_index);
+    }
+
+    /// Keeps the subaddress of an invoice of the given wallet which stopped
+    /// being tracked unpaid out of the wallet's cache until `reuse_delay`
+    /// blocks past the invoice's expiration, so that a late payment can't be
+    /// credited to a new invoice. Without a reuse delay, the subaddress is
+    /// released immediately. Other wallets may use its index meanwhile.
+    pub(crate) fn reserve(&mut self, wallet: &str, sub_index: SubIndex, expiration_height: u64) {
+        if self.reuse_delay == 0 {
+            self.release(wallet, sub_index, false);
+            return;
+        }
+        let wallet = self.resolve(wallet);
+        let until = expiration_height.saturating_add(self.reuse_delay);
+        debug!(
+            "Reserving subaddress {} of wallet {} until height {}",
+            sub_index, wallet, until
+        );
+        self.in_use.remove(&sub_index);
+        let history = self.history(&wallet);
+        history.used.remove(&sub_index);
+        let reserved = history.reserved.entry(sub_index).or_insert(until);
+        *reserved = (*reserved).max(until);
+        self.make_available(sub_index);
+    }
+
+    /// Returns reserved subaddresses which may be reused at the given height
+    /// to the cache.
+    fn release_reserved(&mut self, height: u64) {
+        let mut released = Vec::new();
+        for history in self.wallets.values_mut() {
+            history.reserved.retain(|&sub_index, &mut until| {
+                if until > height {
+                    return true;
+                }
+                released.push(sub_index);
+                false
+            });
+        }
+        for sub_index in released {
+            self.make_available(sub_index);
+        }
+    }
+
+    /// Record that the given subaddresses have received funds, which moves
+    /// their wallet's gap limit. Funded subaddresses are taken out of the cache
+    /// if the allocator does not allow them to be reused.
+    pub(crate) fn mark_funded(&mut self, funded: impl IntoIterator<Item = (String, SubIndex)>) {
+        let reuse_funded = self.allocator.reuse_funded();
+        for (wallet, sub_index) in funded {
+            let wallet = self.resolve(&wallet);
+            let history = self.history(&wallet);
+            history.funded.insert(sub_index);
+            let Some((_, accounts)) = &mut history.tracked else {
+                continue;
+            };
+            if let Some(account) = accounts.get_mut(&sub_index.major) {
+                account.highest_funded_minor_index = account
+                    .highest_funded_minor_index
+                    .max(Some(sub_index.minor));
+                account.highest_used_minor_index =
+                    account.highest_used_minor_index.max(Some(sub_index.minor));
+                if !reuse_funded {
+                    account.available_subaddresses.shift_remove(&sub_index);
+                }
+            }
+        }
+    }
+
+    /// Returns the highest used and funded minor index of each account of the
+    /// given wallet, or `None` if its subaddresses aren't being allocated.
+    pub(crate) fn usage(&self, wallet: &str) -> Option<Vec<SubaddressUsage>> {
+        let (_, accounts) = self.wallets.get(wallet)?.tracked.as_ref()?;
+        Some(
+            accounts
+                .iter()
+                .map(|(&account_index, account)| SubaddressUsage {
+                    account_index,
+                    highest_used_minor_index: account.highest_used_minor_index,
+                    highest_funded_minor_index: account.highest_funded_minor_index,
+                })
+                .collect(),
+        )
     }
 
     pub(crate) fn len(&self) -> usize {
-        self.available_subaddresses.len()
+        self.wallets
+            .values()
+            .filter_map(|history| history.tracked.as_ref())
+            .flat_map(|(_, accounts)| accounts.values())
+            .map(|account| account.available_subaddresses.len())
+            .sum()
     }
 
-    /// Generates `n` subaddresses at the end of the current range, and appends
-    /// them to the subaddress cache.
+    /// Puts the subaddress with the given index back in the cache of each
+    /// wallet which may allocate it, if it has been generated for that wallet.
+    fn make_available(&mut self, sub_index: SubIndex) {
+        let reuse_funded = self.allocator.reuse_funded();
+        for history in self.wallets.values_mut() {
+            if !history.may_allocate(sub_index, &self.in_use, reuse_funded) {
+                continue;
+            }
+            let Some((viewpair, accounts)) = &mut history.tracked else {
+                continue;
+            };
+            let Some(account) = accounts.get_mut(&sub_index.major) else {
+                continue;
+            };
+            if sub_index.minor > account.highest_minor_index {
+                continue;
+            }
+            let address = subaddress::get_subaddress(viewpair, sub_index.into(), None).to_string();
+            account
+                .available_subaddresses
+                .insert_sorted(sub_index, address);
+        }
+    }
+
+    /// Returns the history of the given wallet, creating an empty one if there
+    /// is none. Subaddresses recorded without a wallet belong to the default
+    /// wallet.
+    fn history(&mut self, wallet: &str) -> &mut WalletSubaddresses {
+        let wallet = self.resolve(wallet);
+        self.wallets.entry(wallet).or_default()
+    }
+
+    /// Returns the wallet's primary address, or the default wallet's if it is
+    /// empty.
+    fn resolve(&self, wallet: &str) -> String {
+        if wallet.is_empty() {
+            self.default_wallet.clone()
+        } else {
+            wallet.to_string()
+        }
+    }
+
+    /// Generates `n` subaddresses at the end of the given wallet and account's
+    /// current range, and appends them to the subaddress cache.
     ///
     /// If adding `n` additional subaddresses would extend the cache beyond the
-    /// maximum index of `(1, u32::MAX)`, generation stops prematurely.
+    /// maximum minor index of `u32::MAX`, generation stops prematurely.
     ///
     /// Returns the number of subaddresses appended to the subaddress cache.
-    fn extend_by(&mut self, n: u32) -> u32 {
-        // TODO: Change this to use generate_range().
-        let mut count = 0;
-        for _ in 0..n {
-            if self.highest_minor_index.load(Ordering::Relaxed) == u32::MAX {
-                // We're at the max, time to quit.
-                return count;
-            }
-            let sub_index = SubIndex::new(
-                self.major_index,
-                self.highest_minor_index.load(Ordering::Relaxed) + 1,
-            );
-            let subaddress = format!(
-                "{}",
-                subaddress::get_subaddress(&self.viewpair, sub_index.into(), None)
-            );
-            self.available_subaddresses.insert(sub_index, subaddress);
+    fn extend_by(&mut self, wallet: &str, major_index: u32, n: u32) -> u32 {
+        let reuse_funded = self.allocator.reuse_funded();
+        let Some(history) = self.wallets.get_mut(wallet) else {
+            return 0;
+        };
+        let Some((viewpair, accounts)) = &mut history.tracked else {
+            return 0;
+        };
+        let Some(account) = accounts.get_mut(&major_index) else {
+            return 0;
+        };
+        if account.highest_minor_index == u32::MAX {
+            // We're at the max, time to quit.
+            return 0;
+        }
+        let subaddresses = generate_range(
+            SubIndex::new(major_index, account.highest_minor_index + 1),
+            SubIndex::new(major_index, account.highest_minor_index.saturating_add(n)),
+            viewpair,
+        );
+        let count = u32::try_from(subaddresses.len()).unwrap_or(u32::MAX);
+        if let Some((sub_index, _)) = subaddresses.last() {
+            account.highest_minor_index = sub_index.minor;
             self.highest_minor_index
-                .store(sub_index.minor, Ordering::Relaxed);
-            count += 1;
+                .fetch_max(sub_index.minor, Ordering::Relaxed);
         }
+        account
+            .available_subaddresses
+            .extend(subaddresses.into_iter().filter(|(sub_index, _)| {
+                may_allocate(
+                    *sub_index,
+                    &self.in_use,
+                    &history.funded,
+                    &history.reserved,
+                    reuse_funded,
+                )
+            }));
         count
     }
 }
 
+impl WalletSubaddresses {
+    fn may_allocate(
+        &self,
+        sub_index: SubIndex,
+        in_use: &HashSet<SubIndex>,
+        reuse_funded: bool,
+    ) -> bool {
+        may_allocate(
+            sub_index,
+            in_use,
+            &self.funded,
+            &self.reserved,
+            reuse_funded,
+        )
+    }
+}
+
+/// Whether a subaddress may be allocated to a wallet, given the subaddresses in
+/// use by any wallet and those the wallet has funded or reserved.
+fn may_allocate(
+    sub_index: SubIndex,
+    in_use: &HashSet<SubIndex>,
+    funded: &HashSet<SubIndex>,
+    reserved: &HashMap<SubIndex, u64>,
+    reuse_funded: bool,
+) -> bool {
+    !in_use.contains(&sub_index)
+        && !reserved.contains_key(&sub_index)
+        && (reuse_funded || !funded.contains(&sub_index))
+}
+
+/// Get the wallets and subaddresses of archived invoices which were never paid,
+/// and the height from which each may be reused.
+async fn reserved_subaddresses<S: AsyncStorage>(
+    storage: &S,
+    reuse_delay: u64,
+) -> Result<HashMap<(String, SubIndex), u64>, StorageError> {
+    if reuse_delay == 0 {
+        return Ok(HashMap::new());
+    }
+    let reserved = Arc::new(Mutex::new(HashMap::new()));
+    let cloned_reserved = reserved.clone();
+    storage
+        .try_for_each_archived_invoice(move |invoice_or_err| {
+            let invoice = invoice_or_err?;
+            if invoice.amount_paid() == Amount::ZERO {
+                let until = invoice.expiration_height().saturating_add(reuse_delay);
+                let mut reserved = cloned_reserved
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let height = reserved
+                    .entry((invoice.wallet().to_string(), invoice.index()))
+                    .or_insert(until);
+                *height = (*height).max(until);
+            }
+            Ok(())
+        })
+        .await?;
+    let reserved = reserved
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .drain()
+        .collect();
+    Ok(reserved)
+}
+
 /// Generates range of subaddresses between the two `SubIndex`s provided,
 /// inclusive.
 ///
@@ -197,12 +618,20 @@ fn generate_range(
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod test {
-    use std::{cmp::Ordering, str::FromStr};
+    use std::{
+        cmp::Ordering,
+        str::FromStr,
+        sync::{atomic::AtomicU32, Arc},
+    };
 
     use test_case::test_case;
+    use testing_utils::{other_view_pair, view_pair, OTHER_PRIMARY_ADDRESS, PRIMARY_ADDRESS};
 
-    use super::generate_range;
-    use crate::SubIndex;
+    use super::{generate_range, SubaddressCache};
+    use crate::{
+        storage::{stores::InMemory, Client},
+        AcceptXmrError, SequentialAllocator, SubIndex, SubaddressUsage,
+    };
 
     #[test_case(SubIndex::new(0, 0), SubIndex::new(0, 100))]
     #[test_case(SubIndex::new(0, 0), SubIndex::new(0, 0))]
@@ -253,4 +682,140 @@ mod test {
         };
         assert_eq!(max_generated, expected_max_generated);
     }
+
+    #[tokio::test]
+    async fn gap_limit() {
+        let store = Client::new(InMemory::new());
+        let mut cache = SubaddressCache::init(
+            &store,
+            &[(PRIMARY_ADDRESS.to_string(), view_pair())],
+            &[0],
+            Arc::new(AtomicU32::new(0)),
+            Box::new(SequentialAllocator),
+            Some(3),
+            0,
+        )
+        .await
+        .unwrap();
+
+        for minor in 0..3 {
+            assert_eq!(
+                cache.allocate(PRIMARY_ADDRESS, 0, 0).unwrap().0,
+                SubIndex::new(0, minor)
+            );
+        }
+        assert!(matches!(
+            cache.allocate(PRIMARY_ADDRESS, 0, 0),
+            Err(AcceptXmrError::GapLimitReached(0))
+        ));
+
+        // Funds received by a subaddress move the gap limit.
+        cache.mark_funded([(PRIMARY_ADDRESS.to_string(), SubIndex::new(0, 1))]);
+        for minor in 3..5 {
+            assert_eq!(
+                cache.allocate(PRIMARY_ADDRESS, 0, 0).unwrap().0,
+                SubIndex::new(0, minor)
+            );
+        }
+        assert!(cache.allocate(PRIMARY_ADDRESS, 0, 0).is_err());
+        assert_eq!(
+            cache.usage(PRIMARY_ADDRESS).unwrap(),
+            [SubaddressUsage {
+                account_index: 0,
+                highest_used_minor_index: Some(4),
+                highest_funded_minor_index: Some(1),
+            }]
+        );
+
+        // Released subaddresses within the gap limit can be allocated again.
+        cache.release(PRIMARY_ADDRESS, SubIndex::new(0, 2), false);
+        assert_eq!(
+            cache.allocate(PRIMARY_ADDRESS, 0, 0).unwrap().0,
+            SubIndex::new(0, 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn reuse_delay() {
+        let store = Client::new(InMemory::new());
+        let mut cache = SubaddressCache::init(
+            &store,
+            &[(PRIMARY_ADDRESS.to_string(), view_pair())],
+            &[0],
+            Arc::new(AtomicU32::new(0)),
+            Box::new(SequentialAllocator),
+            None,
+            5,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            cache.allocate(PRIMARY_ADDRESS, 0, 0).unwrap().0,
+            SubIndex::new(0, 0)
+        );
+        cache.reserve(PRIMARY_ADDRESS, SubIndex::new(0, 0), 10);
+
+        // The subaddress is held until the reuse delay past expiration has passed.
+        assert_eq!(
+            cache.allocate(PRIMARY_ADDRESS, 0, 14).unwrap().0,
+            SubIndex::new(0, 1)
+        );
+        assert_eq!(
+            cache.allocate(PRIMARY_ADDRESS, 0, 15).unwrap().0,
+            SubIndex::new(0, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn per_wallet() {
+        let store = Client::new(InMemory::new());
+        let mut cache = SubaddressCache::init(
+            &store,
+            &[(PRIMARY_ADDRESS.to_string(), view_pair())],
+            &[0],
+            Arc::new(AtomicU32::new(0)),
+            Box::new(SequentialAllocator),
+            Some(2),
+            0,
+        )
+        .await
+        .unwrap();
+        cache.insert_wallet(OTHER_PRIMARY_ADDRESS, other_view_pair());
+
+        // Each wallet has its own gap limit, but an index in use by one wallet
+        // isn't allocated to another.
+        let (sub_index, subaddress) = cache.allocate(PRIMARY_ADDRESS, 0, 0).unwrap();
+        assert_eq!(sub_index, SubIndex::new(0, 0));
+        let (other_sub_index, other_subaddress) =
+            cache.allocate(OTHER_PRIMARY_ADDRESS, 0, 0).unwrap();
+        assert_eq!(other_sub_index, SubIndex::new(0, 1));
+        assert_ne!(subaddress, other_subaddress);
+        assert!(matches!(
+            cache.allocate(PRIMARY_ADDRESS, 0, 0),
+            Err(AcceptXmrError::GapLimitReached(0))
+        ));
+
+        // Funds received by one wallet don't move the other's gap limit.
+        cache.mark_funded([(OTHER_PRIMARY_ADDRESS.to_string(), SubIndex::new(0, 1))]);
+        assert!(cache.allocate(PRIMARY_ADDRESS, 0, 0).is_err());
+        assert_eq!(
+            cache.allocate(OTHER_PRIMARY_ADDRESS, 0, 0).unwrap().0,
+            SubIndex::new(0, 2)
+        );
+
+        // Released indices are available to every wallet again.
+        cache.release(PRIMARY_ADDRESS, SubIndex::new(0, 0), false);
+        assert_eq!(
+            cache.allocate(OTHER_PRIMARY_ADDRESS, 0, 0).unwrap().0,
+            SubIndex::new(0, 0)
+        );
+
+        cache.remove_wallet(OTHER_PRIMARY_ADDRESS);
+        assert!(matches!(
+            cache.allocate(OTHER_PRIMARY_ADDRESS, 0, 0),
+            Err(AcceptXmrError::UnknownWallet(_))
+        ));
+        assert!(cache.usage(OTHER_PRIMARY_ADDRESS).is_none());
+    }
 }
@@ -11,7 +11,7 @@ use monero::cryptonote::subaddress;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-const PICONEROS_PER_XMR: u64 = 1_000_000_000_000;
+use crate::Amount;
 
 /// Representation of an invoice. `Invoice`s are created by the
 /// [`PaymentGateway`](crate::PaymentGateway).
@@ -26,11 +26,13 @@ const PICONEROS_PER_XMR: u64 = 1_000_000_000_000;
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "bincode", derive(Encode, Decode))]
 pub struct Invoice {
-    address: String,
+    pub(crate) address: String,
+    /// Primary address of the wallet the subaddress belongs to.
+    wallet: String,
     index: SubIndex,
     creation_height: u64,
-    amount_requested: u64,
-    pub(crate) amount_paid: u64,
+    amount_requested: Amount,
+    pub(crate) amount_paid: Amount,
     /// The height at which the `Invoice` was fully paid. Will be `None`
     /// if not yet fully paid, or if the required XMR is still in the
     /// txpool (which has no height).
@@ -40,14 +42,20 @@ pub struct Invoice {
     expiration_height: u64,
     pub(crate) transfers: Vec<Transfer>,
     pub(crate) description: String,
+    /// The merchant's own reference for this `Invoice`, such as an order ID.
+    /// Unique among tracked invoices.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) reference: Option<String>,
 }
 
 impl Invoice {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         address: String,
+        wallet: String,
         index: SubIndex,
         creation_height: u64,
-        amount_requested: u64,
+        amount_requested: Amount,
         confirmations_required: u64,
         expiration_in: u64,
         description: String,
@@ -55,16 +63,36 @@ impl Invoice {
         let expiration_height = creation_height + expiration_in;
         Invoice {
             address,
+            wallet,
             index,
             creation_height,
             amount_requested,
-            amount_paid: 0,
+            amount_paid: Amount::ZERO,
             paid_height: None,
             confirmations_required,
             current_height: 0,
             expiration_height,
             transfers: Vec::new(),
             description,
+            reference: None,
+        }
+    }
+
+    /// Recalculate the amount paid and the height at which the `Invoice` was
+    /// fully paid from its transfers.
+    pub(crate) fn recalculate_amount_paid(&mut self) {
+        // Zero it out first.
+        self.paid_height = None;
+        self.amount_paid = Amount::ZERO;
+        // Now add up the transfers.
+        for transfer in &self.transfers {
+            // Saturate rather than wrap, so an absurd sum can't look unpaid.
+            self.amount_paid = self
+                .amount_paid
+                .saturating_add(Amount::from_pico(transfer.amount));
+            if self.amount_paid >= self.amount_requested && self.paid_height.is_none() {
+                self.paid_height = transfer.height;
+            }
         }
     }
 
@@ -79,18 +107,9 @@ impl Invoice {
     /// amount field for the user (and sometimes the description field as
     /// well). They are supported by all major wallets.
     #[must_use]
-    #[allow(clippy::cast_precision_loss)]
     pub fn uri(&self) -> String {
-        let piconeros_due = self.amount_requested.saturating_sub(self.amount_paid);
-        let whole_xmr_due = piconeros_due / PICONEROS_PER_XMR;
-        let fractional_xmr_due =
-            (piconeros_due % PICONEROS_PER_XMR) as f64 / PICONEROS_PER_XMR as f64;
-        format!(
-            "monero:{}?tx_amount={}.{}",
-            &self.address,
-            whole_xmr_due,
-            fractional_xmr_due.to_string().trim_start_matches("0.")
-        )
+        let due = self.amount_requested.saturating_sub(self.amount_paid);
+        format!("monero:{}?tx_amount={}", &self.address, due)
     }
 
     /// Returns `true` if the `Invoice` has received the required number of
@@ -123,6 +142,14 @@ impl Invoice {
         &self.address
     }
 
+    /// Returns the primary address of the wallet this `Invoice`'s subaddress
+    /// belongs to. Empty for invoices created by `AcceptXMR` 0.14 or earlier,
+    /// which belong to the default wallet.
+    #[must_use]
+    pub fn wallet(&self) -> &str {
+        &self.wallet
+    }
+
     /// Returns the ID of this invoice.
     #[must_use]
     pub fn id(&self) -> InvoiceId {
@@ -138,30 +165,20 @@ impl Invoice {
         self.index
     }
 
-    /// Returns the blockchain height at which the `Invoice` was created.
-    #[must_use]
-    pub fn creation_height(&self) -> u64 {
-        self.creation_height
-    }
-
-    /// Returns the amount of monero requested in piconeros.
+    /// Returns the account index (i.e. subaddress major index) this `Invoice`
+    /// was allocated from.
     #[must_use]
-    pub fn amount_requested(&self) -> u64 {
-        self.amount_requested
+    pub fn account_index(&self) -> u32 {
+        self.index.major
     }
 
-    /// Returns the amount of monero paid in piconeros.
+    /// Returns the blockchain height at which the `Invoice` was created.
     #[must_use]
-    pub fn amount_paid(&self) -> u64 {
-        self.amount_paid
+    pub fn creation_height(&self) -> u64 {
+        self.creation_height
     }
 
-    /// Returns the amount of monero requested in XMR.
-    ///
-    /// Note that rounding may occur because the precision of `f64` is
-    /// insufficient for representing large amounts of XMR out to many decimal
-    /// places. If accuracy is desired,
-    /// [`amount_requested()`](#method.amount_requested) should be preferred.
+    /// Returns the amount of monero requested.
     ///
     /// # Examples
     ///
@@ -171,7 +188,7 @@ impl Invoice {
     /// #
     /// # use std::time::Duration;
     /// #
-    /// # use acceptxmr::{PaymentGatewayBuilder, storage::stores::InMemory};
+    /// # use acceptxmr::{Amount, PaymentGatewayBuilder, storage::stores::InMemory};
     /// #
     /// # let store = InMemory::new();
     /// #
@@ -179,50 +196,56 @@ impl Invoice {
     /// # let primary_address = "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
     /// #
     /// # let payment_gateway = PaymentGatewayBuilder::new(private_view_key.to_string(), primary_address.to_string(), store)
-    /// #   .build()
+    /// #   .build_with_mock_daemon()
     /// #   .await?;
     /// #
     /// // Create a new `Invoice` for 1 millinero.
-    /// let invoice_id = payment_gateway.new_invoice(1_000_000_000, 3, 5, "for pizza".to_string()).await?;
+    /// let invoice_id = payment_gateway.new_invoice(Amount::ONE_MILLINERO, 3, 5, "for pizza".to_string()).await?;
     /// let small_invoice = payment_gateway.get_invoice(invoice_id).await?.expect("invoice ID not found");
     ///
     /// // One millinero, as expected.
-    /// assert_eq!(small_invoice.xmr_requested(), 0.001);
+    /// assert_eq!(small_invoice.amount_requested().to_string(), "0.001");
     ///
     /// // Create a new `Invoice` for 18446744.073709551615 XMR.
-    /// let invoice_id = payment_gateway.new_invoice(18_446_744_073_709_551_615, 3, 5, "for lambo".to_string()).await?;
+    /// let invoice_id = payment_gateway.new_invoice(Amount::MAX, 3, 5, "for lambo".to_string()).await?;
     /// let large_invoice = payment_gateway.get_invoice(invoice_id).await?.expect("invoice ID not found");
     ///
-    /// // The large value has been rounded slightly due to f64 precision limitations.
-    /// assert_eq!(large_invoice.xmr_requested(), 18446744.07370955);
+    /// // Large values are represented exactly.
+    /// assert_eq!(large_invoice.amount_requested().to_string(), "18446744.073709551615");
     /// #   Ok(())
     /// # }
     /// ```
     #[must_use]
-    #[allow(clippy::cast_precision_loss)]
+    pub fn amount_requested(&self) -> Amount {
+        self.amount_requested
+    }
+
+    /// Returns the amount of monero paid.
+    #[must_use]
+    pub fn amount_paid(&self) -> Amount {
+        self.amount_paid
+    }
+
+    /// Returns the amount of monero requested in XMR.
+    ///
+    /// Note that rounding may occur because the precision of `f64` is
+    /// insufficient for representing large amounts of XMR out to many decimal
+    /// places.
+    #[must_use]
+    #[deprecated(note = "rounds large amounts; use `amount_requested()`, which is exact")]
     pub fn xmr_requested(&self) -> f64 {
-        let whole_xmr = self.amount_requested / PICONEROS_PER_XMR;
-        let fractional_xmr =
-            (self.amount_requested % PICONEROS_PER_XMR) as f64 / PICONEROS_PER_XMR as f64;
-        whole_xmr as f64 + fractional_xmr
+        xmr_f64(self.amount_requested)
     }
 
     /// Returns the amount of monero paid in XMR.
     ///
     /// Note that rounding may occur because the precision of `f64` is
     /// insufficient for representing large amounts of XMR out to many decimal
-    /// places. If accuracy is desired, [`amount_paid()`](#method.amount_paid)
-    /// should be preferred.
-    ///
-    /// For an example of possible rounding error, see
-    /// [`xmr_requested()`](#method.xmr_requested)
+    /// places.
     #[must_use]
-    #[allow(clippy::cast_precision_loss)]
+    #[deprecated(note = "rounds large amounts; use `amount_paid()`, which is exact")]
     pub fn xmr_paid(&self) -> f64 {
-        let whole_xmr = self.amount_paid / PICONEROS_PER_XMR;
-        let fractional_xmr =
-            (self.amount_paid % PICONEROS_PER_XMR) as f64 / PICONEROS_PER_XMR as f64;
-        whole_xmr as f64 + fractional_xmr
+        xmr_f64(self.amount_paid)
     }
 
     /// Returns the number of confirmations this `Invoice` requires before it is
@@ -301,6 +324,23 @@ impl Invoice {
     pub fn description(&self) -> &str {
         &self.description
     }
+
+    /// Returns the merchant's reference for this invoice, such as an order ID,
+    /// if it was created with one.
+    #[must_use]
+    pub fn reference(&self) -> Option<&str> {
+        self.reference.as_deref()
+    }
+}
+
+/// Convert an amount to XMR, rounding if `f64` is too imprecise.
+#[allow(clippy::cast_precision_loss)]
+fn xmr_f64(amount: Amount) -> f64 {
+    let piconeros = amount.as_pico();
+    let piconeros_per_xmr = Amount::ONE_XMR.as_pico();
+    let whole_xmr = piconeros / piconeros_per_xmr;
+    let fractional_xmr = (piconeros % piconeros_per_xmr) as f64 / piconeros_per_xmr as f64;
+    whole_xmr as f64 + fractional_xmr
 }
 
 impl fmt::Display for Invoice {
@@ -320,8 +360,8 @@ impl fmt::Display for Invoice {
             \ntransfers: \
             \n[",
             self.index,
-            monero::Amount::from_pico(self.amount_paid).as_xmr(),
-            monero::Amount::from_pico(self.amount_requested).as_xmr(),
+            self.amount_paid,
+            self.amount_requested,
             confirmations,
             self.creation_height,
             self.current_height,
@@ -362,6 +402,7 @@ impl PartialEq for Invoice {
 
         lhs_transfers == rhs_transfers
             && self.address == other.address
+            && self.wallet == other.wallet
             && self.index == other.index
             && self.creation_height == other.creation_height
             && self.amount_requested == other.amount_requested
@@ -371,6 +412,7 @@ impl PartialEq for Invoice {
             && self.current_height == other.current_height
             && self.expiration_height == other.expiration_height
             && self.description == other.description
+            && self.reference == other.reference
     }
 }
 
@@ -489,11 +531,40 @@ pub(crate) struct Transfer {
     pub(crate) amount: u64,
     /// Block height of the transfer, or None if the outputs are in the txpool.
     pub(crate) height: Option<u64>,
+    /// Hash of the transaction containing the outputs, or `None` if the
+    /// transfer was recorded before hashes were.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) tx_hash: Option<[u8; 32]>,
 }
 
 impl Transfer {
     pub(crate) fn new(amount: u64, height: Option<u64>) -> Transfer {
-        Transfer { amount, height }
+        Transfer {
+            amount,
+            height,
+            tx_hash: None,
+        }
+    }
+
+    /// Create a transfer of outputs in the transaction with the given hash.
+    pub(crate) fn in_tx(tx_hash: [u8; 32], amount: u64, height: Option<u64>) -> Transfer {
+        Transfer {
+            amount,
+            height,
+            tx_hash: Some(tx_hash),
+        }
+    }
+
+    /// Create a transfer of outputs in the transaction with the given
+    /// hex-encoded hash. The hash is left out if it isn't valid.
+    pub(crate) fn in_hex_tx(tx_hash: &str, amount: u64, height: Option<u64>) -> Transfer {
+        Transfer {
+            amount,
+            height,
+            tx_hash: hex::decode(tx_hash)
+                .ok()
+                .and_then(|hash| hash.try_into().ok()),
+        }
     }
 
     /// Compare two transfers by height. Newer is greater.
@@ -550,11 +621,11 @@ mod tests {
     use test_case::test_case;
     use testing_utils::init_logger;
 
-    use crate::{Invoice, InvoiceId, SubIndex};
+    use crate::{Amount, Invoice, InvoiceId, SubIndex};
 
     #[test_case(1, 0 => "0.000000000001".to_string(); "small")]
     #[test_case(u64::MAX, 0 => "18446744.073709551615".to_string(); "big")]
-    #[test_case(1, 1 => "0.0"; "zero")]
+    #[test_case(1, 1 => "0"; "zero")]
     #[test_case(2_460_000_000_000, 1_230_000_000_000 => "1.23"; "partially paid")]
     fn payment_request(requested: u64, paid: u64) -> String {
         // Setup.
@@ -562,14 +633,15 @@ mod tests {
 
         let mut invoice = Invoice::new(
             "testAddress".to_string(),
+            "testWallet".to_string(),
             SubIndex::new(0, 1),
             0,
-            requested,
+            Amount::from_pico(requested),
             5,
             10,
             "test_description".to_string(),
         );
-        invoice.amount_paid = paid;
+        invoice.amount_paid = Amount::from_pico(paid);
 
         let uri = invoice.uri();
         let amount = uri
@@ -582,15 +654,17 @@ mod tests {
     #[test_case(1 => "0.000000000001".to_string(); "small")]
     #[test_case(u64::MAX => "18446744.07370955".to_string(); "big")]
     #[test_case(0 => "0".to_string(); "zero")]
+    #[allow(deprecated)]
     fn xmr_requested(requested: u64) -> String {
         // Setup.
         init_logger();
 
         let invoice = Invoice::new(
             "testAddress".to_string(),
+            "testWallet".to_string(),
             SubIndex::new(0, 1),
             0,
-            requested,
+            Amount::from_pico(requested),
             5,
             10,
             "test_description".to_string(),
@@ -604,9 +678,10 @@ mod tests {
 
         let invoice = Invoice::new(
             "testAddress".to_string(),
+            "testWallet".to_string(),
             SubIndex::new(0, 1),
             12345,
-            1,
+            Amount::from_pico(1),
             5,
             10,
             "test_description".to_string(),
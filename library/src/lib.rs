@@ -45,7 +45,8 @@
 //! successful. `AcceptXMR` is young and unproven, and relies on several crates
 //! which are undergoing rapid changes themselves. For example, the primary
 //! storage layer implementation ([`Sled`](https://docs.rs/sled)) is still in
-//! beta.
+//! beta. [`Redb`](storage::stores::Redb) is a stable embedded alternative, and
+//! can migrate an existing sled database.
 //!
 //! That said, this payment gateway should survive unexpected power loss thanks
 //! to the ability to flush pending invoices to disk each time new
@@ -66,7 +67,7 @@
 //! To reduce the average latency before receiving invoice updates, you may also
 //! consider lowering the [`PaymentGateway`]'s `scan_interval` below the default
 //! of 1 second:
-//! ```
+//! ```no_run
 //! # #[tokio::main]
 //! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! use acceptxmr::{PaymentGatewayBuilder, storage::stores::InMemory};
@@ -116,10 +117,27 @@
 //! The `sled` feature enables the [`Sled`](storage::stores::Sled) storage
 //! implementation. The `bincode` feature will also be enabled by this feature.
 //!
+//! ### `redb`
+//!
+//! The `redb` feature enables the [`Redb`](storage::stores::Redb) storage
+//! implementation. The `bincode` feature will also be enabled by this feature.
+//!
 //! ### `sqlite`
 //!
 //! The `sqlite` feature enables the [`Sqlite`](storage::stores::Sqlite) storage
 //! implementation. The `bincode` feature will also be enabled by this feature.
+//!
+//! ### `encryption`
+//!
+//! The `encryption` feature enables the
+//! [`Encrypted`](storage::stores::Encrypted) storage wrapper, which encrypts
+//! invoices stored by any other storage implementation.
+//!
+//! ### `postgres`
+//!
+//! The `postgres` feature enables the [`Postgres`](storage::stores::Postgres)
+//! storage implementation. The `bincode` feature will also be enabled by this
+//! feature.
 
 #![warn(clippy::panic)]
 #![warn(clippy::unwrap_used)]
@@ -128,25 +146,38 @@
 // Show feature flag tags on `docs.rs`
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+mod amount;
 mod caching;
 mod invoice;
+mod light_wallet;
 mod monerod_client;
 mod payment_gateway;
 mod pubsub;
 mod scanner;
 pub mod storage;
+mod subaddress_allocator;
+mod wallet_rpc;
+mod wallets;
 
 use std::fmt::Debug;
 
+pub use amount::{Amount, Denomination, ParseAmountError};
 pub use invoice::{Invoice, InvoiceId, SubIndex};
 pub use monerod_client::{
-    Client as MonerodClient, MockClient as MonerodMockClient, RpcClient as MonerodRpcClient,
-    RpcError,
+    Client as MonerodClient, DaemonInfo, MockClient as MonerodMockClient,
+    RecordingClient as MonerodRecordingClient, ReplayClient as MonerodReplayClient,
+    RpcClient as MonerodRpcClient, RpcError, RpcPolicy, RpcVersion,
 };
 pub use payment_gateway::{PaymentGateway, PaymentGatewayBuilder, PaymentGatewayStatus};
-pub use pubsub::{Subscriber, SubscriberError};
+pub use pubsub::{
+    InvoiceEvent, InvoiceEventKind, SequencedEvent, Subscriber, SubscriberError, SubscriptionFilter,
+};
 use scanner::ScannerError;
 use storage::StorageError;
+pub use subaddress_allocator::{
+    NeverReuseFunded, RandomAllocator, SequentialAllocator, SubaddressAllocator, SubaddressReport,
+    SubaddressUsage,
+};
 use thiserror::Error;
 
 /// Library's custom error type.
@@ -171,6 +202,64 @@ pub enum AcceptXmrError {
     /// Blockchain scanner encountered an error.
     #[error("blockchain scanner encountered an error: {0}")]
     Scanner(#[from] ScannerError),
+    /// The monero daemon is running on a different network than the one the
+    /// primary address belongs to.
+    #[error("monero daemon is running on {daemon:?}, but the primary address is for {address:?}")]
+    NetworkMismatch {
+        /// Network the daemon is running on.
+        daemon: monero::Network,
+        /// Network the primary address belongs to.
+        address: monero::Network,
+    },
+    /// The monero daemon's RPC interface is of an unsupported version.
+    #[error(
+        "monero daemon RPC version {found} is not supported (expected major version {expected_major})"
+    )]
+    UnsupportedRpcVersion {
+        /// The daemon's RPC version.
+        found: RpcVersion,
+        /// The RPC major version supported by this library.
+        expected_major: u16,
+    },
+    /// The monero daemon's RPC interface is restricted, and an endpoint this
+    /// library relies on is unavailable.
+    #[error("monero daemon RPC is restricted and the {endpoint} endpoint is unavailable: {error}")]
+    RestrictedRpc {
+        /// The unavailable endpoint.
+        endpoint: &'static str,
+        /// Error encountered when calling the endpoint.
+        error: RpcError,
+    },
+    /// The wallet RPC is serving a different wallet than the one the primary
+    /// address belongs to.
+    #[error("wallet RPC is serving wallet {wallet}, but the primary address is {expected}")]
+    WalletMismatch {
+        /// Primary address of the wallet served by the wallet RPC.
+        wallet: String,
+        /// The configured primary address.
+        expected: String,
+    },
+    /// The account index is not one the payment gateway was configured to
+    /// use.
+    #[error("account index {0} is not configured for this payment gateway")]
+    UnknownAccount(u32),
+    /// Every subaddress of the account within the gap limit is in use. More
+    /// become available as subaddresses receive funds, or as invoices are
+    /// removed.
+    #[error("every subaddress of account {0} within the gap limit is in use")]
+    GapLimitReached(u32),
+    /// The wallet is not one the payment gateway tracks payments to.
+    #[error("wallet {0} is not tracked by this payment gateway")]
+    UnknownWallet(String),
+    /// The wallet cannot be removed, because it is the payment gateway's
+    /// default wallet or because it has tracked invoices.
+    #[error("wallet {0} is the default wallet or has tracked invoices")]
+    WalletInUse(String),
+    /// Tracking several wallets is only supported when the payment gateway
+    /// scans the blockchain itself, not when it uses a wallet RPC or light
+    /// wallet server.
+    #[error("multiple wallets are only supported when scanning the blockchain")]
+    MultipleWalletsUnsupported,
     /// Payment gateway is already running.
     #[error("payment gateway is already running")]
     AlreadyRunning,
@@ -0,0 +1,321 @@
+mod scanner;
+
+use std::{collections::HashMap, fmt};
+
+use log::trace;
+pub(crate) use scanner::LightWalletScanner;
+use serde_json::{json, Value};
+
+use crate::{
+    monerod_client::{Client as _, RpcClient, RpcError},
+    SubIndex,
+};
+
+/// A client for a light wallet server implementing the Monero light wallet
+/// REST API, such as `monero-lws`. Wraps an [`RpcClient`], so timeouts,
+/// retries and rate limiting behave the same as for the monero daemon.
+#[derive(Clone)]
+pub(crate) struct LightWalletClient {
+    rpc_client: RpcClient,
+    address: String,
+    view_key: String,
+}
+
+impl LightWalletClient {
+    pub(crate) fn new(
+        rpc_client: RpcClient,
+        address: String,
+        view_key: String,
+    ) -> LightWalletClient {
+        LightWalletClient {
+            rpc_client,
+            address,
+            view_key,
+        }
+    }
+
+    /// Whether calls to the light wallet server are currently suspended
+    /// because it has been failing repeatedly.
+    pub(crate) fn is_degraded(&self) -> bool {
+        self.rpc_client.is_degraded()
+    }
+
+    pub(crate) fn url(&self) -> String {
+        self.rpc_client.url()
+    }
+
+    /// Send a request to `endpoint`, authenticated with the wallet's address
+    /// and view key.
+    async fn request(&self, endpoint: &str, mut body: Value) -> Result<Value, RpcError> {
+        body["address"] = self.address.clone().into();
+        body["view_key"] = self.view_key.clone().into();
+        self.rpc_client.request(&body.to_string(), endpoint).await
+    }
+
+    /// Log in to the light wallet server, creating an account for the wallet
+    /// if it does not already exist. Returns `true` if a new account was
+    /// created.
+    pub(crate) async fn login(&self) -> Result<bool, RpcError> {
+        trace!("Logging in to light wallet server");
+        let res = self
+            .request(
+                "login",
+                json!({ "create_account": true, "generated_locally": false }),
+            )
+            .await?;
+
+        Ok(res["new_address"].as_bool().unwrap_or(false))
+    }
+
+    /// Ask the light wallet server to scan for outputs to subaddresses
+    /// `0..=highest_minor_index` of each of the given accounts.
+    pub(crate) async fn upsert_subaddresses(
+        &self,
+        major_indices: &[u32],
+        highest_minor_index: u32,
+    ) -> Result<(), RpcError> {
+        trace!(
+            "Requesting light wallet server scan subaddresses up to minor index {} of accounts {:?}",
+            highest_minor_index,
+            major_indices
+        );
+        let subaddrs: Vec<Value> = major_indices
+            .iter()
+            .map(|major_index| json!({ "key": major_index, "value": [[0, highest_minor_index]] }))
+            .collect();
+        self.request(
+            "upsert_subaddrs",
+            json!({ "subaddrs": subaddrs, "get_all": false }),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch the wallet's transactions, along with the scan progress of the
+    /// light wallet server.
+    pub(crate) async fn address_txs(&self) -> Result<AddressTxs, RpcError> {
+        trace!("Requesting transactions from light wallet server");
+        let res = self.request("get_address_txs", json!({})).await?;
+
+        AddressTxs::from_json(&res)
+    }
+
+    /// Fetch the outputs received by the wallet. Because the light wallet
+    /// server only has the view key, this includes outputs which may have
+    /// been spent since.
+    pub(crate) async fn received_outputs(&self) -> Result<Vec<ReceivedOutput>, RpcError> {
+        trace!("Requesting outputs from light wallet server");
+        let res = self
+            .request(
+                "get_unspent_outs",
+                json!({
+                    "amount": "0",
+                    "mixin": 0,
+                    "use_dust": true,
+                    "dust_threshold": "0",
+                }),
+            )
+            .await?;
+
+        // The server omits empty lists entirely.
+        res["outputs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(ReceivedOutput::from_json)
+            .collect()
+    }
+}
+
+impl fmt::Debug for LightWalletClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LightWalletClient")
+            .field("rpc_client", &self.rpc_client)
+            .field("address", &self.address)
+            .field("view_key", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// The response to a `get_address_txs` request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AddressTxs {
+    /// Height of the last block scanned by the light wallet server.
+    pub(crate) scanned_block_height: u64,
+    /// Height of the blockchain's top block.
+    pub(crate) blockchain_height: u64,
+    /// The wallet's transactions, by hash.
+    pub(crate) transactions: HashMap<String, LightWalletTx>,
+}
+
+impl AddressTxs {
+    fn from_json(res: &Value) -> Result<AddressTxs, RpcError> {
+        let transactions = res["transactions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|tx| {
+                let hash = tx["hash"].as_str().ok_or_else(|| {
+                    RpcError::MissingData("{{ transactions: [ {{ hash: \"...\" }} ] }}".to_string())
+                })?;
+                Ok((hash.to_string(), LightWalletTx::from_json(tx)))
+            })
+            .collect::<Result<_, RpcError>>()?;
+
+        Ok(AddressTxs {
+            scanned_block_height: u64_field(res, "scanned_block_height")?,
+            blockchain_height: u64_field(res, "blockchain_height")?,
+            transactions,
+        })
+    }
+}
+
+/// A transaction reported by a light wallet server.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct LightWalletTx {
+    pub(crate) unlock_time: u64,
+    /// Whether the transaction is in the txpool.
+    pub(crate) mempool: bool,
+}
+
+impl LightWalletTx {
+    fn from_json(tx: &Value) -> LightWalletTx {
+        LightWalletTx {
+            unlock_time: u64_field(tx, "unlock_time").unwrap_or_default(),
+            mempool: tx["mempool"].as_bool().unwrap_or(false),
+        }
+    }
+}
+
+/// An output received by the wallet, as reported by a light wallet server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ReceivedOutput {
+    pub(crate) sub_index: SubIndex,
+    /// Amount received in piconeros.
+    pub(crate) amount: u64,
+    pub(crate) tx_hash: String,
+    /// Height of the block containing the output, or `None` if it is in the
+    /// txpool.
+    pub(crate) height: Option<u64>,
+}
+
+impl ReceivedOutput {
+    fn from_json(output: &Value) -> Result<ReceivedOutput, RpcError> {
+        // Outputs to the primary address may be reported without a recipient.
+        let recipient = &output["recipient"];
+        let sub_index = SubIndex::new(
+            u32_field(recipient, "maj_i").unwrap_or_default(),
+            u32_field(recipient, "min_i").unwrap_or_default(),
+        );
+        let tx_hash = output["tx_hash"].as_str().ok_or_else(|| {
+            RpcError::MissingData("{{ outputs: [ {{ tx_hash: \"...\" }} ] }}".to_string())
+        })?;
+
+        Ok(ReceivedOutput {
+            sub_index,
+            amount: u64_field(output, "amount")?,
+            tx_hash: tx_hash.to_string(),
+            // Outputs in the txpool are reported at height 0.
+            height: u64_field(output, "height").ok().filter(|&h| h != 0),
+        })
+    }
+}
+
+/// Read an integer field, which light wallet servers may encode as either a
+/// number or a string.
+fn u64_field(json: &Value, field: &str) -> Result<u64, RpcError> {
+    match &json[field] {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        Value::Null => {
+            return Err(RpcError::MissingData(format!("{{ {field}: \"...\" }}")));
+        }
+        _ => None,
+    }
+    .ok_or_else(|| RpcError::DataType {
+        found: json[field].clone(),
+        expected: "u64",
+    })
+}
+
+fn u32_field(json: &Value, field: &str) -> Result<u32, RpcError> {
+    u32::try_from(u64_field(json, field)?).map_err(|_| RpcError::DataType {
+        found: json[field].clone(),
+        expected: "u32",
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::{AddressTxs, LightWalletTx, ReceivedOutput};
+    use crate::SubIndex;
+
+    #[test]
+    fn address_txs_from_json() {
+        let txs = AddressTxs::from_json(&json!({
+            "blockchain_height": 2_477_660,
+            "scanned_block_height": "2477659",
+            "start_height": 2_477_000,
+            "total_received": "1000",
+            "transactions": [
+                { "hash": "aa", "height": 2_477_659, "mempool": false, "unlock_time": 0 },
+                { "hash": "bb", "mempool": true, "unlock_time": "10" },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(txs.blockchain_height, 2_477_660);
+        assert_eq!(txs.scanned_block_height, 2_477_659);
+        assert_eq!(
+            txs.transactions["aa"],
+            LightWalletTx {
+                unlock_time: 0,
+                mempool: false
+            }
+        );
+        assert_eq!(
+            txs.transactions["bb"],
+            LightWalletTx {
+                unlock_time: 10,
+                mempool: true
+            }
+        );
+
+        assert!(AddressTxs::from_json(&json!({ "blockchain_height": 10 })).is_err());
+    }
+
+    #[test]
+    fn received_output_from_json() {
+        let output = ReceivedOutput::from_json(&json!({
+            "amount": "1000",
+            "height": 2_477_659,
+            "recipient": { "maj_i": 0, "min_i": 12 },
+            "tx_hash": "aa",
+        }))
+        .unwrap();
+        assert_eq!(
+            output,
+            ReceivedOutput {
+                sub_index: SubIndex::new(0, 12),
+                amount: 1000,
+                tx_hash: "aa".to_string(),
+                height: Some(2_477_659),
+            }
+        );
+
+        // Outputs without a recipient were sent to the primary address.
+        let output = ReceivedOutput::from_json(&json!({
+            "amount": 5,
+            "height": 0,
+            "tx_hash": "bb",
+        }))
+        .unwrap();
+        assert_eq!(output.sub_index, SubIndex::new(0, 0));
+        assert_eq!(output.height, None);
+
+        assert!(ReceivedOutput::from_json(&json!({ "amount": "x", "tx_hash": "cc" })).is_err());
+    }
+}
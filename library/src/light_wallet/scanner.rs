@@ -0,0 +1,154 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use log::{debug, trace, warn};
+use tokio::join;
+
+use super::LightWalletClient;
+use crate::{
+    invoice::Transfer,
+    pubsub::Publisher,
+    scanner::{rebuild_invoices, save_and_publish, tracked_invoices, ScannerError},
+    storage::AsyncStorage,
+    SubIndex,
+};
+
+/// Tracks payments using outputs detected by a light wallet server, rather
+/// than by scanning blocks itself. Reorgs are handled by the server, so every
+/// scan rebuilds each invoice's transfers from scratch.
+pub(crate) struct LightWalletScanner<S: AsyncStorage> {
+    client: LightWalletClient,
+    store: S,
+    major_indices: Vec<u32>,
+    highest_minor_index: Arc<AtomicU32>,
+    /// Highest minor index the server has been asked to scan for, if any.
+    upserted_minor_index: Option<u32>,
+    atomic_cache_height: Arc<AtomicU64>,
+    atomic_daemon_height: Arc<AtomicU64>,
+    publisher: Arc<Publisher>,
+}
+
+impl<S: AsyncStorage> LightWalletScanner<S> {
+    pub(crate) async fn new(
+        client: LightWalletClient,
+        store: S,
+        major_indices: Vec<u32>,
+        highest_minor_index: Arc<AtomicU32>,
+        atomic_cache_height: Arc<AtomicU64>,
+        atomic_daemon_height: Arc<AtomicU64>,
+        publisher: Arc<Publisher>,
+    ) -> Result<LightWalletScanner<S>, ScannerError> {
+        trace!("Retrieving light wallet server height for scanner setup.");
+        let address_txs = client.address_txs().await?;
+        atomic_cache_height.store(address_txs.scanned_block_height, Ordering::Relaxed);
+        atomic_daemon_height.store(address_txs.blockchain_height + 1, Ordering::Relaxed);
+
+        // Initialize the publisher with all currently-tracked invoices.
+        store
+            .get_invoice_ids()
+            .await?
+            .iter()
+            .for_each(|&id| publisher.insert_invoice(id));
+
+        Ok(LightWalletScanner {
+            client,
+            store,
+            major_indices,
+            highest_minor_index,
+            upserted_minor_index: None,
+            atomic_cache_height,
+            atomic_daemon_height,
+            publisher,
+        })
+    }
+
+    /// Scan for invoice updates.
+    pub(crate) async fn scan(&mut self) -> Result<(), ScannerError> {
+        self.upsert_subaddresses().await;
+
+        let (address_txs, outputs) =
+            join!(self.client.address_txs(), self.client.received_outputs());
+        let (address_txs, outputs) = (address_txs?, outputs?);
+        let cache_height = address_txs.scanned_block_height;
+        self.atomic_cache_height
+            .store(cache_height, Ordering::Relaxed);
+        self.atomic_daemon_height
+            .store(address_txs.blockchain_height + 1, Ordering::Relaxed);
+
+        // Sum up owned outputs by transaction and subaddress.
+        let mut transfers: HashMap<(&str, SubIndex), Transfer> = HashMap::new();
+        for output in &outputs {
+            // The unlock time can't be checked until the server reports the
+            // transaction, so leave the output for a later scan.
+            let Some(tx) = address_txs.transactions.get(&output.tx_hash) else {
+                trace!("Output in unknown transaction with hash {}", output.tx_hash);
+                continue;
+            };
+            // Ensure the time lock is zero.
+            if tx.unlock_time != 0 {
+                debug!("Saw time locked transaction with hash {}", output.tx_hash);
+                continue;
+            }
+            let height = if tx.mempool { None } else { output.height };
+            transfers
+                .entry((&output.tx_hash, output.sub_index))
+                .or_insert(Transfer::in_hex_tx(&output.tx_hash, 0, height))
+                .amount += output.amount;
+        }
+        let transfers: Vec<(SubIndex, Transfer)> = transfers
+            .into_iter()
+            .map(|((_, sub_index), transfer)| (sub_index, transfer))
+            .collect();
+        trace!(
+            "Light wallet server reported {} outputs in {} transfers",
+            outputs.len(),
+            transfers.len()
+        );
+
+        let invoices = tracked_invoices(&self.store).await?;
+        let updated_invoices = rebuild_invoices(invoices, &transfers, cache_height + 1);
+
+        save_and_publish(&self.store, &self.publisher, updated_invoices).await;
+
+        // Update last scanned height in the database.
+        self.store.upsert_height(cache_height).await?;
+
+        // Flush changes to the database.
+        self.store.flush().await?;
+
+        Ok(())
+    }
+
+    /// Ask the light wallet server to scan for outputs to every subaddress the
+    /// payment gateway may use in each account, if it hasn't been asked
+    /// already.
+    async fn upsert_subaddresses(&mut self) {
+        let highest_minor_index = self.highest_minor_index.load(Ordering::Relaxed);
+        if self
+            .upserted_minor_index
+            .is_some_and(|upserted| upserted >= highest_minor_index)
+        {
+            return;
+        }
+        if let Err(e) = self
+            .client
+            .upsert_subaddresses(&self.major_indices, highest_minor_index)
+            .await
+        {
+            // Servers configured with a subaddress lookahead may not need this, so
+            // don't fail the scan or retry until more subaddresses are needed.
+            warn!(
+                "Failed to register subaddresses up to minor index {} of accounts {:?} with light wallet server: {}",
+                highest_minor_index,
+                self.major_indices,
+                e
+            );
+        }
+        self.upserted_minor_index = Some(highest_minor_index);
+    }
+}
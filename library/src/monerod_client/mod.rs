@@ -1,8 +1,11 @@
 mod authentication;
+mod policy;
+mod recording;
 
 use std::{
     any,
     collections::HashSet,
+    fmt,
     fs::File,
     future::Future,
     sync::{Arc, Mutex, PoisonError},
@@ -12,6 +15,7 @@ use std::{
 use authentication::{AuthError, AuthInfo};
 use backoff::{backoff::Backoff, ExponentialBackoffBuilder};
 use bytes::Bytes;
+use futures_util::future::join_all;
 use http_body_util::{BodyExt, Full};
 use hyper::{
     header::{AUTHORIZATION, WWW_AUTHENTICATE},
@@ -25,12 +29,17 @@ use hyper_util::{
 };
 use log::{debug, trace, warn};
 use monero::consensus::{deserialize, encode};
+use policy::PolicyState;
+pub use policy::RpcPolicy;
+pub use recording::{RecordingClient, ReplayClient};
 use serde_json::json;
 use thiserror::Error;
 use tokio::time::{error, timeout};
 
 /// Maximum number of transactions to request at once (daemon limits this).
 const MAX_REQUESTED_TRANSACTIONS: usize = 100;
+/// Major version of the monerod RPC interface this library is written against.
+const SUPPORTED_RPC_MAJOR_VERSION: u16 = 3;
 
 /// A monerod RPC client.
 #[derive(Debug, Clone)]
@@ -39,6 +48,7 @@ pub struct RpcClient {
     url: Uri,
     timeout: Duration,
     auth_info: Arc<Mutex<Option<AuthInfo>>>,
+    policy: Arc<PolicyState>,
 }
 
 impl RpcClient {
@@ -50,6 +60,7 @@ impl RpcClient {
         username: Option<String>,
         password: Option<String>,
         seed: Option<u64>,
+        policy: RpcPolicy,
     ) -> RpcClient {
         let mut hyper_connector = HttpConnector::new();
         hyper_connector.set_connect_timeout(Some(connection_timeout));
@@ -77,10 +88,25 @@ impl RpcClient {
             url,
             timeout: total_timeout,
             auth_info,
+            policy: Arc::new(PolicyState::new(policy)),
         }
     }
 
-    async fn request(&self, body: &str, endpoint: &str) -> Result<serde_json::Value, RpcError> {
+    /// Send a request, retrying and rate limiting according to the client's
+    /// [`RpcPolicy`].
+    pub(crate) async fn request(
+        &self,
+        body: &str,
+        endpoint: &str,
+    ) -> Result<serde_json::Value, RpcError> {
+        self.policy.call(|| self.request_once(body, endpoint)).await
+    }
+
+    async fn request_once(
+        &self,
+        body: &str,
+        endpoint: &str,
+    ) -> Result<serde_json::Value, RpcError> {
         let mut req = Request::builder()
             .method(Method::POST)
             .uri(self.url.clone().to_string() + endpoint)
@@ -140,6 +166,10 @@ impl RpcClient {
             .await;
         }
 
+        if !response.status().is_success() {
+            return Err(RpcError::Status(response.status()));
+        }
+
         let (_parts, body) = response.into_parts();
 
         Ok(serde_json::from_slice(
@@ -150,6 +180,51 @@ impl RpcClient {
                 .to_bytes(),
         )?)
     }
+
+    /// Fetch up to [`MAX_REQUESTED_TRANSACTIONS`] transactions in a single
+    /// request.
+    async fn transactions_chunk(
+        &self,
+        hashes: &[monero::Hash],
+    ) -> Result<Vec<monero::Transaction>, RpcError> {
+        // Build a json containing the hashes of the transactions we want.
+        trace!("Requesting {} transactions", hashes.len());
+        let request_body = r#"{"txs_hashes":"#.to_owned()
+            + &json!(hashes
+                .iter()
+                .map(|x| hex::encode(x.as_bytes())) // Convert from monero::Hash to hex.
+                .collect::<Vec<String>>())
+            .to_string()
+            + "}";
+        let request_endpoint = "get_transactions";
+
+        let res = self.request(&request_body, request_endpoint).await?;
+
+        let hexes = res["txs_as_hex"]
+            .as_array()
+            .ok_or_else(|| RpcError::MissingData("{{ txs_as_hex: [...] }}".to_string()))?;
+        if hashes.len() == hexes.len() {
+            trace!("Received {} transactions", hexes.len());
+        } else {
+            warn!(
+                "Received {} transactions, requested {}",
+                hexes.len(),
+                hashes.len()
+            );
+        }
+
+        let mut transactions = Vec::with_capacity(hexes.len());
+        for tx_json in hexes {
+            let tx_str = tx_json.as_str().ok_or(RpcError::DataType {
+                found: tx_json.clone(),
+                expected: "&str",
+            })?;
+            let tx_hex = hex::decode(tx_str)?;
+            let tx: monero::Transaction = deserialize(&tx_hex)?;
+            transactions.push(tx);
+        }
+        Ok(transactions)
+    }
 }
 
 impl Client for RpcClient {
@@ -242,58 +317,18 @@ impl Client for RpcClient {
         &self,
         hashes: &[monero::Hash],
     ) -> Result<Vec<monero::Transaction>, RpcError> {
-        let mut transactions = Vec::new();
-        for i in 0..=hashes.len() / MAX_REQUESTED_TRANSACTIONS {
-            // We've gotta grab these in parts to avoid putting too much load on the RPC
-            // server, so these are the start and end indexes of the hashes
-            // we're grabbing for now.
-            //
-            // TODO: Get them concurrently.
-            let starting_index: usize = i * MAX_REQUESTED_TRANSACTIONS;
-            let ending_index: usize =
-                std::cmp::min(MAX_REQUESTED_TRANSACTIONS * (i + 1), hashes.len());
-
-            // If requesting an empty list, return what we have now.
-            if ending_index == starting_index {
-                return Ok(transactions);
-            }
-
-            // Build a json containing the hashes of the transactions we want.
-            trace!("Requesting {} transactions", ending_index - starting_index);
-            let request_body = r#"{"txs_hashes":"#.to_owned()
-                + &json!(hashes[starting_index..ending_index]
-                    .iter()
-                    .map(|x| hex::encode(x.as_bytes())) // Convert from monero::Hash to hex.
-                    .collect::<Vec<String>>())
-                .to_string()
-                + "}";
-            let request_endpoint = "get_transactions";
-
-            let res = self.request(&request_body, request_endpoint).await?;
-
-            let hexes = res["txs_as_hex"]
-                .as_array()
-                .ok_or_else(|| RpcError::MissingData("{{ txs_as_hex: [...] }}".to_string()))?;
-            if ending_index - starting_index == hexes.len() {
-                trace!("Received {} transactions", hexes.len());
-            } else {
-                warn!(
-                    "Received {} transactions, requested {}",
-                    hexes.len(),
-                    ending_index - starting_index
-                );
-            }
-
-            // Add these transactions to the total list.
-            for tx_json in hexes {
-                let tx_str = tx_json.as_str().ok_or(RpcError::DataType {
-                    found: tx_json.clone(),
-                    expected: "&str",
-                })?;
-                let tx_hex = hex::decode(tx_str)?;
-                let tx: monero::Transaction = deserialize(&tx_hex)?;
-                transactions.push(tx);
-            }
+        // Request the transactions in chunks to avoid exceeding the daemon's limit
+        // on transactions per request, and send the chunks concurrently.
+        let chunks = join_all(
+            hashes
+                .chunks(MAX_REQUESTED_TRANSACTIONS)
+                .map(|chunk| self.transactions_chunk(chunk)),
+        )
+        .await;
+
+        let mut transactions = Vec::with_capacity(hashes.len());
+        for chunk in chunks {
+            transactions.extend(chunk?);
         }
         Ok(transactions)
     }
@@ -311,9 +346,41 @@ impl Client for RpcClient {
         Ok(count)
     }
 
+    async fn daemon_info(&self) -> Result<DaemonInfo, RpcError> {
+        trace!("Requesting daemon info");
+        let request_body = r#"{"jsonrpc":"2.0","id":"0","method":"get_info"}"#;
+        let request_endpoint = "json_rpc";
+
+        let res = self.request(request_body, request_endpoint).await?;
+
+        DaemonInfo::from_json(&res["result"])
+    }
+
+    async fn rpc_version(&self) -> Result<RpcVersion, RpcError> {
+        trace!("Requesting daemon RPC version");
+        let request_body = r#"{"jsonrpc":"2.0","id":"0","method":"get_version"}"#;
+        let request_endpoint = "json_rpc";
+
+        let res = self.request(request_body, request_endpoint).await?;
+
+        let version = res["result"]["version"].as_u64().ok_or_else(|| {
+            RpcError::MissingData("{{ result: {{ version: \"...\" }} }}".to_string())
+        })?;
+        let version = u32::try_from(version).map_err(|_| RpcError::DataType {
+            found: res["result"]["version"].clone(),
+            expected: any::type_name::<u32>(),
+        })?;
+
+        Ok(RpcVersion::from(version))
+    }
+
     fn url(&self) -> String {
         self.url.clone().to_string()
     }
+
+    fn is_degraded(&self) -> bool {
+        self.policy.is_degraded()
+    }
 }
 
 /// A mocker monerod client. Returns canned responses for testing purposes.
@@ -379,6 +446,21 @@ impl Client for MockClient {
         Ok(2_477_657)
     }
 
+    async fn daemon_info(&self) -> Result<DaemonInfo, RpcError> {
+        Ok(DaemonInfo {
+            network: monero::Network::Mainnet,
+            restricted: false,
+            height: 2_477_657,
+        })
+    }
+
+    async fn rpc_version(&self) -> Result<RpcVersion, RpcError> {
+        Ok(RpcVersion {
+            major: SUPPORTED_RPC_MAJOR_VERSION,
+            minor: 13,
+        })
+    }
+
     fn url(&self) -> String {
         "http://node.example.com".to_string()
     }
@@ -408,8 +490,109 @@ pub trait Client: Clone + Send + Sync {
     ) -> impl Future<Output = Result<Vec<monero::Transaction>, RpcError>> + Send;
     /// Fetch the blockchain height from monerod.
     fn daemon_height(&self) -> impl Future<Output = Result<u64, RpcError>> + Send;
+    /// Fetch general information about the daemon, such as the network it is
+    /// running on.
+    fn daemon_info(&self) -> impl Future<Output = Result<DaemonInfo, RpcError>> + Send;
+    /// Fetch the version of the daemon's RPC interface.
+    fn rpc_version(&self) -> impl Future<Output = Result<RpcVersion, RpcError>> + Send;
     /// The URL of the monero daemon.
     fn url(&self) -> String;
+    /// Whether calls to the daemon are currently suspended because it has
+    /// been failing repeatedly. Defaults to `false`.
+    fn is_degraded(&self) -> bool {
+        false
+    }
+}
+
+/// General information about a monero daemon, as reported by its `get_info`
+/// RPC method.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DaemonInfo {
+    /// The network the daemon is running on.
+    pub network: monero::Network,
+    /// Whether the daemon's RPC interface is restricted.
+    pub restricted: bool,
+    /// The daemon's current blockchain height.
+    pub height: u64,
+}
+
+impl DaemonInfo {
+    fn from_json(result: &serde_json::Value) -> Result<DaemonInfo, RpcError> {
+        let network = match result["nettype"].as_str() {
+            // Regtest daemons report "fakechain", but use mainnet addresses.
+            Some("mainnet" | "fakechain") => monero::Network::Mainnet,
+            Some("stagenet") => monero::Network::Stagenet,
+            Some("testnet") => monero::Network::Testnet,
+            Some(_) => {
+                return Err(RpcError::DataType {
+                    found: result["nettype"].clone(),
+                    expected: any::type_name::<monero::Network>(),
+                })
+            }
+            // Older daemons do not report a nettype, so fall back to the network flags.
+            None if result["stagenet"].as_bool() == Some(true) => monero::Network::Stagenet,
+            None if result["testnet"].as_bool() == Some(true) => monero::Network::Testnet,
+            None if result["mainnet"].as_bool() == Some(true) => monero::Network::Mainnet,
+            None => {
+                return Err(RpcError::MissingData(
+                    "{{ result: {{ nettype: \"...\" }} }}".to_string(),
+                ))
+            }
+        };
+        let restricted = result["restricted"].as_bool().unwrap_or(false);
+        let height = result["height"].as_u64().ok_or_else(|| {
+            RpcError::MissingData("{{ result: {{ height: \"...\" }} }}".to_string())
+        })?;
+
+        Ok(DaemonInfo {
+            network,
+            restricted,
+            height,
+        })
+    }
+}
+
+/// The version of a monero daemon's RPC interface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RpcVersion {
+    /// Major version. Changes to the major version are not backwards
+    /// compatible.
+    pub major: u16,
+    /// Minor version.
+    pub minor: u16,
+}
+
+impl RpcVersion {
+    /// Returns `true` if this version of the RPC interface is supported by
+    /// `AcceptXMR`.
+    #[must_use]
+    pub fn is_supported(&self) -> bool {
+        self.major == SUPPORTED_RPC_MAJOR_VERSION
+    }
+
+    /// The major version of the RPC interface supported by `AcceptXMR`.
+    #[must_use]
+    pub fn supported_major() -> u16 {
+        SUPPORTED_RPC_MAJOR_VERSION
+    }
+}
+
+impl From<u32> for RpcVersion {
+    /// Monerod reports its RPC version as a single integer, with the major
+    /// version in the upper 16 bits and the minor version in the lower 16.
+    fn from(version: u32) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        RpcVersion {
+            major: (version >> 16) as u16,
+            minor: (version & 0xffff) as u16,
+        }
+    }
+}
+
+impl fmt::Display for RpcVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
 }
 
 /// An error originating from the monerod client.
@@ -424,6 +607,9 @@ pub enum RpcError {
     /// HTTP request timed out.
     #[error("HTTP request timed out: {0}")]
     Timeout(#[from] error::Elapsed),
+    /// The daemon responded with an unsuccessful HTTP status code.
+    #[error("daemon responded with HTTP status {0}")]
+    Status(StatusCode),
     /// Failed to decode a hex value.
     #[error("hex decoding failed: {0}")]
     HexDecode(#[from] hex::FromHexError),
@@ -444,7 +630,85 @@ pub enum RpcError {
     /// The response is not valid json.
     #[error("failed to interpret response body as json: {0}")]
     InvalidJson(#[from] serde_json::Error),
+    /// Failed to read or write a fixture file.
+    #[error("failed to read or write fixture: {0}")]
+    Fixture(#[from] std::io::Error),
     /// Failed to authenticate.
     #[error("authentication error: {0}")]
     Auth(#[from] AuthError),
+    /// The RPC server responded with a JSON-RPC error.
+    #[error("RPC server returned error {code}: {message}")]
+    JsonRpc {
+        /// JSON-RPC error code.
+        code: i64,
+        /// Error message.
+        message: String,
+    },
+    /// Calls to the daemon are suspended after repeated failures. Contains
+    /// the time remaining until calls are attempted again.
+    #[error("daemon calls suspended after repeated failures, retrying in {0:?}")]
+    CircuitOpen(Duration),
+}
+
+impl RpcError {
+    /// Whether the error is likely to be resolved by retrying the call (e.g.
+    /// connection failures, timeouts, and rate limiting or server errors).
+    pub(crate) fn is_transient(&self) -> bool {
+        match self {
+            RpcError::Request(_) | RpcError::Timeout(_) => true,
+            RpcError::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::{DaemonInfo, RpcVersion};
+
+    #[test]
+    fn rpc_version_from_u32() {
+        let version = RpcVersion::from(196_621);
+        assert_eq!(
+            version,
+            RpcVersion {
+                major: 3,
+                minor: 13
+            }
+        );
+        assert_eq!(version.to_string(), "3.13");
+        assert!(version.is_supported());
+        assert!(!RpcVersion::from(2 << 16).is_supported());
+    }
+
+    #[test]
+    fn daemon_info_network() {
+        let info = DaemonInfo::from_json(&json!({
+            "height": 10,
+            "nettype": "stagenet",
+            "restricted": true,
+        }))
+        .unwrap();
+        assert_eq!(info.network, monero::Network::Stagenet);
+        assert!(info.restricted);
+        assert_eq!(info.height, 10);
+
+        // Older daemons only report network flags.
+        let info = DaemonInfo::from_json(&json!({
+            "height": 10,
+            "mainnet": false,
+            "stagenet": false,
+            "testnet": true,
+        }))
+        .unwrap();
+        assert_eq!(info.network, monero::Network::Testnet);
+        assert!(!info.restricted);
+
+        assert!(DaemonInfo::from_json(&json!({ "height": 10, "nettype": "moonnet" })).is_err());
+    }
 }
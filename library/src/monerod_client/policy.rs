@@ -0,0 +1,340 @@
+//! Retry, rate limiting and circuit breaking for monerod RPC calls.
+
+use std::{
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use backoff::{backoff::Backoff, ExponentialBackoff, ExponentialBackoffBuilder};
+use log::{debug, warn};
+use tokio::time::{sleep_until, Instant};
+
+use super::RpcError;
+
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Policy controlling how the monerod RPC client retries failed calls, limits
+/// its request rate, and backs off from a daemon that keeps failing.
+///
+/// Only transient failures (connection errors, timeouts, and `429` or `5xx`
+/// responses) are retried and counted by the circuit breaker. Once
+/// [`failure_threshold`](Self::failure_threshold) consecutive calls have
+/// failed, the circuit opens: calls fail immediately with
+/// [`RpcError::CircuitOpen`] for the [`cooldown`](Self::cooldown) period, and
+/// the payment gateway reports itself as
+/// [`Degraded`](crate::PaymentGatewayStatus::Degraded) until a call succeeds
+/// again.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use acceptxmr::RpcPolicy;
+///
+/// // Retry up to 5 times, and send at most 10 requests per second.
+/// let policy = RpcPolicy::default()
+///     .max_retries(5)
+///     .max_requests_per_second(10)
+///     .cooldown(Duration::from_secs(60));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RpcPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_requests_per_second: Option<u32>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl RpcPolicy {
+    /// Maximum number of times a failed call is retried. Defaults to 2.
+    #[must_use]
+    pub fn max_retries(mut self, retries: u32) -> RpcPolicy {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Delay before the first retry. The delay doubles with each subsequent
+    /// retry, up to the [maximum backoff](Self::max_backoff). Defaults to 250
+    /// milliseconds.
+    #[must_use]
+    pub fn initial_backoff(mut self, backoff: Duration) -> RpcPolicy {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Maximum delay between retries. Defaults to 5 seconds.
+    #[must_use]
+    pub fn max_backoff(mut self, backoff: Duration) -> RpcPolicy {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Maximum number of requests sent to the daemon per second, including
+    /// retries. Public nodes commonly rate limit their clients. Defaults to no
+    /// limit.
+    #[must_use]
+    pub fn max_requests_per_second(mut self, requests: u32) -> RpcPolicy {
+        self.max_requests_per_second = Some(requests).filter(|r| *r > 0);
+        self
+    }
+
+    /// Number of consecutive failed calls after which the circuit breaker
+    /// opens. Defaults to 5.
+    #[must_use]
+    pub fn failure_threshold(mut self, failures: u32) -> RpcPolicy {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// Time the circuit breaker stays open before calls to the daemon are
+    /// attempted again. Defaults to 30 seconds.
+    #[must_use]
+    pub fn cooldown(mut self, cooldown: Duration) -> RpcPolicy {
+        self.cooldown = cooldown;
+        self
+    }
+
+    fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoffBuilder::default()
+            .with_initial_interval(self.initial_backoff)
+            .with_max_interval(self.max_backoff)
+            .with_multiplier(2.0)
+            .with_max_elapsed_time(None)
+            .build()
+    }
+}
+
+impl Default for RpcPolicy {
+    fn default() -> Self {
+        RpcPolicy {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_requests_per_second: None,
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+}
+
+/// Runtime state of an [`RpcPolicy`], shared by all clones of a client.
+#[derive(Debug)]
+pub(crate) struct PolicyState {
+    policy: RpcPolicy,
+    next_request: Mutex<Instant>,
+    breaker: Mutex<Breaker>,
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl PolicyState {
+    pub(crate) fn new(policy: RpcPolicy) -> PolicyState {
+        PolicyState {
+            policy,
+            next_request: Mutex::new(Instant::now()),
+            breaker: Mutex::new(Breaker::default()),
+        }
+    }
+
+    /// Call `f` according to the policy, retrying transient failures.
+    pub(crate) async fn call<T, F, Fut>(&self, mut f: F) -> Result<T, RpcError>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, RpcError>>,
+    {
+        self.check_breaker()?;
+
+        let mut backoff = self.policy.backoff();
+        let mut retries = 0;
+        loop {
+            self.wait_for_rate_limit().await;
+            match f().await {
+                Ok(value) => {
+                    self.record_success();
+                    return Ok(value);
+                }
+                Err(e) if e.is_transient() && retries < self.policy.max_retries => {
+                    retries += 1;
+                    #[allow(clippy::expect_used)]
+                    let delay = backoff
+                        .next_backoff()
+                        .expect("RPC retry backoff has no maximum elapsed time. This is a bug.");
+                    debug!("RPC call failed, retrying in {delay:?} (attempt {retries}): {e}");
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    if e.is_transient() {
+                        self.record_failure();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Whether the circuit breaker has tripped and no call has succeeded
+    /// since.
+    pub(crate) fn is_degraded(&self) -> bool {
+        self.breaker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .consecutive_failures
+            >= self.policy.failure_threshold
+    }
+
+    fn check_breaker(&self) -> Result<(), RpcError> {
+        let breaker = self.breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        match breaker.open_until {
+            Some(open_until) if Instant::now() < open_until => Err(RpcError::CircuitOpen(
+                open_until.saturating_duration_since(Instant::now()),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    async fn wait_for_rate_limit(&self) {
+        let Some(requests_per_second) = self.policy.max_requests_per_second else {
+            return;
+        };
+        let interval = Duration::from_secs(1) / requests_per_second;
+        let slot = {
+            let mut next_request = self
+                .next_request
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let slot = (*next_request).max(Instant::now());
+            *next_request = slot + interval;
+            slot
+        };
+        sleep_until(slot).await;
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        if breaker.consecutive_failures >= self.policy.failure_threshold {
+            debug!("RPC call succeeded, closing circuit breaker");
+        }
+        *breaker = Breaker::default();
+    }
+
+    fn record_failure(&self) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        breaker.consecutive_failures = breaker.consecutive_failures.saturating_add(1);
+        if breaker.consecutive_failures >= self.policy.failure_threshold {
+            warn!(
+                "{} consecutive RPC calls failed. Suspending calls to the daemon for {:?}",
+                breaker.consecutive_failures, self.policy.cooldown
+            );
+            breaker.open_until = Some(Instant::now() + self.policy.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use hyper::StatusCode;
+    use tokio::time::Instant;
+
+    use super::{PolicyState, RpcPolicy};
+    use crate::monerod_client::RpcError;
+
+    fn policy() -> RpcPolicy {
+        RpcPolicy::default()
+            .initial_backoff(Duration::from_millis(1))
+            .max_backoff(Duration::from_millis(1))
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let state = PolicyState::new(policy().max_retries(2));
+        let calls = AtomicU32::new(0);
+
+        let result = state
+            .call(|| async {
+                if calls.fetch_add(1, Ordering::Relaxed) < 2 {
+                    Err(RpcError::Status(StatusCode::SERVICE_UNAVAILABLE))
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_permanent_errors() {
+        let state = PolicyState::new(policy().max_retries(2));
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), _> = state
+            .call(|| async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(RpcError::Status(StatusCode::NOT_FOUND))
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(RpcError::Status(StatusCode::NOT_FOUND))
+        ));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert!(!state.is_degraded());
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker() {
+        let state = PolicyState::new(
+            policy()
+                .max_retries(0)
+                .failure_threshold(2)
+                .cooldown(Duration::from_millis(50)),
+        );
+        let fail = || async { Err::<(), _>(RpcError::Status(StatusCode::BAD_GATEWAY)) };
+
+        // Trip the breaker.
+        for _ in 0..2 {
+            assert!(matches!(state.call(fail).await, Err(RpcError::Status(_))));
+        }
+        assert!(state.is_degraded());
+        assert!(matches!(
+            state.call(|| async { Ok(()) }).await,
+            Err(RpcError::CircuitOpen(_))
+        ));
+
+        // After the cooldown, a successful call closes it again.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        state.call(|| async { Ok(()) }).await.unwrap();
+        assert!(!state.is_degraded());
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let state = PolicyState::new(policy().max_requests_per_second(100));
+
+        let start = Instant::now();
+        for _ in 0..5 {
+            state.call(|| async { Ok(()) }).await.unwrap();
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
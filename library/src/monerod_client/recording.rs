@@ -0,0 +1,432 @@
+//! Record-and-replay monerod clients.
+//!
+//! A [`RecordingClient`] wraps another client and writes every response it
+//! receives to a fixture directory. A [`ReplayClient`] then serves those
+//! responses back without a daemon. The fixture directory has the following
+//! layout:
+//!
+//! ```text
+//! fixtures/
+//! ├── blocks/{height}.json       { "hash": "...", "blob": "..." }
+//! ├── transactions/{hash}.json   { "blob": "..." }
+//! ├── daemon_height.json         [ height, ... ]
+//! ├── txpool.json                [ [ tx_hash, ... ], ... ]
+//! ├── txpool_hashes.json         [ [ tx_hash, ... ], ... ]
+//! ├── daemon_info.json           { "nettype": "...", "restricted": ..., "height": ... }
+//! └── rpc_version.json           { "version": ... }
+//! ```
+//!
+//! Responses that change over time (the daemon height and the txpool) are
+//! recorded as sequences, one entry per call. During replay they are served in
+//! the order they were recorded, and the last entry is repeated once the
+//! sequence is exhausted.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use log::{trace, warn};
+use monero::{
+    consensus::{deserialize, serialize},
+    cryptonote::hash::Hashable,
+};
+use serde_json::{json, Value};
+
+use super::{Client, DaemonInfo, RpcError, RpcVersion};
+
+const BLOCKS_DIR: &str = "blocks";
+const TRANSACTIONS_DIR: &str = "transactions";
+const DAEMON_HEIGHT_FILE: &str = "daemon_height.json";
+const TXPOOL_FILE: &str = "txpool.json";
+const TXPOOL_HASHES_FILE: &str = "txpool_hashes.json";
+const DAEMON_INFO_FILE: &str = "daemon_info.json";
+const RPC_VERSION_FILE: &str = "rpc_version.json";
+
+/// A monerod client that records every response received from an inner client
+/// to a fixture directory, so that it can later be served by a
+/// [`ReplayClient`].
+#[derive(Debug, Clone)]
+pub struct RecordingClient<M> {
+    inner: M,
+    dir: PathBuf,
+    sequences: Arc<Mutex<Sequences>>,
+}
+
+impl<M: Client> RecordingClient<M> {
+    /// Create a recording client wrapping `inner`, writing fixtures to `dir`.
+    /// The directory is created if it does not already exist. Existing
+    /// fixtures in the directory will be overwritten.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fixture directory cannot be created.
+    pub fn new(inner: M, dir: impl AsRef<Path>) -> Result<RecordingClient<M>, RpcError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join(BLOCKS_DIR))?;
+        fs::create_dir_all(dir.join(TRANSACTIONS_DIR))?;
+        Ok(RecordingClient {
+            inner,
+            dir,
+            sequences: Arc::new(Mutex::new(Sequences::default())),
+        })
+    }
+
+    /// The inner client whose responses are being recorded.
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    fn record_transactions(&self, transactions: &[monero::Transaction]) -> Result<(), RpcError> {
+        for tx in transactions {
+            write_json(
+                &self.dir.join(TRANSACTIONS_DIR).join(hash_file(&tx.hash())),
+                &json!({ "blob": hex::encode(serialize(tx)) }),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Append `entry` to the sequence selected by `select`, and rewrite the
+    /// sequence's fixture file.
+    fn record_sequence(
+        &self,
+        file: &str,
+        select: fn(&mut Sequences) -> &mut Vec<Value>,
+        entry: Value,
+    ) -> Result<(), RpcError> {
+        let mut sequences = self
+            .sequences
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let sequence = select(&mut sequences);
+        sequence.push(entry);
+        write_json(&self.dir.join(file), &Value::Array(sequence.clone()))
+    }
+}
+
+impl<M: Client> Client for RecordingClient<M> {
+    async fn block(&self, height: u64) -> Result<(monero::Hash, monero::Block), RpcError> {
+        let (hash, block) = self.inner.block(height).await?;
+        trace!("Recording block {}", height);
+        write_json(
+            &self.dir.join(BLOCKS_DIR).join(format!("{height}.json")),
+            &json!({
+                "hash": hex::encode(hash.as_bytes()),
+                "blob": hex::encode(serialize(&block)),
+            }),
+        )?;
+        Ok((hash, block))
+    }
+
+    async fn block_transactions(
+        &self,
+        block: &monero::Block,
+    ) -> Result<Vec<monero::Transaction>, RpcError> {
+        let transactions = self.inner.block_transactions(block).await?;
+        self.record_transactions(&transactions)?;
+        Ok(transactions)
+    }
+
+    async fn txpool(&self) -> Result<Vec<monero::Transaction>, RpcError> {
+        let transactions = self.inner.txpool().await?;
+        self.record_transactions(&transactions)?;
+        let hashes = transactions
+            .iter()
+            .map(|tx| Value::String(hex::encode(tx.hash().as_bytes())))
+            .collect();
+        self.record_sequence(TXPOOL_FILE, |s| &mut s.txpool, Value::Array(hashes))?;
+        Ok(transactions)
+    }
+
+    async fn txpool_hashes(&self) -> Result<HashSet<monero::Hash>, RpcError> {
+        let hashes = self.inner.txpool_hashes().await?;
+        let entry = hashes
+            .iter()
+            .map(|hash| Value::String(hex::encode(hash.as_bytes())))
+            .collect();
+        self.record_sequence(
+            TXPOOL_HASHES_FILE,
+            |s| &mut s.txpool_hashes,
+            Value::Array(entry),
+        )?;
+        Ok(hashes)
+    }
+
+    async fn transactions_by_hashes(
+        &self,
+        hashes: &[monero::Hash],
+    ) -> Result<Vec<monero::Transaction>, RpcError> {
+        let transactions = self.inner.transactions_by_hashes(hashes).await?;
+        self.record_transactions(&transactions)?;
+        Ok(transactions)
+    }
+
+    async fn daemon_height(&self) -> Result<u64, RpcError> {
+        let height = self.inner.daemon_height().await?;
+        self.record_sequence(DAEMON_HEIGHT_FILE, |s| &mut s.daemon_height, json!(height))?;
+        Ok(height)
+    }
+
+    async fn daemon_info(&self) -> Result<DaemonInfo, RpcError> {
+        let info = self.inner.daemon_info().await?;
+        let nettype = match info.network {
+            monero::Network::Mainnet => "mainnet",
+            monero::Network::Stagenet => "stagenet",
+            monero::Network::Testnet => "testnet",
+        };
+        write_json(
+            &self.dir.join(DAEMON_INFO_FILE),
+            &json!({
+                "nettype": nettype,
+                "restricted": info.restricted,
+                "height": info.height,
+            }),
+        )?;
+        Ok(info)
+    }
+
+    async fn rpc_version(&self) -> Result<RpcVersion, RpcError> {
+        let version = self.inner.rpc_version().await?;
+        write_json(
+            &self.dir.join(RPC_VERSION_FILE),
+            &json!({ "version": (u32::from(version.major) << 16) | u32::from(version.minor) }),
+        )?;
+        Ok(version)
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+
+    fn is_degraded(&self) -> bool {
+        self.inner.is_degraded()
+    }
+}
+
+/// A monerod client that serves responses previously written by a
+/// [`RecordingClient`], without contacting a daemon.
+#[derive(Debug, Clone)]
+pub struct ReplayClient {
+    dir: PathBuf,
+    sequences: Arc<Sequences>,
+    cursors: Arc<Mutex<Cursors>>,
+}
+
+impl ReplayClient {
+    /// Create a replay client serving the fixtures in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a recorded sequence exists but cannot be read.
+    pub fn new(dir: impl AsRef<Path>) -> Result<ReplayClient, RpcError> {
+        let dir = dir.as_ref().to_path_buf();
+        let sequences = Sequences {
+            daemon_height: read_sequence(&dir.join(DAEMON_HEIGHT_FILE))?,
+            txpool: read_sequence(&dir.join(TXPOOL_FILE))?,
+            txpool_hashes: read_sequence(&dir.join(TXPOOL_HASHES_FILE))?,
+        };
+        Ok(ReplayClient {
+            dir,
+            sequences: Arc::new(sequences),
+            cursors: Arc::new(Mutex::new(Cursors::default())),
+        })
+    }
+
+    fn transaction(&self, hash: &monero::Hash) -> Result<monero::Transaction, RpcError> {
+        let fixture = read_json(&self.dir.join(TRANSACTIONS_DIR).join(hash_file(hash)))?;
+        let tx_str = fixture["blob"]
+            .as_str()
+            .ok_or_else(|| RpcError::MissingData("{{ blob: \"...\" }}".to_string()))?;
+        Ok(deserialize(&hex::decode(tx_str)?)?)
+    }
+
+    /// Return the next entry of the sequence selected by `select`, repeating
+    /// the last entry once the sequence is exhausted.
+    fn next_in_sequence(
+        &self,
+        name: &str,
+        select: fn(&Sequences) -> &Vec<Value>,
+        cursor: fn(&mut Cursors) -> &mut usize,
+    ) -> Result<&Value, RpcError> {
+        let sequence = select(&self.sequences);
+        let mut cursors = self.cursors.lock().unwrap_or_else(PoisonError::into_inner);
+        let cursor = cursor(&mut cursors);
+        let entry = sequence
+            .get(*cursor)
+            .or_else(|| sequence.last())
+            .ok_or_else(|| RpcError::MissingData(format!("no recorded {name} responses")))?;
+        *cursor = (*cursor + 1).min(sequence.len());
+        Ok(entry)
+    }
+}
+
+impl Client for ReplayClient {
+    async fn block(&self, height: u64) -> Result<(monero::Hash, monero::Block), RpcError> {
+        let fixture = read_json(&self.dir.join(BLOCKS_DIR).join(format!("{height}.json")))?;
+        let hash_str = fixture["hash"]
+            .as_str()
+            .ok_or_else(|| RpcError::MissingData("{{ hash: \"...\" }}".to_string()))?;
+        let hash = parse_hash(hash_str)?;
+        let block_str = fixture["blob"]
+            .as_str()
+            .ok_or_else(|| RpcError::MissingData("{{ blob: \"...\" }}".to_string()))?;
+        let block = deserialize(&hex::decode(block_str)?)?;
+        Ok((hash, block))
+    }
+
+    async fn block_transactions(
+        &self,
+        block: &monero::Block,
+    ) -> Result<Vec<monero::Transaction>, RpcError> {
+        self.transactions_by_hashes(&block.tx_hashes).await
+    }
+
+    async fn txpool(&self) -> Result<Vec<monero::Transaction>, RpcError> {
+        let hashes =
+            parse_hashes(self.next_in_sequence("txpool", |s| &s.txpool, |c| &mut c.txpool)?)?;
+        hashes.iter().map(|hash| self.transaction(hash)).collect()
+    }
+
+    async fn txpool_hashes(&self) -> Result<HashSet<monero::Hash>, RpcError> {
+        Ok(parse_hashes(self.next_in_sequence(
+            "txpool hashes",
+            |s| &s.txpool_hashes,
+            |c| &mut c.txpool_hashes,
+        )?)?
+        .into_iter()
+        .collect())
+    }
+
+    async fn transactions_by_hashes(
+        &self,
+        hashes: &[monero::Hash],
+    ) -> Result<Vec<monero::Transaction>, RpcError> {
+        // Like the daemon, omit transactions which are unknown rather than failing.
+        let mut transactions = Vec::with_capacity(hashes.len());
+        for hash in hashes {
+            if self
+                .dir
+                .join(TRANSACTIONS_DIR)
+                .join(hash_file(hash))
+                .exists()
+            {
+                transactions.push(self.transaction(hash)?);
+            }
+        }
+        if transactions.len() != hashes.len() {
+            warn!(
+                "Replayed {} transactions, requested {}",
+                transactions.len(),
+                hashes.len()
+            );
+        }
+        Ok(transactions)
+    }
+
+    async fn daemon_height(&self) -> Result<u64, RpcError> {
+        let height = self.next_in_sequence(
+            "daemon height",
+            |s| &s.daemon_height,
+            |c| &mut c.daemon_height,
+        )?;
+        height.as_u64().ok_or_else(|| RpcError::DataType {
+            found: height.clone(),
+            expected: "u64",
+        })
+    }
+
+    async fn daemon_info(&self) -> Result<DaemonInfo, RpcError> {
+        DaemonInfo::from_json(&read_json(&self.dir.join(DAEMON_INFO_FILE))?)
+    }
+
+    async fn rpc_version(&self) -> Result<RpcVersion, RpcError> {
+        let fixture = read_json(&self.dir.join(RPC_VERSION_FILE))?;
+        let version = fixture["version"]
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| RpcError::DataType {
+                found: fixture["version"].clone(),
+                expected: "u32",
+            })?;
+        Ok(RpcVersion::from(version))
+    }
+
+    fn url(&self) -> String {
+        format!("file://{}", self.dir.display())
+    }
+}
+
+/// Recorded responses which change from call to call.
+#[derive(Debug, Default)]
+struct Sequences {
+    daemon_height: Vec<Value>,
+    txpool: Vec<Value>,
+    txpool_hashes: Vec<Value>,
+}
+
+/// Position of the next response to replay in each sequence.
+#[derive(Debug, Default)]
+struct Cursors {
+    daemon_height: usize,
+    txpool: usize,
+    txpool_hashes: usize,
+}
+
+fn hash_file(hash: &monero::Hash) -> String {
+    format!("{}.json", hex::encode(hash.as_bytes()))
+}
+
+fn parse_hashes(value: &Value) -> Result<Vec<monero::Hash>, RpcError> {
+    value
+        .as_array()
+        .ok_or_else(|| RpcError::DataType {
+            found: value.clone(),
+            expected: "array",
+        })?
+        .iter()
+        .map(|hash| {
+            let hash_str = hash.as_str().ok_or_else(|| RpcError::DataType {
+                found: hash.clone(),
+                expected: "&str",
+            })?;
+            parse_hash(hash_str)
+        })
+        .collect()
+}
+
+fn parse_hash(hash_str: &str) -> Result<monero::Hash, RpcError> {
+    let bytes = <[u8; 32]>::try_from(hex::decode(hash_str)?.as_slice()).map_err(|_| {
+        RpcError::DataType {
+            found: Value::String(hash_str.to_string()),
+            expected: "32 byte hash",
+        }
+    })?;
+    Ok(monero::Hash(bytes))
+}
+
+fn read_sequence(path: &Path) -> Result<Vec<Value>, RpcError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    match read_json(path)? {
+        Value::Array(sequence) => Ok(sequence),
+        other => Err(RpcError::DataType {
+            found: other,
+            expected: "array",
+        }),
+    }
+}
+
+fn read_json(path: &Path) -> Result<Value, RpcError> {
+    let file = fs::File::open(path)
+        .map_err(|e| RpcError::MissingData(format!("{}: {e}", path.display())))?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+fn write_json(path: &Path, value: &Value) -> Result<(), RpcError> {
+    fs::write(path, serde_json::to_vec_pretty(value)?)?;
+    Ok(())
+}
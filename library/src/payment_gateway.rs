@@ -1,6 +1,7 @@
 use std::{
     fmt::Debug,
     ops::Deref,
+    path::Path,
     str::FromStr,
     sync::{
         atomic::{self, AtomicU32, AtomicU64},
@@ -11,19 +12,28 @@ use std::{
 };
 
 use hyper::Uri;
+use indexmap::IndexMap;
 use log::{debug, error, info, trace, warn};
 use monero::cryptonote::onetime_key::SubKeyChecker;
 use tokio::{join, sync::Mutex as AsyncMutex, time};
 
 use crate::{
     caching::SubaddressCache,
+    light_wallet::{LightWalletClient, LightWalletScanner},
     monerod_client::{
-        Client as MonerodClient, MockClient as MonerodMockClient, RpcClient as MonerodRpcClient,
+        Client as MonerodClient, MockClient as MonerodMockClient,
+        RecordingClient as MonerodRecordingClient, ReplayClient as MonerodReplayClient,
+        RpcClient as MonerodRpcClient, RpcPolicy, RpcVersion,
     },
-    pubsub::{Publisher, Subscriber},
-    scanner::{Scanner, ScannerHandle},
-    storage::{Client as StorageClient, Storage},
-    AcceptXmrError, Invoice, InvoiceId,
+    pubsub::{
+        InvoiceEvent, Publisher, SequencedEvent, Subscriber, SubscriptionFilter, EVENT_PAGE_LEN,
+    },
+    scanner::{Scanner, ScannerError, ScannerHandle},
+    storage::{AsyncStorage, Client, InvoicePage, InvoiceQuery, OutputKeyStats, Storage},
+    wallet_rpc::{WalletRpcClient, WalletRpcScanner},
+    wallets::Wallets,
+    AcceptXmrError, Amount, Invoice, InvoiceId, RandomAllocator, SubIndex, SubaddressAllocator,
+    SubaddressReport,
 };
 
 const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_millis(1000);
@@ -33,6 +43,7 @@ const DEFAULT_RPC_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
 /// Timeout for total call completion.
 const DEFAULT_RPC_TOTAL_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_BLOCK_CACHE_SIZE: usize = 10;
+const DEFAULT_BLOCK_FETCH_CONCURRENCY: usize = 10;
 
 /// The `PaymentGateway` allows you to track new [`Invoice`](Invoice)s, remove
 /// old `Invoice`s from tracking, and subscribe to `Invoice`s that are already
@@ -44,13 +55,18 @@ pub struct PaymentGateway<S: Storage, M: MonerodClient = MonerodRpcClient>(
 #[doc(hidden)]
 pub struct PaymentGatewayInner<S: Storage, M: MonerodClient = MonerodRpcClient> {
     monerod_client: M,
-    viewpair: monero::ViewPair,
+    backend: Backend,
+    wallets: Arc<Wallets>,
     scan_interval: Duration,
-    store: StorageClient<S>,
+    store: Client<S>,
     subaddresses: Mutex<SubaddressCache>,
-    major_index: u32,
+    /// Account indices invoices may be allocated from. The first is the
+    /// default.
+    major_indices: Vec<u32>,
     highest_minor_index: Arc<AtomicU32>,
     initial_height: Option<u64>,
+    block_fetch_concurrency: usize,
+    output_key_retention: Option<u64>,
     block_cache_height: Arc<AtomicU64>,
     cached_daemon_height: Arc<AtomicU64>,
     scanner_handle: AsyncMutex<Option<ScannerHandle>>,
@@ -116,97 +132,166 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
             };
         }
 
+        if let Some(scanner) = self.remote_scanner().await? {
+            return self.run_remote(scanner).await;
+        }
+
         // Gather info needed by the scanner.
         let monerod_client = self.monerod_client.clone();
-        let viewpair = self.viewpair;
+        let wallets = self.wallets.clone();
         let scan_interval = self.scan_interval;
-        let major_index = self.major_index;
+        let major_indices = self.major_indices.clone();
         let highest_minor_index = self.highest_minor_index.clone();
         let block_cache_height = self.block_cache_height.clone();
         let cached_daemon_height = self.cached_daemon_height.clone();
         let initial_height = self.initial_height;
+        let block_fetch_concurrency = self.block_fetch_concurrency;
         let publisher = self.publisher.clone();
         let store = self.store.clone();
         let command_receiver = self.scanner_command_sender.1.clone();
 
         // Create scanner.
         debug!("Creating blockchain scanner");
-        let mut scanner: Scanner<S, M> = Scanner::new(
+        let mut scanner: Scanner<Client<S>, M> = Scanner::new(
             monerod_client,
             store,
             DEFAULT_BLOCK_CACHE_SIZE,
+            block_fetch_concurrency,
             block_cache_height,
             cached_daemon_height,
             initial_height,
             publisher,
+            self.wallets.clone(),
+            self.output_key_retention,
         )
         .await?;
 
         // Spawn the scanning thread.
         info!("Starting blockchain scanner");
         *self.scanner_handle.lock().await = Some(ScannerHandle::from(tokio::spawn(async move {
-            // Create persistent sub key checker for efficient tx output checking.
-            let mut sub_key_checker = SubKeyChecker::new(
-                &viewpair,
-                major_index..major_index.saturating_add(1),
-                0..highest_minor_index
-                    .load(atomic::Ordering::Relaxed)
-                    .saturating_add(1),
-            );
             // Scan for transactions once every scan_interval.
             let mut blockscan_interval = time::interval(scan_interval);
-            loop {
-                // If we're received the stop signal, stop.
-                match command_receiver
-                    .lock()
-                    .unwrap_or_else(PoisonError::into_inner)
-                    .try_recv()
-                {
-                    Ok(MessageToScanner::Stop) => {
-                        info!("Scanner received stop signal. Stopping scanning thread");
-                        break;
+            // Sub key checkers are recreated whenever a wallet is added or removed.
+            'wallets: loop {
+                let (wallets_version, view_pairs) = wallets.snapshot();
+                // Create persistent sub key checkers for efficient tx output checking.
+                let mut sub_key_checkers = new_sub_key_checkers(
+                    &view_pairs,
+                    &major_indices,
+                    highest_minor_index.load(atomic::Ordering::Relaxed),
+                );
+                loop {
+                    // If we're received the stop signal, stop.
+                    if stop_requested(&command_receiver) {
+                        break 'wallets;
                     }
-                    Err(TryRecvError::Empty) => {}
-                    Err(TryRecvError::Disconnected) => {
-                        error!(
-                            "Scanner lost connection to payment gateway. Stopping scanning thread."
+                    if wallets.version() != wallets_version {
+                        continue 'wallets;
+                    }
+                    // Update sub key checkers if necessary.
+                    if sub_key_checkers.iter().any(|(_, checker)| {
+                        checker.table.len() / major_indices.len()
+                            <= highest_minor_index.load(atomic::Ordering::Relaxed) as usize
+                    }) {
+                        sub_key_checkers = new_sub_key_checkers(
+                            &view_pairs,
+                            &major_indices,
+                            highest_minor_index.load(atomic::Ordering::Relaxed),
                         );
-                        break;
                     }
+                    // Scan!
+                    if let Err(e) = if scanner.is_synchronized().await {
+                        // Scan at the specified interval if we're caught up.
+                        trace!("Waiting for scan interval.");
+                        let (_, result) =
+                            join!(blockscan_interval.tick(), scanner.scan(&sub_key_checkers));
+                        result
+                    } else {
+                        // Scan as fast as we can if we're behind.
+                        trace!(
+                            "Scanning at max speed to catch up. Cache height: {}, daemon height: {}",
+                            scanner.cache_height().await,
+                            scanner.daemon_height().await
+                        );
+                        scanner.scan(&sub_key_checkers).await
+                    } {
+                        error!(
+                            "Payment gateway encountered an error while scanning for payments: {}",
+                            e
+                        );
+                    };
                 }
-                // Update sub key checker if necessary.
-                if sub_key_checker.table.len()
-                    <= highest_minor_index.load(atomic::Ordering::Relaxed) as usize
-                {
-                    sub_key_checker = SubKeyChecker::new(
-                        &viewpair,
-                        major_index..major_index.saturating_add(1),
-                        0..highest_minor_index
-                            .load(atomic::Ordering::Relaxed)
-                            .saturating_add(1),
-                    );
+            }
+
+            Ok(())
+        })));
+        debug!("Scanner started successfully");
+        Ok(())
+    }
+
+    /// Create the scanner for a remote backend, or `None` if payments are
+    /// tracked by scanning blocks from the daemon.
+    async fn remote_scanner(&self) -> Result<Option<RemoteScanner<Client<S>>>, AcceptXmrError> {
+        let scanner = match &self.backend {
+            Backend::Daemon => return Ok(None),
+            Backend::WalletRpc(wallet) => {
+                debug!("Creating wallet RPC scanner");
+                let scanner = WalletRpcScanner::new(
+                    (**wallet).clone(),
+                    self.store.clone(),
+                    self.major_indices.clone(),
+                    self.block_cache_height.clone(),
+                    self.cached_daemon_height.clone(),
+                    self.publisher.clone(),
+                )
+                .await?;
+                RemoteScanner::WalletRpc(scanner)
+            }
+            Backend::LightWallet(client) => {
+                debug!("Creating light wallet scanner");
+                let scanner = LightWalletScanner::new(
+                    (**client).clone(),
+                    self.store.clone(),
+                    self.major_indices.clone(),
+                    self.highest_minor_index.clone(),
+                    self.block_cache_height.clone(),
+                    self.cached_daemon_height.clone(),
+                    self.publisher.clone(),
+                )
+                .await?;
+                RemoteScanner::LightWallet(scanner)
+            }
+        };
+
+        Ok(Some(scanner))
+    }
+
+    /// Runs the payment gateway using a backend which reports payments
+    /// directly, instead of scanning blocks.
+    async fn run_remote(
+        &self,
+        mut scanner: RemoteScanner<Client<S>>,
+    ) -> Result<(), AcceptXmrError> {
+        let scan_interval = self.scan_interval;
+        let command_receiver = self.scanner_command_sender.1.clone();
+
+        // Spawn the scanning thread.
+        info!("Starting {} scanner", scanner.name());
+        *self.scanner_handle.lock().await = Some(ScannerHandle::from(tokio::spawn(async move {
+            // Scan for transfers once every scan_interval.
+            let mut scan_interval = time::interval(scan_interval);
+            loop {
+                // If we're received the stop signal, stop.
+                if stop_requested(&command_receiver) {
+                    break;
                 }
-                // Scan!
-                if let Err(e) = if scanner.is_synchronized().await {
-                    // Scan at the specified interval if we're caught up.
-                    trace!("Waiting for scan interval.");
-                    let (_, result) =
-                        join!(blockscan_interval.tick(), scanner.scan(&sub_key_checker));
-                    result
-                } else {
-                    // Scan as fast as we can if we're behind.
-                    trace!(
-                        "Scanning at max speed to catch up. Cache height: {}, daemon height: {}",
-                        scanner.cache_height().await,
-                        scanner.daemon_height().await
-                    );
-                    scanner.scan(&sub_key_checker).await
-                } {
+                let (_, result) = join!(scan_interval.tick(), scanner.scan());
+                if let Err(e) = result {
                     error!(
                         "Payment gateway encountered an error while scanning for payments: {}",
                         e
                     );
-                };
+                }
             }
 
             Ok(())
@@ -216,7 +301,7 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
     }
 
     /// Returns the enum [`PaymentGatewayStatus`] describing whether the payment
-    /// gateway is running, not running, or has experienced an error.
+    /// gateway is running, degraded, not running, or has experienced an error.
     #[must_use]
     pub async fn status(&self) -> PaymentGatewayStatus {
         let scanner_handle = self.scanner_handle.lock().await;
@@ -233,6 +318,9 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
                     PaymentGatewayStatus::NotRunning
                 }
             }
+            Some(_) if self.monerod_client.is_degraded() || self.backend.is_degraded() => {
+                PaymentGatewayStatus::Degraded
+            }
             Some(_) => PaymentGatewayStatus::Running,
         }
     }
@@ -273,25 +361,222 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
     /// the ID of the new invoice. Use a [`Subscriber`] to receive updates
     /// on the new invoice invoice as they occur.
     ///
+    /// The amount requested may be an [`Amount`], or a number of piconeros.
+    /// The invoice is allocated from the default account, i.e. the first of
+    /// the payment gateway's [account indices](Self::account_indices).
+    ///
     /// # Errors
     ///
     /// Returns an error if there are any underlying issues modifying data in
     /// the database.
     pub async fn new_invoice(
         &self,
-        piconeros: u64,
+        amount: impl Into<Amount>,
         confirmations_required: u64,
         expiration_in: u64,
         description: String,
     ) -> Result<InvoiceId, AcceptXmrError> {
-        let amount = piconeros;
+        self.new_invoice_for_account(
+            self.major_indices[0],
+            amount,
+            confirmations_required,
+            expiration_in,
+            description,
+        )
+        .await
+    }
 
-        // Get subaddress in base58, and subaddress index.
-        let (sub_index, subaddress) = self
-            .subaddresses
-            .lock()
-            .unwrap_or_else(PoisonError::into_inner)
-            .remove_random();
+    /// Adds a new [`Invoice`] allocated from the given account to the payment
+    /// gateway for tracking, and returns the ID of the new invoice.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an [`AcceptXmrError::UnknownAccount`] error if the payment
+    ///   gateway was not configured to use `account_index`.
+    ///
+    /// * Returns an [`AcceptXmrError::GapLimitReached`] error if a
+    ///   [gap limit](PaymentGatewayBuilder::subaddress_gap_limit) is set, and
+    ///   every subaddress of the account within it is in use.
+    ///
+    /// * Returns an error if there are any underlying issues modifying data in
+    ///   the database.
+    pub async fn new_invoice_for_account(
+        &self,
+        account_index: u32,
+        amount: impl Into<Amount>,
+        confirmations_required: u64,
+        expiration_in: u64,
+        description: String,
+    ) -> Result<InvoiceId, AcceptXmrError> {
+        self.new_invoice_for_wallet(
+            &self.wallets.default_wallet(),
+            account_index,
+            amount,
+            confirmations_required,
+            expiration_in,
+            description,
+        )
+        .await
+    }
+
+    /// Adds a new [`Invoice`] to the payment gateway for tracking, paid to a
+    /// subaddress of the given wallet and account, and returns the ID of the
+    /// new invoice. The wallet is identified by its primary address, and must
+    /// have been [added](Self::add_wallet) to the payment gateway first.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an [`AcceptXmrError::UnknownWallet`] error if the payment
+    ///   gateway does not track payments to `wallet`.
+    ///
+    /// * Returns an [`AcceptXmrError::UnknownAccount`] error if the payment
+    ///   gateway was not configured to use `account_index`.
+    ///
+    /// * Returns an [`AcceptXmrError::GapLimitReached`] error if a
+    ///   [gap limit](PaymentGatewayBuilder::subaddress_gap_limit) is set, and
+    ///   every subaddress of the account within it is in use.
+    ///
+    /// * Returns an error if there are any underlying issues modifying data in
+    ///   the database.
+    pub async fn new_invoice_for_wallet(
+        &self,
+        wallet: &str,
+        account_index: u32,
+        amount: impl Into<Amount>,
+        confirmations_required: u64,
+        expiration_in: u64,
+        description: String,
+    ) -> Result<InvoiceId, AcceptXmrError> {
+        self.create_invoice(
+            wallet,
+            account_index,
+            amount.into(),
+            confirmations_required,
+            expiration_in,
+            description,
+            None,
+        )
+        .await
+    }
+
+    /// Adds a new [`Invoice`] allocated from the given account to the payment
+    /// gateway for tracking, with the merchant's own `reference` for it (such
+    /// as an order ID), and returns the ID of the new invoice. The invoice can
+    /// later be found by its reference using
+    /// [`get_invoice_by_reference`](Self::get_invoice_by_reference).
+    ///
+    /// References are unique among tracked invoices. If a tracked or archived
+    /// invoice with the same reference already exists, no invoice is created,
+    /// and the ID of the existing invoice is returned instead, even if it was
+    /// created with different details. Creating an invoice for an order is
+    /// therefore safe to retry, even after the first invoice has been paid and
+    /// archived. Once the archived invoice is
+    /// [pruned](PaymentGateway::prune_archive), its reference may be
+    /// used again.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an [`AcceptXmrError::UnknownAccount`] error if the payment
+    ///   gateway was not configured to use `account_index`.
+    ///
+    /// * Returns an [`AcceptXmrError::GapLimitReached`] error if a
+    ///   [gap limit](PaymentGatewayBuilder::subaddress_gap_limit) is set, and
+    ///   every subaddress of the account within it is in use.
+    ///
+    /// * Returns an error if there are any underlying issues modifying data in
+    ///   the database.
+    pub async fn new_invoice_with_reference(
+        &self,
+        reference: String,
+        account_index: u32,
+        amount: impl Into<Amount>,
+        confirmations_required: u64,
+        expiration_in: u64,
+        description: String,
+    ) -> Result<InvoiceId, AcceptXmrError> {
+        self.new_invoice_for_wallet_with_reference(
+            reference,
+            &self.wallets.default_wallet(),
+            account_index,
+            amount,
+            confirmations_required,
+            expiration_in,
+            description,
+        )
+        .await
+    }
+
+    /// Adds a new [`Invoice`] paid to a subaddress of the given wallet and
+    /// account to the payment gateway for tracking, with the merchant's own
+    /// `reference` for it, and returns the ID of the new invoice. The wallet is
+    /// identified by its primary address, and must have been
+    /// [added](Self::add_wallet) to the payment gateway first.
+    ///
+    /// References are unique across all wallets. As with
+    /// [`new_invoice_with_reference`](Self::new_invoice_with_reference), if a
+    /// tracked or archived invoice with the same reference already exists, its
+    /// ID is returned instead, even if it belongs to another wallet.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an [`AcceptXmrError::UnknownWallet`] error if the payment
+    ///   gateway does not track payments to `wallet`.
+    ///
+    /// * Returns an [`AcceptXmrError::UnknownAccount`] error if the payment
+    ///   gateway was not configured to use `account_index`.
+    ///
+    /// * Returns an [`AcceptXmrError::GapLimitReached`] error if a
+    ///   [gap limit](PaymentGatewayBuilder::subaddress_gap_limit) is set, and
+    ///   every subaddress of the account within it is in use.
+    ///
+    /// * Returns an error if there are any underlying issues modifying data in
+    ///   the database.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new_invoice_for_wallet_with_reference(
+        &self,
+        reference: String,
+        wallet: &str,
+        account_index: u32,
+        amount: impl Into<Amount>,
+        confirmations_required: u64,
+        expiration_in: u64,
+        description: String,
+    ) -> Result<InvoiceId, AcceptXmrError> {
+        if let Some(existing) = self.get_invoice_by_reference(&reference).await? {
+            return Ok(existing.id());
+        }
+        if let Some(archived) = self.get_archived_invoice_by_reference(&reference).await? {
+            return Ok(archived.id());
+        }
+        self.create_invoice(
+            wallet,
+            account_index,
+            amount.into(),
+            confirmations_required,
+            expiration_in,
+            description,
+            Some(reference),
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn create_invoice(
+        &self,
+        wallet: &str,
+        account_index: u32,
+        amount: Amount,
+        confirmations_required: u64,
+        expiration_in: u64,
+        description: String,
+        reference: Option<String>,
+    ) -> Result<InvoiceId, AcceptXmrError> {
+        if self.wallets.view_pair(wallet).is_none() {
+            return Err(AcceptXmrError::UnknownWallet(wallet.to_string()));
+        }
+        if !self.major_indices.contains(&account_index) {
+            return Err(AcceptXmrError::UnknownAccount(account_index));
+        }
 
         let cached_daemon_height = self.cached_daemon_height.load(atomic::Ordering::Relaxed);
         let creation_height = if cached_daemon_height != 0 {
@@ -300,9 +585,37 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
             self.daemon_height().await?
         };
 
+        // Get subaddress in base58, and subaddress index.
+        let (sub_index, subaddress) = match &self.backend {
+            Backend::Daemon | Backend::LightWallet(_) => {
+                let allocated = self
+                    .subaddresses
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .allocate(wallet, account_index, creation_height);
+                let (sub_index, subaddress) = match allocated {
+                    Err(AcceptXmrError::GapLimitReached(_)) => {
+                        // Subaddresses may have received funds since, moving the gap limit.
+                        let funded = self.store.get_funded_subaddresses().await?;
+                        let mut subaddresses = self
+                            .subaddresses
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner);
+                        subaddresses.mark_funded(funded);
+                        subaddresses.allocate(wallet, account_index, creation_height)?
+                    }
+                    result => result?,
+                };
+                (sub_index, subaddress)
+            }
+            Backend::WalletRpc(wallet) => wallet.create_address(account_index).await?,
+        };
+        self.wallets.set_owner(sub_index, wallet.to_string());
+
         // Create invoice object.
-        let invoice = Invoice::new(
+        let mut invoice = Invoice::new(
             subaddress,
+            wallet.to_string(),
             sub_index,
             creation_height,
             amount,
@@ -310,15 +623,43 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
             expiration_in,
             description,
         );
+        invoice.reference = reference;
 
-        // Insert invoice into database for tracking.
-        self.store.insert_invoice(invoice.clone()).await?;
+        // Insert invoice into database for tracking. The event log is locked
+        // first, so that no update to the invoice is logged before its creation.
+        let mut event_log = self.publisher.event_log().await;
+        if let Err(e) = self.store.insert_invoice(invoice.clone()).await {
+            // An invoice with the same reference may have been created since it
+            // was checked for. If so, the new invoice isn't needed.
+            if let Some(reference) = invoice.reference() {
+                if let Some(existing) = self
+                    .store
+                    .get_invoice_by_reference(reference.to_string())
+                    .await?
+                {
+                    self.release_subaddress(wallet, sub_index, false);
+                    return Ok(existing.id());
+                }
+            }
+            return Err(e.into());
+        }
         debug!(
             "Now tracking invoice to subaddress index {}",
             invoice.index()
         );
 
+        // An invoice missing from the event log must not be tracked, so undo
+        // its creation if it can't be logged.
+        let created = InvoiceEvent::Created {
+            invoice: invoice.clone(),
+        };
+        if let Err(e) = event_log.log(&self.store, vec![created]).await {
+            self.store.remove_invoice(invoice.id()).await?;
+            self.release_subaddress(wallet, sub_index, false);
+            return Err(e.into());
+        }
         self.publisher.insert_invoice(invoice.id());
+        event_log.publish(invoice.id(), None).await;
 
         // Return invoice id so the user can build identify their invoice, and make a
         // subscriber for it if desired.
@@ -336,25 +677,93 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
         &self,
         invoice_id: InvoiceId,
     ) -> Result<Option<Invoice>, AcceptXmrError> {
-        match self.store.remove_invoice(invoice_id).await? {
-            Some(old) => {
-                if !(old.is_expired()
-                    || old.is_confirmed() && old.creation_height() < old.current_height())
-                {
-                    warn!("Removed an invoice which was neither expired, nor fully confirmed and a block or more old. Was this intentional?");
-                }
-                // Put the subaddress back in the subaddress cache.
-                self.subaddresses
-                    .lock()
-                    .unwrap_or_else(PoisonError::into_inner)
-                    .insert(invoice_id.sub_index, old.address().to_string());
+        self.stop_tracking(invoice_id, false).await
+    }
+
+    /// Move an invoice to the archive (i.e. stop tracking it, but keep it for
+    /// later retrieval with
+    /// [`get_archived_invoice`](PaymentGateway::get_archived_invoice)),
+    /// returning the invoice if it existed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues modifying/retrieving
+    /// data in the database.
+    pub async fn archive_invoice(
+        &self,
+        invoice_id: InvoiceId,
+    ) -> Result<Option<Invoice>, AcceptXmrError> {
+        self.stop_tracking(invoice_id, true).await
+    }
 
-                // Kill any related subscriptions.
-                self.publisher.remove_invoice(invoice_id);
+    /// Stop tracking an invoice, moving it to the archive if `archive` is set,
+    /// and clean up after it. Its removal is logged first, so that it can't be
+    /// removed without event subscribers being told.
+    async fn stop_tracking(
+        &self,
+        invoice_id: InvoiceId,
+        archive: bool,
+    ) -> Result<Option<Invoice>, AcceptXmrError> {
+        let mut event_log = self.publisher.event_log().await;
+        let Some(invoice) = self.store.get_invoice(invoice_id).await? else {
+            return Ok(None);
+        };
+        event_log
+            .log(&self.store, vec![InvoiceEvent::Removed { invoice }])
+            .await?;
+        let removed = if archive {
+            self.store.archive_invoice(invoice_id).await?
+        } else {
+            self.store.remove_invoice(invoice_id).await?
+        };
+        let Some(old) = removed else {
+            return Ok(None);
+        };
 
-                Ok(Some(old))
-            }
-            None => Ok(None),
+        if !(old.is_expired() || old.is_confirmed() && old.creation_height() < old.current_height())
+        {
+            warn!("Stopped tracking an invoice which was neither expired, nor fully confirmed and a block or more old. Was this intentional?");
+        }
+        // Remember that the subaddress received funds, so the allocator can refuse
+        // to reuse it.
+        if old.amount_paid() > Amount::ZERO {
+            self.store
+                .insert_funded_subaddress(old.wallet().to_string(), invoice_id.sub_index)
+                .await?;
+            self.release_subaddress(old.wallet(), invoice_id.sub_index, true);
+        } else {
+            self.reserve_subaddress(old.wallet(), invoice_id.sub_index, old.expiration_height());
+        }
+
+        // Notify event subscribers, then kill any related subscriptions.
+        event_log.publish(invoice_id, None).await;
+        self.publisher.remove_invoice(invoice_id);
+
+        Ok(Some(old))
+    }
+
+    /// Put a subaddress which is no longer used by an invoice back in the
+    /// subaddress cache. Subaddresses created by the wallet RPC are not reused.
+    fn release_subaddress(&self, wallet: &str, sub_index: SubIndex, funded: bool) {
+        self.wallets.clear_owner(sub_index);
+        if !matches!(self.backend, Backend::WalletRpc(_)) {
+            self.subaddresses
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .release(wallet, sub_index, funded);
+        }
+    }
+
+    /// Keep the subaddress of an unpaid invoice out of the subaddress cache
+    /// until the [reuse delay](PaymentGatewayBuilder::subaddress_reuse_delay)
+    /// past its expiration has passed.
+    fn reserve_subaddress(&self, wallet: &str, sub_index: SubIndex, expiration_height: u64) {
+        self.wallets.clear_owner(sub_index);
+        if !matches!(self.backend, Backend::WalletRpc(_)) {
+            self.subaddresses
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .reserve(wallet, sub_index, expiration_height);
         }
     }
 
@@ -372,6 +781,139 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
         self.publisher.subscribe_all()
     }
 
+    /// Returns a `Subscriber` which receives an [`InvoiceEvent`] for each
+    /// change to the invoice with the given ID, rather than a full snapshot.
+    /// Returns `None` if no tracked invoice exists for that ID.
+    #[must_use]
+    pub fn subscribe_events(&self, invoice_id: InvoiceId) -> Option<Subscriber<InvoiceEvent>> {
+        self.publisher.subscribe_events(invoice_id)
+    }
+
+    /// Returns a `Subscriber` which receives an [`InvoiceEvent`] for each
+    /// change to any invoice, including the creation and removal of invoices.
+    #[must_use]
+    pub fn subscribe_all_events(&self) -> Subscriber<InvoiceEvent> {
+        self.publisher.subscribe_all_events()
+    }
+
+    /// Returns a `Subscriber` which receives only the [`InvoiceEvent`]s, for
+    /// any invoice, that pass the given filter.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # use acceptxmr::{PaymentGatewayBuilder, storage::stores::InMemory};
+    /// use acceptxmr::{InvoiceEventKind, SubscriptionFilter};
+    ///
+    /// # let private_view_key = "ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03";
+    /// # let primary_address = "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
+    /// # let payment_gateway = PaymentGatewayBuilder::new(private_view_key.to_string(), primary_address.to_string(), InMemory::new())
+    /// #     .build()
+    /// #     .await?;
+    /// // Only receive invoices as they become fully confirmed.
+    /// let mut subscriber = payment_gateway.subscribe_filtered(
+    ///     SubscriptionFilter::new().event_kinds([InvoiceEventKind::Confirmed]),
+    /// );
+    /// while let Some(event) = subscriber.recv().await {
+    ///     println!("Invoice {} confirmed", event.invoice().id());
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn subscribe_filtered(&self, filter: SubscriptionFilter) -> Subscriber<InvoiceEvent> {
+        self.publisher.subscribe_filtered(filter)
+    }
+
+    /// Returns a `Subscriber` which first replays every logged
+    /// [`InvoiceEvent`] with a sequence number of at least `sequence`, then
+    /// receives new events, for any invoice, as they happen.
+    ///
+    /// Every event is recorded in the [`EventStorage`](crate::storage::EventStorage)
+    /// log before the change it describes is saved, so a consumer which
+    /// remembers the sequence number of the last event it processed can
+    /// resubscribe from the next one after disconnecting or restarting, and
+    /// miss nothing. Events may be delivered more than once, for example if
+    /// saving a change failed and was retried, so consumers should handle them
+    /// idempotently. Pass `0` to replay the whole log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event log could not be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # use acceptxmr::{PaymentGatewayBuilder, storage::stores::InMemory};
+    /// # let private_view_key = "ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03";
+    /// # let primary_address = "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
+    /// # let payment_gateway = PaymentGatewayBuilder::new(private_view_key.to_string(), primary_address.to_string(), InMemory::new())
+    /// #     .build()
+    /// #     .await?;
+    /// # let last_processed: u64 = 0;
+    /// let mut subscriber = payment_gateway.subscribe_from(last_processed + 1).await?;
+    /// while let Some(logged) = subscriber.recv().await {
+    ///     println!("{}: {:?}", logged.sequence, logged.event.kind());
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub async fn subscribe_from(
+        &self,
+        sequence: u64,
+    ) -> Result<Subscriber<SequencedEvent>, AcceptXmrError> {
+        Ok(self.publisher.subscribe_from(&self.store, sequence).await?)
+    }
+
+    /// Remove every event with a sequence number less than `sequence` from the
+    /// event log. Call this once every consumer has processed those events, to
+    /// keep the log from growing indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues modifying data in
+    /// the database.
+    pub async fn prune_event_log(&self, sequence: u64) -> Result<(), AcceptXmrError> {
+        Ok(self.store.prune_events(sequence).await?)
+    }
+
+    /// Remove every event logged below blockchain height `height` from the
+    /// event log, for keeping events for a fixed period rather than until
+    /// every consumer has processed them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues modifying/retrieving
+    /// data in the database.
+    pub async fn prune_event_log_below_height(&self, height: u64) -> Result<(), AcceptXmrError> {
+        let logged_height = |event: &InvoiceEvent| {
+            let invoice = event.invoice();
+            invoice.current_height().max(invoice.creation_height())
+        };
+        // Events are logged in order of height, so everything before the first
+        // event at or above `height` is older.
+        let mut sequence = 0;
+        loop {
+            let events = self.store.get_events_from(sequence, EVENT_PAGE_LEN).await?;
+            if let Some((first_kept, _)) = events
+                .iter()
+                .find(|(_, event)| logged_height(event) >= height)
+            {
+                sequence = *first_kept;
+                break;
+            }
+            match events.last() {
+                Some((last, _)) => sequence = last + 1,
+                None => break,
+            }
+        }
+        Ok(self.store.prune_events(sequence).await?)
+    }
+
     /// Get current height of daemon using a monero daemon remote procedure
     /// call.
     ///
@@ -380,7 +922,12 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
     /// Returns an error if a connection can not be made to the daemon, or if
     /// the daemon's response cannot be parsed.
     pub async fn daemon_height(&self) -> Result<u64, AcceptXmrError> {
-        Ok(self.monerod_client.daemon_height().await?)
+        match &self.backend {
+            Backend::LightWallet(client) => Ok(client.address_txs().await?.blockchain_height + 1),
+            Backend::Daemon | Backend::WalletRpc(_) => {
+                Ok(self.monerod_client.daemon_height().await?)
+            }
+        }
     }
 
     /// Get current height of block cache.
@@ -405,25 +952,241 @@ impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
         Ok(self.store.get_invoice(invoice_id).await?)
     }
 
-    /// Get a list of all currently tracked invoice IDs.
+    /// Get the tracked invoice with the given [reference](Invoice::reference),
+    /// if it exists. Archived invoices are not included.
     ///
     /// # Errors
     ///
     /// Returns an error if there are any underlying issues retrieving data from
     /// the database.
-    pub async fn get_invoice_ids(&self) -> Result<Vec<InvoiceId>, AcceptXmrError> {
-        Ok(self.store.get_invoice_ids().await?)
+    pub async fn get_invoice_by_reference(
+        &self,
+        reference: &str,
+    ) -> Result<Option<Invoice>, AcceptXmrError> {
+        Ok(self
+            .store
+            .get_invoice_by_reference(reference.to_string())
+            .await?)
     }
 
-    /// Returns URL of configured daemon.
-    #[must_use]
-    pub fn daemon_url(&self) -> String {
-        self.monerod_client.url()
+    /// Get a page of the currently tracked invoices selected by `query`.
+    /// Archived invoices are not included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues retrieving data from
+    /// the database.
+    pub async fn query_invoices(&self, query: InvoiceQuery) -> Result<InvoicePage, AcceptXmrError> {
+        Ok(self.store.query_invoices(query).await?)
     }
-}
 
-/// A builder for the payment gateway. Used to configure your desired monero
-/// daemon, scan interval, view key, etc.
+    /// Get the archived invoice associated with the given [`InvoiceId`], if it
+    /// exists. Archived invoices are no longer tracked, so they are not
+    /// updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues retrieving data from
+    /// the database.
+    pub async fn get_archived_invoice(
+        &self,
+        invoice_id: InvoiceId,
+    ) -> Result<Option<Invoice>, AcceptXmrError> {
+        Ok(self.store.get_archived_invoice(invoice_id).await?)
+    }
+
+    /// Get an archived invoice with the given [reference](Invoice::reference),
+    /// if one exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues retrieving data from
+    /// the database.
+    pub async fn get_archived_invoice_by_reference(
+        &self,
+        reference: &str,
+    ) -> Result<Option<Invoice>, AcceptXmrError> {
+        Ok(self
+            .store
+            .get_archived_invoice_by_reference(reference.to_string())
+            .await?)
+    }
+
+    /// Get statistics about the output keys stored for [burning
+    /// bug](https://www.getmonero.org/2018/09/25/a-post-mortum-of-the-burning-bug.html)
+    /// detection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues retrieving data from
+    /// the database.
+    pub async fn output_key_stats(&self) -> Result<OutputKeyStats, AcceptXmrError> {
+        Ok(self.store.output_key_stats().await?)
+    }
+
+    /// Permanently delete archived invoices last updated below `height`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues modifying data in
+    /// the database.
+    pub async fn prune_archive(&self, height: u64) -> Result<(), AcceptXmrError> {
+        Ok(self.store.prune_archive(height).await?)
+    }
+
+    /// Get a list of all currently tracked invoice IDs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are any underlying issues retrieving data from
+    /// the database.
+    pub async fn get_invoice_ids(&self) -> Result<Vec<InvoiceId>, AcceptXmrError> {
+        Ok(self.store.get_invoice_ids().await?)
+    }
+
+    /// Start tracking payments to another wallet, identified by its primary
+    /// address. All wallets share the same blocks and transactions from the
+    /// daemon, and are checked in a single pass. Returns `false` if the wallet
+    /// was already tracked.
+    ///
+    /// Wallets can be added before or while the payment gateway is running.
+    /// Payments are only detected in blocks scanned after the wallet was
+    /// added. Added wallets are not persisted, so a wallet which has invoices
+    /// must also be registered using
+    /// [`PaymentGatewayBuilder::additional_wallet`], or the payment gateway
+    /// will refuse to start after a restart.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an [`AcceptXmrError::MultipleWalletsUnsupported`] error if
+    ///   the payment gateway uses a wallet RPC or light wallet server.
+    ///
+    /// * Returns an [`AcceptXmrError::Parse`] error if the primary address or
+    ///   private view key cannot be parsed.
+    pub fn add_wallet(
+        &self,
+        primary_address: &str,
+        private_view_key: &str,
+    ) -> Result<bool, AcceptXmrError> {
+        if !matches!(self.backend, Backend::Daemon) {
+            return Err(AcceptXmrError::MultipleWalletsUnsupported);
+        }
+        let (primary_address, viewpair) = parse_wallet(primary_address, private_view_key)?;
+        let primary_address = primary_address.to_string();
+        // Generate the wallet's subaddresses before the scanner starts looking for
+        // payments to them.
+        self.subaddresses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert_wallet(&primary_address, viewpair);
+        Ok(self.wallets.insert(primary_address, viewpair))
+    }
+
+    /// Stop tracking payments to the wallet with the given primary address.
+    /// Returns `false` if the wallet was not tracked.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AcceptXmrError::WalletInUse`] error if the wallet is the
+    /// default wallet the payment gateway was built with, or if it has tracked
+    /// invoices.
+    pub fn remove_wallet(&self, primary_address: &str) -> Result<bool, AcceptXmrError> {
+        let removed = self.wallets.remove(primary_address)?;
+        if removed {
+            self.subaddresses
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove_wallet(primary_address);
+        }
+        Ok(removed)
+    }
+
+    /// Returns the primary addresses of the wallets payments are tracked to,
+    /// starting with the default wallet.
+    #[must_use]
+    pub fn wallets(&self) -> Vec<String> {
+        self.wallets.primary_addresses()
+    }
+
+    /// Returns the account indices (i.e. subaddress major indices) invoices may
+    /// be allocated from. The first is the default account used by
+    /// [`new_invoice`](Self::new_invoice).
+    #[must_use]
+    pub fn account_indices(&self) -> &[u32] {
+        &self.major_indices
+    }
+
+    /// Returns a report of the highest subaddress indices used in each
+    /// account of the default wallet, to tell a wallet restored from seed how
+    /// far to look ahead.
+    ///
+    /// Subaddresses created by a wallet RPC are tracked by the wallet itself,
+    /// and are not included.
+    #[must_use]
+    pub fn subaddress_report(&self) -> SubaddressReport {
+        SubaddressReport {
+            accounts: self
+                .subaddresses
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .usage(&self.wallets.default_wallet())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns a report of the highest subaddress indices used in each
+    /// account of the wallet with the given primary address. Each wallet
+    /// allocates its own subaddresses, so each has its own report.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AcceptXmrError::UnknownWallet`] error if payments to the
+    /// wallet are not tracked.
+    pub fn subaddress_report_for_wallet(
+        &self,
+        primary_address: &str,
+    ) -> Result<SubaddressReport, AcceptXmrError> {
+        let accounts = self
+            .subaddresses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .usage(primary_address)
+            .ok_or_else(|| AcceptXmrError::UnknownWallet(primary_address.to_string()))?;
+        Ok(SubaddressReport { accounts })
+    }
+
+    /// Returns URL of configured daemon, or of the light wallet server if one
+    /// is used instead.
+    #[must_use]
+    pub fn daemon_url(&self) -> String {
+        match &self.backend {
+            Backend::LightWallet(client) => client.url(),
+            Backend::Daemon | Backend::WalletRpc(_) => self.monerod_client.url(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<S: Storage + 'static, M: MonerodClient + 'static> PaymentGateway<S, M> {
+    /// Write a [dump](crate::storage::dump) of the payment gateway's store to
+    /// `writer`. No changes are made to the store while the dump is being
+    /// written, so it is consistent even while the payment gateway is running.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store could not be read, or if the dump could
+    /// not be written.
+    #[cfg(feature = "serde")]
+    pub async fn export_storage<W: std::io::Write + Send + 'static>(
+        &self,
+        writer: W,
+    ) -> Result<(), AcceptXmrError> {
+        Ok(self.store.export(Box::new(writer)).await?)
+    }
+}
+
+/// A builder for the payment gateway. Used to configure your desired monero
+/// daemon, scan interval, view key, etc.
 ///
 /// # Examples
 ///
@@ -452,15 +1215,26 @@ pub struct PaymentGatewayBuilder<S> {
     daemon_url: String,
     daemon_username: Option<String>,
     daemon_password: Option<String>,
+    wallet_rpc_url: Option<String>,
+    wallet_rpc_username: Option<String>,
+    wallet_rpc_password: Option<String>,
+    light_wallet_server_url: Option<String>,
     rpc_timeout: Duration,
     rpc_connection_timeout: Duration,
+    rpc_policy: RpcPolicy,
     private_view_key: String,
     primary_address: String,
     scan_interval: Duration,
     store: S,
-    major_index: u32,
+    major_indices: Vec<u32>,
+    additional_wallets: Vec<(String, String)>,
     initial_height: Option<u64>,
+    block_fetch_concurrency: usize,
+    output_key_retention: Option<u64>,
     seed: Option<u64>,
+    subaddress_allocator: Option<Box<dyn SubaddressAllocator>>,
+    subaddress_gap_limit: Option<u32>,
+    subaddress_reuse_delay: u64,
 }
 
 impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
@@ -475,15 +1249,26 @@ impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
             daemon_url: DEFAULT_DAEMON.to_string(),
             daemon_username: None,
             daemon_password: None,
+            wallet_rpc_url: None,
+            wallet_rpc_username: None,
+            wallet_rpc_password: None,
+            light_wallet_server_url: None,
             rpc_timeout: DEFAULT_RPC_TOTAL_TIMEOUT,
             rpc_connection_timeout: DEFAULT_RPC_CONNECTION_TIMEOUT,
+            rpc_policy: RpcPolicy::default(),
             private_view_key,
             primary_address,
             scan_interval: DEFAULT_SCAN_INTERVAL,
             store,
-            major_index: 0,
+            major_indices: vec![0],
+            additional_wallets: Vec::new(),
             initial_height: None,
+            block_fetch_concurrency: DEFAULT_BLOCK_FETCH_CONCURRENCY,
+            output_key_retention: None,
             seed: None,
+            subaddress_allocator: None,
+            subaddress_gap_limit: None,
+            subaddress_reuse_delay: 0,
         }
     }
 
@@ -535,6 +1320,57 @@ impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
         self
     }
 
+    /// Track payments using the `monero-wallet-rpc` instance at `url`, instead
+    /// of scanning the blockchain. The wallet should be a view-only wallet for
+    /// the configured primary address.
+    ///
+    /// Subaddresses for new invoices are created using the wallet's
+    /// `create_address` method, and payments are tracked using its
+    /// `get_transfers` method. The monero daemon is still used to check
+    /// compatibility and to determine the creation height of invoices made
+    /// before the payment gateway is run.
+    ///
+    /// RPC timeouts and the [`RpcPolicy`] apply to the wallet RPC as well.
+    /// Overrides [`light_wallet_server_url`](Self::light_wallet_server_url).
+    #[must_use]
+    pub fn wallet_rpc_url(mut self, url: String) -> PaymentGatewayBuilder<S> {
+        self.wallet_rpc_url = Some(url);
+        self.light_wallet_server_url = None;
+        self
+    }
+
+    /// If your wallet RPC requires a password, configure it here.
+    #[must_use]
+    pub fn wallet_rpc_login(
+        mut self,
+        username: String,
+        password: String,
+    ) -> PaymentGatewayBuilder<S> {
+        self.wallet_rpc_username = Some(username);
+        self.wallet_rpc_password = Some(password);
+        self
+    }
+
+    /// Track payments using the light wallet server at `url` (e.g.
+    /// `monero-lws`), instead of scanning the blockchain. No monero daemon is
+    /// needed.
+    ///
+    /// The payment gateway logs in to the server with the primary address and
+    /// private view key, creating an account if necessary, and asks it to scan
+    /// for outputs to the subaddresses it uses. Payments are tracked using the
+    /// server's `get_address_txs` and `get_unspent_outs` endpoints. The server
+    /// decides the height its scanning starts from, so
+    /// [`initial_height`](Self::initial_height) has no effect.
+    ///
+    /// RPC timeouts and the [`RpcPolicy`] apply to the light wallet server as
+    /// well. Overrides [`wallet_rpc_url`](Self::wallet_rpc_url).
+    #[must_use]
+    pub fn light_wallet_server_url(mut self, url: String) -> PaymentGatewayBuilder<S> {
+        self.light_wallet_server_url = Some(url);
+        self.wallet_rpc_url = None;
+        self
+    }
+
     /// Time before an remote procedure call times out. If this amount of time
     /// elapses without receiving a full response from the RPC daemon, the
     /// current scan will be aborted and restarted. Defaults to 10 seconds.
@@ -554,6 +1390,35 @@ impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
         self
     }
 
+    /// Set the [`RpcPolicy`] controlling how failed remote procedure calls are
+    /// retried, how many requests are sent to the monero daemon per second,
+    /// and when calls to a failing daemon are suspended. Defaults to
+    /// [`RpcPolicy::default()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use acceptxmr::{storage::stores::InMemory, PaymentGatewayBuilder, RpcPolicy};
+    ///
+    /// let private_view_key =
+    ///     "ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03";
+    /// let primary_address =
+    ///     "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
+    ///
+    /// // Stay under a public node's rate limit.
+    /// let payment_gateway_builder = PaymentGatewayBuilder::new(
+    ///     private_view_key.to_string(),
+    ///     primary_address.to_string(),
+    ///     InMemory::new(),
+    /// )
+    /// .rpc_policy(RpcPolicy::default().max_requests_per_second(5));
+    /// ```
+    #[must_use]
+    pub fn rpc_policy(mut self, policy: RpcPolicy) -> PaymentGatewayBuilder<S> {
+        self.rpc_policy = policy;
+        self
+    }
+
     /// Set the minimum scan interval. New blocks and transactions will be
     /// scanned for relevant outputs at most every `interval`. Defaults to 1
     /// second.
@@ -572,11 +1437,164 @@ impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
         self
     }
 
+    /// Set the strategy used to choose which subaddress each new invoice is
+    /// paid to, and whether funded subaddresses are reused. Defaults to a
+    /// [`RandomAllocator`], seeded with the [seed](Self::seed) if one is set.
+    ///
+    /// Not used when tracking payments with a wallet RPC, which creates a new
+    /// subaddress for every invoice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use acceptxmr::{
+    /// #     storage::stores::InMemory, NeverReuseFunded, PaymentGatewayBuilder,
+    /// #     SequentialAllocator,
+    /// # };
+    /// # let private_view_key = "ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03";
+    /// # let primary_address = "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
+    /// let builder = PaymentGatewayBuilder::new(
+    ///     private_view_key.to_string(),
+    ///     primary_address.to_string(),
+    ///     InMemory::new(),
+    /// )
+    /// // Use subaddresses in order, and never reuse one which received funds.
+    /// .subaddress_allocator(NeverReuseFunded::new(SequentialAllocator));
+    /// ```
+    #[must_use]
+    pub fn subaddress_allocator(
+        mut self,
+        allocator: impl SubaddressAllocator + 'static,
+    ) -> PaymentGatewayBuilder<S> {
+        self.subaddress_allocator = Some(Box::new(allocator));
+        self
+    }
+
+    /// Only allocate subaddresses at most `gap_limit` minor indices past the
+    /// highest one of the account which has received funds. A wallet restored
+    /// from seed only looks a limited number of subaddresses ahead of the
+    /// highest funded one (200 by default for `monero-wallet-cli`), so this
+    /// keeps every allocated subaddress discoverable. Unbounded by default.
+    ///
+    /// Once every subaddress within the gap limit is in use, new invoices fail
+    /// with [`AcceptXmrError::GapLimitReached`] until more receive funds or
+    /// invoices are removed. A gap limit of 0 is treated as 1.
+    #[must_use]
+    pub fn subaddress_gap_limit(mut self, gap_limit: u32) -> PaymentGatewayBuilder<S> {
+        self.subaddress_gap_limit = Some(gap_limit.max(1));
+        self
+    }
+
+    /// Keep the subaddress of an invoice which is removed or archived without
+    /// receiving funds from being allocated again until `blocks` blocks past
+    /// the invoice's expiration, so that a late payment can't be credited to a
+    /// new invoice. Defaults to 0, i.e. the subaddress may be reused as soon as
+    /// the invoice is removed or archived.
+    ///
+    /// Not used when tracking payments with a wallet RPC, which creates a new
+    /// subaddress for every invoice.
+    #[must_use]
+    pub fn subaddress_reuse_delay(mut self, blocks: u64) -> PaymentGatewayBuilder<S> {
+        self.subaddress_reuse_delay = blocks;
+        self
+    }
+
     /// Set the account index (i.e. subaddress major index) the payment gateway
-    /// should use. Defaults to account index 0.
+    /// should use. Defaults to account index 0. Replaces any accounts set with
+    /// [`account_indices`](Self::account_indices).
     #[must_use]
     pub fn account_index(mut self, index: u32) -> PaymentGatewayBuilder<S> {
-        self.major_index = index;
+        self.major_indices = vec![index];
+        self
+    }
+
+    /// Set several account indices (i.e. subaddress major indices) the payment
+    /// gateway should allocate invoices from, e.g. to keep separate revenue
+    /// streams in separate accounts. All accounts are scanned together.
+    ///
+    /// The first index is the default account, used by
+    /// [`PaymentGateway::new_invoice`]. Use
+    /// [`PaymentGateway::new_invoice_for_account`] to allocate an invoice from
+    /// a specific account. Duplicate indices are ignored, and an empty list
+    /// leaves the accounts unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use acceptxmr::{storage::stores::InMemory, PaymentGatewayBuilder};
+    ///
+    /// let private_view_key =
+    ///     "ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03";
+    /// let primary_address =
+    ///     "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
+    ///
+    /// // Shop sales go to account 0 by default, donations to account 1.
+    /// let payment_gateway_builder = PaymentGatewayBuilder::new(
+    ///     private_view_key.to_string(),
+    ///     primary_address.to_string(),
+    ///     InMemory::new(),
+    /// )
+    /// .account_indices([0, 1]);
+    /// ```
+    #[must_use]
+    pub fn account_indices(
+        mut self,
+        indices: impl IntoIterator<Item = u32>,
+    ) -> PaymentGatewayBuilder<S> {
+        let mut major_indices = Vec::new();
+        for index in indices {
+            if !major_indices.contains(&index) {
+                major_indices.push(index);
+            }
+        }
+        if !major_indices.is_empty() {
+            self.major_indices = major_indices;
+        }
+        self
+    }
+
+    /// Track payments to another wallet, identified by its primary address,
+    /// from the start. Invoices for it can be created using
+    /// [`PaymentGateway::new_invoice_for_wallet`].
+    ///
+    /// Wallets [added](PaymentGateway::add_wallet) while the payment gateway
+    /// is running are not persisted, so any wallet with invoices in storage
+    /// must be registered here, or [`build`](Self::build) fails. Only
+    /// supported when the payment gateway scans the blockchain itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use acceptxmr::{storage::stores::InMemory, PaymentGatewayBuilder};
+    ///
+    /// let private_view_key =
+    ///     "ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03";
+    /// let primary_address =
+    ///     "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
+    ///
+    /// let other_private_view_key =
+    ///     "0909090909090909090909090909090909090909090909090909090909090900";
+    /// let other_primary_address =
+    ///     "49EoGL99szpehtkKuWCuaFduM6dopUmCfbaUTo58YgNq2VuMg6m8q3fYHS9omA66spPC54oY9T6HP6h6wRV5oy4aD6ff7y4";
+    ///
+    /// let payment_gateway_builder = PaymentGatewayBuilder::new(
+    ///     private_view_key.to_string(),
+    ///     primary_address.to_string(),
+    ///     InMemory::new(),
+    /// )
+    /// .additional_wallet(
+    ///     other_primary_address.to_string(),
+    ///     other_private_view_key.to_string(),
+    /// );
+    /// ```
+    #[must_use]
+    pub fn additional_wallet(
+        mut self,
+        primary_address: String,
+        private_view_key: String,
+    ) -> PaymentGatewayBuilder<S> {
+        self.additional_wallets
+            .push((primary_address, private_view_key));
         self
     }
 
@@ -596,29 +1614,91 @@ impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
         self
     }
 
+    /// Set the number of blocks to fetch concurrently while catching up to the
+    /// blockchain tip (e.g. after starting from an old initial height). Higher
+    /// values speed up synchronization at the cost of more load on the monero
+    /// daemon. Defaults to 10.
+    #[must_use]
+    pub fn block_fetch_concurrency(mut self, blocks: usize) -> PaymentGatewayBuilder<S> {
+        self.block_fetch_concurrency = blocks.max(1);
+        self
+    }
+
+    /// Drop output keys recorded more than `depth` blocks below the scan
+    /// height. Output keys are kept to detect the [burning
+    /// bug](https://www.getmonero.org/2018/09/25/a-post-mortum-of-the-burning-bug.html),
+    /// and by default are never dropped, so their storage grows for as long as
+    /// the payment gateway runs.
+    ///
+    /// This is a tradeoff: once an output's key is dropped, a later output
+    /// reusing that key is credited as a new payment. Only set a retention
+    /// depth if you accept that risk for outputs older than `depth` blocks.
+    /// Keys are pruned at most once every 720 blocks (about a day).
+    #[must_use]
+    pub fn output_key_retention(mut self, depth: u64) -> PaymentGatewayBuilder<S> {
+        self.output_key_retention = Some(depth);
+        self
+    }
+
     /// Build the payment gateway.
     ///
+    /// Before returning, the monero daemon is queried to verify that it is on
+    /// the same network as the primary address, that its RPC version is
+    /// supported, and that (if its RPC is restricted) the endpoints needed for
+    /// scanning are available. If a [wallet RPC](Self::wallet_rpc_url) is
+    /// configured, it is queried to verify that it serves the primary
+    /// address's wallet. If a [light wallet
+    /// server](Self::light_wallet_server_url) is configured, the daemon is not
+    /// queried, and the payment gateway logs in to the server instead.
+    ///
     /// # Errors
     ///
     /// Returns an error if the database cannot be opened at the path specified,
-    /// if the internal RPC client cannot parse the provided URL, or if the
-    /// primary address or private view key cannot be parsed.
+    /// if the internal RPC client cannot parse the provided URL, if the
+    /// primary address or private view key cannot be parsed, or if the daemon
+    /// is unreachable or incompatible.
+    ///
+    /// Returns an [`AcceptXmrError::UnknownWallet`] error if the store holds
+    /// invoices for a wallet which is neither the primary address's nor an
+    /// [additional wallet](Self::additional_wallet), and an
+    /// [`AcceptXmrError::MultipleWalletsUnsupported`] error if additional
+    /// wallets are registered along with a wallet RPC or light wallet server.
     pub async fn build(self) -> Result<PaymentGateway<S>, AcceptXmrError> {
-        let monerod_client = MonerodRpcClient::new(
-            self.daemon_url
-                .parse::<Uri>()
-                .map_err(|e| AcceptXmrError::Parse {
-                    datatype: "Uri",
-                    input: self.daemon_url.clone(),
-                    error: e.to_string(),
-                })?,
-            self.rpc_timeout,
-            self.rpc_connection_timeout,
-            self.daemon_username.clone(),
-            self.daemon_password.clone(),
-            self.seed,
-        );
+        let monerod_client = self.rpc_client()?;
+        self.build_inner(monerod_client).await
+    }
+
+    /// Build a payment gateway which records every response from the monero
+    /// daemon to `fixture_dir`. The recorded fixtures can later be served
+    /// offline using [`build_with_replay`](Self::build_with_replay), which is
+    /// useful for writing deterministic tests against real chain data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fixture directory cannot be created, or for any
+    /// of the reasons [`build`](Self::build) might fail.
+    pub async fn build_recording(
+        self,
+        fixture_dir: impl AsRef<Path>,
+    ) -> Result<PaymentGateway<S, MonerodRecordingClient<MonerodRpcClient>>, AcceptXmrError> {
+        let monerod_client = MonerodRecordingClient::new(self.rpc_client()?, fixture_dir)?;
+        self.build_inner(monerod_client).await
+    }
 
+    /// Build a payment gateway which serves monero daemon responses previously
+    /// recorded by [`build_recording`](Self::build_recording) from
+    /// `fixture_dir`, without contacting a daemon.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recorded fixtures cannot be read, if the
+    /// database cannot be opened, or if the primary address or viewkey cannot
+    /// be parsed.
+    pub async fn build_with_replay(
+        self,
+        fixture_dir: impl AsRef<Path>,
+    ) -> Result<PaymentGateway<S, MonerodReplayClient>, AcceptXmrError> {
+        let monerod_client = MonerodReplayClient::new(fixture_dir)?;
         self.build_inner(monerod_client).await
     }
 
@@ -636,36 +1716,112 @@ impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
         self.build_inner(monerod_client).await
     }
 
+    fn rpc_client(&self) -> Result<MonerodRpcClient, AcceptXmrError> {
+        self.http_rpc_client(
+            &self.daemon_url,
+            self.daemon_username.clone(),
+            self.daemon_password.clone(),
+        )
+    }
+
+    fn wallet_rpc_client(&self) -> Result<Option<WalletRpcClient>, AcceptXmrError> {
+        let Some(url) = &self.wallet_rpc_url else {
+            return Ok(None);
+        };
+        Ok(Some(WalletRpcClient::new(self.http_rpc_client(
+            url,
+            self.wallet_rpc_username.clone(),
+            self.wallet_rpc_password.clone(),
+        )?)))
+    }
+
+    fn light_wallet_client(&self) -> Result<Option<LightWalletClient>, AcceptXmrError> {
+        let Some(url) = &self.light_wallet_server_url else {
+            return Ok(None);
+        };
+        Ok(Some(LightWalletClient::new(
+            self.http_rpc_client(url, None, None)?,
+            self.primary_address.clone(),
+            self.private_view_key.clone(),
+        )))
+    }
+
+    fn http_rpc_client(
+        &self,
+        url: &str,
+        username: Option<String>,
+        password: Option<String>,
+    ) -> Result<MonerodRpcClient, AcceptXmrError> {
+        Ok(MonerodRpcClient::new(
+            url.parse::<Uri>().map_err(|e| AcceptXmrError::Parse {
+                datatype: "Uri",
+                input: url.to_string(),
+                error: e.to_string(),
+            })?,
+            self.rpc_timeout,
+            self.rpc_connection_timeout,
+            username,
+            password,
+            self.seed,
+            self.rpc_policy,
+        ))
+    }
+
     async fn build_inner<M: MonerodClient>(
         self,
         monerod_client: M,
     ) -> Result<PaymentGateway<S, M>, AcceptXmrError> {
-        let store = StorageClient::new(self.store);
-
-        let viewpair = monero::ViewPair {
-            view: monero::PrivateKey::from_str(&self.private_view_key).map_err(|e| {
-                AcceptXmrError::Parse {
-                    datatype: "PrivateKey",
-                    input: "[REDACTED]".to_string(),
-                    error: e.to_string(),
+        let wallet_rpc_client = self.wallet_rpc_client()?;
+        let light_wallet_client = self.light_wallet_client()?;
+        let store = Client::new(self.store);
+
+        let (primary_address, viewpair) =
+            parse_wallet(&self.primary_address, &self.private_view_key)?;
+        let mut view_pairs = IndexMap::from([(primary_address.to_string(), viewpair)]);
+        for (address, private_view_key) in &self.additional_wallets {
+            let (address, viewpair) = parse_wallet(address, private_view_key)?;
+            view_pairs.entry(address.to_string()).or_insert(viewpair);
+        }
+
+        let backend = match (wallet_rpc_client, light_wallet_client) {
+            (Some(wallet), _) => {
+                check_daemon(&monerod_client, primary_address.network).await?;
+                check_wallet(&wallet, &primary_address).await?;
+                Backend::WalletRpc(Box::new(wallet))
+            }
+            (None, Some(client)) => {
+                if client.login().await? {
+                    info!(
+                        "Created new account on light wallet server at {}",
+                        client.url()
+                    );
                 }
-            })?,
-            spend: monero::Address::from_str(&self.primary_address)
-                .map_err(|e| AcceptXmrError::Parse {
-                    datatype: "Address",
-                    input: self.primary_address.to_string(),
-                    error: e.to_string(),
-                })?
-                .public_spend,
+                Backend::LightWallet(Box::new(client))
+            }
+            (None, None) => {
+                check_daemon(&monerod_client, primary_address.network).await?;
+                Backend::Daemon
+            }
         };
 
+        if view_pairs.len() > 1 && !matches!(backend, Backend::Daemon) {
+            return Err(AcceptXmrError::MultipleWalletsUnsupported);
+        }
+        let wallets = Wallets::init(&store, view_pairs).await?;
         let highest_minor_index = Arc::new(AtomicU32::new(0));
         let subaddresses = SubaddressCache::init(
             &store,
-            viewpair,
-            self.major_index,
+            &wallets.snapshot().1,
+            &self.major_indices,
             highest_minor_index.clone(),
-            self.seed,
+            self.subaddress_allocator.unwrap_or_else(|| {
+                Box::new(
+                    self.seed
+                        .map_or_else(RandomAllocator::new, RandomAllocator::from_seed),
+                )
+            }),
+            self.subaddress_gap_limit,
+            self.subaddress_reuse_delay,
         )
         .await?;
         debug!("Generated {} initial subaddresses", subaddresses.len());
@@ -678,13 +1834,16 @@ impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
 
         Ok(PaymentGateway(Arc::new(PaymentGatewayInner {
             monerod_client,
-            viewpair,
+            backend,
+            wallets: Arc::new(wallets),
             scan_interval: self.scan_interval,
             store,
             subaddresses: Mutex::new(subaddresses),
-            major_index: self.major_index,
+            major_indices: self.major_indices,
             highest_minor_index,
             initial_height: self.initial_height,
+            block_fetch_concurrency: self.block_fetch_concurrency,
+            output_key_retention: self.output_key_retention,
             block_cache_height: Arc::new(atomic::AtomicU64::new(0)),
             cached_daemon_height: Arc::new(atomic::AtomicU64::new(0)),
             scanner_handle: AsyncMutex::new(None),
@@ -694,11 +1853,202 @@ impl<S: Storage + 'static> PaymentGatewayBuilder<S> {
     }
 }
 
-/// Enumeration of possible payment gateway states.
+/// Create a [`SubKeyChecker`] for subaddresses `0..=highest_minor_index` of
+/// each account of each wallet, so all accounts are checked in a single pass.
+fn new_sub_key_checkers<'a>(
+    view_pairs: &'a [(String, monero::ViewPair)],
+    major_indices: &[u32],
+    highest_minor_index: u32,
+) -> Vec<(&'a str, SubKeyChecker<'a>)> {
+    let minor_indices = 0..highest_minor_index.saturating_add(1);
+    view_pairs
+        .iter()
+        .map(|(wallet, viewpair)| {
+            let mut checker = SubKeyChecker::new(viewpair, 0..0, minor_indices.clone());
+            for &major_index in major_indices {
+                checker.table.extend(
+                    SubKeyChecker::new(
+                        viewpair,
+                        major_index..major_index.saturating_add(1),
+                        minor_indices.clone(),
+                    )
+                    .table,
+                );
+            }
+            (wallet.as_str(), checker)
+        })
+        .collect()
+}
+
+/// Parse a wallet's primary address and private view key.
+fn parse_wallet(
+    primary_address: &str,
+    private_view_key: &str,
+) -> Result<(monero::Address, monero::ViewPair), AcceptXmrError> {
+    let primary_address =
+        monero::Address::from_str(primary_address).map_err(|e| AcceptXmrError::Parse {
+            datatype: "Address",
+            input: primary_address.to_string(),
+            error: e.to_string(),
+        })?;
+    let viewpair = monero::ViewPair {
+        view: monero::PrivateKey::from_str(private_view_key).map_err(|e| {
+            AcceptXmrError::Parse {
+                datatype: "PrivateKey",
+                input: "[REDACTED]".to_string(),
+                error: e.to_string(),
+            }
+        })?,
+        spend: primary_address.public_spend,
+    };
+    Ok((primary_address, viewpair))
+}
+
+/// Verify that the daemon is on the expected network, speaks a supported RPC
+/// version, and provides the endpoints needed for scanning.
+async fn check_daemon<M: MonerodClient>(
+    monerod_client: &M,
+    network: monero::Network,
+) -> Result<(), AcceptXmrError> {
+    let version = monerod_client.rpc_version().await?;
+    if !version.is_supported() {
+        return Err(AcceptXmrError::UnsupportedRpcVersion {
+            found: version,
+            expected_major: RpcVersion::supported_major(),
+        });
+    }
+
+    let info = monerod_client.daemon_info().await?;
+    if info.network != network {
+        return Err(AcceptXmrError::NetworkMismatch {
+            daemon: info.network,
+            address: network,
+        });
+    }
+
+    if info.restricted {
+        debug!(
+            "Monero daemon's RPC is restricted. Checking that required endpoints are available."
+        );
+        monerod_client
+            .txpool_hashes()
+            .await
+            .map_err(|error| AcceptXmrError::RestrictedRpc {
+                endpoint: "get_transaction_pool_hashes",
+                error,
+            })?;
+        monerod_client
+            .txpool()
+            .await
+            .map_err(|error| AcceptXmrError::RestrictedRpc {
+                endpoint: "get_transaction_pool",
+                error,
+            })?;
+    }
+    debug!(
+        "Monero daemon at {} is compatible (network: {:?}, RPC version: {}).",
+        monerod_client.url(),
+        info.network,
+        version
+    );
+
+    Ok(())
+}
+
+/// Verify that the wallet RPC serves the wallet the primary address belongs
+/// to.
+async fn check_wallet(
+    wallet: &WalletRpcClient,
+    primary_address: &monero::Address,
+) -> Result<(), AcceptXmrError> {
+    let wallet_address = wallet.address(0).await?;
+    if wallet_address != primary_address.to_string() {
+        return Err(AcceptXmrError::WalletMismatch {
+            wallet: wallet_address,
+            expected: primary_address.to_string(),
+        });
+    }
+    debug!("Wallet RPC serves the wallet for primary address {primary_address}.");
+
+    Ok(())
+}
+
+/// Returns `true` if the scanning thread should stop, either because it
+/// received the stop signal or because it lost connection to the payment
+/// gateway.
+fn stop_requested(command_receiver: &Mutex<Receiver<MessageToScanner>>) -> bool {
+    match command_receiver
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .try_recv()
+    {
+        Ok(MessageToScanner::Stop) => {
+            info!("Scanner received stop signal. Stopping scanning thread");
+            true
+        }
+        Err(TryRecvError::Empty) => false,
+        Err(TryRecvError::Disconnected) => {
+            error!("Scanner lost connection to payment gateway. Stopping scanning thread.");
+            true
+        }
+    }
+}
+
+/// Source of the payment information the payment gateway scans.
+#[derive(Debug, Clone)]
+pub(crate) enum Backend {
+    /// Scan blocks and the txpool fetched from the monero daemon.
+    Daemon,
+    /// Track transfers reported by `monero-wallet-rpc`.
+    WalletRpc(Box<WalletRpcClient>),
+    /// Track outputs detected by a light wallet server.
+    LightWallet(Box<LightWalletClient>),
+}
+
+impl Backend {
+    fn is_degraded(&self) -> bool {
+        match self {
+            Backend::Daemon => false,
+            Backend::WalletRpc(wallet) => wallet.is_degraded(),
+            Backend::LightWallet(client) => client.is_degraded(),
+        }
+    }
+}
+
+/// Scanner for a backend which reports payments directly, rather than
+/// providing blocks to scan.
+enum RemoteScanner<S: AsyncStorage> {
+    WalletRpc(WalletRpcScanner<S>),
+    LightWallet(LightWalletScanner<S>),
+}
+
+impl<S: AsyncStorage> RemoteScanner<S> {
+    async fn scan(&mut self) -> Result<(), ScannerError> {
+        match self {
+            RemoteScanner::WalletRpc(scanner) => scanner.scan().await,
+            RemoteScanner::LightWallet(scanner) => scanner.scan().await,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            RemoteScanner::WalletRpc(_) => "wallet RPC",
+            RemoteScanner::LightWallet(_) => "light wallet",
+        }
+    }
+}
+
+/// Enumeration of possible payment gateway states. More states may be added in
+/// future, so matches should include a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum PaymentGatewayStatus {
     /// The payment gateway is scanning for incoming payments.
     Running,
+    /// The payment gateway is running, but calls to the monero daemon are
+    /// suspended after repeated failures (see [`RpcPolicy`]). Scanning resumes
+    /// once the daemon responds again.
+    Degraded,
     /// The payment gateway is not scanning for incoming payments.
     NotRunning,
     /// The payment gateway encountered an error while scanning for incoming
@@ -714,7 +2064,7 @@ pub(crate) enum MessageToScanner {
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
-    use testing_utils::{init_logger, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY};
+    use testing_utils::{init_logger, MockDaemon, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY};
 
     use crate::{storage::stores::InMemory, MonerodClient, PaymentGateway, PaymentGatewayBuilder};
 
@@ -722,6 +2072,7 @@ mod tests {
     async fn daemon_url() {
         // Setup.
         init_logger();
+        let mock_daemon = MockDaemon::new_mock_daemon().await;
         let store = InMemory::new();
 
         let payment_gateway: PaymentGateway<InMemory> = PaymentGatewayBuilder::<InMemory>::new(
@@ -729,14 +2080,11 @@ mod tests {
             PRIMARY_ADDRESS.to_string(),
             store,
         )
-        .daemon_url("http://example.com:18081".to_string())
+        .daemon_url(mock_daemon.url(""))
         .build()
         .await
         .unwrap();
 
-        assert_eq!(
-            payment_gateway.monerod_client.url(),
-            "http://example.com:18081/"
-        );
+        assert_eq!(payment_gateway.monerod_client.url(), mock_daemon.url("/"));
     }
 }
@@ -1,41 +1,71 @@
-//! Subscribers should be used to receive invoice updates.
+//! Subscribers should be used to receive invoice updates, either as
+//! [`Invoice`] snapshots or as [`InvoiceEvent`]s describing what changed.
 
 /// Max size of subscriber backlog.
 const SUBSCRIPTION_BUFFER_LEN: usize = 2048;
+/// Number of logged events read from storage at a time.
+pub(crate) const EVENT_PAGE_LEN: usize = 256;
 
 use std::{
-    collections::HashMap,
-    fmt::Debug,
+    collections::{HashMap, HashSet},
+    fmt::{self, Debug},
     future::Future,
     pin::Pin,
-    sync::{Mutex, PoisonError},
+    sync::{Arc, Mutex, PoisonError, Weak},
     task::{Context, Poll},
     time::Duration,
 };
 
+#[cfg(feature = "bincode")]
+use bincode::{Decode, Encode};
 use indexmap::IndexMap;
-use log::warn;
+use log::{debug, error, warn};
+use serde_json::Value;
 use thiserror::Error;
 use tokio::{
-    sync::mpsc::{channel, error::TryRecvError, Receiver, Sender},
+    sync::{
+        mpsc::{
+            channel,
+            error::{TryRecvError, TrySendError},
+            Receiver, Sender,
+        },
+        Mutex as AsyncMutex, MutexGuard as AsyncMutexGuard,
+    },
     time::error::Elapsed,
 };
 
-use crate::{Invoice, InvoiceId};
+use crate::{
+    invoice::Transfer,
+    storage::{AsyncStorage, StorageError},
+    Amount, Invoice, InvoiceId,
+};
 
 /// A means of receiving updates on a given invoice. Subscribers are returned by
 /// [`PaymentGateways`](crate::PaymentGateway) when subscribing to a invoice.
-pub struct Subscriber(Receiver<Invoice>);
+///
+/// A `Subscriber` receives a full [`Invoice`] snapshot whenever anything about
+/// the invoice changes. A `Subscriber<InvoiceEvent>`, returned by
+/// [`subscribe_events()`](crate::PaymentGateway::subscribe_events), instead
+/// receives [`InvoiceEvent`]s describing what changed. To migrate, replace
+/// `subscribe()` with `subscribe_events()`, and use
+/// [`InvoiceEvent::invoice()`] wherever the snapshot is still needed.
+///
+/// Up to 2048 updates are queued for a subscriber. Once its backlog is full,
+/// the payment gateway waits for the subscriber to catch up before publishing
+/// more updates, so a subscriber which stops receiving them stalls the scanner
+/// and invoice creation. In particular, don't wait on the payment gateway to
+/// create, remove or archive invoices in the same task that receives updates.
+pub struct Subscriber<T = Invoice>(Receiver<T>);
 
-impl Subscriber {
-    pub(crate) fn new(receiver: Receiver<Invoice>) -> Subscriber {
+impl<T> Subscriber<T> {
+    pub(crate) fn new(receiver: Receiver<T>) -> Subscriber<T> {
         Subscriber(receiver)
     }
 
     /// Waits for a invoice update from this subscriber.
     ///
     /// Returns `None` if the channel is closed.
-    pub async fn recv(&mut self) -> Option<Invoice> {
+    pub async fn recv(&mut self) -> Option<T> {
         self.0.recv().await
     }
 
@@ -46,7 +76,7 @@ impl Subscriber {
     /// # Panics
     ///
     /// This function panics if called within an asynchronous execution context.
-    pub fn blocking_recv(&mut self) -> Option<Invoice> {
+    pub fn blocking_recv(&mut self) -> Option<T> {
         self.0.blocking_recv()
     }
 
@@ -56,7 +86,7 @@ impl Subscriber {
     /// # Errors
     ///
     /// Returns an error if the channel is closed or if there is no update.
-    pub fn try_recv(&mut self) -> Result<Invoice, SubscriberError> {
+    pub fn try_recv(&mut self) -> Result<T, SubscriberError> {
         Ok(self.0.try_recv()?)
     }
 
@@ -67,25 +97,475 @@ impl Subscriber {
     /// # Errors
     ///
     /// Returns an error if no update is received in time.
-    pub async fn recv_timeout(
-        &mut self,
-        timeout: Duration,
-    ) -> Result<Option<Invoice>, SubscriberError> {
+    pub async fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<T>, SubscriberError> {
         Ok(tokio::time::timeout(timeout, self.0.recv()).await?)
     }
 }
 
-impl Future for Subscriber {
-    type Output = Option<Invoice>;
+impl<T> Future for Subscriber<T> {
+    type Output = Option<T>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         self.0.poll_recv(cx)
     }
 }
 
+/// Something that happened to an invoice, along with the invoice's state
+/// afterwards. Received from a `Subscriber<InvoiceEvent>`.
+///
+/// A single invoice update may produce several events. For example, a transfer
+/// which pays an invoice in full produces a [`TransferDetected`] event followed
+/// by a [`Paid`] event.
+///
+/// [`TransferDetected`]: InvoiceEvent::TransferDetected
+/// [`Paid`]: InvoiceEvent::Paid
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "bincode", derive(Encode, Decode))]
+pub enum InvoiceEvent {
+    /// The invoice was created.
+    Created {
+        /// The new invoice.
+        invoice: Invoice,
+    },
+    /// A transfer to the invoice's subaddress was found in the txpool or in a
+    /// block.
+    TransferDetected {
+        /// The updated invoice.
+        invoice: Invoice,
+        /// Amount transferred.
+        amount: Amount,
+        /// Height of the block containing the transfer, or `None` if it is in
+        /// the txpool.
+        height: Option<u64>,
+    },
+    /// A transfer previously seen in the txpool was included in a block.
+    TransferConfirmed {
+        /// The updated invoice.
+        invoice: Invoice,
+        /// Amount transferred.
+        amount: Amount,
+        /// Height of the block containing the transfer.
+        height: u64,
+    },
+    /// The invoice has been paid in full.
+    Paid {
+        /// The updated invoice.
+        invoice: Invoice,
+    },
+    /// The invoice has received the required number of confirmations.
+    Confirmed {
+        /// The updated invoice.
+        invoice: Invoice,
+    },
+    /// The invoice reached its expiration height.
+    Expired {
+        /// The updated invoice.
+        invoice: Invoice,
+    },
+    /// The invoice was removed, and will receive no further updates.
+    Removed {
+        /// The invoice as it was when removed.
+        invoice: Invoice,
+    },
+    /// Transfers previously credited to the invoice are gone, because of a
+    /// blockchain reorganization or because they were dropped from the txpool.
+    Reorged {
+        /// The updated invoice.
+        invoice: Invoice,
+        /// Total amount of the transfers no longer credited.
+        amount: Amount,
+    },
+    /// The invoice changed in a way no other event describes. Changes to only
+    /// the invoice's current height, and so its number of confirmations, are
+    /// not events, and are only sent to `Invoice` subscribers.
+    Amended {
+        /// The updated invoice.
+        invoice: Invoice,
+    },
+}
+
+impl InvoiceEvent {
+    /// Returns the kind of event this is.
+    #[must_use]
+    pub fn kind(&self) -> InvoiceEventKind {
+        match self {
+            InvoiceEvent::Created { .. } => InvoiceEventKind::Created,
+            InvoiceEvent::TransferDetected { .. } => InvoiceEventKind::TransferDetected,
+            InvoiceEvent::TransferConfirmed { .. } => InvoiceEventKind::TransferConfirmed,
+            InvoiceEvent::Paid { .. } => InvoiceEventKind::Paid,
+            InvoiceEvent::Confirmed { .. } => InvoiceEventKind::Confirmed,
+            InvoiceEvent::Expired { .. } => InvoiceEventKind::Expired,
+            InvoiceEvent::Removed { .. } => InvoiceEventKind::Removed,
+            InvoiceEvent::Reorged { .. } => InvoiceEventKind::Reorged,
+            InvoiceEvent::Amended { .. } => InvoiceEventKind::Amended,
+        }
+    }
+
+    /// Returns the invoice's state after the event.
+    #[must_use]
+    pub fn invoice(&self) -> &Invoice {
+        match self {
+            InvoiceEvent::Created { invoice }
+            | InvoiceEvent::TransferDetected { invoice, .. }
+            | InvoiceEvent::TransferConfirmed { invoice, .. }
+            | InvoiceEvent::Paid { invoice }
+            | InvoiceEvent::Confirmed { invoice }
+            | InvoiceEvent::Expired { invoice }
+            | InvoiceEvent::Removed { invoice }
+            | InvoiceEvent::Reorged { invoice, .. }
+            | InvoiceEvent::Amended { invoice } => invoice,
+        }
+    }
+
+    /// Returns a mutable reference to the invoice's state after the event.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn invoice_mut(&mut self) -> &mut Invoice {
+        match self {
+            InvoiceEvent::Created { invoice }
+            | InvoiceEvent::TransferDetected { invoice, .. }
+            | InvoiceEvent::TransferConfirmed { invoice, .. }
+            | InvoiceEvent::Paid { invoice }
+            | InvoiceEvent::Confirmed { invoice }
+            | InvoiceEvent::Expired { invoice }
+            | InvoiceEvent::Removed { invoice }
+            | InvoiceEvent::Reorged { invoice, .. }
+            | InvoiceEvent::Amended { invoice } => invoice,
+        }
+    }
+
+    /// Returns the invoice's state after the event, consuming the event.
+    #[must_use]
+    pub fn into_invoice(self) -> Invoice {
+        match self {
+            InvoiceEvent::Created { invoice }
+            | InvoiceEvent::TransferDetected { invoice, .. }
+            | InvoiceEvent::TransferConfirmed { invoice, .. }
+            | InvoiceEvent::Paid { invoice }
+            | InvoiceEvent::Confirmed { invoice }
+            | InvoiceEvent::Expired { invoice }
+            | InvoiceEvent::Removed { invoice }
+            | InvoiceEvent::Reorged { invoice, .. }
+            | InvoiceEvent::Amended { invoice } => invoice,
+        }
+    }
+
+    /// Describe the difference between an invoice's old and new state as a
+    /// list of events. Returns an empty list if only the invoice's current
+    /// height changed, since there is nothing worth logging.
+    pub(crate) fn from_update(old: Option<&Invoice>, new: &Invoice) -> Vec<InvoiceEvent> {
+        let Some(old) = old else {
+            return vec![InvoiceEvent::Amended {
+                invoice: new.clone(),
+            }];
+        };
+
+        // Transfers present only in the old or new invoice.
+        let mut removed: Vec<Transfer> = old.transfers.clone();
+        let mut added = Vec::new();
+        for transfer in &new.transfers {
+            match removed.iter().position(|t| t == transfer) {
+                Some(i) => {
+                    removed.swap_remove(i);
+                }
+                None => added.push(*transfer),
+            }
+        }
+
+        let mut events = Vec::new();
+        let mut detected = Vec::new();
+        for transfer in added {
+            // A transfer which was in the txpool is now in a block. Transfers
+            // recorded before transaction hashes were can only be paired by
+            // amount.
+            let from_txpool = transfer.height.and_then(|height| {
+                removed
+                    .iter()
+                    .position(|t| {
+                        t.height.is_none()
+                            && t.amount == transfer.amount
+                            && (t.tx_hash.is_none()
+                                || transfer.tx_hash.is_none()
+                                || t.tx_hash == transfer.tx_hash)
+                    })
+                    .map(|i| (i, height))
+            });
+            match from_txpool {
+                Some((i, height)) => {
+                    removed.swap_remove(i);
+                    events.push(InvoiceEvent::TransferConfirmed {
+                        invoice: new.clone(),
+                        amount: Amount::from_pico(transfer.amount),
+                        height,
+                    });
+                }
+                None => detected.push(InvoiceEvent::TransferDetected {
+                    invoice: new.clone(),
+                    amount: Amount::from_pico(transfer.amount),
+                    height: transfer.height,
+                }),
+            }
+        }
+        if !removed.is_empty() {
+            events.insert(
+                0,
+                InvoiceEvent::Reorged {
+                    invoice: new.clone(),
+                    amount: removed.iter().fold(Amount::ZERO, |total, transfer| {
+                        total.saturating_add(Amount::from_pico(transfer.amount))
+                    }),
+                },
+            );
+        }
+        events.append(&mut detected);
+
+        if !old.is_paid() && new.is_paid() {
+            events.push(InvoiceEvent::Paid {
+                invoice: new.clone(),
+            });
+        }
+        if !old.is_confirmed() && new.is_confirmed() {
+            events.push(InvoiceEvent::Confirmed {
+                invoice: new.clone(),
+            });
+        }
+        if !old.is_expired() && new.is_expired() {
+            events.push(InvoiceEvent::Expired {
+                invoice: new.clone(),
+            });
+        }
+        if events.is_empty() {
+            let mut old_at_new_height = old.clone();
+            old_at_new_height.current_height = new.current_height;
+            if old_at_new_height != *new {
+                events.push(InvoiceEvent::Amended {
+                    invoice: new.clone(),
+                });
+            }
+        }
+        events
+    }
+}
+
+/// An [`InvoiceEvent`] along with its position in the payment gateway's event
+/// log. Received from a `Subscriber<SequencedEvent>`, returned by
+/// [`subscribe_from()`](crate::PaymentGateway::subscribe_from).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequencedEvent {
+    /// Sequence number of the event. Sequence numbers increase with every
+    /// event logged, and are never reused.
+    pub sequence: u64,
+    /// The event.
+    pub event: InvoiceEvent,
+}
+
+/// The kind of an [`InvoiceEvent`], without the data it carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InvoiceEventKind {
+    /// See [`InvoiceEvent::Created`].
+    Created,
+    /// See [`InvoiceEvent::TransferDetected`].
+    TransferDetected,
+    /// See [`InvoiceEvent::TransferConfirmed`].
+    TransferConfirmed,
+    /// See [`InvoiceEvent::Paid`].
+    Paid,
+    /// See [`InvoiceEvent::Confirmed`].
+    Confirmed,
+    /// See [`InvoiceEvent::Expired`].
+    Expired,
+    /// See [`InvoiceEvent::Removed`].
+    Removed,
+    /// See [`InvoiceEvent::Reorged`].
+    Reorged,
+    /// See [`InvoiceEvent::Amended`].
+    Amended,
+}
+
+/// Selects which [`InvoiceEvent`]s a filtered subscription receives. Events
+/// must pass every criterion set. A filter with no criteria passes every
+/// event.
+///
+/// Filters are evaluated before events are queued, so events a subscriber is
+/// not interested in don't accumulate while it is busy.
+///
+/// # Examples
+///
+/// ```
+/// use acceptxmr::{Amount, InvoiceEventKind, SubscriptionFilter};
+///
+/// // Confirmed invoices from account 1 which were paid at least 1 XMR.
+/// let filter = SubscriptionFilter::new()
+///     .event_kinds([InvoiceEventKind::Confirmed])
+///     .min_amount_paid(Amount::ONE_XMR)
+///     .account_index(1);
+/// ```
+#[derive(Clone, Default)]
+pub struct SubscriptionFilter {
+    kinds: Option<HashSet<InvoiceEventKind>>,
+    min_amount_paid: Option<Amount>,
+    metadata_key: Option<String>,
+    account_indices: Option<HashSet<u32>>,
+    predicates: Vec<Arc<EventPredicate>>,
+}
+
+type EventPredicate = dyn Fn(&InvoiceEvent) -> bool + Send + Sync;
+
+impl SubscriptionFilter {
+    /// Create a filter which passes every event.
+    #[must_use]
+    pub fn new() -> SubscriptionFilter {
+        SubscriptionFilter::default()
+    }
+
+    /// Only pass events of the given kinds. Calling this again adds to the
+    /// kinds passed.
+    #[must_use]
+    pub fn event_kinds(mut self, kinds: impl IntoIterator<Item = InvoiceEventKind>) -> Self {
+        self.kinds.get_or_insert_with(HashSet::new).extend(kinds);
+        self
+    }
+
+    /// Only pass events for invoices which have been paid at least `amount`.
+    #[must_use]
+    pub fn min_amount_paid(mut self, amount: impl Into<Amount>) -> Self {
+        self.min_amount_paid = Some(amount.into());
+        self
+    }
+
+    /// Only pass events for invoices whose description is a JSON object with
+    /// the given top-level key, such as the `order` or `callback` recorded by
+    /// `AcceptXMR-Server`.
+    #[must_use]
+    pub fn metadata_key(mut self, key: impl Into<String>) -> Self {
+        self.metadata_key = Some(key.into());
+        self
+    }
+
+    /// Only pass events for invoices allocated from the given account. Calling
+    /// this again adds to the accounts passed.
+    #[must_use]
+    pub fn account_index(mut self, account_index: u32) -> Self {
+        self.account_indices
+            .get_or_insert_with(HashSet::new)
+            .insert(account_index);
+        self
+    }
+
+    /// Only pass events for which `predicate` returns `true`. Calling this
+    /// again adds another predicate, which must also return `true`.
+    ///
+    /// The predicate is called on the payment gateway's scanning task, so it
+    /// should return quickly.
+    #[must_use]
+    pub fn predicate(
+        mut self,
+        predicate: impl Fn(&InvoiceEvent) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    /// Returns `true` if the event passes this filter.
+    #[must_use]
+    pub fn matches(&self, event: &InvoiceEvent) -> bool {
+        let invoice = event.invoice();
+        self.kinds.iter().all(|kinds| kinds.contains(&event.kind()))
+            && self
+                .min_amount_paid
+                .iter()
+                .all(|&amount| invoice.amount_paid() >= amount)
+            && self
+                .account_indices
+                .iter()
+                .all(|accounts| accounts.contains(&invoice.account_index()))
+            && self.metadata_key.iter().all(|key| {
+                serde_json::from_str::<Value>(invoice.description())
+                    .is_ok_and(|metadata| metadata.get(key).is_some())
+            })
+            && self.predicates.iter().all(|predicate| predicate(event))
+    }
+}
+
+impl Debug for SubscriptionFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubscriptionFilter")
+            .field("kinds", &self.kinds)
+            .field("min_amount_paid", &self.min_amount_paid)
+            .field("metadata_key", &self.metadata_key)
+            .field("account_indices", &self.account_indices)
+            .field("predicates", &self.predicates.len())
+            .finish()
+    }
+}
+
+/// The sending half of a [`Subscriber`].
+#[derive(Clone)]
+enum Sink {
+    Snapshots(Sender<Invoice>),
+    Events {
+        sender: Sender<InvoiceEvent>,
+        filter: Option<Arc<SubscriptionFilter>>,
+    },
+    Sequenced(Sender<SequencedEvent>),
+}
+
+impl Sink {
+    /// Send the snapshot, the events or the logged events, whichever this
+    /// sink accepts. Snapshots and events wait for room in the subscriber's
+    /// backlog, but logged events don't, so that replaying the log can't stall
+    /// publishing. Returns `false` if the subscriber has been dropped, or has
+    /// fallen so far behind the log that it should be dropped.
+    async fn send(
+        &self,
+        snapshot: Option<&Invoice>,
+        events: &[InvoiceEvent],
+        logged: &[SequencedEvent],
+    ) -> bool {
+        match self {
+            Sink::Snapshots(sender) => match snapshot {
+                Some(invoice) => sender.send(invoice.clone()).await.is_ok(),
+                None => !sender.is_closed(),
+            },
+            Sink::Events { sender, filter } => {
+                let events = events
+                    .iter()
+                    .filter(|event| filter.iter().all(|filter| filter.matches(event)));
+                for event in events {
+                    if sender.send(event.clone()).await.is_err() {
+                        return false;
+                    }
+                }
+                true
+            }
+            Sink::Sequenced(sender) => {
+                logged.iter().all(|event| try_send(sender, event.clone())) && !sender.is_closed()
+            }
+        }
+    }
+}
+
+/// Queue `message` for a subscriber replaying the event log. Returns `false` if
+/// the subscriber has been dropped or its backlog is full.
+fn try_send<T>(sender: &Sender<T>, message: T) -> bool {
+    match sender.try_send(message) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            debug!(
+                "Event log subscriber fell more than {SUBSCRIPTION_BUFFER_LEN} events behind; \
+                it will catch up from the log."
+            );
+            false
+        }
+        Err(TrySendError::Closed(_)) => false,
+    }
+}
+
 pub(crate) struct Publisher {
-    invoice_subs: Mutex<HashMap<InvoiceId, IndexMap<SenderId, Sender<Invoice>>>>,
-    global_subs: Mutex<IndexMap<SenderId, Sender<Invoice>>>,
+    invoice_subs: Mutex<HashMap<InvoiceId, IndexMap<SenderId, Sink>>>,
+    global_subs: Mutex<IndexMap<SenderId, Sink>>,
+    /// Held while logging and publishing events, so that events are published
+    /// in the order of their sequence numbers.
+    event_log: AsyncMutex<()>,
 }
 
 impl Publisher {
@@ -93,31 +573,117 @@ impl Publisher {
         Publisher {
             invoice_subs: Mutex::new(HashMap::new()),
             global_subs: Mutex::new(IndexMap::new()),
+            event_log: AsyncMutex::new(()),
         }
     }
 
     pub(crate) fn subscribe(&self, invoice_id: InvoiceId) -> Option<Subscriber> {
         let (tx, rx) = channel(SUBSCRIPTION_BUFFER_LEN);
-        let mut invoice_subs = self
-            .invoice_subs
-            .lock()
-            .unwrap_or_else(PoisonError::into_inner);
-        invoice_subs
-            .get_mut(&invoice_id)?
-            .insert(SenderId::new(), tx);
+        self.insert_sink(Some(invoice_id), Sink::Snapshots(tx))?;
         Some(Subscriber::new(rx))
     }
 
     pub(crate) fn subscribe_all(&self) -> Subscriber {
         let (tx, rx) = channel(SUBSCRIPTION_BUFFER_LEN);
-        let mut global_subs = self
-            .global_subs
-            .lock()
-            .unwrap_or_else(PoisonError::into_inner);
-        global_subs.insert(SenderId::new(), tx);
+        self.insert_sink(None, Sink::Snapshots(tx));
+        Subscriber::new(rx)
+    }
+
+    pub(crate) fn subscribe_events(
+        &self,
+        invoice_id: InvoiceId,
+    ) -> Option<Subscriber<InvoiceEvent>> {
+        let (tx, rx) = channel(SUBSCRIPTION_BUFFER_LEN);
+        self.insert_sink(
+            Some(invoice_id),
+            Sink::Events {
+                sender: tx,
+                filter: None,
+            },
+        )?;
+        Some(Subscriber::new(rx))
+    }
+
+    pub(crate) fn subscribe_all_events(&self) -> Subscriber<InvoiceEvent> {
+        let (tx, rx) = channel(SUBSCRIPTION_BUFFER_LEN);
+        self.insert_sink(
+            None,
+            Sink::Events {
+                sender: tx,
+                filter: None,
+            },
+        );
         Subscriber::new(rx)
     }
 
+    pub(crate) fn subscribe_filtered(
+        &self,
+        filter: SubscriptionFilter,
+    ) -> Subscriber<InvoiceEvent> {
+        let (tx, rx) = channel(SUBSCRIPTION_BUFFER_LEN);
+        self.insert_sink(
+            None,
+            Sink::Events {
+                sender: tx,
+                filter: Some(Arc::new(filter)),
+            },
+        );
+        Subscriber::new(rx)
+    }
+
+    /// Replay logged events with a sequence number of at least `sequence`,
+    /// then deliver new events as they are logged. The log is read a page at
+    /// a time, and read again if the subscriber falls too far behind to be
+    /// sent new events directly, so that it misses none.
+    pub(crate) async fn subscribe_from<S: AsyncStorage>(
+        self: &Arc<Self>,
+        store: &S,
+        sequence: u64,
+    ) -> Result<Subscriber<SequencedEvent>, StorageError> {
+        // Subscribe to live events before reading the log, so that no event
+        // falls between the two. Events in both are skipped below.
+        let live_rx = self.subscribe_sequenced();
+        let page = store.get_events_from(sequence, EVENT_PAGE_LEN).await?;
+
+        let (tx, rx) = channel(SUBSCRIPTION_BUFFER_LEN);
+        let publisher = Arc::downgrade(self);
+        let store = store.clone();
+        tokio::spawn(async move {
+            let mut replay = Replay {
+                tx,
+                next_sequence: sequence,
+            };
+            replay.run(&publisher, &store, live_rx, page).await;
+        });
+        Ok(Subscriber::new(rx))
+    }
+
+    /// Add a sink receiving every logged event as it is published.
+    fn subscribe_sequenced(&self) -> Receiver<SequencedEvent> {
+        let (tx, rx) = channel(SUBSCRIPTION_BUFFER_LEN);
+        self.insert_sink(None, Sink::Sequenced(tx));
+        rx
+    }
+
+    /// Add a sink for the given invoice, or for all invoices if `invoice_id`
+    /// is `None`. Returns `None` if the invoice is not tracked.
+    fn insert_sink(&self, invoice_id: Option<InvoiceId>, sink: Sink) -> Option<()> {
+        if let Some(id) = invoice_id {
+            let mut invoice_subs = self
+                .invoice_subs
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            invoice_subs.get_mut(&id)?.insert(SenderId::new(), sink);
+        } else {
+            let mut global_subs = self
+                .global_subs
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            global_subs.insert(SenderId::new(), sink);
+        }
+        Some(())
+    }
+
     pub(crate) fn insert_invoice(&self, invoice_id: InvoiceId) {
         let mut invoice_subs = self
             .invoice_subs
@@ -139,44 +705,15 @@ impl Publisher {
         invoice_subs.remove(&invoice_id);
     }
 
-    pub(crate) async fn send_updates(&self, invoice: &Invoice) {
-        let mut index = 0;
-        let mut sender_id;
-        let mut closed = false;
-        loop {
-            match self.get_sender_by_index(Some(invoice.id()), index) {
-                Some((id, sender)) => {
-                    sender_id = id;
-                    if sender.send(invoice.clone()).await.is_err() {
-                        closed = true;
-                    }
-                }
-                None => break,
-            }
-            if closed {
-                self.remove_sender(Some(invoice.id()), sender_id);
-            } else {
-                index += 1;
-            }
-        }
-
-        index = 0;
-        closed = false;
-        loop {
-            match self.get_sender_by_index(None, index) {
-                Some((id, sender)) => {
-                    sender_id = id;
-                    if sender.send(invoice.clone()).await.is_err() {
-                        closed = true;
-                    }
-                }
-                None => break,
-            }
-            if closed {
-                self.remove_sender(None, sender_id);
-            } else {
-                index += 1;
-            }
+    /// Lock the event log, so that events can be logged and then published
+    /// in the order of their sequence numbers. Changes to invoices should be
+    /// saved while the lock is held, after their events are logged.
+    pub(crate) async fn event_log(&self) -> EventLog<'_> {
+        EventLog {
+            publisher: self,
+            _guard: self.event_log.lock().await,
+            events: Vec::new(),
+            logged: Vec::new(),
         }
     }
 
@@ -184,7 +721,7 @@ impl Publisher {
         &self,
         invoice_id: Option<InvoiceId>,
         index: usize,
-    ) -> Option<(SenderId, Sender<Invoice>)> {
+    ) -> Option<(SenderId, Sink)> {
         if let Some(id) = invoice_id {
             let mut invoice_subs = self
                 .invoice_subs
@@ -205,7 +742,8 @@ impl Publisher {
         }
     }
 
-    /// It's important that this function is only called within `send_updates`,
+    /// It's important that this function is only called within
+    /// [`EventLog::publish()`],
     /// because changing the order of senders could cause some [`Subscriber`]s
     /// to miss updates if done at the wrong time.
     fn remove_sender(&self, invoice_id: Option<InvoiceId>, sender_id: SenderId) {
@@ -229,6 +767,143 @@ impl Publisher {
     }
 }
 
+/// Exclusive access to the event log, returned by
+/// [`Publisher::event_log()`]. Events are logged before the change they
+/// describe is saved, so that no saved change goes unlogged, and are published
+/// once it is saved. Dropping an `EventLog` without publishing its events
+/// leaves them logged, to be logged again when the change is retried.
+pub(crate) struct EventLog<'a> {
+    publisher: &'a Publisher,
+    _guard: AsyncMutexGuard<'a, ()>,
+    events: Vec<InvoiceEvent>,
+    logged: Vec<SequencedEvent>,
+}
+
+impl EventLog<'_> {
+    /// Append events to the log, to be published by
+    /// [`publish()`](EventLog::publish).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the events could not be logged, in which
+    /// case the change they describe should not be saved.
+    pub(crate) async fn log<S: AsyncStorage>(
+        &mut self,
+        store: &S,
+        events: Vec<InvoiceEvent>,
+    ) -> Result<(), StorageError> {
+        for event in &events {
+            let sequence = store.append_event(event.clone()).await?;
+            self.logged.push(SequencedEvent {
+                sequence,
+                event: event.clone(),
+            });
+        }
+        self.events.extend(events);
+        Ok(())
+    }
+
+    /// Publish the logged events and an updated snapshot of the invoice, if
+    /// there is one, to every subscriber of the invoice and every global
+    /// subscriber. Subscribers which have been dropped are removed.
+    pub(crate) async fn publish(self, invoice_id: InvoiceId, snapshot: Option<&Invoice>) {
+        let publisher = self.publisher;
+        for subs in [Some(invoice_id), None] {
+            let mut index = 0;
+            while let Some((sender_id, sink)) = publisher.get_sender_by_index(subs, index) {
+                if sink.send(snapshot, &self.events, &self.logged).await {
+                    index += 1;
+                } else {
+                    publisher.remove_sender(subs, sender_id);
+                }
+            }
+        }
+    }
+}
+
+/// Delivers logged events to a subscriber created by
+/// [`Publisher::subscribe_from()`], first from the log, then as they are
+/// published.
+struct Replay {
+    tx: Sender<SequencedEvent>,
+    /// Sequence number of the next event the subscriber needs.
+    next_sequence: u64,
+}
+
+impl Replay {
+    /// Deliver events until the subscriber is dropped, the payment gateway is
+    /// dropped, or the log can't be read. `page` holds the first page of the
+    /// log, read after subscribing to `live_rx`.
+    async fn run<S: AsyncStorage>(
+        &mut self,
+        publisher: &Weak<Publisher>,
+        store: &S,
+        mut live_rx: Receiver<SequencedEvent>,
+        mut page: Vec<(u64, InvoiceEvent)>,
+    ) {
+        loop {
+            // Catch up from the log.
+            loop {
+                let last_page = page.len() < EVENT_PAGE_LEN;
+                for (sequence, event) in page {
+                    if !self.send(SequencedEvent { sequence, event }).await {
+                        return;
+                    }
+                }
+                if last_page {
+                    break;
+                }
+                page = match store
+                    .get_events_from(self.next_sequence, EVENT_PAGE_LEN)
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(e) => {
+                        error!("Failed to read event log, ending subscription: {}", e);
+                        return;
+                    }
+                };
+            }
+
+            // Then deliver events as they are published, until the live sink
+            // is dropped for falling behind.
+            while let Some(event) = live_rx.recv().await {
+                if !self.send(event).await {
+                    return;
+                }
+            }
+
+            // Resubscribe before reading the log again, so that no event falls
+            // between the two.
+            let Some(publisher) = publisher.upgrade() else {
+                return;
+            };
+            live_rx = publisher.subscribe_sequenced();
+            drop(publisher);
+            page = match store
+                .get_events_from(self.next_sequence, EVENT_PAGE_LEN)
+                .await
+            {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("Failed to read event log, ending subscription: {}", e);
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Send an event to the subscriber unless it has already been sent.
+    /// Returns `false` if the subscriber has been dropped.
+    async fn send(&mut self, event: SequencedEvent) -> bool {
+        if event.sequence < self.next_sequence {
+            return true;
+        }
+        self.next_sequence = event.sequence + 1;
+        self.tx.send(event).await.is_ok()
+    }
+}
+
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 struct SenderId(u128);
 
@@ -248,3 +923,205 @@ pub enum SubscriberError {
     #[error("subscriber try recv failed: {0}")]
     TryRecv(#[from] TryRecvError),
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::sync::mpsc::channel;
+
+    use super::{
+        try_send, InvoiceEvent, InvoiceEventKind, Publisher, SubscriptionFilter,
+        SUBSCRIPTION_BUFFER_LEN,
+    };
+    use crate::{
+        invoice::Transfer,
+        storage::{stores::InMemory, Client},
+        Amount, Invoice, SubIndex,
+    };
+
+    fn invoice(transfers: &[Transfer], current_height: u64) -> Invoice {
+        let mut invoice = Invoice::new(
+            "testAddress".to_string(),
+            "testWallet".to_string(),
+            SubIndex::new(0, 1),
+            10,
+            Amount::from_pico(1_000),
+            2,
+            5,
+            "test_description".to_string(),
+        );
+        invoice.transfers = transfers.to_vec();
+        invoice.current_height = current_height;
+        invoice.recalculate_amount_paid();
+        invoice
+    }
+
+    fn kinds(events: &[InvoiceEvent]) -> Vec<InvoiceEventKind> {
+        events.iter().map(InvoiceEvent::kind).collect()
+    }
+
+    #[test]
+    fn from_update() {
+        let unpaid = invoice(&[], 10);
+        let in_txpool = invoice(&[Transfer::new(1_000, None)], 10);
+        let mined = invoice(&[Transfer::new(1_000, Some(10))], 11);
+        let confirmed = invoice(&[Transfer::new(1_000, Some(10))], 12);
+        let expired = invoice(&[], 15);
+
+        let events = InvoiceEvent::from_update(Some(&unpaid), &in_txpool);
+        assert_eq!(
+            kinds(&events),
+            [InvoiceEventKind::TransferDetected, InvoiceEventKind::Paid]
+        );
+        assert_eq!(
+            events[0],
+            InvoiceEvent::TransferDetected {
+                invoice: in_txpool.clone(),
+                amount: Amount::from_pico(1_000),
+                height: None,
+            }
+        );
+
+        let events = InvoiceEvent::from_update(Some(&in_txpool), &mined);
+        assert_eq!(kinds(&events), [InvoiceEventKind::TransferConfirmed]);
+        assert_eq!(
+            events[0],
+            InvoiceEvent::TransferConfirmed {
+                invoice: mined.clone(),
+                amount: Amount::from_pico(1_000),
+                height: 10,
+            }
+        );
+
+        let events = InvoiceEvent::from_update(Some(&mined), &confirmed);
+        assert_eq!(kinds(&events), [InvoiceEventKind::Confirmed]);
+
+        let events = InvoiceEvent::from_update(Some(&in_txpool), &unpaid);
+        assert_eq!(
+            events,
+            [InvoiceEvent::Reorged {
+                invoice: unpaid.clone(),
+                amount: Amount::from_pico(1_000),
+            }]
+        );
+
+        let events = InvoiceEvent::from_update(Some(&unpaid), &expired);
+        assert_eq!(kinds(&events), [InvoiceEventKind::Expired]);
+
+        // Height changes alone aren't events.
+        assert!(InvoiceEvent::from_update(Some(&unpaid), &invoice(&[], 11)).is_empty());
+        let still_confirmed = invoice(&[Transfer::new(1_000, Some(10))], 13);
+        assert!(InvoiceEvent::from_update(Some(&confirmed), &still_confirmed).is_empty());
+
+        let events = InvoiceEvent::from_update(None, &unpaid);
+        assert_eq!(kinds(&events), [InvoiceEventKind::Amended]);
+    }
+
+    #[test]
+    fn from_update_pairs_transfers_by_tx_hash() {
+        let in_txpool = invoice(&[Transfer::in_tx([1; 32], 1_000, None)], 10);
+        // A different transaction of the same amount was mined, and the first
+        // was dropped from the txpool.
+        let other_mined = invoice(&[Transfer::in_tx([2; 32], 1_000, Some(10))], 11);
+        let mined = invoice(&[Transfer::in_tx([1; 32], 1_000, Some(10))], 11);
+
+        let events = InvoiceEvent::from_update(Some(&in_txpool), &other_mined);
+        assert_eq!(
+            kinds(&events),
+            [
+                InvoiceEventKind::Reorged,
+                InvoiceEventKind::TransferDetected
+            ]
+        );
+
+        let events = InvoiceEvent::from_update(Some(&in_txpool), &mined);
+        assert_eq!(kinds(&events), [InvoiceEventKind::TransferConfirmed]);
+
+        // Transfers recorded before hashes were are paired by amount.
+        let unhashed = invoice(&[Transfer::new(1_000, None)], 10);
+        let events = InvoiceEvent::from_update(Some(&unhashed), &other_mined);
+        assert_eq!(kinds(&events), [InvoiceEventKind::TransferConfirmed]);
+    }
+
+    #[tokio::test]
+    async fn lagging_replay_catches_up_from_log() {
+        let store = Client::new(InMemory::new());
+        let publisher = Arc::new(Publisher::new());
+        let mut subscriber = publisher.subscribe_from(&store, 0).await.unwrap();
+
+        // Publish more events than both the subscriber's backlog and its live
+        // sink can hold, so that the live sink is dropped.
+        let unpaid = invoice(&[], 10);
+        let count = 3 * SUBSCRIPTION_BUFFER_LEN as u64;
+        for _ in 0..count {
+            let mut event_log = publisher.event_log().await;
+            event_log
+                .log(
+                    &store,
+                    vec![InvoiceEvent::Amended {
+                        invoice: unpaid.clone(),
+                    }],
+                )
+                .await
+                .unwrap();
+            event_log.publish(unpaid.id(), None).await;
+        }
+
+        for sequence in 1..=count {
+            let logged = subscriber
+                .recv_timeout(Duration::from_secs(5))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(logged.sequence, sequence);
+        }
+    }
+
+    #[test]
+    fn lagging_subscriber_is_dropped() {
+        let (tx, mut rx) = channel(1);
+        assert!(try_send(&tx, 1));
+        assert!(!try_send(&tx, 2));
+        assert_eq!(rx.try_recv().unwrap(), 1);
+
+        drop(rx);
+        assert!(!try_send(&tx, 3));
+    }
+
+    #[test]
+    fn filter() {
+        let unpaid = invoice(&[], 10);
+        let paid = invoice(&[Transfer::new(1_000, None)], 10);
+        let event = |invoice: &Invoice| InvoiceEvent::Paid {
+            invoice: invoice.clone(),
+        };
+
+        assert!(SubscriptionFilter::new().matches(&event(&unpaid)));
+
+        let kinds = SubscriptionFilter::new().event_kinds([InvoiceEventKind::Confirmed]);
+        assert!(!kinds.matches(&event(&paid)));
+        let kinds = kinds.event_kinds([InvoiceEventKind::Paid]);
+        assert!(kinds.matches(&event(&paid)));
+
+        let amount = SubscriptionFilter::new().min_amount_paid(1_000);
+        assert!(amount.matches(&event(&paid)));
+        assert!(!amount.matches(&event(&unpaid)));
+
+        let account = SubscriptionFilter::new().account_index(1);
+        assert!(!account.matches(&event(&paid)));
+        assert!(account.account_index(0).matches(&event(&paid)));
+
+        let metadata = SubscriptionFilter::new().metadata_key("order");
+        assert!(!metadata.matches(&event(&paid)));
+        let mut with_order = paid.clone();
+        with_order.description = r#"{"order":"large pizza"}"#.to_string();
+        assert!(metadata.matches(&event(&with_order)));
+
+        let predicate = SubscriptionFilter::new()
+            .min_amount_paid(1_000)
+            .predicate(|event| event.invoice().current_height() > 10);
+        assert!(!predicate.matches(&event(&paid)));
+    }
+}
@@ -27,12 +27,21 @@ use crate::{
         Client as MonerodClient, RpcClient as MonerodRpcClient, RpcError as MonerodRpcError,
     },
     pubsub::Publisher,
-    storage::{Client as StorageClient, OutputId, OutputPubKey, Storage, StorageError},
-    Invoice, SubIndex,
+    storage::{AsyncStorage, OutputId, OutputPubKey, StorageError},
+    wallets::Wallets,
+    Invoice, InvoiceEvent, InvoiceId, SubIndex,
 };
 
-pub(crate) struct Scanner<S: Storage, M: MonerodClient = MonerodRpcClient> {
-    store: StorageClient<S>,
+/// Outputs of a transaction owned by a wallet, along with the transaction's
+/// hash and the wallet's primary address.
+type OwnedOutputs<'a> = (monero::Hash, &'a str, Vec<OwnedTxOut<'a>>);
+
+/// Minimum number of blocks between prunings of the output key store, so that
+/// it is not scanned for old keys after every block.
+const OUTPUT_KEY_PRUNE_INTERVAL: u64 = 720;
+
+pub(crate) struct Scanner<S: AsyncStorage, M: MonerodClient = MonerodRpcClient> {
+    store: S,
     // Block cache and txpool cache are mutexed to allow concurrent block &
     // txpool scanning. This is necessary even though txpool scanning doesn't
     // use the block cache, and vice versa, because rust doesn't allow mutably
@@ -40,19 +49,32 @@ pub(crate) struct Scanner<S: Storage, M: MonerodClient = MonerodRpcClient> {
     block_cache: AsyncMutex<BlockCache<M>>,
     txpool_cache: AsyncMutex<TxpoolCache<M>>,
     publisher: Arc<Publisher>,
+    wallets: Arc<Wallets>,
     first_scan: bool,
+    /// Number of blocks output keys are kept for, if they are pruned at all.
+    output_key_retention: Option<u64>,
+    /// Height at which output keys were last pruned.
+    last_key_prune: u64,
+    /// Invoice updates which couldn't be logged, by invoice ID. The transfers
+    /// they add are only found once, so they are applied again on the next
+    /// scan.
+    unsaved_updates: HashMap<InvoiceId, Invoice>,
 }
 
-impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
+impl<S: AsyncStorage, M: MonerodClient> Scanner<S, M> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn new(
         monerod_client: M,
-        store: StorageClient<S>,
+        store: S,
         block_cache_size: usize,
+        block_fetch_concurrency: usize,
         atomic_cache_height: Arc<AtomicU64>,
         atomic_daemon_height: Arc<AtomicU64>,
         // Optionally specify the height to start scanning from.
         initial_height: Option<u64>,
         publisher: Arc<Publisher>,
+        wallets: Arc<Wallets>,
+        output_key_retention: Option<u64>,
     ) -> Result<Scanner<S, M>, ScannerError> {
         trace!("Retrieving daemon height for scanner setup.");
 
@@ -76,6 +98,7 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
             BlockCache::init(
                 monerod_client.clone(),
                 block_cache_size,
+                block_fetch_concurrency,
                 atomic_cache_height,
                 atomic_daemon_height
             ),
@@ -94,22 +117,29 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
             block_cache: AsyncMutex::new(block_cache?),
             txpool_cache: AsyncMutex::new(txpool_cache?),
             publisher,
+            wallets,
             first_scan: true,
+            output_key_retention,
+            last_key_prune: 0,
+            unsaved_updates: HashMap::new(),
         })
     }
 
-    /// Scan for invoice updates.
+    /// Scan for invoice updates, checking outputs against each wallet's sub key
+    /// checker.
     pub(crate) async fn scan(
         &mut self,
-        sub_key_checker: &SubKeyChecker<'_>,
+        sub_key_checkers: &[(&str, SubKeyChecker<'_>)],
     ) -> Result<(), ScannerError> {
         // Update block and txpool caches.
         let (blocks_updated, new_transactions) = self.update_caches().await?;
+        // Outputs in the txpool are expected in the next block.
+        let txpool_height = self.block_cache.lock().await.height() + 1;
 
         // Scan block cache and new transactions in the txpool.
         let (blocks_amounts_or_err, txpool_amounts_or_err) = join!(
-            self.scan_blocks(sub_key_checker, blocks_updated),
-            self.scan_txpool(sub_key_checker, &new_transactions)
+            self.scan_blocks(sub_key_checkers, blocks_updated),
+            self.scan_txpool(sub_key_checkers, &new_transactions, txpool_height)
         );
 
         let blocks_amounts = match blocks_amounts_or_err {
@@ -135,49 +165,56 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
             self.first_scan = false;
         }
 
-        let updated_invoices = self.update_invoices(transfers, blocks_updated).await?;
+        let unsaved_updates = std::mem::take(&mut self.unsaved_updates);
+        let updated_invoices = self
+            .update_invoices(transfers, blocks_updated, unsaved_updates)
+            .await?;
 
-        // Save and log updates.
-        for invoice in updated_invoices {
-            debug!(
-                "Invoice update for subaddress index {}: \
-                    \n{}",
-                invoice.index(),
-                invoice
-            );
-            let result = self.store.update_invoice(invoice.clone()).await;
-            if let Err(e) = result {
-                error!(
-                    "Failed to save update to invoice for index {} to database: {}",
-                    invoice.index(),
-                    e
-                );
-            } else {
-                // If the update was successful, send an update down the
-                // subscriber channel.
-                self.publisher.send_updates(&invoice).await;
-                debug!(
-                    "Published invoice update for subaddress index {}",
-                    invoice.index()
-                );
-            }
-        }
+        self.unsaved_updates = save_and_publish(&self.store, &self.publisher, updated_invoices)
+            .await
+            .into_iter()
+            .map(|invoice| (invoice.id(), invoice))
+            .collect();
 
         // Update last scanned height in the database.
         let cache_height = self.block_cache.lock().await.height();
         self.store.upsert_height(cache_height).await?;
 
+        self.prune_output_keys(cache_height).await?;
+
         // Flush changes to the database.
         self.store.flush().await?;
 
         Ok(())
     }
 
+    /// Remove output keys recorded more than the retention depth below
+    /// `cache_height`, if a retention depth is set and enough blocks have
+    /// passed since they were last pruned.
+    async fn prune_output_keys(&mut self, cache_height: u64) -> Result<(), ScannerError> {
+        let Some(retention) = self.output_key_retention else {
+            return Ok(());
+        };
+        if cache_height < self.last_key_prune + OUTPUT_KEY_PRUNE_INTERVAL {
+            return Ok(());
+        }
+
+        let prune_height = cache_height.saturating_sub(retention);
+        let pruned = self.store.prune_output_keys(prune_height).await?;
+        debug!("Pruned {pruned} output keys recorded below height {prune_height}");
+        self.last_key_prune = cache_height;
+        Ok(())
+    }
+
+    /// Apply the transfers found by this scan to the tracked invoices,
+    /// returning those which changed. Invoices with an unsaved update from the
+    /// previous scan start from that update rather than the stored invoice.
     async fn update_invoices(
         &self,
         transfers: Vec<(SubIndex, Transfer)>,
         blocks_updated: usize,
-    ) -> Result<Vec<Invoice>, ScannerError> {
+        mut unsaved_updates: HashMap<InvoiceId, Invoice>,
+    ) -> Result<Vec<InvoiceUpdate>, ScannerError> {
         let block_cache_height = self.block_cache.lock().await.height();
         let deepest_update = block_cache_height - blocks_updated as u64 + 1;
 
@@ -196,7 +233,9 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
                     return Ok(());
                 }
             };
-            let mut invoice = old_invoice.clone();
+            let mut invoice = unsaved_updates
+                .remove(&old_invoice.id())
+                .unwrap_or_else(|| old_invoice.clone());
 
             // Remove transfers occurring in or after the deepest block update.
             invoice.transfers.retain(|transfer| {
@@ -225,22 +264,14 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
 
             // No need to recalculate total paid_amount or paid_at unless something changed.
             if invoice != old_invoice {
-                // Zero it out first.
-                invoice.paid_height = None;
-                invoice.amount_paid = 0;
-                // Now add up the transfers.
-                for transfer in &invoice.transfers {
-                    invoice.amount_paid += transfer.amount;
-                    if invoice.amount_paid >= invoice.amount_requested()
-                        && invoice.paid_height.is_none()
-                    {
-                        invoice.paid_height = transfer.height;
-                    }
-                }
+                invoice.recalculate_amount_paid();
 
                 // This invoice has been updated. We can now add it in with the other
                 // updated_invoices.
-                cloned_invoices.lock().unwrap_or_else(PoisonError::into_inner).push(invoice);
+                cloned_invoices
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push((old_invoice, invoice));
             }
 
             Ok(())
@@ -270,7 +301,7 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
     /// subaddress indices.
     async fn scan_blocks(
         &self,
-        sub_key_checker: &SubKeyChecker<'_>,
+        sub_key_checkers: &[(&str, SubKeyChecker<'_>)],
         mut blocks_updated: usize,
     ) -> Result<Vec<(SubIndex, Transfer)>, ScannerError> {
         let block_cache = self.block_cache.lock().await;
@@ -285,8 +316,9 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
         // Scan updated blocks.
         for i in (0..blocks_updated).rev() {
             let transactions = &block_cache.blocks()[i].transactions;
+            let block_cache_height: u64 = block_cache.height() - i as u64;
             let amounts_received = self
-                .scan_transactions(transactions, sub_key_checker)
+                .scan_transactions(transactions, sub_key_checkers, block_cache_height)
                 .await?;
             trace!(
                 "Scanned {} transactions from block {}, and found {} transactions to tracked invoices",
@@ -295,18 +327,23 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
                 amounts_received.len(),
             );
 
-            let block_cache_height: u64 = block_cache.height() - i as u64;
-
             // Add what was found into the list.
             transfers.extend::<Vec<(SubIndex, Transfer)>>(
                 amounts_received
                     .into_iter()
-                    .flat_map(|(_, amounts)| amounts)
-                    .map(|OwnedAmount { sub_index, amount }| {
-                        (
-                            sub_index,
-                            Transfer::new(amount.as_pico(), Some(block_cache_height)),
-                        )
+                    .flat_map(|(hash, amounts)| {
+                        amounts
+                            .into_iter()
+                            .map(move |OwnedAmount { sub_index, amount }| {
+                                (
+                                    sub_index,
+                                    Transfer::in_tx(
+                                        hash.to_bytes(),
+                                        amount.as_pico(),
+                                        Some(block_cache_height),
+                                    ),
+                                )
+                            })
                     })
                     .collect(),
             );
@@ -315,13 +352,15 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
         Ok(transfers)
     }
 
-    /// Retrieve and scan transaction pool.
+    /// Retrieve and scan transaction pool. Output keys found are recorded at
+    /// `height`.
     ///
     /// Returns a vector of tuples of the form (subaddress index, amount)
     async fn scan_txpool(
         &self,
-        sub_key_checker: &SubKeyChecker<'_>,
+        sub_key_checkers: &[(&str, SubKeyChecker<'_>)],
         new_transactions: &[Transaction],
+        height: u64,
     ) -> Result<Vec<(SubIndex, Transfer)>, ScannerError> {
         let mut txpool_cache = self.txpool_cache.lock().await;
 
@@ -331,7 +370,7 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
 
         // Scan txpool.
         let amounts_received = self
-            .scan_transactions(new_transactions, sub_key_checker)
+            .scan_transactions(new_transactions, sub_key_checkers, height)
             .await?;
         trace!(
             "Scanned {} transactions from txpool, and found {} transfers for tracked invoices",
@@ -347,7 +386,10 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
                     amounts
                         .iter()
                         .map(|OwnedAmount { sub_index, amount }| {
-                            (*sub_index, Transfer::new(amount.as_pico(), None))
+                            (
+                                *sub_index,
+                                Transfer::in_tx(hash.to_bytes(), amount.as_pico(), None),
+                            )
                         })
                         .collect(),
                 )
@@ -367,14 +409,17 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
             .collect())
     }
 
+    /// Scan transactions for owned outputs, recording their output keys at
+    /// `height`.
     async fn scan_transactions(
         &self,
         transactions: &[monero::Transaction],
-        sub_key_checker: &SubKeyChecker<'_>,
+        sub_key_checkers: &[(&str, SubKeyChecker<'_>)],
+        height: u64,
     ) -> Result<HashMap<monero::Hash, Vec<OwnedAmount>>, ScannerError> {
         let mut amounts_received = HashMap::new();
 
-        let owned_outputs_per_tx: Vec<(monero::Hash, Vec<OwnedTxOut<'_>>)> = transactions
+        let owned_outputs_per_tx: Vec<OwnedOutputs<'_>> = transactions
             .par_iter()
             .filter(|tx| {
                 // Ensure the time lock is zero.
@@ -386,18 +431,21 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
                 }
             })
             .try_fold(Vec::new, |mut outputs_per_tx, tx| {
-                let outputs = tx.check_outputs_with(sub_key_checker)?;
-                outputs_per_tx.push((tx.hash(), outputs));
-                Ok::<Vec<(monero::Hash, Vec<OwnedTxOut<'_>>)>, ScannerError>(outputs_per_tx)
+                let tx_hash = tx.hash();
+                for &(wallet, ref sub_key_checker) in sub_key_checkers {
+                    let outputs = tx.check_outputs_with(sub_key_checker)?;
+                    outputs_per_tx.push((tx_hash, wallet, outputs));
+                }
+                Ok::<Vec<OwnedOutputs<'_>>, ScannerError>(outputs_per_tx)
             })
             .try_reduce(Vec::new, |mut outputs, mut other_outputs| {
                 outputs.append(&mut other_outputs);
                 Ok(outputs)
             })?;
 
-        for (tx_hash, owned_outputs) in owned_outputs_per_tx {
+        for (tx_hash, wallet, owned_outputs) in owned_outputs_per_tx {
             for output in &owned_outputs {
-                if !self.output_key_is_unique(output, tx_hash).await? {
+                if !self.output_key_is_unique(output, tx_hash, height).await? {
                     debug!(
                         "Owned output #{} in transaction {} has duplicate public key.",
                         output.index(),
@@ -408,9 +456,11 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
 
                 let sub_index = SubIndex::from(output.sub_index());
 
-                // If this invoice is being tracked, add the amount and subindex to the result
-                // set.
-                if self.store.contains_sub_index(sub_index).await? {
+                // If this invoice is being tracked, and belongs to the wallet the output was
+                // sent to, add the amount and subindex to the result set.
+                if self.store.contains_sub_index(sub_index).await?
+                    && self.wallets.is_owner(sub_index, wallet)
+                {
                     let amount = OwnedAmount {
                         sub_index,
                         amount: output.amount().ok_or(ScannerError::Unblind(sub_index))?,
@@ -428,11 +478,12 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
 
     /// Returns `true` if the output key is unique to this output, or false if
     /// the key has been used by a previous output (indicating an instance of
-    /// the burning bug).
+    /// the burning bug). New keys are recorded at `height`.
     async fn output_key_is_unique(
         &self,
         output: &OwnedTxOut<'_>,
         tx_hash: monero::Hash,
+        height: u64,
     ) -> Result<bool, ScannerError> {
         let key = match output.out().target {
             TxOutTarget::ToKey { key } | TxOutTarget::ToTaggedKey { key, view_tag: _ } => key,
@@ -448,7 +499,7 @@ impl<S: Storage + 'static, M: MonerodClient> Scanner<S, M> {
             }
         } else {
             self.store
-                .insert_output_key(OutputPubKey(key), output_id)
+                .insert_output_key(OutputPubKey(key), output_id, height)
                 .await?;
         }
         Ok(true)
@@ -490,9 +541,138 @@ struct OwnedAmount {
     amount: Amount,
 }
 
-async fn last_height<S: Storage + 'static>(
-    store: &StorageClient<S>,
-) -> Result<Option<u64>, ScannerError> {
+/// Retrieve all tracked invoices.
+pub(crate) async fn tracked_invoices<S: AsyncStorage>(
+    store: &S,
+) -> Result<Vec<Invoice>, ScannerError> {
+    let invoices = Arc::new(Mutex::new(Vec::new()));
+    let cloned_invoices = invoices.clone();
+    store
+        .try_for_each_invoice(move |invoice_or_err| {
+            match invoice_or_err {
+                Ok(invoice) => cloned_invoices
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(invoice),
+                Err(e) => error!(
+                    "Failed to retrieve invoice from database while iterating through database: {}",
+                    e
+                ),
+            }
+            // Return OK here because we still want to process the others.
+            Ok(())
+        })
+        .await?;
+    let invoices = invoices
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .to_vec();
+    Ok(invoices)
+}
+
+/// Replace each invoice's transfers with those in `transfers` that were sent to
+/// its subaddress since it was created. Used by backends that report every
+/// relevant transfer on each scan, rather than only new ones.
+///
+/// Returns the invoices which changed.
+pub(crate) fn rebuild_invoices(
+    invoices: Vec<Invoice>,
+    transfers: &[(SubIndex, Transfer)],
+    current_height: u64,
+) -> Vec<InvoiceUpdate> {
+    let mut updated_invoices = Vec::new();
+    for old_invoice in invoices {
+        let mut invoice = old_invoice.clone();
+        invoice.transfers = transfers
+            .iter()
+            .filter(|(sub_index, transfer)| {
+                sub_index == &invoice.index()
+                    // Creation height - 1 because creation height is one greater than top block
+                    // height.
+                    && transfer
+                        .cmp_by_height(&Transfer::new(0, Some(invoice.creation_height() - 1)))
+                        .is_gt()
+            })
+            .map(|(_, transfer)| *transfer)
+            .collect();
+        invoice.transfers.sort_by(Transfer::cmp_by_height);
+        invoice.current_height = current_height;
+
+        if invoice != old_invoice {
+            invoice.recalculate_amount_paid();
+            updated_invoices.push((old_invoice, invoice));
+        }
+    }
+    updated_invoices
+}
+
+/// An invoice's state before and after an update.
+pub(crate) type InvoiceUpdate = (Invoice, Invoice);
+
+/// Save updated invoices to the database and publish them to subscribers.
+///
+/// The events describing each update are logged before it is saved. If they
+/// can't be logged, the update is skipped, and returned so that the caller can
+/// apply it again.
+pub(crate) async fn save_and_publish<S: AsyncStorage>(
+    store: &S,
+    publisher: &Publisher,
+    updated_invoices: Vec<InvoiceUpdate>,
+) -> Vec<Invoice> {
+    let mut unlogged = Vec::new();
+    for (old_invoice, invoice) in updated_invoices {
+        debug!(
+            "Invoice update for subaddress index {}: \
+                \n{}",
+            invoice.index(),
+            invoice
+        );
+        let mut event_log = publisher.event_log().await;
+        let events = InvoiceEvent::from_update(Some(&old_invoice), &invoice);
+        if let Err(e) = event_log.log(store, events).await {
+            error!(
+                "Failed to log update to invoice for index {}: {}",
+                invoice.index(),
+                e
+            );
+            unlogged.push(invoice);
+            continue;
+        }
+        if let Err(e) = store.update_invoice(invoice.clone()).await {
+            error!(
+                "Failed to save update to invoice for index {} to database: {}",
+                invoice.index(),
+                e
+            );
+            continue;
+        }
+        // The update was successful, so send an update down the subscriber
+        // channel.
+        event_log.publish(invoice.id(), Some(&invoice)).await;
+        debug!(
+            "Published invoice update for subaddress index {}",
+            invoice.index()
+        );
+
+        // Remember subaddresses which received funds, for the subaddress
+        // allocator and gap limit.
+        if invoice.amount_paid() > crate::Amount::ZERO {
+            if let Err(e) = store
+                .insert_funded_subaddress(invoice.wallet().to_string(), invoice.index())
+                .await
+            {
+                error!(
+                    "Failed to record funded subaddress index {} in database: {}",
+                    invoice.index(),
+                    e
+                );
+            }
+        }
+    }
+    unlogged
+}
+
+async fn last_height<S: AsyncStorage>(store: &S) -> Result<Option<u64>, ScannerError> {
     if let Some(h) = store.get_height().await? {
         info!("Last block scanned: {}", h);
         return Ok(Some(h));
@@ -0,0 +1,266 @@
+use crate::{Invoice, InvoiceId};
+
+/// The [`ArchiveStorage`] trait describes the invoice archive storage layer for
+/// `AcceptXMR`. Invoices which no longer need to be tracked can be moved to the
+/// archive instead of being removed, so that they can still be retrieved by
+/// ID. Archived invoices are not part of [`InvoiceStorage`](super::InvoiceStorage),
+/// so the scanner does not iterate over them.
+pub trait ArchiveStorage: Send + Sync {
+    /// Error type for the storage layer.
+    type Error: std::error::Error + Send + 'static;
+
+    /// Move an invoice from invoice storage to the archive, returning the
+    /// invoice if it existed. An archived invoice with the same ID is
+    /// replaced. If the invoice does not exist, does nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the invoice could not be archived.
+    fn archive(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error>;
+
+    /// Retrieve an archived invoice, returning `None` if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the invoice could not be read.
+    fn get_archived(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error>;
+
+    /// Retrieve an archived invoice by its merchant reference, returning
+    /// `None` if no archived invoice has that reference. If several archived
+    /// invoices share the reference, any one of them may be returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the invoice could not be read.
+    fn get_archived_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error>;
+
+    /// Iterates over all archived invoices, executing the supplied closure on
+    /// each.
+    ///
+    /// # Errors
+    ///
+    /// Stops iterating and returns an error if the supplied closure returns an
+    /// error.
+    fn try_for_each_archived<F>(&self, f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>;
+
+    /// Remove every archived invoice with a current height less than `height`
+    /// from the archive.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn prune_archive(&mut self, height: u64) -> Result<(), Self::Error>;
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use std::fmt::{Debug, Display};
+
+    use test_case::test_case;
+    use testing_utils::new_temp_dir;
+
+    #[cfg(feature = "postgres")]
+    use crate::storage::stores::Postgres;
+    use crate::{
+        storage::{
+            stores::{InMemory, Redb, Sled, Sqlite, TableNames},
+            ArchiveStorage, InvoiceStorage,
+        },
+        Amount, Invoice, SubIndex,
+    };
+    #[cfg(feature = "postgres")]
+    use testing_utils::new_postgres_db;
+
+    fn dummy_invoice(creation_height: u64, current_height: u64) -> Invoice {
+        let mut invoice = Invoice::new(
+            "testAddress".to_string(),
+            "testWallet".to_string(),
+            SubIndex::new(0, 1),
+            creation_height,
+            Amount::from_pico(1),
+            5,
+            10,
+            "test description".to_string(),
+        );
+        invoice.current_height = current_height;
+        invoice
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn archive_and_get<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + ArchiveStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let invoice = dummy_invoice(10, 15);
+        InvoiceStorage::insert(&mut store, invoice.clone()).unwrap();
+
+        assert_eq!(store.archive(invoice.id()).unwrap(), Some(invoice.clone()));
+        assert_eq!(
+            store.get_archived(invoice.id()).unwrap(),
+            Some(invoice.clone())
+        );
+        // Archived invoices are no longer in invoice storage.
+        assert_eq!(InvoiceStorage::get(&store, invoice.id()).unwrap(), None);
+        assert!(InvoiceStorage::is_empty(&store).unwrap());
+
+        // Archiving an invoice which doesn't exist does nothing.
+        assert_eq!(store.archive(invoice.id()).unwrap(), None);
+        assert_eq!(store.get_archived(invoice.id()).unwrap(), Some(invoice));
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn archive_releases_reference<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + ArchiveStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let mut old = dummy_invoice(10, 15);
+        old.reference = Some("order 1".to_string());
+        let mut new = dummy_invoice(20, 25);
+        new.reference = Some("order 1".to_string());
+
+        InvoiceStorage::insert(&mut store, old.clone()).unwrap();
+        store.archive(old.id()).unwrap();
+        assert_eq!(store.get_by_reference("order 1").unwrap(), None);
+
+        // The reference may be used again once its invoice is archived.
+        InvoiceStorage::insert(&mut store, new.clone()).unwrap();
+        assert_eq!(store.get_by_reference("order 1").unwrap(), Some(new));
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn get_archived_by_reference<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + ArchiveStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let mut first = dummy_invoice(10, 15);
+        first.reference = Some("order 1".to_string());
+        let mut second = dummy_invoice(20, 25);
+        second.reference = Some("order 1".to_string());
+        let mut other = dummy_invoice(30, 35);
+        other.reference = Some("order 2".to_string());
+        for invoice in [&first, &second, &other] {
+            InvoiceStorage::insert(&mut store, invoice.clone()).unwrap();
+            store.archive(invoice.id()).unwrap();
+        }
+
+        let found = store.get_archived_by_reference("order 1").unwrap().unwrap();
+        assert!(found == first || found == second);
+        assert_eq!(
+            store.get_archived_by_reference("order 2").unwrap(),
+            Some(other.clone())
+        );
+        assert_eq!(store.get_archived_by_reference("order 3").unwrap(), None);
+
+        // Archived invoices sharing a reference are pruned separately.
+        store.prune_archive(20).unwrap();
+        assert_eq!(
+            store.get_archived_by_reference("order 1").unwrap(),
+            Some(second)
+        );
+        store.prune_archive(30).unwrap();
+        assert_eq!(store.get_archived_by_reference("order 1").unwrap(), None);
+
+        // Replacing an archived invoice replaces its reference.
+        let mut replacement = other.clone();
+        replacement.reference = Some("order 3".to_string());
+        InvoiceStorage::insert(&mut store, replacement.clone()).unwrap();
+        store.archive(replacement.id()).unwrap();
+        assert_eq!(store.get_archived_by_reference("order 2").unwrap(), None);
+        assert_eq!(
+            store.get_archived_by_reference("order 3").unwrap(),
+            Some(replacement)
+        );
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn archive_replaces<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + ArchiveStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let old = dummy_invoice(10, 15);
+        let new = dummy_invoice(10, 20);
+        InvoiceStorage::insert(&mut store, old.clone()).unwrap();
+        store.archive(old.id()).unwrap();
+        InvoiceStorage::insert(&mut store, new.clone()).unwrap();
+        store.archive(new.id()).unwrap();
+
+        assert_eq!(store.get_archived(old.id()).unwrap(), Some(new));
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn try_for_each_archived<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + ArchiveStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let archived = [dummy_invoice(10, 15), dummy_invoice(20, 25)];
+        for invoice in &archived {
+            InvoiceStorage::insert(&mut store, invoice.clone()).unwrap();
+            store.archive(invoice.id()).unwrap();
+        }
+        // Invoices which are not archived are not iterated over.
+        InvoiceStorage::insert(&mut store, dummy_invoice(30, 35)).unwrap();
+
+        let mut found = Vec::new();
+        store
+            .try_for_each_archived(|invoice| {
+                found.push(invoice?);
+                Ok(())
+            })
+            .unwrap();
+        found.sort_by_key(Invoice::creation_height);
+
+        assert_eq!(found, archived);
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn prune_archive<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + ArchiveStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        // Spans more than one byte, to check heights are compared numerically.
+        let old = dummy_invoice(10, 255);
+        let recent = dummy_invoice(20, 256);
+        for invoice in [&old, &recent] {
+            InvoiceStorage::insert(&mut store, invoice.clone()).unwrap();
+            store.archive(invoice.id()).unwrap();
+        }
+
+        store.prune_archive(256).unwrap();
+
+        assert_eq!(store.get_archived(old.id()).unwrap(), None);
+        assert_eq!(store.get_archived(recent.id()).unwrap(), Some(recent));
+    }
+}
@@ -0,0 +1,250 @@
+use std::future::Future;
+
+use super::{InvoicePage, InvoiceQuery, OutputId, OutputKeyStats, OutputPubKey};
+use crate::{storage::StorageError, Invoice, InvoiceEvent, InvoiceId, SubIndex};
+
+/// The [`AsyncStorage`] trait describes the asynchronous interface the payment
+/// gateway uses to reach its storage layer.
+///
+/// Synchronous [`Storage`](super::Storage) implementations are adapted through
+/// [`Client`](super::Client), which runs the store on a dedicated task and
+/// forwards each call to it over a channel. The payment gateway, scanners and event log only depend on this
+/// trait, so that a store built on a native async driver (e.g. a pooled
+/// Postgres connection) can implement it directly and serve calls
+/// concurrently. No such store exists yet, so the trait is not public and
+/// [`PaymentGateway::builder`](crate::PaymentGateway::builder) still takes a
+/// [`Storage`](super::Storage).
+///
+/// Implementations are cloned freely and shared between the payment gateway
+/// and its scanning task, so clones must refer to the same underlying store.
+///
+/// # Errors
+///
+/// Every method returns a [`StorageError`] if there was an underlying issue
+/// with the database. Implementations should wrap their own errors in
+/// [`StorageError::Internal`].
+pub(crate) trait AsyncStorage: Clone + Send + Sync + 'static {
+    /// Inserts a new [`Invoice`]. Returns an error if an invoice with the
+    /// same ID or merchant reference already exists.
+    fn insert_invoice(
+        &self,
+        invoice: Invoice,
+    ) -> impl Future<Output = Result<(), StorageError>> + Send;
+
+    /// Removes an [`Invoice`], returning it if it was present.
+    fn remove_invoice(
+        &self,
+        id: InvoiceId,
+    ) -> impl Future<Output = Result<Option<Invoice>, StorageError>> + Send;
+
+    /// Updates an existing [`Invoice`], returning the old version if it was
+    /// present. Does nothing if the invoice does not exist.
+    fn update_invoice(
+        &self,
+        invoice: Invoice,
+    ) -> impl Future<Output = Result<Option<Invoice>, StorageError>> + Send;
+
+    /// Returns the [`Invoice`] with the given ID, if it exists.
+    fn get_invoice(
+        &self,
+        id: InvoiceId,
+    ) -> impl Future<Output = Result<Option<Invoice>, StorageError>> + Send;
+
+    /// Returns the [`Invoice`] with the given merchant reference, if it
+    /// exists.
+    fn get_invoice_by_reference(
+        &self,
+        reference: String,
+    ) -> impl Future<Output = Result<Option<Invoice>, StorageError>> + Send;
+
+    /// Returns the IDs of all stored invoices.
+    fn get_invoice_ids(&self) -> impl Future<Output = Result<Vec<InvoiceId>, StorageError>> + Send;
+
+    /// Returns the page of invoices matching an [`InvoiceQuery`].
+    fn query_invoices(
+        &self,
+        query: InvoiceQuery,
+    ) -> impl Future<Output = Result<InvoicePage, StorageError>> + Send;
+
+    /// Returns whether any stored invoice uses the given subaddress index.
+    fn contains_sub_index(
+        &self,
+        index: SubIndex,
+    ) -> impl Future<Output = Result<bool, StorageError>> + Send;
+
+    /// Calls a closure on every stored invoice, stopping at the first error
+    /// the closure returns.
+    fn try_for_each_invoice<F>(
+        &self,
+        f: F,
+    ) -> impl Future<Output = Result<(), StorageError>> + Send
+    where
+        F: FnMut(Result<Invoice, StorageError>) -> Result<(), StorageError> + Send + 'static;
+
+    /// Returns the lowest height at which any stored invoice was created.
+    fn lowest_invoice_height(
+        &self,
+    ) -> impl Future<Output = Result<Option<u64>, StorageError>> + Send;
+
+    /// Returns the most recently scanned block height, if it exists.
+    fn get_height(&self) -> impl Future<Output = Result<Option<u64>, StorageError>> + Send;
+
+    /// Updates the most recently scanned block height, returning the old
+    /// height if it existed.
+    fn upsert_height(
+        &self,
+        height: u64,
+    ) -> impl Future<Output = Result<Option<u64>, StorageError>> + Send;
+
+    /// Returns the ID of the output using the given one-time public key, if
+    /// it has been seen.
+    fn get_output_key_id(
+        &self,
+        key: OutputPubKey,
+    ) -> impl Future<Output = Result<Option<OutputId>, StorageError>> + Send;
+
+    /// Records an output's one-time public key along with the height it was
+    /// seen at.
+    fn insert_output_key(
+        &self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> impl Future<Output = Result<(), StorageError>> + Send;
+
+    /// Removes output keys recorded below the given height, returning how
+    /// many were removed.
+    fn prune_output_keys(
+        &self,
+        height: u64,
+    ) -> impl Future<Output = Result<u64, StorageError>> + Send;
+
+    /// Returns statistics describing the recorded output keys.
+    fn output_key_stats(&self)
+        -> impl Future<Output = Result<OutputKeyStats, StorageError>> + Send;
+
+    /// Marks a subaddress of the given wallet as having received funds.
+    fn insert_funded_subaddress(
+        &self,
+        wallet: String,
+        sub_index: SubIndex,
+    ) -> impl Future<Output = Result<(), StorageError>> + Send;
+
+    /// Returns the wallet and index of every subaddress that has received
+    /// funds.
+    fn get_funded_subaddresses(
+        &self,
+    ) -> impl Future<Output = Result<Vec<(String, SubIndex)>, StorageError>> + Send;
+
+    /// Appends an [`InvoiceEvent`] to the event log, returning its sequence
+    /// number.
+    fn append_event(
+        &self,
+        event: InvoiceEvent,
+    ) -> impl Future<Output = Result<u64, StorageError>> + Send;
+
+    /// Returns up to `limit` logged events with a sequence number at or above
+    /// `sequence`, in order.
+    fn get_events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<(u64, InvoiceEvent)>, StorageError>> + Send;
+
+    /// Removes all logged events with a sequence number below `sequence`.
+    fn prune_events(&self, sequence: u64) -> impl Future<Output = Result<(), StorageError>> + Send;
+
+    /// Moves an [`Invoice`] into the archive, returning it if it was present.
+    fn archive_invoice(
+        &self,
+        id: InvoiceId,
+    ) -> impl Future<Output = Result<Option<Invoice>, StorageError>> + Send;
+
+    /// Returns the archived [`Invoice`] with the given ID, if it exists.
+    fn get_archived_invoice(
+        &self,
+        id: InvoiceId,
+    ) -> impl Future<Output = Result<Option<Invoice>, StorageError>> + Send;
+
+    /// Returns an archived [`Invoice`] with the given merchant reference, if
+    /// one exists.
+    fn get_archived_invoice_by_reference(
+        &self,
+        reference: String,
+    ) -> impl Future<Output = Result<Option<Invoice>, StorageError>> + Send;
+
+    /// Calls a closure on every archived invoice, stopping at the first error
+    /// the closure returns.
+    fn try_for_each_archived_invoice<F>(
+        &self,
+        f: F,
+    ) -> impl Future<Output = Result<(), StorageError>> + Send
+    where
+        F: FnMut(Result<Invoice, StorageError>) -> Result<(), StorageError> + Send + 'static;
+
+    /// Removes archived invoices that expired below the given height.
+    fn prune_archive(&self, height: u64) -> impl Future<Output = Result<(), StorageError>> + Send;
+
+    /// Flushes all changes to disk.
+    fn flush(&self) -> impl Future<Output = Result<(), StorageError>> + Send;
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        storage::{stores::InMemory, AsyncStorage, Client, StorageError},
+        Amount, Invoice, SubIndex,
+    };
+
+    fn dummy_invoice(index: u32) -> Invoice {
+        Invoice::new(
+            "4a1wsbqdcbucqt3dagfmqvfchxscf43m6c5r4b6jxt3duwualncu9xtenrpmumcb3c16kvp9y7thflcj5bamw3umsy93w3w".to_string(),
+            "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf".to_string(),
+            SubIndex::new(0, index),
+            123,
+            Amount::from_pico(1),
+            1,
+            1,
+            "description".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn sync_store_round_trip() {
+        let store = Client::new(InMemory::new());
+        let invoice = dummy_invoice(1);
+
+        store.insert_invoice(invoice.clone()).await.unwrap();
+        assert_eq!(
+            store.clone().get_invoice(invoice.id()).await.unwrap(),
+            Some(invoice.clone())
+        );
+        assert_eq!(store.upsert_height(10).await.unwrap(), None);
+        assert_eq!(store.get_height().await.unwrap(), Some(10));
+        assert_eq!(store.get_invoice_ids().await.unwrap(), vec![invoice.id()]);
+    }
+
+    #[tokio::test]
+    async fn for_each_stops_at_first_error() {
+        let store = Client::new(InMemory::new());
+        for index in 0..3 {
+            store.insert_invoice(dummy_invoice(index)).await.unwrap();
+        }
+
+        let calls = Arc::new(Mutex::new(0));
+        let cloned_calls = calls.clone();
+        let result = store
+            .try_for_each_invoice(move |invoice_or_err| {
+                invoice_or_err?;
+                *cloned_calls.lock().unwrap() += 1;
+                Err(StorageError::Receive)
+            })
+            .await;
+
+        assert!(matches!(result, Err(StorageError::Receive)));
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,545 @@
+//! Backend-agnostic dumps of a store, for backups and for moving data between
+//! storage layers. A dump written from any [`Storage`] implementation using
+//! [`export`] can be restored into any other using [`import`].
+
+use std::io::{self, BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{HeightStorage, InvoiceStorage, OutputId, OutputKeyStorage, OutputPubKey, Storage};
+use crate::{Invoice, SubIndex};
+
+/// Version of the dump format written by [`export`].
+///
+/// A dump is a sequence of JSON objects, one per line. The first line is a
+/// header identifying the format and its version. Each following line is a
+/// record of the scan height, an output key, a funded subaddress, an invoice,
+/// or an archived invoice.
+///
+/// Version 2 records the height of each output key. Keys in version 1 dumps
+/// are imported at the dump's scan height.
+///
+/// Version 3 records the wallet of each funded subaddress. Subaddresses in
+/// older dumps are imported with an empty wallet.
+pub const DUMP_VERSION: u32 = 3;
+
+/// Name of the format, recorded in the header of every dump.
+const DUMP_FORMAT: &str = "acceptxmr-dump";
+
+/// First line of a dump.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+/// A line of a dump, following the header.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Record {
+    Height {
+        height: u64,
+    },
+    OutputKey {
+        key: OutputPubKey,
+        output_id: OutputId,
+        #[serde(default)]
+        height: Option<u64>,
+    },
+    FundedSubaddress {
+        #[serde(default)]
+        wallet: String,
+        sub_index: SubIndex,
+    },
+    Invoice(Invoice),
+    ArchivedInvoice(Invoice),
+}
+
+/// Write the invoices, archived invoices, output keys, funded subaddresses and
+/// scan height in `store` to `writer`, as a dump which can be restored into
+/// any other store using [`import`]. The event log is not included.
+///
+/// To take a consistent backup of a store in use by a running payment gateway,
+/// use [`PaymentGateway::export_storage`](crate::PaymentGateway::export_storage)
+/// instead.
+///
+/// # Errors
+///
+/// Returns an error if the store could not be read, or if the dump could not be
+/// written.
+pub fn export<S: Storage>(store: &S, mut writer: impl Write) -> Result<(), DumpError> {
+    write_line(
+        &mut writer,
+        &Header {
+            format: DUMP_FORMAT.to_string(),
+            version: DUMP_VERSION,
+        },
+    )?;
+
+    if let Some(height) = HeightStorage::get(store).map_err(DumpError::storage)? {
+        write_line(&mut writer, &Record::Height { height })?;
+    }
+
+    // The stores' iterators can only be stopped by their own errors, so the
+    // first failed write is kept here and the rest are skipped.
+    let mut written = Ok(());
+    store
+        .try_for_each_key(|key_or_err| {
+            let (key, output_id, height) = key_or_err?;
+            if written.is_ok() {
+                written = write_line(
+                    &mut writer,
+                    &Record::OutputKey {
+                        key,
+                        output_id,
+                        height: Some(height),
+                    },
+                );
+            }
+            Ok(())
+        })
+        .map_err(DumpError::storage)?;
+    written?;
+
+    for (wallet, sub_index) in store.funded().map_err(DumpError::storage)? {
+        write_line(&mut writer, &Record::FundedSubaddress { wallet, sub_index })?;
+    }
+
+    // Archived invoices come before invoices, so that an invoice never has to
+    // be moved aside to import an archived invoice with the same ID.
+    let mut written = Ok(());
+    store
+        .try_for_each_archived(|invoice_or_err| {
+            let invoice = invoice_or_err?;
+            if written.is_ok() {
+                written = write_line(&mut writer, &Record::ArchivedInvoice(invoice));
+            }
+            Ok(())
+        })
+        .map_err(DumpError::storage)?;
+    written?;
+
+    let mut written = Ok(());
+    InvoiceStorage::try_for_each(store, |invoice_or_err| {
+        let invoice = invoice_or_err?;
+        if written.is_ok() {
+            written = write_line(&mut writer, &Record::Invoice(invoice));
+        }
+        Ok(())
+    })
+    .map_err(DumpError::storage)?;
+    written?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Restore a dump written by [`export`] into `store`, which must not hold any
+/// invoices or a scan height.
+///
+/// The whole dump is read and parsed before anything is written, so a
+/// malformed dump leaves `store` untouched. Records are not written in a
+/// transaction though, so if `store` fails partway through the import it will
+/// hold part of the dump, and should be discarded rather than used or
+/// imported into again.
+///
+/// # Errors
+///
+/// Returns an error if `store` is not empty, if the dump could not be read or
+/// is of an unsupported version, or if its contents could not be stored.
+pub fn import<S: Storage>(store: &mut S, reader: impl BufRead) -> Result<(), DumpError> {
+    if !InvoiceStorage::is_empty(store).map_err(DumpError::storage)?
+        || HeightStorage::get(store)
+            .map_err(DumpError::storage)?
+            .is_some()
+    {
+        return Err(DumpError::NotEmpty);
+    }
+
+    let mut lines = reader.lines();
+    let header = match lines.next() {
+        Some(line) => serde_json::from_str::<Header>(&line?)
+            .ok()
+            .filter(|header| header.format == DUMP_FORMAT)
+            .ok_or(DumpError::MissingHeader)?,
+        None => return Err(DumpError::MissingHeader),
+    };
+    if header.version > DUMP_VERSION {
+        return Err(DumpError::Unsupported {
+            found: header.version,
+            supported: DUMP_VERSION,
+        });
+    }
+
+    let mut records = Vec::new();
+    // Line numbers start at 1, and the header has already been read.
+    for (line_number, line) in (2..).zip(lines) {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line).map_err(|source| DumpError::Parse {
+            line: line_number,
+            source,
+        })?;
+        records.push(record);
+    }
+
+    // The scan height comes before any output keys, and is used for keys
+    // without a height of their own.
+    let mut scan_height = None;
+    for record in records {
+        match record {
+            Record::Height { height } => {
+                HeightStorage::upsert(store, height).map_err(DumpError::storage)?;
+                scan_height = Some(height);
+            }
+            Record::OutputKey {
+                key,
+                output_id,
+                height,
+            } => {
+                let height = height.or(scan_height).unwrap_or_default();
+                OutputKeyStorage::insert(store, key, output_id, height)
+                    .map_err(DumpError::storage)?;
+            }
+            Record::FundedSubaddress { wallet, sub_index } => {
+                store
+                    .insert_funded(&wallet, sub_index)
+                    .map_err(DumpError::storage)?;
+            }
+            Record::Invoice(invoice) => {
+                InvoiceStorage::insert(store, invoice).map_err(DumpError::storage)?;
+            }
+            Record::ArchivedInvoice(invoice) => {
+                let invoice_id = invoice.id();
+                InvoiceStorage::insert(store, invoice).map_err(DumpError::storage)?;
+                store.archive(invoice_id).map_err(DumpError::storage)?;
+            }
+        }
+    }
+
+    store.flush().map_err(DumpError::storage)
+}
+
+fn write_line<T: Serialize>(mut writer: impl Write, value: &T) -> Result<(), DumpError> {
+    serde_json::to_writer(&mut writer, value).map_err(DumpError::Serialize)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// An error occurring while exporting or importing a dump of a store.
+#[derive(Error, Debug)]
+pub enum DumpError {
+    /// Failed to read or write the dump.
+    #[error("failed to read or write dump: {0}")]
+    Io(#[from] io::Error),
+    /// Failed to serialize a line of the dump.
+    #[error("failed to serialize dump: {0}")]
+    Serialize(serde_json::Error),
+    /// A line of the dump could not be parsed.
+    #[error("failed to parse line {line} of dump: {source}")]
+    Parse {
+        /// Line number, starting at 1.
+        line: usize,
+        /// Error encountered.
+        source: serde_json::Error,
+    },
+    /// The dump does not begin with an `AcceptXMR` dump header.
+    #[error("not an AcceptXMR dump: missing header")]
+    MissingHeader,
+    /// The dump was written by a newer version of `AcceptXMR`.
+    #[error(
+        "dump format version {found} is newer than the newest supported version ({supported})"
+    )]
+    Unsupported {
+        /// Format version of the dump.
+        found: u32,
+        /// Newest format version supported by this version of `AcceptXMR`.
+        supported: u32,
+    },
+    /// The store being imported into already holds invoices or a scan height.
+    #[error("cannot import into a store which is not empty")]
+    NotEmpty,
+    /// An error caused by the store being exported or imported.
+    #[error("storage error: {0}")]
+    Storage(Box<dyn std::error::Error + Send>),
+}
+
+impl DumpError {
+    fn storage<E: std::error::Error + Send + 'static>(error: E) -> Self {
+        DumpError::Storage(Box::new(error))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::fmt::Debug;
+
+    use test_case::test_case;
+    #[cfg(feature = "postgres")]
+    use testing_utils::new_postgres_db;
+    use testing_utils::new_temp_dir;
+
+    use super::{export, import, DumpError, DUMP_VERSION};
+    #[cfg(feature = "postgres")]
+    use crate::storage::stores::Postgres;
+    use crate::{
+        invoice::Transfer,
+        storage::{
+            stores::{InMemory, Redb, Sled, Sqlite, TableNames},
+            ArchiveStorage, HeightStorage, InvoiceStorage, OutputId, OutputKeyStorage,
+            OutputPubKey, Storage, SubaddressStorage,
+        },
+        Amount, Invoice, SubIndex,
+    };
+
+    fn dummy_invoices() -> Vec<Invoice> {
+        let mut paid = Invoice::new(
+            "4a1wsbqdcbucqt3dagfmqvfchxscf43m6c5r4b6jxt3duwualncu9xtenrpmumcb3c16kvp9y7thflcj5bamw3umsy93w3w".to_string(),
+            "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf".to_string(),
+            SubIndex::new(0, 1),
+            100,
+            Amount::from_pico(u64::MAX),
+            2,
+            10,
+            "paid \"order\"\nwith newline".to_string(),
+        );
+        paid.transfers.push(Transfer::new(u64::MAX, Some(101)));
+        paid.amount_paid = Amount::from_pico(u64::MAX);
+        paid.paid_height = Some(101);
+        paid.current_height = 102;
+
+        let unpaid = Invoice::new(
+            "4A1WSBQdCbUCqt3DaGfmqVFchXScF43M6c5r4B6JXT3dUwuALncU9XTEnRPmUMcB3c16kVP9Y7thFLCJ5BaMW3UmSy93w3w".to_string(),
+            "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf".to_string(),
+            SubIndex::new(0, 2),
+            101,
+            Amount::from_pico(1),
+            0,
+            10,
+            String::new(),
+        );
+
+        vec![paid, unpaid]
+    }
+
+    fn dummy_archived_invoice() -> Invoice {
+        let mut archived = Invoice::new(
+            "4A1WSBQdCbUCqt3DaGfmqVFchXScF43M6c5r4B6JXT3dUwuALncU9XTEnRPmUMcB3c16kVP9Y7thFLCJ5BaMW3UmSy93w3w".to_string(),
+            "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf".to_string(),
+            SubIndex::new(0, 3),
+            50,
+            Amount::from_pico(1),
+            0,
+            10,
+            "archived".to_string(),
+        );
+        archived.current_height = 61;
+        archived
+    }
+
+    fn dummy_output_key() -> (OutputPubKey, OutputId) {
+        (
+            OutputPubKey([7; 32]),
+            OutputId {
+                tx_hash: [8; 32],
+                index: 1,
+            },
+        )
+    }
+
+    /// Returns a store holding some of everything included in a dump.
+    fn populated_store() -> InMemory {
+        let mut store = InMemory::new();
+        for invoice in dummy_invoices() {
+            InvoiceStorage::insert(&mut store, invoice).unwrap();
+        }
+        let (key, output_id) = dummy_output_key();
+        OutputKeyStorage::insert(&mut store, key, output_id, 101).unwrap();
+        HeightStorage::upsert(&mut store, 102).unwrap();
+        store.insert_funded("wallet", SubIndex::new(0, 1)).unwrap();
+        let archived = dummy_archived_invoice();
+        InvoiceStorage::insert(&mut store, archived.clone()).unwrap();
+        store.archive(archived.id()).unwrap();
+        store
+    }
+
+    fn dump(store: &impl Storage) -> Vec<u8> {
+        let mut dump = Vec::new();
+        export(store, &mut dump).unwrap();
+        dump
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn round_trip<S>(mut store: S)
+    where
+        S: Storage,
+        <S as InvoiceStorage>::Error: Debug,
+        <S as OutputKeyStorage>::Error: Debug,
+        <S as HeightStorage>::Error: Debug,
+        <S as SubaddressStorage>::Error: Debug,
+        <S as ArchiveStorage>::Error: Debug,
+    {
+        let original = dump(&populated_store());
+        import(&mut store, original.as_slice()).unwrap();
+
+        for invoice in dummy_invoices() {
+            assert_eq!(
+                InvoiceStorage::get(&store, invoice.id()).unwrap(),
+                Some(invoice)
+            );
+        }
+        let (key, output_id) = dummy_output_key();
+        assert_eq!(OutputKeyStorage::get(&store, key).unwrap(), Some(output_id));
+        assert_eq!(store.key_stats().unwrap().lowest_height, Some(101));
+        assert_eq!(HeightStorage::get(&store).unwrap(), Some(102));
+        assert_eq!(
+            store.funded().unwrap(),
+            vec![("wallet".to_string(), SubIndex::new(0, 1))]
+        );
+        let archived = dummy_archived_invoice();
+        assert_eq!(store.get_archived(archived.id()).unwrap(), Some(archived));
+
+        // Dumping the restored store gives the same contents, though invoices
+        // may be listed in a different order.
+        let mut restored: Vec<_> = String::from_utf8(dump(&store))
+            .unwrap()
+            .lines()
+            .map(ToString::to_string)
+            .collect();
+        let mut original: Vec<_> = String::from_utf8(original)
+            .unwrap()
+            .lines()
+            .map(ToString::to_string)
+            .collect();
+        restored.sort();
+        original.sort();
+        assert_eq!(restored, original);
+    }
+
+    #[test]
+    fn header_first() {
+        let dump = String::from_utf8(dump(&InMemory::new())).unwrap();
+        assert_eq!(
+            dump,
+            format!("{{\"format\":\"acceptxmr-dump\",\"version\":{DUMP_VERSION}}}\n")
+        );
+    }
+
+    #[test]
+    fn import_into_non_empty_store() {
+        let dump = dump(&populated_store());
+        let mut store = InMemory::new();
+        HeightStorage::upsert(&mut store, 1).unwrap();
+
+        assert!(matches!(
+            import(&mut store, dump.as_slice()),
+            Err(DumpError::NotEmpty)
+        ));
+    }
+
+    #[test]
+    fn v1_output_keys_get_scan_height() {
+        // Version 1 dumps have no output key heights, or funded subaddress
+        // wallets.
+        let dump: Vec<String> = String::from_utf8(dump(&populated_store()))
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let mut value: serde_json::Value = serde_json::from_str(line).unwrap();
+                let object = value.as_object_mut().unwrap();
+                if object.contains_key("format") {
+                    object.insert("version".to_string(), 1.into());
+                }
+                match object.get("type").and_then(|t| t.as_str()) {
+                    Some("output-key") => {
+                        object.remove("height");
+                    }
+                    Some("funded-subaddress") => {
+                        object.remove("wallet");
+                    }
+                    _ => {}
+                }
+                value.to_string()
+            })
+            .collect();
+        let mut store = InMemory::new();
+        import(&mut store, dump.join("\n").as_bytes()).unwrap();
+
+        let (key, output_id) = dummy_output_key();
+        let mut keys = Vec::new();
+        store
+            .try_for_each_key(|key_or_err| {
+                keys.push(key_or_err?);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(keys, vec![(key, output_id, 102)]);
+        assert_eq!(
+            store.funded().unwrap(),
+            vec![(String::new(), SubIndex::new(0, 1))]
+        );
+    }
+
+    #[test]
+    fn newer_dump_is_refused() {
+        let dump = format!(
+            "{{\"format\":\"acceptxmr-dump\",\"version\":{}}}\n",
+            DUMP_VERSION + 1
+        );
+
+        assert!(matches!(
+            import(&mut InMemory::new(), dump.as_bytes()),
+            Err(DumpError::Unsupported { found, supported })
+                if found == DUMP_VERSION + 1 && supported == DUMP_VERSION
+        ));
+    }
+
+    #[test]
+    fn missing_header() {
+        assert!(matches!(
+            import(
+                &mut InMemory::new(),
+                &b"{\"type\":\"height\",\"height\":1}\n"[..]
+            ),
+            Err(DumpError::MissingHeader)
+        ));
+        assert!(matches!(
+            import(&mut InMemory::new(), &b""[..]),
+            Err(DumpError::MissingHeader)
+        ));
+    }
+
+    #[test]
+    fn invalid_record() {
+        let dump = format!(
+            "{{\"format\":\"acceptxmr-dump\",\"version\":{DUMP_VERSION}}}\n{{\"type\":\"height\"}}\n"
+        );
+
+        assert!(matches!(
+            import(&mut InMemory::new(), dump.as_bytes()),
+            Err(DumpError::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_record_writes_nothing() {
+        let dump = format!(
+            "{{\"format\":\"acceptxmr-dump\",\"version\":{DUMP_VERSION}}}\n{{\"type\":\"height\",\"height\":1}}\n{{\"type\":\"height\"}}\n"
+        );
+        let mut store = InMemory::new();
+
+        assert!(matches!(
+            import(&mut store, dump.as_bytes()),
+            Err(DumpError::Parse { line: 3, .. })
+        ));
+        assert_eq!(HeightStorage::get(&store).unwrap(), None);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::InvoiceEvent;
+
+/// The [`EventStorage`] trait describes the event log storage layer for
+/// `AcceptXMR`. This layer records every [`InvoiceEvent`] published, in order,
+/// so that subscribers can replay events they missed while disconnected, or
+/// across restarts.
+pub trait EventStorage: Send + Sync {
+    /// Error type for the storage layer.
+    type Error: std::error::Error + Send + 'static;
+
+    /// Append an event to the log and return its sequence number. Sequence
+    /// numbers start at 1, increase with every event appended, and are never
+    /// reused, even after the events they belong to are pruned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn append_event(&mut self, event: InvoiceEvent) -> Result<u64, Self::Error>;
+
+    /// Returns up to `limit` events in the log with a sequence number of at
+    /// least `sequence`, along with their sequence numbers, in ascending order.
+    /// The log can be read a page at a time by passing one more than the last
+    /// sequence number returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, Self::Error>;
+
+    /// Remove every event with a sequence number less than `sequence` from the
+    /// log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn prune_events(&mut self, sequence: u64) -> Result<(), Self::Error>;
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use std::fmt::{Debug, Display};
+
+    use test_case::test_case;
+    use testing_utils::new_temp_dir;
+
+    #[cfg(feature = "postgres")]
+    use crate::storage::stores::Postgres;
+    use crate::{
+        storage::{
+            stores::{InMemory, Redb, Sled, Sqlite, TableNames},
+            EventStorage,
+        },
+        Amount, Invoice, InvoiceEvent, SubIndex,
+    };
+    #[cfg(feature = "postgres")]
+    use testing_utils::new_postgres_db;
+
+    fn dummy_event(height: u64) -> InvoiceEvent {
+        let invoice = Invoice::new(
+            "testAddress".to_string(),
+            "testWallet".to_string(),
+            SubIndex::new(0, 1),
+            height,
+            Amount::from_pico(1),
+            5,
+            10,
+            "test description".to_string(),
+        );
+        InvoiceEvent::Created { invoice }
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn append_and_replay<S, E>(mut store: S)
+    where
+        S: EventStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let first = store.append_event(dummy_event(1)).unwrap();
+        let second = store.append_event(dummy_event(2)).unwrap();
+        let third = store.append_event(dummy_event(3)).unwrap();
+        assert_eq!(first, 1);
+        assert!(first < second && second < third);
+
+        assert_eq!(
+            store.events_from(0, usize::MAX).unwrap(),
+            [
+                (first, dummy_event(1)),
+                (second, dummy_event(2)),
+                (third, dummy_event(3))
+            ]
+        );
+        assert_eq!(
+            store.events_from(second, usize::MAX).unwrap(),
+            [(second, dummy_event(2)), (third, dummy_event(3))]
+        );
+        assert!(store.events_from(third + 1, usize::MAX).unwrap().is_empty());
+
+        // The log can be read a page at a time.
+        assert_eq!(
+            store.events_from(0, 2).unwrap(),
+            [(first, dummy_event(1)), (second, dummy_event(2))]
+        );
+        assert_eq!(
+            store.events_from(second + 1, 2).unwrap(),
+            [(third, dummy_event(3))]
+        );
+        assert!(store.events_from(0, 0).unwrap().is_empty());
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn prune<S, E>(mut store: S)
+    where
+        S: EventStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        store.append_event(dummy_event(1)).unwrap();
+        let second = store.append_event(dummy_event(2)).unwrap();
+
+        store.prune_events(second).unwrap();
+        assert_eq!(
+            store.events_from(0, usize::MAX).unwrap(),
+            [(second, dummy_event(2))]
+        );
+
+        // Sequence numbers are not reused after pruning.
+        store.prune_events(second + 1).unwrap();
+        assert!(store.events_from(0, usize::MAX).unwrap().is_empty());
+        let third = store.append_event(dummy_event(3)).unwrap();
+        assert!(third > second);
+    }
+
+    #[test_case(&Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(&InMemory::new(); "in-memory")]
+    #[test_case(&Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn empty<S, E>(store: &S)
+    where
+        S: EventStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        assert!(store.events_from(0, usize::MAX).unwrap().is_empty());
+    }
+}
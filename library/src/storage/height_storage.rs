@@ -29,14 +29,20 @@ mod test {
     use test_case::test_case;
     use testing_utils::new_temp_dir;
 
+    #[cfg(feature = "postgres")]
+    use crate::storage::stores::Postgres;
     use crate::storage::{
-        stores::{InMemory, Sled, Sqlite},
+        stores::{InMemory, Redb, Sled, Sqlite, TableNames},
         HeightStorage,
     };
+    #[cfg(feature = "postgres")]
+    use testing_utils::new_postgres_db;
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn upsert_and_check<S, E>(mut store: S)
     where
         S: HeightStorage<Error = E> + 'static,
@@ -46,9 +52,11 @@ mod test {
         assert_eq!(store.get().unwrap(), Some(123));
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn upsert_existing<S, E>(mut store: S)
     where
         S: HeightStorage<Error = E> + 'static,
@@ -61,9 +69,11 @@ mod test {
         assert_eq!(store.get().unwrap(), Some(124));
     }
 
-    #[test_case(&Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(&Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(&InMemory::new(); "in-memory")]
-    #[test_case(&Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(&Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn doesnt_contain_key<S, E>(store: &S)
     where
         S: HeightStorage<Error = E> + 'static,
@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+
+use super::{InvoicePage, InvoiceQuery};
 use crate::{Invoice, InvoiceId, SubIndex};
 
 /// The [`InvoiceStorage`] trait describes the invoice storage layer for
@@ -10,8 +13,9 @@ pub trait InvoiceStorage: Send + Sync {
     ///
     /// # Errors
     ///
-    /// Returns an error if the invoice could not be inserted, or if it already
-    /// exists.
+    /// Returns an error if the invoice could not be inserted, if it already
+    /// exists, or if another invoice in storage has the same
+    /// [reference](Invoice::reference).
     fn insert(&mut self, invoice: Invoice) -> Result<(), Self::Error>;
 
     /// Remove invoice from storage, returning the invoice if it existed.
@@ -22,7 +26,9 @@ pub trait InvoiceStorage: Send + Sync {
     fn remove(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error>;
 
     /// Update existing invoice in storage, returning old value if it existed.
-    /// If the invoice does not already exist, does nothing.
+    /// If the invoice does not already exist, does nothing. An invoice's
+    /// [reference](Invoice::reference) never changes, so implementations need
+    /// not update an index of references.
     ///
     /// # Errors
     ///
@@ -36,6 +42,14 @@ pub trait InvoiceStorage: Send + Sync {
     /// Returns an error if the invoice could not read.
     fn get(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error>;
 
+    /// Retrieve the invoice with the given [reference](Invoice::reference) from
+    /// storage, returning `None` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the invoice could not read.
+    fn get_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error>;
+
     /// Retrieve all currently-tracked invoice ids from storage.
     ///
     /// # Errors
@@ -94,6 +108,37 @@ pub trait InvoiceStorage: Send + Sync {
 
         Ok(lowest)
     }
+
+    /// Returns the page of invoices selected by `query`, in ascending order of
+    /// ID.
+    ///
+    /// The default implementation iterates over every invoice. Stores which
+    /// can read invoices in order of ID, or filter them in the database,
+    /// should override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the invoices could not be read.
+    fn query(&self, query: &InvoiceQuery) -> Result<InvoicePage, Self::Error> {
+        let limit = query.get_limit();
+        let mut selected = BTreeMap::new();
+        self.try_for_each(|invoice_or_err| {
+            let invoice = invoice_or_err?;
+            if query.get_after().iter().all(|&after| invoice.id() > after)
+                && query.matches(&invoice)
+            {
+                selected.insert(invoice.id(), invoice);
+                // Keep one more than the limit, to tell whether there is
+                // another page.
+                if selected.len() > limit.saturating_add(1) {
+                    selected.pop_last();
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(InvoicePage::new(selected.into_values().collect(), limit))
+    }
 }
 
 #[cfg(test)]
@@ -104,20 +149,25 @@ mod test {
     use test_case::test_case;
     use testing_utils::new_temp_dir;
 
+    #[cfg(feature = "postgres")]
+    use crate::storage::stores::Postgres;
     use crate::{
         storage::{
-            stores::{InMemory, Sled, Sqlite},
-            InvoiceStorage,
+            stores::{InMemory, Redb, Sled, Sqlite, TableNames},
+            InvoiceQuery, InvoiceStatus, InvoiceStorage,
         },
-        Invoice, SubIndex,
+        Amount, Invoice, SubIndex,
     };
+    #[cfg(feature = "postgres")]
+    use testing_utils::new_postgres_db;
 
     fn dummy_invoice() -> Invoice {
         Invoice::new(
             "4a1wsbqdcbucqt3dagfmqvfchxscf43m6c5r4b6jxt3duwualncu9xtenrpmumcb3c16kvp9y7thflcj5bamw3umsy93w3w".to_string(),
+            "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf".to_string(),
             SubIndex::new(123, 123),
             123,
-            1,
+            Amount::from_pico(1),
             1,
             1,
             "description".to_string(),
@@ -127,18 +177,21 @@ mod test {
     fn dummy_invoice_2() -> Invoice {
         Invoice::new(
             "4A1WSBQdCbUCqt3DaGfmqVFchXScF43M6c5r4B6JXT3dUwuALncU9XTEnRPmUMcB3c16kVP9Y7thFLCJ5BaMW3UmSy93w3w".to_string(),
+            "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf".to_string(),
             SubIndex::new(321, 321),
             321,
-           2,
+            Amount::from_pico(2),
             2,
             2,
             "description_2".to_string(),
         )
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn insert_and_get<S, E>(mut store: S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -149,9 +202,11 @@ mod test {
         assert_eq!(store.get(invoice.id()).unwrap(), Some(invoice));
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn insert_existing<S, E>(mut store: S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -169,9 +224,11 @@ mod test {
         assert_ne!(store.get(invoice.id()).unwrap(), Some(invoice));
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn remove<S, E>(mut store: S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -185,9 +242,11 @@ mod test {
         assert_eq!(store.get(invoice.id()).unwrap(), None);
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn remove_non_existent<S, E>(mut store: S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -200,9 +259,11 @@ mod test {
         assert_eq!(store.get(invoice.id()).unwrap(), None);
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn update<S, E>(mut store: S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -221,9 +282,11 @@ mod test {
         assert_eq!(store.get(invoice.id()).unwrap(), Some(updated_invoice));
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn update_empty<S, E>(mut store: S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -234,9 +297,11 @@ mod test {
         assert_eq!(store.get(invoice.id()).unwrap(), None);
     }
 
-    #[test_case(&Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(&Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(&InMemory::new(); "in-memory")]
-    #[test_case(&Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(&Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn get_non_existent<S, E>(store: &S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -248,9 +313,11 @@ mod test {
         assert_eq!(store.get(invoice.id()).unwrap(), None);
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn get_ids<S, E>(mut store: S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -274,9 +341,11 @@ mod test {
         assert_eq!(expected_ids, actual_ids);
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn contains_subindex<S, E>(mut store: S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -288,9 +357,11 @@ mod test {
         assert!(store.contains_sub_index(SubIndex::new(123, 123)).unwrap());
     }
 
-    #[test_case(&Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(&Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(&InMemory::new(); "in-memory")]
-    #[test_case(&Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(&Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn doesnt_contain_subindex<S, E>(store: &S)
     where
         S: InvoiceStorage<Error = E> + 'static,
@@ -299,9 +370,11 @@ mod test {
         assert!(!store.contains_sub_index(SubIndex::new(123, 123)).unwrap());
     }
 
-    #[test_case(&mut Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(&mut Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(&mut InMemory::new(); "in-memory")]
-    #[test_case(&mut Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(&mut Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&mut Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&mut Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn for_each<S, E>(store: &mut S)
     where
         S: InvoiceStorage<Error = E>,
@@ -322,9 +395,11 @@ mod test {
         assert_eq!(count, 1);
     }
 
-    #[test_case(&mut Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(&mut Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(&mut InMemory::new(); "in-memory")]
-    #[test_case(&mut Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(&mut Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&mut Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&mut Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn for_each_empty<S, E>(store: &mut S)
     where
         S: InvoiceStorage<Error = E>,
@@ -344,9 +419,11 @@ mod test {
         assert_eq!(count, 0);
     }
 
-    #[test_case(&mut Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(&mut Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(&mut InMemory::new(); "in-memory")]
-    #[test_case(&mut Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(&mut Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&mut Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&mut Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn is_empty<S, E>(store: &mut S)
     where
         S: InvoiceStorage<Error = E>,
@@ -362,9 +439,11 @@ mod test {
         assert!(store.is_empty().unwrap());
     }
 
-    #[test_case(&mut Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(&mut Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(&mut InMemory::new(); "in-memory")]
-    #[test_case(&mut Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(&mut Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&mut Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&mut Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn lowest_height<S, E>(store: &mut S)
     where
         S: InvoiceStorage<Error = E>,
@@ -377,4 +456,186 @@ mod test {
 
         assert_eq!(store.lowest_height().unwrap(), Some(0));
     }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn get_by_reference<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let mut invoice = dummy_invoice();
+        invoice.reference = Some("order 1".to_string());
+        // Invoices without a reference don't conflict with each other.
+        let unreferenced = dummy_invoice_2();
+        store.insert(invoice.clone()).unwrap();
+        store.insert(unreferenced.clone()).unwrap();
+
+        assert_eq!(
+            store.get_by_reference("order 1").unwrap(),
+            Some(invoice.clone())
+        );
+        assert_eq!(store.get_by_reference("order 2").unwrap(), None);
+
+        // References are unique.
+        store.remove(unreferenced.id()).unwrap();
+        let mut duplicate = unreferenced;
+        duplicate.reference = Some("order 1".to_string());
+        store
+            .insert(duplicate.clone())
+            .expect_err("inserting invoice with existing reference should fail");
+        assert_eq!(store.get(duplicate.id()).unwrap(), None);
+        assert_eq!(
+            store.get_by_reference("order 1").unwrap(),
+            Some(invoice.clone())
+        );
+
+        // Removing an invoice frees its reference.
+        store.remove(invoice.id()).unwrap();
+        assert_eq!(store.get_by_reference("order 1").unwrap(), None);
+        store.insert(duplicate.clone()).unwrap();
+        assert_eq!(store.get_by_reference("order 1").unwrap(), Some(duplicate));
+    }
+
+    /// Invoices with subaddress indices and creation heights which sort
+    /// differently as numbers than as little-endian bytes.
+    fn query_invoices() -> Vec<Invoice> {
+        [
+            (0, 1, 300),
+            (0, 256, 10),
+            (1, 0, 256),
+            (1, 0, 1_000),
+            (256, 2, 20),
+        ]
+        .into_iter()
+        .map(|(major, minor, height)| {
+            let mut invoice = Invoice::new(
+                "testAddress".to_string(),
+                "testWallet".to_string(),
+                SubIndex::new(major, minor),
+                height,
+                Amount::from_pico(height),
+                1,
+                10,
+                "description".to_string(),
+            );
+            invoice.current_height = height;
+            invoice
+        })
+        .collect()
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn query<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let invoices = query_invoices();
+        for invoice in &invoices {
+            store.insert(invoice.clone()).unwrap();
+        }
+
+        // Results are ordered by ID.
+        let page = store.query(&InvoiceQuery::new()).unwrap();
+        assert_eq!(page.invoices, invoices);
+        assert_eq!(page.next, None);
+
+        let page = store
+            .query(
+                &InvoiceQuery::new()
+                    .min_creation_height(20)
+                    .max_creation_height(300),
+            )
+            .unwrap();
+        assert_eq!(
+            page.invoices,
+            [
+                invoices[0].clone(),
+                invoices[2].clone(),
+                invoices[4].clone()
+            ]
+        );
+
+        let page = store
+            .query(
+                &InvoiceQuery::new()
+                    .min_amount_requested(256)
+                    .statuses([InvoiceStatus::Unpaid]),
+            )
+            .unwrap();
+        assert_eq!(
+            page.invoices,
+            [
+                invoices[0].clone(),
+                invoices[2].clone(),
+                invoices[3].clone()
+            ]
+        );
+
+        let page = store
+            .query(&InvoiceQuery::new().statuses([InvoiceStatus::Paid]))
+            .unwrap();
+        assert!(page.invoices.is_empty());
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn query_pages<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let invoices = query_invoices();
+        for invoice in &invoices {
+            store.insert(invoice.clone()).unwrap();
+        }
+
+        let query = InvoiceQuery::new().max_creation_height(999).limit(2);
+        let mut pages = Vec::new();
+        let mut page = store.query(&query).unwrap();
+        while let Some(next) = page.next {
+            pages.push(page.invoices);
+            page = store.query(&query.clone().after(next)).unwrap();
+        }
+        pages.push(page.invoices);
+
+        assert_eq!(
+            pages,
+            [
+                vec![invoices[0].clone(), invoices[1].clone()],
+                vec![invoices[2].clone(), invoices[4].clone()]
+            ]
+        );
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn query_unbounded_limit<S, E>(mut store: S)
+    where
+        S: InvoiceStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let invoices = query_invoices();
+        for invoice in &invoices {
+            store.insert(invoice.clone()).unwrap();
+        }
+
+        let page = store.query(&InvoiceQuery::new().limit(usize::MAX)).unwrap();
+        assert_eq!(page.invoices, invoices);
+        assert_eq!(page.next, None);
+    }
 }
@@ -3,25 +3,48 @@
 //! can implement the [`Storage`] trait themselves for a custom storage
 //! solution.
 
+mod archive_storage;
+mod async_storage;
+#[cfg(feature = "serde")]
+pub mod dump;
+mod event_storage;
 mod height_storage;
 mod invoice_storage;
 mod output_key_storage;
+mod query;
+#[cfg(feature = "bincode")]
+mod schema;
 pub mod stores;
+mod subaddress_storage;
 
+pub use archive_storage::ArchiveStorage;
+pub(crate) use async_storage::AsyncStorage;
+pub use event_storage::EventStorage;
 pub use height_storage::HeightStorage;
 pub use invoice_storage::InvoiceStorage;
 use log::error;
-pub use output_key_storage::{OutputId, OutputKeyStorage, OutputPubKey};
+pub use output_key_storage::{OutputId, OutputKeyStats, OutputKeyStorage, OutputPubKey};
+pub use query::{InvoicePage, InvoiceQuery, InvoiceStatus, DEFAULT_QUERY_LIMIT};
+#[cfg(feature = "bincode")]
+pub use schema::{SchemaError, SCHEMA_VERSION};
+pub use subaddress_storage::SubaddressStorage;
 use thiserror::Error;
 use tokio::sync::{
     mpsc::{self},
     oneshot,
 };
 
-use crate::{Invoice, InvoiceId, SubIndex};
+use crate::{Invoice, InvoiceEvent, InvoiceId, SubIndex};
 
 /// A supertrait of all necessary storage traits.
-pub trait Storage: InvoiceStorage + OutputKeyStorage + HeightStorage {
+pub trait Storage:
+    InvoiceStorage
+    + OutputKeyStorage
+    + HeightStorage
+    + SubaddressStorage
+    + EventStorage
+    + ArchiveStorage
+{
     /// Error type for the storage layer.
     type Error: std::error::Error + Send + 'static;
 
@@ -46,6 +69,7 @@ struct Manager<S: Storage> {
 }
 
 impl<S: Storage> Manager<S> {
+    #[allow(clippy::too_many_lines)]
     fn handle(&mut self, message: Method<S>) {
         match message {
             // Invoice storage methods.
@@ -83,12 +107,29 @@ impl<S: Storage> Manager<S> {
                     );
                 };
             }
+            Method::GetInvoiceByReference {
+                reference,
+                response,
+            } => {
+                let invoice = InvoiceStorage::get_by_reference(&self.store, &reference);
+                if response.send(invoice).is_err() {
+                    error!(
+                        "Failed to send GetInvoiceByReference response to storage client. Reference: {reference}"
+                    );
+                }
+            }
             Method::GetInvoiceIds { response } => {
                 let invoice_ids = InvoiceStorage::get_ids(&self.store);
                 if response.send(invoice_ids).is_err() {
                     error!("Failed to send GetInvoiceIds response to storage client.");
                 };
             }
+            Method::QueryInvoices { query, response } => {
+                let page = InvoiceStorage::query(&self.store, &query);
+                if response.send(page).is_err() {
+                    error!("Failed to send QueryInvoices response to storage client.");
+                }
+            }
             Method::ContainsSubIndex { index, response } => {
                 if response.send(self.store.contains_sub_index(index)).is_err() {
                     error!(
@@ -97,8 +138,19 @@ impl<S: Storage> Manager<S> {
                     );
                 }
             }
-            Method::ForEachInvoice { f, response } => {
-                let result = self.store.try_for_each(f);
+            Method::ForEachInvoice { mut f, response } => {
+                // Errors returned by `f` can't be passed through the store, so
+                // stop calling `f` after the first one and return it afterwards.
+                let mut result = Ok(());
+                let for_each = self.store.try_for_each(|invoice_or_err| {
+                    if result.is_ok() {
+                        result = f(invoice_or_err.map_err(|e| StorageError::Internal(Box::new(e))));
+                    }
+                    Ok(())
+                });
+                let result = for_each
+                    .map_err(|e| StorageError::Internal(Box::new(e)))
+                    .and(result);
                 if response.send(result).is_err() {
                     error!("Failed to send ForEachInvoice response to storage client.");
                 };
@@ -134,21 +186,144 @@ impl<S: Storage> Manager<S> {
             Method::InsertOutputKey {
                 key,
                 output_id,
+                height,
                 response,
             } => {
                 if response
-                    .send(OutputKeyStorage::insert(&mut self.store, key, output_id))
+                    .send(OutputKeyStorage::insert(
+                        &mut self.store,
+                        key,
+                        output_id,
+                        height,
+                    ))
                     .is_err()
                 {
                     error!("Failed to send InsertOutputKey response to storage client.");
                 };
             }
+            Method::PruneOutputKeys { height, response } => {
+                if response.send(self.store.prune_keys(height)).is_err() {
+                    error!("Failed to send PruneOutputKeys response to storage client.");
+                }
+            }
+            Method::OutputKeyStats(response) => {
+                if response.send(self.store.key_stats()).is_err() {
+                    error!("Failed to send OutputKeyStats response to storage client.");
+                }
+            }
+
+            Method::InsertFundedSubaddress {
+                wallet,
+                sub_index,
+                response,
+            } => {
+                if response
+                    .send(self.store.insert_funded(&wallet, sub_index))
+                    .is_err()
+                {
+                    error!(
+                        "Failed to send InsertFundedSubaddress response to storage client. Index: {}",
+                        sub_index
+                    );
+                }
+            }
+            Method::GetFundedSubaddresses(response) => {
+                if response.send(self.store.funded()).is_err() {
+                    error!("Failed to send GetFundedSubaddresses response to storage client.");
+                }
+            }
+
+            Method::AppendEvent { event, response } => {
+                if response.send(self.store.append_event(event)).is_err() {
+                    error!("Failed to send AppendEvent response to storage client.");
+                }
+            }
+            Method::GetEventsFrom {
+                sequence,
+                limit,
+                response,
+            } => {
+                if response
+                    .send(self.store.events_from(sequence, limit))
+                    .is_err()
+                {
+                    error!(
+                        "Failed to send GetEventsFrom response to storage client. Sequence: {}",
+                        sequence
+                    );
+                }
+            }
+            Method::PruneEvents { sequence, response } => {
+                if response.send(self.store.prune_events(sequence)).is_err() {
+                    error!(
+                        "Failed to send PruneEvents response to storage client. Sequence: {}",
+                        sequence
+                    );
+                }
+            }
+
+            Method::ArchiveInvoice { id, response } => {
+                if response.send(self.store.archive(id)).is_err() {
+                    error!(
+                        "Failed to send ArchiveInvoice response to storage client. Invoice ID: {id}"
+                    );
+                }
+            }
+            Method::GetArchivedInvoice { id, response } => {
+                if response.send(self.store.get_archived(id)).is_err() {
+                    error!(
+                        "Failed to send GetArchivedInvoice response to storage client. Invoice ID: {id}"
+                    );
+                }
+            }
+            Method::GetArchivedInvoiceByReference {
+                reference,
+                response,
+            } => {
+                let invoice = self.store.get_archived_by_reference(&reference);
+                if response.send(invoice).is_err() {
+                    error!(
+                        "Failed to send GetArchivedInvoiceByReference response to storage client. Reference: {reference}"
+                    );
+                }
+            }
+            Method::ForEachArchivedInvoice { mut f, response } => {
+                // As with `ForEachInvoice`, errors returned by `f` are held until
+                // the store finishes iterating.
+                let mut result = Ok(());
+                let for_each = self.store.try_for_each_archived(|invoice_or_err| {
+                    if result.is_ok() {
+                        result = f(invoice_or_err.map_err(|e| StorageError::Internal(Box::new(e))));
+                    }
+                    Ok(())
+                });
+                let result = for_each
+                    .map_err(|e| StorageError::Internal(Box::new(e)))
+                    .and(result);
+                if response.send(result).is_err() {
+                    error!("Failed to send ForEachArchivedInvoice response to storage client.");
+                }
+            }
+            Method::PruneArchive { height, response } => {
+                if response.send(self.store.prune_archive(height)).is_err() {
+                    error!(
+                        "Failed to send PruneArchive response to storage client. Height: {}",
+                        height
+                    );
+                }
+            }
 
             Method::Flush(response) => {
                 if response.send(self.store.flush()).is_err() {
                     error!("Failed to send Flush response to storage client.");
                 };
             }
+            #[cfg(feature = "serde")]
+            Method::Export { writer, response } => {
+                if response.send(dump::export(&self.store, writer)).is_err() {
+                    error!("Failed to send Export response to storage client.");
+                }
+            }
         }
     }
 }
@@ -170,16 +345,24 @@ enum Method<S: Storage> {
         id: InvoiceId,
         response: oneshot::Sender<Result<Option<Invoice>, <S as InvoiceStorage>::Error>>,
     },
+    GetInvoiceByReference {
+        reference: String,
+        response: oneshot::Sender<Result<Option<Invoice>, <S as InvoiceStorage>::Error>>,
+    },
     GetInvoiceIds {
         response: oneshot::Sender<Result<Vec<InvoiceId>, <S as InvoiceStorage>::Error>>,
     },
+    QueryInvoices {
+        query: InvoiceQuery,
+        response: oneshot::Sender<Result<InvoicePage, <S as InvoiceStorage>::Error>>,
+    },
     ContainsSubIndex {
         index: SubIndex,
         response: oneshot::Sender<Result<bool, <S as InvoiceStorage>::Error>>,
     },
     ForEachInvoice {
-        f: Box<ForEachClosure<S>>,
-        response: oneshot::Sender<Result<(), <S as InvoiceStorage>::Error>>,
+        f: Box<ForEachClosure>,
+        response: oneshot::Sender<Result<(), StorageError>>,
     },
     LowestInvoiceHeight(oneshot::Sender<Result<Option<u64>, <S as InvoiceStorage>::Error>>),
     GetHeight(oneshot::Sender<Result<Option<u64>, <S as HeightStorage>::Error>>),
@@ -194,17 +377,86 @@ enum Method<S: Storage> {
     InsertOutputKey {
         key: OutputPubKey,
         output_id: OutputId,
+        height: u64,
         response: oneshot::Sender<Result<(), <S as OutputKeyStorage>::Error>>,
     },
+    PruneOutputKeys {
+        height: u64,
+        response: oneshot::Sender<Result<u64, <S as OutputKeyStorage>::Error>>,
+    },
+    OutputKeyStats(oneshot::Sender<Result<OutputKeyStats, <S as OutputKeyStorage>::Error>>),
+    InsertFundedSubaddress {
+        wallet: String,
+        sub_index: SubIndex,
+        response: oneshot::Sender<Result<(), <S as SubaddressStorage>::Error>>,
+    },
+    GetFundedSubaddresses(
+        oneshot::Sender<Result<Vec<FundedSubaddress>, <S as SubaddressStorage>::Error>>,
+    ),
+    AppendEvent {
+        event: InvoiceEvent,
+        response: oneshot::Sender<Result<u64, <S as EventStorage>::Error>>,
+    },
+    GetEventsFrom {
+        sequence: u64,
+        limit: usize,
+        response: oneshot::Sender<Result<Vec<LoggedEvent>, <S as EventStorage>::Error>>,
+    },
+    PruneEvents {
+        sequence: u64,
+        response: oneshot::Sender<Result<(), <S as EventStorage>::Error>>,
+    },
+    ArchiveInvoice {
+        id: InvoiceId,
+        response: oneshot::Sender<Result<Option<Invoice>, <S as ArchiveStorage>::Error>>,
+    },
+    GetArchivedInvoice {
+        id: InvoiceId,
+        response: oneshot::Sender<Result<Option<Invoice>, <S as ArchiveStorage>::Error>>,
+    },
+    GetArchivedInvoiceByReference {
+        reference: String,
+        response: oneshot::Sender<Result<Option<Invoice>, <S as ArchiveStorage>::Error>>,
+    },
+    ForEachArchivedInvoice {
+        f: Box<ForEachClosure>,
+        response: oneshot::Sender<Result<(), StorageError>>,
+    },
+    PruneArchive {
+        height: u64,
+        response: oneshot::Sender<Result<(), <S as ArchiveStorage>::Error>>,
+    },
     Flush(oneshot::Sender<Result<(), <S as Storage>::Error>>),
+    #[cfg(feature = "serde")]
+    Export {
+        writer: Box<dyn std::io::Write + Send>,
+        response: oneshot::Sender<Result<(), dump::DumpError>>,
+    },
 }
 
-type ForEachClosure<S> = dyn FnMut(Result<Invoice, <S as InvoiceStorage>::Error>) -> Result<(), <S as InvoiceStorage>::Error>
-    + Send;
+/// An [`InvoiceEvent`] along with its sequence number in the event log.
+type LoggedEvent = (u64, InvoiceEvent);
+
+/// Wallet and index of a subaddress which received funds.
+type FundedSubaddress = (String, SubIndex);
 
+type ForEachClosure = dyn FnMut(Result<Invoice, StorageError>) -> Result<(), StorageError> + Send;
+
+/// An [`AsyncStorage`] handle to a synchronous [`Storage`] implementation.
+///
+/// The store is moved onto a dedicated task, and every call made through the
+/// handle is sent to that task over a channel and run there in order. Cloned
+/// handles share the same task.
 pub(crate) struct Client<S: Storage>(mpsc::Sender<Method<S>>);
 
 impl<S: Storage + 'static> Client<S> {
+    /// Moves `store` onto a new storage task and returns a handle to it. The
+    /// task stops once every handle has been dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from outside of a tokio runtime.
+    #[must_use]
     pub(crate) fn new(store: S) -> Self {
         let (sender, receiver) = mpsc::channel(64);
         let mut manager = Manager { store, receiver };
@@ -217,8 +469,10 @@ impl<S: Storage + 'static> Client<S> {
 
         Self(sender)
     }
+}
 
-    pub(crate) async fn insert_invoice(&self, invoice: Invoice) -> Result<(), StorageError> {
+impl<S: Storage + 'static> AsyncStorage for Client<S> {
+    async fn insert_invoice(&self, invoice: Invoice) -> Result<(), StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::InsertInvoice {
@@ -231,10 +485,7 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn remove_invoice(
-        &self,
-        id: InvoiceId,
-    ) -> Result<Option<Invoice>, StorageError> {
+    async fn remove_invoice(&self, id: InvoiceId) -> Result<Option<Invoice>, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::RemoveInvoice {
@@ -247,10 +498,7 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn update_invoice(
-        &self,
-        invoice: Invoice,
-    ) -> Result<Option<Invoice>, StorageError> {
+    async fn update_invoice(&self, invoice: Invoice) -> Result<Option<Invoice>, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::UpdateInvoice {
@@ -263,7 +511,7 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn get_invoice(&self, id: InvoiceId) -> Result<Option<Invoice>, StorageError> {
+    async fn get_invoice(&self, id: InvoiceId) -> Result<Option<Invoice>, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::GetInvoice {
@@ -276,7 +524,23 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn get_invoice_ids(&self) -> Result<Vec<InvoiceId>, StorageError> {
+    async fn get_invoice_by_reference(
+        &self,
+        reference: String,
+    ) -> Result<Option<Invoice>, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::GetInvoiceByReference {
+                reference,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn get_invoice_ids(&self) -> Result<Vec<InvoiceId>, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::GetInvoiceIds { response: sender })
@@ -286,7 +550,20 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn contains_sub_index(&self, index: SubIndex) -> Result<bool, StorageError> {
+    async fn query_invoices(&self, query: InvoiceQuery) -> Result<InvoicePage, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::QueryInvoices {
+                query,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn contains_sub_index(&self, index: SubIndex) -> Result<bool, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::ContainsSubIndex {
@@ -299,13 +576,9 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn try_for_each_invoice<F>(&self, f: F) -> Result<(), StorageError>
+    async fn try_for_each_invoice<F>(&self, f: F) -> Result<(), StorageError>
     where
-        F: FnMut(
-                Result<Invoice, <S as InvoiceStorage>::Error>,
-            ) -> Result<(), <S as InvoiceStorage>::Error>
-            + Send
-            + 'static,
+        F: FnMut(Result<Invoice, StorageError>) -> Result<(), StorageError> + Send + 'static,
     {
         let (sender, receiver) = oneshot::channel();
         self.0
@@ -315,11 +588,10 @@ impl<S: Storage + 'static> Client<S> {
             })
             .await
             .map_err(|e| StorageError::Send(Box::new(e)))?;
-        let response = receiver.await.map_err(|_| StorageError::Receive)?;
-        response.map_err(|e| StorageError::Internal(Box::new(e)))
+        receiver.await.map_err(|_| StorageError::Receive)?
     }
 
-    pub(crate) async fn lowest_invoice_height(&self) -> Result<Option<u64>, StorageError> {
+    async fn lowest_invoice_height(&self) -> Result<Option<u64>, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::LowestInvoiceHeight(sender))
@@ -329,7 +601,7 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn get_height(&self) -> Result<Option<u64>, StorageError> {
+    async fn get_height(&self) -> Result<Option<u64>, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::GetHeight(sender))
@@ -339,7 +611,7 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn upsert_height(&self, height: u64) -> Result<Option<u64>, StorageError> {
+    async fn upsert_height(&self, height: u64) -> Result<Option<u64>, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::UpsertHeight {
@@ -352,10 +624,7 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn get_output_key_id(
-        &self,
-        key: OutputPubKey,
-    ) -> Result<Option<OutputId>, StorageError> {
+    async fn get_output_key_id(&self, key: OutputPubKey) -> Result<Option<OutputId>, StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::GetOutputKeyId {
@@ -368,16 +637,59 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn insert_output_key(
+    async fn insert_output_key(
         &self,
         key: OutputPubKey,
         output_id: OutputId,
+        height: u64,
     ) -> Result<(), StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::InsertOutputKey {
                 key,
                 output_id,
+                height,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn prune_output_keys(&self, height: u64) -> Result<u64, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::PruneOutputKeys {
+                height,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn output_key_stats(&self) -> Result<OutputKeyStats, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::OutputKeyStats(sender))
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn insert_funded_subaddress(
+        &self,
+        wallet: String,
+        sub_index: SubIndex,
+    ) -> Result<(), StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::InsertFundedSubaddress {
+                wallet,
+                sub_index,
                 response: sender,
             })
             .await
@@ -386,7 +698,131 @@ impl<S: Storage + 'static> Client<S> {
         response.map_err(|e| StorageError::Internal(Box::new(e)))
     }
 
-    pub(crate) async fn flush(&self) -> Result<(), StorageError> {
+    async fn get_funded_subaddresses(&self) -> Result<Vec<(String, SubIndex)>, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::GetFundedSubaddresses(sender))
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn append_event(&self, event: InvoiceEvent) -> Result<u64, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::AppendEvent {
+                event,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn get_events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::GetEventsFrom {
+                sequence,
+                limit,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn prune_events(&self, sequence: u64) -> Result<(), StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::PruneEvents {
+                sequence,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn archive_invoice(&self, id: InvoiceId) -> Result<Option<Invoice>, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::ArchiveInvoice {
+                id,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn get_archived_invoice(&self, id: InvoiceId) -> Result<Option<Invoice>, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::GetArchivedInvoice {
+                id,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn get_archived_invoice_by_reference(
+        &self,
+        reference: String,
+    ) -> Result<Option<Invoice>, StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::GetArchivedInvoiceByReference {
+                reference,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn try_for_each_archived_invoice<F>(&self, f: F) -> Result<(), StorageError>
+    where
+        F: FnMut(Result<Invoice, StorageError>) -> Result<(), StorageError> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::ForEachArchivedInvoice {
+                f: Box::new(f),
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        receiver.await.map_err(|_| StorageError::Receive)?
+    }
+
+    async fn prune_archive(&self, height: u64) -> Result<(), StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::PruneArchive {
+                height,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+
+    async fn flush(&self) -> Result<(), StorageError> {
         let (sender, receiver) = oneshot::channel();
         self.0
             .send(Method::Flush(sender))
@@ -397,6 +833,25 @@ impl<S: Storage + 'static> Client<S> {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<S: Storage + 'static> Client<S> {
+    pub(crate) async fn export(
+        &self,
+        writer: Box<dyn std::io::Write + Send>,
+    ) -> Result<(), StorageError> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .send(Method::Export {
+                writer,
+                response: sender,
+            })
+            .await
+            .map_err(|e| StorageError::Send(Box::new(e)))?;
+        let response = receiver.await.map_err(|_| StorageError::Receive)?;
+        response.map_err(|e| StorageError::Internal(Box::new(e)))
+    }
+}
+
 impl<S: Storage> Clone for Client<S> {
     fn clone(&self) -> Self {
         Self(self.0.clone())
@@ -6,17 +6,29 @@ use serde::{Deserialize, Serialize};
 /// The [`OutputKeyStorage`] trait describes the output public key storage layer
 /// for `AcceptXMR`. This layer is necessary for protection against the [burning
 /// bug](https://www.getmonero.org/2018/09/25/a-post-mortum-of-the-burning-bug.html).
+///
+/// Every owned output's key is kept, so this layer grows for as long as the
+/// payment gateway runs. Keys can be [pruned](OutputKeyStorage::prune_keys)
+/// to bound its size, but a burning bug attack reusing the key of a pruned
+/// output will not be detected.
 pub trait OutputKeyStorage: Send + Sync {
     /// Error type for the storage layer.
     type Error: std::error::Error + Send + 'static;
 
-    /// Insert an output's public key into storage.
+    /// Insert an output's public key into storage, along with the height it
+    /// was found at. Outputs found in the txpool are recorded at the height of
+    /// the next block.
     ///
     /// # Errors
     ///
     /// Returns an error if the key could not be inserted, or if it already
     /// exists.
-    fn insert(&mut self, key: OutputPubKey, output_id: OutputId) -> Result<(), Self::Error>;
+    fn insert(
+        &mut self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> Result<(), Self::Error>;
 
     /// Returns the output ID associated with the given key, if it exists.
     ///
@@ -24,6 +36,65 @@ pub trait OutputKeyStorage: Send + Sync {
     ///
     /// Returns an error if there was an underlying issue with the database.
     fn get(&self, key: OutputPubKey) -> Result<Option<OutputId>, Self::Error>;
+
+    /// Iterates over all output keys in storage, executing the supplied closure
+    /// on each key along with the ID of its output and the height it was
+    /// recorded at.
+    ///
+    /// # Errors
+    ///
+    /// Stops iterating and returns an error if the supplied closure returns an
+    /// error.
+    fn try_for_each_key<F>(&self, f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<(OutputPubKey, OutputId, u64), Self::Error>) -> Result<(), Self::Error>;
+
+    /// Remove every output key recorded at a height less than `height`,
+    /// returning the number of keys removed.
+    ///
+    /// Pruned keys can no longer be used to detect the burning bug. An
+    /// attacker can then get a payment credited twice by sending a new output
+    /// with the same key as a pruned one, so only prune keys at a depth you
+    /// are willing to accept that risk for.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn prune_keys(&mut self, height: u64) -> Result<u64, Self::Error>;
+
+    /// Returns statistics about the output keys in storage.
+    ///
+    /// The default implementation iterates over every key. Stores which can
+    /// count keys in the database should override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn key_stats(&self) -> Result<OutputKeyStats, Self::Error> {
+        let mut stats = OutputKeyStats::default();
+        self.try_for_each_key(|key_or_err| {
+            let (_, _, height) = key_or_err?;
+            stats.count += 1;
+            stats.lowest_height = Some(stats.lowest_height.map_or(height, |h| h.min(height)));
+            stats.highest_height = Some(stats.highest_height.map_or(height, |h| h.max(height)));
+            Ok(())
+        })?;
+        Ok(stats)
+    }
+}
+
+/// Statistics about the output keys in an [`OutputKeyStorage`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OutputKeyStats {
+    /// Number of output keys stored.
+    pub count: u64,
+    /// Lowest height an output key was recorded at, or `None` if there are no
+    /// keys.
+    pub lowest_height: Option<u64>,
+    /// Highest height an output key was recorded at, or `None` if there are
+    /// no keys.
+    pub highest_height: Option<u64>,
 }
 
 /// An output's public key.
@@ -58,10 +129,14 @@ mod test {
     use test_case::test_case;
     use testing_utils::new_temp_dir;
 
+    #[cfg(feature = "postgres")]
+    use crate::storage::stores::Postgres;
     use crate::storage::{
-        stores::{InMemory, Sled, Sqlite},
-        OutputId, OutputKeyStorage, OutputPubKey,
+        stores::{InMemory, Redb, Sled, Sqlite, TableNames},
+        OutputId, OutputKeyStats, OutputKeyStorage, OutputPubKey,
     };
+    #[cfg(feature = "postgres")]
+    use testing_utils::new_postgres_db;
 
     fn dummy_key() -> OutputPubKey {
         OutputPubKey([0; 32])
@@ -74,9 +149,11 @@ mod test {
         }
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn insert_and_check<S, E>(mut store: S)
     where
         S: OutputKeyStorage<Error = E> + 'static,
@@ -84,13 +161,15 @@ mod test {
     {
         let key = dummy_key();
         let output_id = dummy_id();
-        store.insert(key, output_id).unwrap();
+        store.insert(key, output_id, 10).unwrap();
         assert_eq!(store.get(key).unwrap(), Some(output_id));
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn insert_existing<S, E>(mut store: S)
     where
         S: OutputKeyStorage<Error = E> + 'static,
@@ -99,19 +178,21 @@ mod test {
         let key = dummy_key();
         let output_id = dummy_id();
 
-        store.insert(key, output_id).unwrap();
+        store.insert(key, output_id, 10).unwrap();
         assert_eq!(store.get(key).unwrap(), Some(output_id));
 
         store
-            .insert(key, output_id)
+            .insert(key, output_id, 10)
             .expect_err("inserting existing key should fail");
         // Check that the key is still present.
         assert_eq!(store.get(key).unwrap(), Some(output_id));
     }
 
-    #[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
     #[test_case(InMemory::new(); "in-memory")]
-    #[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
     fn doesnt_contain_key<S, E>(mut store: S)
     where
         S: OutputKeyStorage<Error = E> + 'static,
@@ -120,8 +201,86 @@ mod test {
         let key = OutputPubKey([1; 32]);
         let output_id = dummy_id();
 
-        store.insert(key, output_id).unwrap();
+        store.insert(key, output_id, 10).unwrap();
 
         assert!(store.get(dummy_key()).unwrap().is_none());
     }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn for_each_key<S, E>(mut store: S)
+    where
+        S: OutputKeyStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        let other_key = OutputPubKey([1; 32]);
+        let other_id = OutputId {
+            tx_hash: [1; 32],
+            index: 2,
+        };
+        store.insert(dummy_key(), dummy_id(), 10).unwrap();
+        store.insert(other_key, other_id, 20).unwrap();
+
+        let mut keys = Vec::new();
+        store
+            .try_for_each_key(|key_or_err| {
+                keys.push(key_or_err?);
+                Ok(())
+            })
+            .unwrap();
+        keys.sort();
+
+        assert_eq!(
+            keys,
+            vec![(dummy_key(), dummy_id(), 10), (other_key, other_id, 20)]
+        );
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn prune_and_stats<S, E>(mut store: S)
+    where
+        S: OutputKeyStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        assert_eq!(store.key_stats().unwrap(), OutputKeyStats::default());
+
+        for i in 0..5 {
+            let id = OutputId {
+                tx_hash: [i; 32],
+                index: 0,
+            };
+            store
+                .insert(OutputPubKey([i; 32]), id, 100 + u64::from(i))
+                .unwrap();
+        }
+        assert_eq!(
+            store.key_stats().unwrap(),
+            OutputKeyStats {
+                count: 5,
+                lowest_height: Some(100),
+                highest_height: Some(104),
+            }
+        );
+
+        assert_eq!(store.prune_keys(102).unwrap(), 2);
+        assert!(store.get(OutputPubKey([1; 32])).unwrap().is_none());
+        assert!(store.get(OutputPubKey([2; 32])).unwrap().is_some());
+        assert_eq!(
+            store.key_stats().unwrap(),
+            OutputKeyStats {
+                count: 3,
+                lowest_height: Some(102),
+                highest_height: Some(104),
+            }
+        );
+
+        assert_eq!(store.prune_keys(102).unwrap(), 0);
+    }
 }
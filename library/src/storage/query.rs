@@ -0,0 +1,373 @@
+use std::collections::HashSet;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Amount, Invoice, InvoiceId};
+
+/// Number of invoices returned per page when no limit is set.
+pub const DEFAULT_QUERY_LIMIT: usize = 100;
+
+/// Selects a page of invoices from [`InvoiceStorage`](super::InvoiceStorage).
+/// Invoices must pass every criterion set. A query with no criteria selects
+/// every invoice.
+///
+/// Results are ordered by [`InvoiceId`], and split into pages of at most
+/// [`limit`](InvoiceQuery::limit) invoices. To get the next page, repeat the
+/// query with [`after`](InvoiceQuery::after) set to the
+/// [`next`](InvoicePage::next) cursor of the previous page.
+///
+/// # Examples
+///
+/// ```
+/// use acceptxmr::storage::{InvoiceQuery, InvoiceStatus};
+///
+/// // Unpaid invoices created in roughly the last day, as of height 3_000_000.
+/// let query = InvoiceQuery::new()
+///     .statuses([InvoiceStatus::Unpaid])
+///     .min_creation_height(3_000_000 - 720);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceQuery {
+    statuses: Option<HashSet<InvoiceStatus>>,
+    min_creation_height: Option<u64>,
+    max_creation_height: Option<u64>,
+    min_amount_requested: Option<Amount>,
+    max_amount_requested: Option<Amount>,
+    metadata: Vec<(String, Option<Value>)>,
+    after: Option<InvoiceId>,
+    limit: usize,
+}
+
+impl Default for InvoiceQuery {
+    fn default() -> Self {
+        InvoiceQuery {
+            statuses: None,
+            min_creation_height: None,
+            max_creation_height: None,
+            min_amount_requested: None,
+            max_amount_requested: None,
+            metadata: Vec::new(),
+            after: None,
+            limit: DEFAULT_QUERY_LIMIT,
+        }
+    }
+}
+
+impl InvoiceQuery {
+    /// Create a query which selects every invoice, [`DEFAULT_QUERY_LIMIT`] at
+    /// a time.
+    #[must_use]
+    pub fn new() -> InvoiceQuery {
+        InvoiceQuery::default()
+    }
+
+    /// Only select invoices with one of the given statuses. Calling this again
+    /// adds to the statuses selected.
+    #[must_use]
+    pub fn statuses(mut self, statuses: impl IntoIterator<Item = InvoiceStatus>) -> Self {
+        self.statuses
+            .get_or_insert_with(HashSet::new)
+            .extend(statuses);
+        self
+    }
+
+    /// Only select invoices created at or above `height`.
+    #[must_use]
+    pub fn min_creation_height(mut self, height: u64) -> Self {
+        self.min_creation_height = Some(height);
+        self
+    }
+
+    /// Only select invoices created at or below `height`.
+    #[must_use]
+    pub fn max_creation_height(mut self, height: u64) -> Self {
+        self.max_creation_height = Some(height);
+        self
+    }
+
+    /// Only select invoices requesting at least `amount`.
+    #[must_use]
+    pub fn min_amount_requested(mut self, amount: impl Into<Amount>) -> Self {
+        self.min_amount_requested = Some(amount.into());
+        self
+    }
+
+    /// Only select invoices requesting at most `amount`.
+    #[must_use]
+    pub fn max_amount_requested(mut self, amount: impl Into<Amount>) -> Self {
+        self.max_amount_requested = Some(amount.into());
+        self
+    }
+
+    /// Only select invoices whose description is a JSON object with the given
+    /// top-level key, such as the `order` or `callback` recorded by
+    /// `AcceptXMR-Server`. Calling this again adds another key, which must also
+    /// be present.
+    #[must_use]
+    pub fn metadata_key(mut self, key: impl Into<String>) -> Self {
+        self.metadata.push((key.into(), None));
+        self
+    }
+
+    /// Only select invoices whose description is a JSON object with the given
+    /// top-level key set to `value`. Calling this again adds another key, which
+    /// must also match.
+    #[must_use]
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.metadata.push((key.into(), Some(value.into())));
+        self
+    }
+
+    /// Only select invoices with an ID greater than `cursor`, i.e. those
+    /// following the page which `cursor` was returned with.
+    #[must_use]
+    pub fn after(mut self, cursor: InvoiceId) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+
+    /// Return at most `limit` invoices per page. Defaults to
+    /// [`DEFAULT_QUERY_LIMIT`].
+    #[must_use]
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Returns the lowest creation height selected, if any.
+    #[must_use]
+    pub fn get_min_creation_height(&self) -> Option<u64> {
+        self.min_creation_height
+    }
+
+    /// Returns the highest creation height selected, if any.
+    #[must_use]
+    pub fn get_max_creation_height(&self) -> Option<u64> {
+        self.max_creation_height
+    }
+
+    /// Returns the cursor invoices must follow, if any.
+    #[must_use]
+    pub fn get_after(&self) -> Option<InvoiceId> {
+        self.after
+    }
+
+    /// Returns the maximum number of invoices per page.
+    #[must_use]
+    pub fn get_limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns `true` if the invoice passes this query's criteria. The cursor
+    /// and limit are not considered.
+    #[must_use]
+    pub fn matches(&self, invoice: &Invoice) -> bool {
+        self.statuses
+            .iter()
+            .all(|statuses| statuses.contains(&InvoiceStatus::of(invoice)))
+            && self
+                .min_creation_height
+                .iter()
+                .all(|&height| invoice.creation_height() >= height)
+            && self
+                .max_creation_height
+                .iter()
+                .all(|&height| invoice.creation_height() <= height)
+            && self
+                .min_amount_requested
+                .iter()
+                .all(|&amount| invoice.amount_requested() >= amount)
+            && self
+                .max_amount_requested
+                .iter()
+                .all(|&amount| invoice.amount_requested() <= amount)
+            && (self.metadata.is_empty() || self.matches_metadata(invoice))
+    }
+
+    fn matches_metadata(&self, invoice: &Invoice) -> bool {
+        let Ok(metadata) = serde_json::from_str::<Value>(invoice.description()) else {
+            return false;
+        };
+        self.metadata
+            .iter()
+            .all(|(key, value)| match (metadata.get(key), value) {
+                (Some(found), Some(value)) => found == value,
+                (Some(_), None) => true,
+                (None, _) => false,
+            })
+    }
+
+    /// Collect a page of results from invoices in ascending order of ID,
+    /// reading no further than needed to fill it.
+    pub(crate) fn collect_page<E>(
+        &self,
+        invoices: impl IntoIterator<Item = Result<Invoice, E>>,
+    ) -> Result<InvoicePage, E> {
+        let mut selected = Vec::new();
+        for invoice in invoices {
+            let invoice = invoice?;
+            if self.after.iter().all(|&after| invoice.id() > after) && self.matches(&invoice) {
+                selected.push(invoice);
+                // One more than the limit is read, to tell whether there is
+                // another page.
+                if selected.len() > self.limit {
+                    break;
+                }
+            }
+        }
+        Ok(InvoicePage::new(selected, self.limit))
+    }
+}
+
+/// Where an invoice is in its lifecycle.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum InvoiceStatus {
+    /// Not yet paid in full, and not expired.
+    Unpaid,
+    /// Paid in full, but without the required number of confirmations yet.
+    Paid,
+    /// Paid in full, with the required number of confirmations.
+    Confirmed,
+    /// Expired without being paid in full.
+    Expired,
+}
+
+impl InvoiceStatus {
+    /// Returns the status of the invoice.
+    #[must_use]
+    pub fn of(invoice: &Invoice) -> InvoiceStatus {
+        if invoice.is_confirmed() {
+            InvoiceStatus::Confirmed
+        } else if invoice.is_paid() {
+            InvoiceStatus::Paid
+        } else if invoice.is_expired() {
+            InvoiceStatus::Expired
+        } else {
+            InvoiceStatus::Unpaid
+        }
+    }
+}
+
+/// A page of invoices selected by an [`InvoiceQuery`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoicePage {
+    /// The invoices selected, in ascending order of ID.
+    pub invoices: Vec<Invoice>,
+    /// Cursor to pass to [`InvoiceQuery::after`] to get the next page, or
+    /// `None` if this is the last page.
+    pub next: Option<InvoiceId>,
+}
+
+impl InvoicePage {
+    /// Build a page from up to `limit + 1` selected invoices, sorted by ID.
+    /// If there are more than `limit`, there is another page.
+    pub(crate) fn new(mut invoices: Vec<Invoice>, limit: usize) -> InvoicePage {
+        let next = if invoices.len() > limit {
+            invoices.truncate(limit);
+            invoices.last().map(Invoice::id)
+        } else {
+            None
+        };
+        InvoicePage { invoices, next }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use serde_json::json;
+
+    use super::{InvoicePage, InvoiceQuery, InvoiceStatus};
+    use crate::{invoice::Transfer, Amount, Invoice, SubIndex};
+
+    fn invoice(minor: u32, amount: u64, description: &str) -> Invoice {
+        Invoice::new(
+            "testAddress".to_string(),
+            "testWallet".to_string(),
+            SubIndex::new(0, minor),
+            10,
+            Amount::from_pico(amount),
+            1,
+            10,
+            description.to_string(),
+        )
+    }
+
+    #[test]
+    fn status() {
+        let mut invoice = invoice(1, 100, "");
+        invoice.current_height = 15;
+        assert_eq!(InvoiceStatus::of(&invoice), InvoiceStatus::Unpaid);
+
+        invoice.current_height = 20;
+        assert_eq!(InvoiceStatus::of(&invoice), InvoiceStatus::Expired);
+
+        invoice.transfers.push(Transfer::new(100, None));
+        invoice.amount_paid = Amount::from_pico(100);
+        assert_eq!(InvoiceStatus::of(&invoice), InvoiceStatus::Paid);
+
+        invoice.transfers[0] = Transfer::new(100, Some(19));
+        invoice.paid_height = Some(19);
+        assert_eq!(InvoiceStatus::of(&invoice), InvoiceStatus::Confirmed);
+    }
+
+    #[test]
+    fn amount_range() {
+        let query = InvoiceQuery::new()
+            .min_amount_requested(10)
+            .max_amount_requested(20);
+        assert!(!query.matches(&invoice(1, 9, "")));
+        assert!(query.matches(&invoice(1, 10, "")));
+        assert!(query.matches(&invoice(1, 20, "")));
+        assert!(!query.matches(&invoice(1, 21, "")));
+    }
+
+    #[test]
+    fn metadata() {
+        let order = invoice(1, 1, &json!({"order": "pizza"}).to_string());
+        let other_order = invoice(1, 1, &json!({"order": "pasta"}).to_string());
+        let not_json = invoice(1, 1, "pizza");
+
+        let has_order = InvoiceQuery::new().metadata_key("order");
+        assert!(has_order.matches(&order));
+        assert!(has_order.matches(&other_order));
+        assert!(!has_order.matches(&not_json));
+
+        let pizza = InvoiceQuery::new().metadata("order", "pizza");
+        assert!(pizza.matches(&order));
+        assert!(!pizza.matches(&other_order));
+        assert!(!pizza.matches(&not_json));
+    }
+
+    #[test]
+    fn pages() {
+        let invoices: Vec<_> = (1..=5).map(|minor| invoice(minor, 1, "")).collect();
+        let query = InvoiceQuery::new().limit(2);
+
+        let first = query
+            .collect_page(invoices.iter().cloned().map(Ok::<_, ()>))
+            .unwrap();
+        assert_eq!(first.invoices, invoices[..2]);
+        assert_eq!(first.next, Some(invoices[1].id()));
+
+        let last = query
+            .clone()
+            .after(invoices[3].id())
+            .collect_page(invoices.iter().cloned().map(Ok::<_, ()>))
+            .unwrap();
+        assert_eq!(
+            last,
+            InvoicePage {
+                invoices: invoices[4..].to_vec(),
+                next: None
+            }
+        );
+    }
+}
@@ -0,0 +1,743 @@
+use bincode::error::DecodeError;
+use thiserror::Error;
+
+use crate::{Invoice, InvoiceEvent, SubIndex};
+
+/// Version of the layout in which the built-in [stores](super::stores) keep
+/// their data. Each store records the version it was written with, and
+/// upgrades older data to this version when opened.
+///
+/// * Version 0 is the layout of `AcceptXMR` 0.14 and earlier, which did not
+///   record its version.
+/// * Version 1 records the primary address of the wallet each invoice belongs
+///   to.
+/// * Version 2 records the merchant's optional reference for each invoice.
+/// * Version 3 records the height each output key was recorded at. Keys
+///   recorded by older versions are given the store's scan height when
+///   upgraded.
+/// * Version 4 indexes archived invoices by reference.
+/// * Version 5 records the hash of the transaction each transfer to an invoice
+///   was found in. Transfers recorded by older versions have no hash.
+/// * Version 6 records the wallet each funded subaddress belongs to.
+///   Subaddresses recorded by older versions have an empty wallet.
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// First schema version recording the height of each output key.
+pub(crate) const OUTPUT_KEY_HEIGHT_VERSION: u32 = 3;
+
+/// A [`Transfer`](crate::invoice::Transfer) as encoded before schema version
+/// 5 added its transaction hash.
+type TransferV4 = (u64, Option<u64>);
+
+/// The fields of an [`Invoice`] preceding its transfers, as encoded since
+/// schema version 1.
+type InvoiceV4Head = (
+    String,
+    String,
+    SubIndex,
+    u64,
+    u64,
+    u64,
+    Option<u64>,
+    u64,
+    u64,
+    u64,
+);
+
+/// An [`Invoice`] as encoded before schema version 1 added its wallet.
+type InvoiceV0 = (
+    String,
+    SubIndex,
+    u64,
+    u64,
+    u64,
+    Option<u64>,
+    u64,
+    u64,
+    u64,
+    Vec<TransferV4>,
+    String,
+);
+/// An [`Invoice`] as encoded before schema version 2 added its reference.
+type InvoiceV1 = (
+    String,
+    String,
+    SubIndex,
+    u64,
+    u64,
+    u64,
+    Option<u64>,
+    u64,
+    u64,
+    u64,
+    Vec<TransferV4>,
+    String,
+);
+/// An [`Invoice`] as encoded before schema version 5 added the transaction
+/// hash of its transfers.
+type InvoiceV4 = (
+    String,
+    String,
+    SubIndex,
+    u64,
+    u64,
+    u64,
+    Option<u64>,
+    u64,
+    u64,
+    u64,
+    Vec<TransferV4>,
+    String,
+    Option<String>,
+);
+
+/// Upgrades a bincode-encoded [`Invoice`] from one schema version to the next.
+type InvoiceUpgrade = fn(&[u8]) -> Result<Vec<u8>, DecodeError>;
+
+/// Steps upgrading an invoice from the schema version at their index to the
+/// next one.
+const INVOICE_UPGRADES: [InvoiceUpgrade; SCHEMA_VERSION as usize] = [
+    add_invoice_wallet,
+    add_invoice_reference,
+    unchanged_invoice,
+    unchanged_invoice,
+    add_transfer_tx_hashes,
+    unchanged_invoice,
+];
+
+/// Returns the schema version of a store, given the version it has recorded
+/// and whether it holds any invoices or output keys.
+///
+/// A store with no recorded version is either new, in which case it is
+/// considered up to date, or was written before versions were recorded.
+///
+/// # Errors
+///
+/// Returns an error if the store was written with a newer schema than this
+/// version of `AcceptXMR` supports.
+pub(crate) fn stored_version(recorded: Option<u32>, is_empty: bool) -> Result<u32, SchemaError> {
+    let version = match recorded {
+        Some(version) => version,
+        None if is_empty => SCHEMA_VERSION,
+        None => 0,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(SchemaError::Unsupported {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    Ok(version)
+}
+
+/// Upgrade a bincode-encoded [`Invoice`] from the given schema version to
+/// [`SCHEMA_VERSION`].
+///
+/// # Errors
+///
+/// Returns an error if the invoice is not a valid invoice of the given schema
+/// version.
+pub(crate) fn upgrade_invoice(bytes: &[u8], version: u32) -> Result<Vec<u8>, SchemaError> {
+    let mut upgraded = bytes.to_vec();
+    let steps = INVOICE_UPGRADES.get(version as usize..).unwrap_or_default();
+    for (from, upgrade) in (version..).zip(steps) {
+        upgraded = upgrade(&upgraded).map_err(|source| SchemaError::Upgrade {
+            version: from,
+            source,
+        })?;
+    }
+
+    // Make sure the result can be read before it replaces the original.
+    bincode::decode_from_slice::<Invoice, _>(&upgraded, bincode::config::standard()).map_err(
+        |source| SchemaError::Upgrade {
+            version: SCHEMA_VERSION,
+            source,
+        },
+    )?;
+    Ok(upgraded)
+}
+
+/// Upgrade a bincode-encoded [`InvoiceEvent`] from the given schema version to
+/// [`SCHEMA_VERSION`]. Every event is encoded as its variant's tag followed by
+/// the invoice it carries, so only that invoice needs upgrading.
+///
+/// # Errors
+///
+/// Returns an error if the event is not a valid event of the given schema
+/// version.
+pub(crate) fn upgrade_event(bytes: &[u8], version: u32) -> Result<Vec<u8>, SchemaError> {
+    let config = bincode::config::standard();
+    let upgrade_error = |source| SchemaError::Upgrade { version, source };
+
+    let (_tag, tag_len): (u32, usize) =
+        bincode::decode_from_slice(bytes, config).map_err(upgrade_error)?;
+    let invoice_end = tag_len + invoice_len(&bytes[tag_len..], version).map_err(upgrade_error)?;
+    let invoice = upgrade_invoice(&bytes[tag_len..invoice_end], version)?;
+
+    let mut upgraded = Vec::with_capacity(bytes.len() - invoice_end + tag_len + invoice.len());
+    upgraded.extend_from_slice(&bytes[..tag_len]);
+    upgraded.extend_from_slice(&invoice);
+    upgraded.extend_from_slice(&bytes[invoice_end..]);
+
+    // Make sure the result can be read before it replaces the original.
+    bincode::decode_from_slice::<InvoiceEvent, _>(&upgraded, config).map_err(|source| {
+        SchemaError::Upgrade {
+            version: SCHEMA_VERSION,
+            source,
+        }
+    })?;
+    Ok(upgraded)
+}
+
+/// Returns the length of the bincode-encoded [`Invoice`] of the given schema
+/// version at the start of `bytes`.
+fn invoice_len(bytes: &[u8], version: u32) -> Result<usize, DecodeError> {
+    let config = bincode::config::standard();
+    Ok(match version {
+        0 => bincode::decode_from_slice::<InvoiceV0, _>(bytes, config)?.1,
+        1 => bincode::decode_from_slice::<InvoiceV1, _>(bytes, config)?.1,
+        2..=4 => bincode::decode_from_slice::<InvoiceV4, _>(bytes, config)?.1,
+        _ => bincode::decode_from_slice::<Invoice, _>(bytes, config)?.1,
+    })
+}
+
+/// Version 1 added the wallet's primary address directly after the invoice's
+/// address. Older invoices all belong to the default wallet, so their wallet
+/// is left empty.
+fn add_invoice_wallet(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let (_address, address_len): (String, usize) =
+        bincode::decode_from_slice(bytes, bincode::config::standard())?;
+
+    let mut upgraded = Vec::with_capacity(bytes.len() + 1);
+    upgraded.extend_from_slice(&bytes[..address_len]);
+    // An empty string is encoded as its length, zero.
+    upgraded.push(0);
+    upgraded.extend_from_slice(&bytes[address_len..]);
+    Ok(upgraded)
+}
+
+/// Version 2 added the invoice's reference after its description. Older
+/// invoices have no reference.
+#[allow(clippy::unnecessary_wraps)]
+fn add_invoice_reference(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut upgraded = Vec::with_capacity(bytes.len() + 1);
+    upgraded.extend_from_slice(bytes);
+    // `None` is encoded as a single zero byte.
+    upgraded.push(0);
+    Ok(upgraded)
+}
+
+/// Versions 3 and 4 only changed how output keys and archived invoices are
+/// indexed.
+#[allow(clippy::unnecessary_wraps)]
+fn unchanged_invoice(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    Ok(bytes.to_vec())
+}
+
+/// Version 5 added the transaction hash after each transfer's height. Older
+/// transfers have no hash.
+fn add_transfer_tx_hashes(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let config = bincode::config::standard();
+    let (_head, mut position): (InvoiceV4Head, usize) = bincode::decode_from_slice(bytes, config)?;
+    let (transfer_count, count_len): (u64, usize) =
+        bincode::decode_from_slice(&bytes[position..], config)?;
+    position += count_len;
+
+    let mut upgraded = Vec::with_capacity(bytes.len());
+    upgraded.extend_from_slice(&bytes[..position]);
+    for _ in 0..transfer_count {
+        let (_transfer, transfer_len): (TransferV4, usize) =
+            bincode::decode_from_slice(&bytes[position..], config)?;
+        upgraded.extend_from_slice(&bytes[position..position + transfer_len]);
+        // `None` is encoded as a single zero byte.
+        upgraded.push(0);
+        position += transfer_len;
+    }
+    upgraded.extend_from_slice(&bytes[position..]);
+    Ok(upgraded)
+}
+
+/// An error occurring while checking or upgrading the schema of a store.
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    /// The store was written by a newer version of `AcceptXMR`, with a schema
+    /// this version can't read.
+    #[error(
+        "storage schema version {found} is newer than the newest supported version ({supported}); upgrade AcceptXMR to open this database"
+    )]
+    Unsupported {
+        /// Schema version of the store.
+        found: u32,
+        /// Newest schema version supported by this version of `AcceptXMR`.
+        supported: u32,
+    },
+    /// A stored invoice could not be upgraded from the given schema version.
+    #[error("failed to upgrade invoice from storage schema version {version}: {source}")]
+    Upgrade {
+        /// Schema version the invoice failed to upgrade from.
+        version: u32,
+        /// Error decoding the invoice.
+        source: DecodeError,
+    },
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::{fmt::Debug, ops::Deref, thread, time::Duration};
+
+    use test_case::test_case;
+    #[cfg(feature = "postgres")]
+    use testing_utils::copy_postgres_fixture;
+    use testing_utils::{copy_db_fixture, PRIMARY_ADDRESS};
+
+    use super::{
+        stored_version, upgrade_event, upgrade_invoice, InvoiceV4, SchemaError, SCHEMA_VERSION,
+    };
+    #[cfg(feature = "postgres")]
+    use crate::storage::stores::{Postgres, PostgresStorageError};
+    use crate::{
+        invoice::Transfer,
+        storage::{
+            stores::{
+                Redb, RedbStorageError, Sled, SledStorageError, Sqlite, SqliteStorageError,
+                TableNames,
+            },
+            ArchiveStorage, EventStorage, HeightStorage, InvoiceStorage, OutputId, OutputKeyStats,
+            OutputKeyStorage, OutputPubKey, Storage, SubaddressStorage,
+        },
+        Amount, Invoice, InvoiceEvent, SubIndex,
+    };
+
+    fn open_sled(path: &str) -> Result<Sled, SledStorageError> {
+        retry_while_locked(
+            || Sled::new(path, &TableNames::default()),
+            |e| matches!(e, SledStorageError::Database(_)),
+        )
+    }
+
+    /// Sled keeps a database locked until its background writes finish, which
+    /// may be shortly after it is dropped.
+    fn retry_while_locked<T, E>(
+        open: impl Fn() -> Result<T, E>,
+        is_locked: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut result = open();
+        for _ in 0..50 {
+            match &result {
+                Err(e) if is_locked(e) => thread::sleep(Duration::from_millis(20)),
+                _ => break,
+            }
+            result = open();
+        }
+        result
+    }
+
+    fn open_sqlite(path: &str) -> Result<Sqlite, SqliteStorageError> {
+        Sqlite::new(path, &TableNames::default())
+    }
+
+    fn open_redb(path: &str) -> Result<Redb, RedbStorageError> {
+        Redb::new(path, &TableNames::default())
+    }
+
+    #[cfg(feature = "postgres")]
+    fn open_postgres(config: &str) -> Result<Postgres, PostgresStorageError> {
+        Postgres::new(config, &TableNames::default())
+    }
+
+    /// Invoices in the version 0 fixtures.
+    fn v0_invoices() -> Vec<Invoice> {
+        fixture_invoices("")
+    }
+
+    /// Invoices tracked in the fixtures, belonging to the given wallet.
+    fn fixture_invoices(wallet: &str) -> Vec<Invoice> {
+        let mut paid = Invoice::new(
+            "82iP4uj3tUb1oP5vYC8gnkWd4zcmCRgCB1e5cK7twDi5UT6xvw4yFqz1Y9iXb7dDL8ZLr4vsBhbwCQrm8GypKkH7PJrHLL2".to_string(),
+            wallet.to_string(),
+            SubIndex::new(0, 1),
+            2_477_657,
+            Amount::from_pico(1_000_000_000),
+            2,
+            100,
+            "paid".to_string(),
+        );
+        paid.transfers
+            .push(Transfer::new(1_000_000_000, Some(2_477_660)));
+        paid.amount_paid = Amount::from_pico(1_000_000_000);
+        paid.paid_height = Some(2_477_660);
+        paid.current_height = 2_477_661;
+
+        let mut unpaid = Invoice::new(
+            "8Bbn5aA4NzTT8Wp8YrpSt5WZ14rV5YhWhMTMU2qT4aTpK6WKD2NgKpMJpRmGK7i5FYKVP9ToUXa2Ve1hjmjzszfNCTbpHnD".to_string(),
+            wallet.to_string(),
+            SubIndex::new(0, 2),
+            2_477_658,
+            Amount::from_pico(42),
+            0,
+            10,
+            "unpaid".to_string(),
+        );
+        unpaid.current_height = 2_477_661;
+
+        vec![paid, unpaid]
+    }
+
+    fn assert_v0_contents<S>(store: &S)
+    where
+        S: Storage,
+        <S as InvoiceStorage>::Error: Debug,
+        <S as OutputKeyStorage>::Error: Debug,
+        <S as HeightStorage>::Error: Debug,
+    {
+        for invoice in v0_invoices() {
+            assert_eq!(
+                InvoiceStorage::get(store, invoice.id()).unwrap(),
+                Some(invoice)
+            );
+        }
+        assert_eq!(
+            OutputKeyStorage::get(store, OutputPubKey([7; 32])).unwrap(),
+            Some(OutputId {
+                tx_hash: [8; 32],
+                index: 1
+            })
+        );
+        // Output keys recorded before heights were are given the scan height.
+        assert_eq!(
+            store.key_stats().unwrap(),
+            OutputKeyStats {
+                count: 1,
+                lowest_height: Some(2_477_661),
+                highest_height: Some(2_477_661),
+            }
+        );
+        assert_eq!(HeightStorage::get(store).unwrap(), Some(2_477_661));
+    }
+
+    #[test_case("v0/sled", open_sled; "sled")]
+    #[test_case("v0/sqlite.db", open_sqlite; "sqlite")]
+    fn upgrade_v0<S, E>(fixture: &str, open: fn(&str) -> Result<S, E>)
+    where
+        S: Storage,
+        E: Debug,
+        <S as InvoiceStorage>::Error: Debug,
+        <S as OutputKeyStorage>::Error: Debug,
+        <S as HeightStorage>::Error: Debug,
+    {
+        let path = copy_db_fixture(fixture);
+
+        let store = open(&path).unwrap();
+        assert_v0_contents(&store);
+        drop(store);
+
+        // Invoices are not upgraded again when reopened.
+        let store = open(&path).unwrap();
+        assert_v0_contents(&store);
+    }
+
+    /// Tracked invoices in the version 1 fixtures, and the invoice archived in
+    /// them.
+    fn v1_invoices() -> (Invoice, Invoice, Invoice) {
+        let [paid, unpaid]: [Invoice; 2] = fixture_invoices(PRIMARY_ADDRESS).try_into().unwrap();
+
+        let mut archived = Invoice::new(
+            "84E2Lo3vkCWqVDqmqj2zYGnRnnpM4zf9j1ZAbiDG6NhyHoSoWPQMqjFVRXL2M3jwqFUEm2gVzK1FQxDXNsbStg7E7VL4Lzh".to_string(),
+            PRIMARY_ADDRESS.to_string(),
+            SubIndex::new(0, 3),
+            2_477_600,
+            Amount::from_pico(5),
+            0,
+            10,
+            "archived".to_string(),
+        );
+        archived.current_height = 2_477_610;
+
+        (paid, unpaid, archived)
+    }
+
+    fn assert_v1_contents<S>(store: &S)
+    where
+        S: Storage,
+        <S as InvoiceStorage>::Error: Debug,
+        <S as OutputKeyStorage>::Error: Debug,
+        <S as HeightStorage>::Error: Debug,
+        <S as SubaddressStorage>::Error: Debug,
+        <S as EventStorage>::Error: Debug,
+        <S as ArchiveStorage>::Error: Debug,
+    {
+        let (paid, unpaid, archived) = v1_invoices();
+        for invoice in [&paid, &unpaid] {
+            assert_eq!(
+                InvoiceStorage::get(store, invoice.id()).unwrap().as_ref(),
+                Some(invoice)
+            );
+        }
+        assert_eq!(
+            store.get_archived(archived.id()).unwrap(),
+            Some(archived.clone())
+        );
+
+        // Events logged before version 2 carry invoices without references.
+        assert_eq!(
+            store.events_from(0, usize::MAX).unwrap(),
+            vec![
+                (1, InvoiceEvent::Created { invoice: unpaid }),
+                (
+                    2,
+                    InvoiceEvent::TransferDetected {
+                        invoice: paid.clone(),
+                        amount: Amount::from_pico(1_000_000_000),
+                        height: Some(2_477_660),
+                    }
+                ),
+                (3, InvoiceEvent::Paid { invoice: paid }),
+            ]
+        );
+
+        // Output keys recorded before heights were recorded are given the scan height.
+        assert_eq!(
+            store.key_stats().unwrap(),
+            OutputKeyStats {
+                count: 1,
+                lowest_height: Some(2_477_661),
+                highest_height: Some(2_477_661),
+            }
+        );
+        assert_eq!(HeightStorage::get(store).unwrap(), Some(2_477_661));
+        // Funded subaddresses recorded before version 6 have no wallet.
+        assert_eq!(
+            store.funded().unwrap(),
+            vec![(String::new(), SubIndex::new(0, 1))]
+        );
+    }
+
+    #[test_case("v1/sled", copy_db_fixture, open_sled; "sled")]
+    #[test_case("v1/sqlite.db", copy_db_fixture, open_sqlite; "sqlite")]
+    #[test_case("v1/redb.db", copy_db_fixture, open_redb; "redb")]
+    #[cfg_attr(feature = "postgres", test_case("v1/postgres.sql", copy_postgres_fixture, open_postgres => ignore; "postgres"))]
+    fn upgrade_v1<S, E, P>(fixture: &str, copy: fn(&str) -> P, open: fn(&str) -> Result<S, E>)
+    where
+        P: Deref<Target = str>,
+        S: Storage,
+        E: Debug,
+        <S as InvoiceStorage>::Error: Debug,
+        <S as OutputKeyStorage>::Error: Debug,
+        <S as HeightStorage>::Error: Debug,
+        <S as SubaddressStorage>::Error: Debug,
+        <S as EventStorage>::Error: Debug,
+        <S as ArchiveStorage>::Error: Debug,
+    {
+        let path = copy(fixture);
+
+        let store = open(&path).unwrap();
+        assert_v1_contents(&store);
+        drop(store);
+
+        // Nothing is upgraded again when reopened.
+        let store = open(&path).unwrap();
+        assert_v1_contents(&store);
+    }
+
+    #[test]
+    fn newer_sled_schema_is_refused() {
+        let path = copy_db_fixture("v0/sled");
+        drop(open_sled(&path).unwrap());
+
+        let db =
+            retry_while_locked(|| sled::open(&path), |e| matches!(e, sled::Error::Io(_))).unwrap();
+        db.open_tree("invoices schema version")
+            .unwrap()
+            .insert(
+                "version",
+                bincode::encode_to_vec(SCHEMA_VERSION + 1, bincode::config::standard()).unwrap(),
+            )
+            .unwrap();
+        db.flush().unwrap();
+        drop(db);
+
+        assert!(matches!(
+            open_sled(&path),
+            Err(SledStorageError::Schema(SchemaError::Unsupported { .. }))
+        ));
+    }
+
+    #[test]
+    fn newer_sqlite_schema_is_refused() {
+        let path = copy_db_fixture("v0/sqlite.db");
+        drop(open_sqlite(&path).unwrap());
+
+        sqlite::open(&path)
+            .unwrap()
+            .execute(format!(
+                "UPDATE \"invoices schema version\" SET version = {}",
+                SCHEMA_VERSION + 1
+            ))
+            .unwrap();
+
+        assert!(matches!(
+            open_sqlite(&path),
+            Err(SqliteStorageError::Schema(SchemaError::Unsupported { .. }))
+        ));
+    }
+
+    #[test]
+    fn newer_redb_schema_is_refused() {
+        let path = testing_utils::new_temp_dir();
+        drop(open_redb(&path).unwrap());
+
+        let db = redb::Database::create(&path).unwrap();
+        let txn = db.begin_write().unwrap();
+        txn.open_table(redb::TableDefinition::<(), u32>::new(
+            "invoices schema version",
+        ))
+        .unwrap()
+        .insert((), SCHEMA_VERSION + 1)
+        .unwrap();
+        txn.commit().unwrap();
+        drop(db);
+
+        assert!(matches!(
+            open_redb(&path),
+            Err(RedbStorageError::Schema(SchemaError::Unsupported { .. }))
+        ));
+    }
+
+    #[test]
+    fn new_store_is_current() {
+        assert_eq!(stored_version(None, true).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn unversioned_store_is_version_0() {
+        assert_eq!(stored_version(None, false).unwrap(), 0);
+    }
+
+    #[test]
+    fn newer_schema_is_unsupported() {
+        assert!(matches!(
+            stored_version(Some(SCHEMA_VERSION + 1), false),
+            Err(SchemaError::Unsupported { found, supported })
+                if found == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+        ));
+    }
+
+    #[test]
+    fn current_invoice_is_unchanged() {
+        let invoice = Invoice::new(
+            "testAddress".to_string(),
+            "testWallet".to_string(),
+            SubIndex::new(0, 1),
+            10,
+            Amount::from_pico(1),
+            5,
+            10,
+            "test description".to_string(),
+        );
+        let bytes = bincode::encode_to_vec(invoice, bincode::config::standard()).unwrap();
+
+        assert_eq!(upgrade_invoice(&bytes, SCHEMA_VERSION).unwrap(), bytes);
+    }
+
+    #[test]
+    fn v1_event_is_upgraded() {
+        let (paid, _, _) = v1_invoices();
+        let event = InvoiceEvent::TransferConfirmed {
+            invoice: paid,
+            amount: Amount::from_pico(1_000_000_000),
+            height: 2_477_660,
+        };
+        let config = bincode::config::standard();
+        let invoice = encode_v4(event.invoice());
+        // The event's tag, its invoice without the reference (which is encoded
+        // as a single byte, since it is `None`), then its amount and height.
+        let mut bytes = bincode::encode_to_vec(2_u32, config).unwrap();
+        bytes.extend_from_slice(&invoice[..invoice.len() - 1]);
+        bytes.extend(
+            bincode::encode_to_vec((Amount::from_pico(1_000_000_000), 2_477_660_u64), config)
+                .unwrap(),
+        );
+
+        let upgraded = upgrade_event(&bytes, 1).unwrap();
+
+        assert_eq!(
+            bincode::decode_from_slice::<InvoiceEvent, _>(&upgraded, config)
+                .unwrap()
+                .0,
+            event
+        );
+    }
+
+    /// Encode an invoice as version 4 did, before transfers recorded their
+    /// transaction hash.
+    fn encode_v4(invoice: &Invoice) -> Vec<u8> {
+        let v4: InvoiceV4 = (
+            invoice.address().to_string(),
+            invoice.wallet().to_string(),
+            invoice.index(),
+            invoice.creation_height(),
+            invoice.amount_requested().as_pico(),
+            invoice.amount_paid().as_pico(),
+            invoice.paid_height,
+            invoice.confirmations_required(),
+            invoice.current_height(),
+            invoice.expiration_height(),
+            invoice
+                .transfers
+                .iter()
+                .map(|transfer| (transfer.amount, transfer.height))
+                .collect(),
+            invoice.description().to_string(),
+            invoice.reference().map(ToString::to_string),
+        );
+        bincode::encode_to_vec(v4, bincode::config::standard()).unwrap()
+    }
+
+    #[test]
+    fn v4_transfers_have_no_tx_hash() {
+        let (mut paid, _, _) = v1_invoices();
+        paid.transfers.push(Transfer::new(5, None));
+        paid.reference = Some("order-1".to_string());
+
+        let upgraded = upgrade_invoice(&encode_v4(&paid), 4).unwrap();
+        let (decoded, _): (Invoice, _) =
+            bincode::decode_from_slice(&upgraded, bincode::config::standard()).unwrap();
+        assert_eq!(decoded, paid);
+        assert!(decoded
+            .transfers
+            .iter()
+            .all(|transfer| transfer.tx_hash.is_none()));
+    }
+
+    #[test]
+    fn v1_invoice_has_no_reference() {
+        let invoice = Invoice::new(
+            "testAddress".to_string(),
+            "testWallet".to_string(),
+            SubIndex::new(0, 1),
+            10,
+            Amount::from_pico(1),
+            5,
+            10,
+            "test description".to_string(),
+        );
+        let bytes = encode_v4(&invoice);
+        // Version 1 invoices end with the description.
+        let v1_bytes = &bytes[..bytes.len() - 1];
+
+        let upgraded = upgrade_invoice(v1_bytes, 1).unwrap();
+        let (decoded, _): (Invoice, _) =
+            bincode::decode_from_slice(&upgraded, bincode::config::standard()).unwrap();
+        assert_eq!(decoded, invoice);
+        assert_eq!(decoded.reference(), None);
+    }
+
+    #[test]
+    fn invalid_invoice() {
+        assert!(matches!(
+            upgrade_invoice(&[1, 2, 3], 0),
+            Err(SchemaError::Upgrade { .. })
+        ));
+    }
+}
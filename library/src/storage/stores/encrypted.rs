@@ -0,0 +1,788 @@
+use std::fmt;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use thiserror::Error;
+
+use crate::{
+    storage::{
+        ArchiveStorage, EventStorage, HeightStorage, InvoiceStorage, OutputId, OutputKeyStats,
+        OutputKeyStorage, OutputPubKey, Storage, SubaddressStorage,
+    },
+    Invoice, InvoiceEvent, InvoiceId, SubIndex,
+};
+
+/// Prefix of encrypted values. It is followed by the hex-encoded key ID,
+/// nonce and ciphertext.
+const ENCRYPTED_PREFIX: &str = "enc1:";
+/// Length of the ID identifying which key a value was encrypted with.
+const KEY_ID_LEN: usize = 4;
+/// Length of an `XChaCha20Poly1305` nonce.
+const NONCE_LEN: usize = 24;
+
+/// Encrypting wrapper around another store. Invoice addresses, descriptions
+/// and references are encrypted with `XChaCha20Poly1305` before being passed
+/// to the inner store, including those of archived invoices and logged
+/// events. Invoice IDs, wallets, amounts, heights, output keys and funded
+/// subaddresses are not encrypted, because the inner store needs them to
+/// index, order and prune its contents.
+///
+/// References are encrypted deterministically, so that the inner store can
+/// look them up. This reveals which invoices share a reference, but tracked
+/// invoices never do.
+///
+/// Values stored before encryption was enabled are read as plaintext, and are
+/// encrypted when next written. Dumps taken with
+/// [`dump::export`](crate::storage::dump::export) contain decrypted values.
+///
+/// # Key Rotation
+///
+/// New values are encrypted with the current key, and values encrypted with
+/// any of the [old keys](Encrypted::old_keys) can still be read. To rotate
+/// keys, make the current key an old key, add a new current key, and call
+/// [`reencrypt`](Encrypted::reencrypt). Archived invoices and logged events
+/// are not re-encrypted, so an old key should be kept until the invoices
+/// archived and events logged while it was current have been pruned.
+///
+/// ```
+/// use acceptxmr::storage::stores::{Encrypted, EncryptionKey, InMemory};
+///
+/// let old_key = EncryptionKey::from_secret("the old 32+ character secret key");
+/// let new_key = EncryptionKey::from_secret("the new 32+ character secret key");
+///
+/// let mut store = Encrypted::new(InMemory::new(), new_key).old_keys([old_key]);
+/// store.reencrypt()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Encrypted<S> {
+    inner: S,
+    key: EncryptionKey,
+    old_keys: Vec<EncryptionKey>,
+}
+
+impl<S> Encrypted<S> {
+    /// Wrap a store, encrypting values with `key`.
+    #[must_use]
+    pub fn new(inner: S, key: EncryptionKey) -> Encrypted<S> {
+        Encrypted {
+            inner,
+            key,
+            old_keys: Vec::new(),
+        }
+    }
+
+    /// Keys which values may have previously been encrypted with. These are
+    /// used to read values, but never to write them.
+    #[must_use]
+    pub fn old_keys(mut self, keys: impl IntoIterator<Item = EncryptionKey>) -> Encrypted<S> {
+        self.old_keys = keys.into_iter().collect();
+        self
+    }
+
+    /// Unwrap the inner store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// The current key, followed by the old keys.
+    fn keys(&self) -> impl Iterator<Item = &EncryptionKey> {
+        std::iter::once(&self.key).chain(&self.old_keys)
+    }
+
+    fn encrypt_invoice<E>(
+        &self,
+        mut invoice: Invoice,
+    ) -> Result<Invoice, EncryptedStorageError<E>> {
+        let id = id_bytes(invoice.id());
+        invoice.address = self
+            .key
+            .encrypt_random(&invoice.address, &aad("address", &id))?;
+        invoice.description = self
+            .key
+            .encrypt_random(&invoice.description, &aad("description", &id))?;
+        invoice.reference = invoice
+            .reference
+            .map(|reference| self.key.encrypt_reference(&reference))
+            .transpose()?;
+        Ok(invoice)
+    }
+
+    fn decrypt_invoice<E>(
+        &self,
+        mut invoice: Invoice,
+    ) -> Result<Invoice, EncryptedStorageError<E>> {
+        let id = id_bytes(invoice.id());
+        invoice.address = self.decrypt(invoice.address, &aad("address", &id))?;
+        invoice.description = self.decrypt(invoice.description, &aad("description", &id))?;
+        invoice.reference = invoice
+            .reference
+            .map(|reference| self.decrypt(reference, &aad("reference", &[])))
+            .transpose()?;
+        Ok(invoice)
+    }
+
+    fn encrypt_event<E>(
+        &self,
+        mut event: InvoiceEvent,
+    ) -> Result<InvoiceEvent, EncryptedStorageError<E>> {
+        let invoice = event.invoice_mut();
+        *invoice = self.encrypt_invoice(invoice.clone())?;
+        Ok(event)
+    }
+
+    fn decrypt_event<E>(
+        &self,
+        mut event: InvoiceEvent,
+    ) -> Result<InvoiceEvent, EncryptedStorageError<E>> {
+        let invoice = event.invoice_mut();
+        *invoice = self.decrypt_invoice(invoice.clone())?;
+        Ok(event)
+    }
+
+    /// Decrypt a stored value with whichever key it was encrypted with. Values
+    /// without the encrypted prefix are returned unchanged.
+    fn decrypt<E>(&self, value: String, aad: &[u8]) -> Result<String, EncryptedStorageError<E>> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value);
+        };
+        let bytes = hex::decode(encoded).map_err(|_| EncryptedStorageError::Decryption)?;
+        if bytes.len() < KEY_ID_LEN + NONCE_LEN {
+            return Err(EncryptedStorageError::Decryption);
+        }
+        let (key_id, rest) = bytes.split_at(KEY_ID_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let key = self
+            .keys()
+            .find(|key| key.id == key_id)
+            .ok_or(EncryptedStorageError::UnknownKey)?;
+        let plaintext = key
+            .cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| EncryptedStorageError::Decryption)?;
+        String::from_utf8(plaintext).map_err(|_| EncryptedStorageError::Decryption)
+    }
+}
+
+impl<S: InvoiceStorage> Encrypted<S> {
+    /// Re-encrypt every tracked invoice with the current key. This should be
+    /// called after rotating keys, so that the old keys are only needed for
+    /// archived invoices and logged events.
+    ///
+    /// Invoices whose reference was encrypted with an old key are removed and
+    /// inserted again, so that the inner store indexes the new encrypted
+    /// reference.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an invoice could not be decrypted, or if the inner
+    /// store returns an error.
+    pub fn reencrypt(&mut self) -> Result<(), EncryptedStorageError<<S as InvoiceStorage>::Error>> {
+        for invoice_id in self.inner.get_ids().map_err(EncryptedStorageError::Inner)? {
+            let Some(stored) = self
+                .inner
+                .get(invoice_id)
+                .map_err(EncryptedStorageError::Inner)?
+            else {
+                continue;
+            };
+            let invoice = self.encrypt_invoice(self.decrypt_invoice(stored)?)?;
+
+            let reindex = match invoice.reference() {
+                Some(reference) => self
+                    .inner
+                    .get_by_reference(reference)
+                    .map_err(EncryptedStorageError::Inner)?
+                    .is_none(),
+                None => false,
+            };
+            if reindex {
+                self.inner
+                    .remove(invoice_id)
+                    .map_err(EncryptedStorageError::Inner)?;
+                self.inner
+                    .insert(invoice)
+                    .map_err(EncryptedStorageError::Inner)?;
+            } else {
+                self.inner
+                    .update(invoice)
+                    .map_err(EncryptedStorageError::Inner)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: InvoiceStorage> InvoiceStorage for Encrypted<S> {
+    type Error = EncryptedStorageError<<S as InvoiceStorage>::Error>;
+
+    fn insert(&mut self, invoice: Invoice) -> Result<(), Self::Error> {
+        // The inner store only detects duplicate references encrypted with the
+        // current key.
+        if let Some(reference) = invoice.reference() {
+            if self.get_by_reference(reference)?.is_some() {
+                return Err(EncryptedStorageError::DuplicateReference);
+            }
+        }
+        let invoice = self.encrypt_invoice(invoice)?;
+        self.inner
+            .insert(invoice)
+            .map_err(EncryptedStorageError::Inner)
+    }
+
+    fn remove(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        self.inner
+            .remove(invoice_id)
+            .map_err(EncryptedStorageError::Inner)?
+            .map(|invoice| self.decrypt_invoice(invoice))
+            .transpose()
+    }
+
+    fn update(&mut self, invoice: Invoice) -> Result<Option<Invoice>, Self::Error> {
+        let invoice = self.encrypt_invoice(invoice)?;
+        self.inner
+            .update(invoice)
+            .map_err(EncryptedStorageError::Inner)?
+            .map(|invoice| self.decrypt_invoice(invoice))
+            .transpose()
+    }
+
+    fn get(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        self.inner
+            .get(invoice_id)
+            .map_err(EncryptedStorageError::Inner)?
+            .map(|invoice| self.decrypt_invoice(invoice))
+            .transpose()
+    }
+
+    fn get_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error> {
+        for key in self.keys() {
+            let encrypted = key.encrypt_reference(reference)?;
+            if let Some(invoice) = self
+                .inner
+                .get_by_reference(&encrypted)
+                .map_err(EncryptedStorageError::Inner)?
+            {
+                return self.decrypt_invoice(invoice).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn get_ids(&self) -> Result<Vec<InvoiceId>, Self::Error> {
+        self.inner.get_ids().map_err(EncryptedStorageError::Inner)
+    }
+
+    fn contains_sub_index(&self, sub_index: SubIndex) -> Result<bool, Self::Error> {
+        self.inner
+            .contains_sub_index(sub_index)
+            .map_err(EncryptedStorageError::Inner)
+    }
+
+    fn try_for_each<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        // Errors returned by `f` can't be passed through the inner store, so
+        // stop calling `f` after the first one and return it afterwards.
+        let mut result = Ok(());
+        self.inner
+            .try_for_each(|invoice_or_err| {
+                if result.is_ok() {
+                    result = f(invoice_or_err
+                        .map_err(EncryptedStorageError::Inner)
+                        .and_then(|invoice| self.decrypt_invoice(invoice)));
+                }
+                Ok(())
+            })
+            .map_err(EncryptedStorageError::Inner)?;
+        result
+    }
+
+    fn is_empty(&self) -> Result<bool, Self::Error> {
+        self.inner.is_empty().map_err(EncryptedStorageError::Inner)
+    }
+
+    fn lowest_height(&self) -> Result<Option<u64>, Self::Error> {
+        self.inner
+            .lowest_height()
+            .map_err(EncryptedStorageError::Inner)
+    }
+}
+
+impl<S: OutputKeyStorage> OutputKeyStorage for Encrypted<S> {
+    type Error = EncryptedStorageError<<S as OutputKeyStorage>::Error>;
+
+    fn insert(
+        &mut self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .insert(key, output_id, height)
+            .map_err(EncryptedStorageError::Inner)
+    }
+
+    fn get(&self, key: OutputPubKey) -> Result<Option<OutputId>, Self::Error> {
+        self.inner.get(key).map_err(EncryptedStorageError::Inner)
+    }
+
+    fn try_for_each_key<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<(OutputPubKey, OutputId, u64), Self::Error>) -> Result<(), Self::Error>,
+    {
+        let mut result = Ok(());
+        self.inner
+            .try_for_each_key(|key_or_err| {
+                if result.is_ok() {
+                    result = f(key_or_err.map_err(EncryptedStorageError::Inner));
+                }
+                Ok(())
+            })
+            .map_err(EncryptedStorageError::Inner)?;
+        result
+    }
+
+    fn prune_keys(&mut self, height: u64) -> Result<u64, Self::Error> {
+        self.inner
+            .prune_keys(height)
+            .map_err(EncryptedStorageError::Inner)
+    }
+
+    fn key_stats(&self) -> Result<OutputKeyStats, Self::Error> {
+        self.inner.key_stats().map_err(EncryptedStorageError::Inner)
+    }
+}
+
+impl<S: HeightStorage> HeightStorage for Encrypted<S> {
+    type Error = EncryptedStorageError<<S as HeightStorage>::Error>;
+
+    fn upsert(&mut self, height: u64) -> Result<Option<u64>, Self::Error> {
+        self.inner
+            .upsert(height)
+            .map_err(EncryptedStorageError::Inner)
+    }
+
+    fn get(&self) -> Result<Option<u64>, Self::Error> {
+        self.inner.get().map_err(EncryptedStorageError::Inner)
+    }
+}
+
+impl<S: SubaddressStorage> SubaddressStorage for Encrypted<S> {
+    type Error = EncryptedStorageError<<S as SubaddressStorage>::Error>;
+
+    fn insert_funded(&mut self, wallet: &str, sub_index: SubIndex) -> Result<(), Self::Error> {
+        self.inner
+            .insert_funded(wallet, sub_index)
+            .map_err(EncryptedStorageError::Inner)
+    }
+
+    fn is_funded(&self, wallet: &str, sub_index: SubIndex) -> Result<bool, Self::Error> {
+        self.inner
+            .is_funded(wallet, sub_index)
+            .map_err(EncryptedStorageError::Inner)
+    }
+
+    fn funded(&self) -> Result<Vec<(String, SubIndex)>, Self::Error> {
+        self.inner.funded().map_err(EncryptedStorageError::Inner)
+    }
+}
+
+impl<S: EventStorage> EventStorage for Encrypted<S> {
+    type Error = EncryptedStorageError<<S as EventStorage>::Error>;
+
+    fn append_event(&mut self, event: InvoiceEvent) -> Result<u64, Self::Error> {
+        let event = self.encrypt_event(event)?;
+        self.inner
+            .append_event(event)
+            .map_err(EncryptedStorageError::Inner)
+    }
+
+    fn events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, Self::Error> {
+        self.inner
+            .events_from(sequence, limit)
+            .map_err(EncryptedStorageError::Inner)?
+            .into_iter()
+            .map(|(sequence, event)| Ok((sequence, self.decrypt_event(event)?)))
+            .collect()
+    }
+
+    fn prune_events(&mut self, sequence: u64) -> Result<(), Self::Error> {
+        self.inner
+            .prune_events(sequence)
+            .map_err(EncryptedStorageError::Inner)
+    }
+}
+
+impl<S: ArchiveStorage> ArchiveStorage for Encrypted<S> {
+    type Error = EncryptedStorageError<<S as ArchiveStorage>::Error>;
+
+    fn archive(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        self.inner
+            .archive(invoice_id)
+            .map_err(EncryptedStorageError::Inner)?
+            .map(|invoice| self.decrypt_invoice(invoice))
+            .transpose()
+    }
+
+    fn get_archived(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        self.inner
+            .get_archived(invoice_id)
+            .map_err(EncryptedStorageError::Inner)?
+            .map(|invoice| self.decrypt_invoice(invoice))
+            .transpose()
+    }
+
+    fn get_archived_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error> {
+        // Archived invoices keep the reference ciphertext of the key they were
+        // encrypted with, so try each key.
+        for key in self.keys() {
+            let encrypted = key.encrypt_reference(reference)?;
+            if let Some(invoice) = self
+                .inner
+                .get_archived_by_reference(&encrypted)
+                .map_err(EncryptedStorageError::Inner)?
+            {
+                return self.decrypt_invoice(invoice).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn try_for_each_archived<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        let mut result = Ok(());
+        self.inner
+            .try_for_each_archived(|invoice_or_err| {
+                if result.is_ok() {
+                    result = f(invoice_or_err
+                        .map_err(EncryptedStorageError::Inner)
+                        .and_then(|invoice| self.decrypt_invoice(invoice)));
+                }
+                Ok(())
+            })
+            .map_err(EncryptedStorageError::Inner)?;
+        result
+    }
+
+    fn prune_archive(&mut self, height: u64) -> Result<(), Self::Error> {
+        self.inner
+            .prune_archive(height)
+            .map_err(EncryptedStorageError::Inner)
+    }
+}
+
+impl<S: Storage> Storage for Encrypted<S> {
+    type Error = EncryptedStorageError<<S as Storage>::Error>;
+
+    fn flush(&self) -> Result<(), <Self as Storage>::Error> {
+        self.inner.flush().map_err(EncryptedStorageError::Inner)
+    }
+}
+
+/// A key for [`Encrypted`] storage.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    cipher: XChaCha20Poly1305,
+    /// Key for deriving the nonces of deterministically encrypted references.
+    nonce_key: [u8; 32],
+    /// Identifies which key a value was encrypted with.
+    id: [u8; KEY_ID_LEN],
+}
+
+impl EncryptionKey {
+    /// Create a key from 32 secret random bytes.
+    #[must_use]
+    pub fn new(key: [u8; 32]) -> EncryptionKey {
+        let nonce_key = blake3::derive_key("AcceptXMR storage encryption reference nonce", &key);
+        let id_hash = blake3::derive_key("AcceptXMR storage encryption key ID", &key);
+        let mut id = [0; KEY_ID_LEN];
+        id.copy_from_slice(&id_hash[..KEY_ID_LEN]);
+
+        EncryptionKey {
+            cipher: XChaCha20Poly1305::new(&key.into()),
+            nonce_key,
+            id,
+        }
+    }
+
+    /// Derive a key from a secret, such as a long random string. The secret is
+    /// hashed but not stretched, so it must be at least as hard to guess as the
+    /// key itself. Passwords are not suitable.
+    #[must_use]
+    pub fn from_secret(secret: impl AsRef<[u8]>) -> EncryptionKey {
+        EncryptionKey::new(blake3::derive_key(
+            "AcceptXMR storage encryption key",
+            secret.as_ref(),
+        ))
+    }
+
+    /// Encrypt a value with a random nonce.
+    fn encrypt_random<E>(
+        &self,
+        plaintext: &str,
+        aad: &[u8],
+    ) -> Result<String, EncryptedStorageError<E>> {
+        self.encrypt(rand::random(), plaintext, aad)
+    }
+
+    /// Encrypt a reference with a nonce derived from it, so that the same
+    /// reference always has the same ciphertext.
+    fn encrypt_reference<E>(&self, reference: &str) -> Result<String, EncryptedStorageError<E>> {
+        let hash = blake3::keyed_hash(&self.nonce_key, reference.as_bytes());
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&hash.as_bytes()[..NONCE_LEN]);
+        self.encrypt(nonce, reference, &aad("reference", &[]))
+    }
+
+    fn encrypt<E>(
+        &self,
+        nonce: [u8; NONCE_LEN],
+        plaintext: &str,
+        aad: &[u8],
+    ) -> Result<String, EncryptedStorageError<E>> {
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad,
+                },
+            )
+            .map_err(|_| EncryptedStorageError::Encryption)?;
+
+        let mut bytes = Vec::with_capacity(KEY_ID_LEN + NONCE_LEN + ciphertext.len());
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(format!("{ENCRYPTED_PREFIX}{}", hex::encode(bytes)))
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("id", &hex::encode(self.id))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Associated data binding an encrypted value to the field, and if applicable
+/// the invoice, it belongs to. This stops values from being swapped between
+/// fields or invoices.
+fn aad(field: &str, id: &[u8]) -> Vec<u8> {
+    [field.as_bytes(), id].concat()
+}
+
+fn id_bytes(invoice_id: InvoiceId) -> Vec<u8> {
+    [
+        &invoice_id.sub_index.major.to_le_bytes()[..],
+        &invoice_id.sub_index.minor.to_le_bytes(),
+        &invoice_id.creation_height.to_le_bytes(),
+    ]
+    .concat()
+}
+
+/// An error occurring while encrypting, decrypting, or storing values in
+/// [`Encrypted`] storage.
+#[derive(Error, Debug)]
+pub enum EncryptedStorageError<E> {
+    /// An error caused by the inner store.
+    #[error("inner storage error: {0}")]
+    Inner(#[source] E),
+    /// Failed to insert an [`Invoice`] because another has the same reference.
+    #[error("duplicate invoice reference")]
+    DuplicateReference,
+    /// A stored value was encrypted with a key which is neither the current
+    /// key nor one of the old keys.
+    #[error("value was encrypted with an unknown key")]
+    UnknownKey,
+    /// A stored value could not be decrypted, because it is malformed or has
+    /// been tampered with.
+    #[error("failed to decrypt value")]
+    Decryption,
+    /// A value could not be encrypted.
+    #[error("failed to encrypt value")]
+    Encryption,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use crate::{
+        storage::{
+            stores::{
+                Encrypted, EncryptedStorageError, EncryptionKey, InMemory, Sqlite, TableNames,
+            },
+            ArchiveStorage, EventStorage, InvoiceStorage,
+        },
+        Amount, Invoice, InvoiceEvent, SubIndex,
+    };
+
+    fn dummy_invoice(minor: u32, reference: Option<&str>) -> Invoice {
+        let mut invoice = Invoice::new(
+            "4A1WSBQdCbUCqt3DaGfmqVFchXScF43M6c5r4B6JXT3dUwuALncU9XTEnRPmUMcB3c16kVP9Y7thFLCJ5BaMW3UmSy93w3w".to_string(),
+            "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf".to_string(),
+            SubIndex::new(0, minor),
+            100,
+            Amount::from_pico(1),
+            1,
+            1,
+            r#"{"order": "pizza"}"#.to_string(),
+        );
+        invoice.reference = reference.map(ToString::to_string);
+        invoice
+    }
+
+    #[test]
+    fn encrypts_at_rest() {
+        let key = EncryptionKey::from_secret("secret");
+        let mut store = Encrypted::new(
+            Sqlite::new(":memory:", &TableNames::default()).unwrap(),
+            key,
+        );
+        let invoice = dummy_invoice(1, Some("order-1"));
+        store.insert(invoice.clone()).unwrap();
+
+        let stored = store.inner.get(invoice.id()).unwrap().unwrap();
+        assert!(stored.address().starts_with("enc1:"));
+        assert!(stored.description().starts_with("enc1:"));
+        assert!(stored.reference().unwrap().starts_with("enc1:"));
+        assert!(!stored.description().contains("pizza"));
+
+        assert_eq!(store.get(invoice.id()).unwrap(), Some(invoice.clone()));
+        assert_eq!(
+            store.get_by_reference("order-1").unwrap(),
+            Some(invoice.clone())
+        );
+        assert!(matches!(
+            store.insert(dummy_invoice(2, Some("order-1"))),
+            Err(EncryptedStorageError::DuplicateReference)
+        ));
+
+        store
+            .append_event(InvoiceEvent::Created {
+                invoice: invoice.clone(),
+            })
+            .unwrap();
+        let (_, stored_event) = store
+            .inner
+            .events_from(0, usize::MAX)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert!(stored_event.invoice().description().starts_with("enc1:"));
+        let (_, event) = store.events_from(0, usize::MAX).unwrap().pop().unwrap();
+        assert_eq!(event.invoice(), &invoice);
+
+        store.archive(invoice.id()).unwrap();
+        let stored = store.inner.get_archived(invoice.id()).unwrap().unwrap();
+        assert!(stored.description().starts_with("enc1:"));
+        assert_eq!(
+            store.get_archived(invoice.id()).unwrap(),
+            Some(invoice.clone())
+        );
+        assert_eq!(
+            store.get_archived_by_reference("order-1").unwrap(),
+            Some(invoice)
+        );
+    }
+
+    #[test]
+    fn wrong_key() {
+        let mut store = Encrypted::new(InMemory::new(), EncryptionKey::from_secret("secret"));
+        let invoice = dummy_invoice(1, None);
+        store.insert(invoice.clone()).unwrap();
+
+        let store = Encrypted::new(store.into_inner(), EncryptionKey::from_secret("other"));
+        assert!(matches!(
+            store.get(invoice.id()),
+            Err(EncryptedStorageError::UnknownKey)
+        ));
+    }
+
+    #[test]
+    fn tampered_value() {
+        let mut store = Encrypted::new(InMemory::new(), EncryptionKey::from_secret("secret"));
+        let invoice_1 = dummy_invoice(1, None);
+        let invoice_2 = dummy_invoice(2, None);
+        store.insert(invoice_1.clone()).unwrap();
+        store.insert(invoice_2.clone()).unwrap();
+
+        // Swap the encrypted descriptions of two invoices.
+        let mut stored_1 = store.inner.get(invoice_1.id()).unwrap().unwrap();
+        let stored_2 = store.inner.get(invoice_2.id()).unwrap().unwrap();
+        stored_1.description = stored_2.description;
+        store.inner.update(stored_1).unwrap();
+
+        assert!(matches!(
+            store.get(invoice_1.id()),
+            Err(EncryptedStorageError::Decryption)
+        ));
+        assert_eq!(store.get(invoice_2.id()).unwrap(), Some(invoice_2));
+    }
+
+    #[test]
+    fn plaintext_is_readable() {
+        let mut inner = InMemory::new();
+        let invoice = dummy_invoice(1, Some("order-1"));
+        InvoiceStorage::insert(&mut inner, invoice.clone()).unwrap();
+
+        let mut store = Encrypted::new(inner, EncryptionKey::from_secret("secret"));
+        assert_eq!(store.get(invoice.id()).unwrap(), Some(invoice.clone()));
+
+        store.reencrypt().unwrap();
+        let stored = store.inner.get(invoice.id()).unwrap().unwrap();
+        assert!(stored.description().starts_with("enc1:"));
+        assert_eq!(
+            store.get_by_reference("order-1").unwrap(),
+            Some(invoice.clone())
+        );
+    }
+
+    #[test]
+    fn rotate_keys() {
+        let old_key = EncryptionKey::from_secret("old");
+        let new_key = EncryptionKey::from_secret("new");
+
+        let mut store = Encrypted::new(InMemory::new(), old_key.clone());
+        let invoice_1 = dummy_invoice(1, Some("order-1"));
+        let invoice_2 = dummy_invoice(2, None);
+        store.insert(invoice_1.clone()).unwrap();
+        store.insert(invoice_2.clone()).unwrap();
+
+        // Both keys can be read from before re-encrypting.
+        let mut store = Encrypted::new(store.into_inner(), new_key.clone()).old_keys([old_key]);
+        assert_eq!(store.get(invoice_1.id()).unwrap(), Some(invoice_1.clone()));
+        assert_eq!(
+            store.get_by_reference("order-1").unwrap(),
+            Some(invoice_1.clone())
+        );
+        assert!(matches!(
+            store.insert(dummy_invoice(3, Some("order-1"))),
+            Err(EncryptedStorageError::DuplicateReference)
+        ));
+        store.reencrypt().unwrap();
+
+        // The old key is no longer needed.
+        let store = Encrypted::new(store.into_inner(), new_key);
+        assert_eq!(store.get(invoice_1.id()).unwrap(), Some(invoice_1.clone()));
+        assert_eq!(store.get(invoice_2.id()).unwrap(), Some(invoice_2));
+        assert_eq!(store.get_by_reference("order-1").unwrap(), Some(invoice_1));
+    }
+}
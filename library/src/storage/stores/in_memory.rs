@@ -1,10 +1,16 @@
-use std::collections::{btree_map::Entry, BTreeMap};
+use std::{
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap},
+    ops::Bound,
+};
 
 use thiserror::Error;
 
 use crate::{
-    storage::{HeightStorage, InvoiceStorage, OutputId, OutputKeyStorage, OutputPubKey, Storage},
-    Invoice, InvoiceId, SubIndex,
+    storage::{
+        ArchiveStorage, EventStorage, HeightStorage, InvoicePage, InvoiceQuery, InvoiceStorage,
+        OutputId, OutputKeyStorage, OutputPubKey, Storage, SubaddressStorage,
+    },
+    Invoice, InvoiceEvent, InvoiceId, SubIndex,
 };
 
 /// In-memory store. Note that invoices stored in memory will not be recoverable
@@ -13,8 +19,13 @@ use crate::{
 /// mitigation will also be reset after application restart.
 pub struct InMemory {
     invoices: BTreeMap<InvoiceId, Invoice>,
-    output_keys: BTreeMap<OutputPubKey, OutputId>,
+    references: HashMap<String, InvoiceId>,
+    output_keys: BTreeMap<OutputPubKey, (OutputId, u64)>,
     height: Option<u64>,
+    funded_subaddresses: BTreeSet<(String, SubIndex)>,
+    events: BTreeMap<u64, InvoiceEvent>,
+    next_sequence: u64,
+    archived: BTreeMap<InvoiceId, Invoice>,
 }
 
 impl InMemory {
@@ -23,8 +34,13 @@ impl InMemory {
     pub fn new() -> InMemory {
         InMemory {
             invoices: BTreeMap::new(),
+            references: HashMap::new(),
             output_keys: BTreeMap::new(),
             height: None,
+            funded_subaddresses: BTreeSet::new(),
+            events: BTreeMap::new(),
+            next_sequence: 1,
+            archived: BTreeMap::new(),
         }
     }
 }
@@ -42,12 +58,22 @@ impl InvoiceStorage for InMemory {
         if self.invoices.contains_key(&invoice.id()) {
             return Err(InMemoryStorageError::DuplicateInvoice);
         }
+        if let Some(reference) = invoice.reference() {
+            if self.references.contains_key(reference) {
+                return Err(InMemoryStorageError::DuplicateReference);
+            }
+            self.references.insert(reference.to_string(), invoice.id());
+        }
         self.invoices.insert(invoice.id(), invoice);
         Ok(())
     }
 
     fn remove(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
-        Ok(self.invoices.remove(&invoice_id))
+        let old = self.invoices.remove(&invoice_id);
+        if let Some(reference) = old.as_ref().and_then(Invoice::reference) {
+            self.references.remove(reference);
+        }
+        Ok(old)
     }
 
     fn update(&mut self, invoice: Invoice) -> Result<Option<Invoice>, Self::Error> {
@@ -61,6 +87,14 @@ impl InvoiceStorage for InMemory {
         Ok(self.invoices.get(&invoice_id).cloned())
     }
 
+    fn get_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error> {
+        Ok(self
+            .references
+            .get(reference)
+            .and_then(|invoice_id| self.invoices.get(invoice_id))
+            .cloned())
+    }
+
     fn get_ids(&self) -> Result<Vec<InvoiceId>, Self::Error> {
         Ok(self.invoices.keys().copied().collect::<Vec<InvoiceId>>())
     }
@@ -85,21 +119,51 @@ impl InvoiceStorage for InMemory {
     fn is_empty(&self) -> Result<bool, Self::Error> {
         Ok(self.invoices.is_empty())
     }
+
+    fn query(&self, query: &InvoiceQuery) -> Result<InvoicePage, Self::Error> {
+        let start = query.get_after().map_or(Bound::Unbounded, Bound::Excluded);
+        query.collect_page(
+            self.invoices
+                .range((start, Bound::Unbounded))
+                .map(|(_, invoice)| Ok(invoice.clone())),
+        )
+    }
 }
 
 impl OutputKeyStorage for InMemory {
     type Error = InMemoryStorageError;
 
-    fn insert(&mut self, key: OutputPubKey, output_id: OutputId) -> Result<(), Self::Error> {
+    fn insert(
+        &mut self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> Result<(), Self::Error> {
         if self.output_keys.contains_key(&key) {
             return Err(InMemoryStorageError::DuplicateOutputKey);
         }
-        self.output_keys.insert(key, output_id);
+        self.output_keys.insert(key, (output_id, height));
         Ok(())
     }
 
     fn get(&self, key: OutputPubKey) -> Result<Option<OutputId>, Self::Error> {
-        Ok(self.output_keys.get(&key).copied())
+        Ok(self.output_keys.get(&key).map(|(output_id, _)| *output_id))
+    }
+
+    fn try_for_each_key<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<(OutputPubKey, OutputId, u64), Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.output_keys
+            .iter()
+            .try_for_each(|(key, (output_id, height))| f(Ok((*key, *output_id, *height))))
+    }
+
+    fn prune_keys(&mut self, height: u64) -> Result<u64, Self::Error> {
+        let count_before = self.output_keys.len();
+        self.output_keys
+            .retain(|_, (_, key_height)| *key_height >= height);
+        Ok((count_before - self.output_keys.len()) as u64)
     }
 }
 
@@ -117,6 +181,94 @@ impl HeightStorage for InMemory {
     }
 }
 
+impl SubaddressStorage for InMemory {
+    type Error = InMemoryStorageError;
+
+    fn insert_funded(&mut self, wallet: &str, sub_index: SubIndex) -> Result<(), Self::Error> {
+        self.funded_subaddresses
+            .insert((wallet.to_string(), sub_index));
+        Ok(())
+    }
+
+    fn is_funded(&self, wallet: &str, sub_index: SubIndex) -> Result<bool, Self::Error> {
+        Ok(self
+            .funded_subaddresses
+            .contains(&(wallet.to_string(), sub_index)))
+    }
+
+    fn funded(&self) -> Result<Vec<(String, SubIndex)>, Self::Error> {
+        Ok(self.funded_subaddresses.iter().cloned().collect())
+    }
+}
+
+impl EventStorage for InMemory {
+    type Error = InMemoryStorageError;
+
+    fn append_event(&mut self, event: InvoiceEvent) -> Result<u64, Self::Error> {
+        let sequence = self.next_sequence;
+        self.events.insert(sequence, event);
+        self.next_sequence += 1;
+        Ok(sequence)
+    }
+
+    fn events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, Self::Error> {
+        Ok(self
+            .events
+            .range(sequence..)
+            .take(limit)
+            .map(|(sequence, event)| (*sequence, event.clone()))
+            .collect())
+    }
+
+    fn prune_events(&mut self, sequence: u64) -> Result<(), Self::Error> {
+        self.events = self.events.split_off(&sequence);
+        Ok(())
+    }
+}
+
+impl ArchiveStorage for InMemory {
+    type Error = InMemoryStorageError;
+
+    fn archive(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        let Some(invoice) = InvoiceStorage::remove(self, invoice_id)? else {
+            return Ok(None);
+        };
+        self.archived.insert(invoice_id, invoice.clone());
+        Ok(Some(invoice))
+    }
+
+    fn get_archived(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        Ok(self.archived.get(&invoice_id).cloned())
+    }
+
+    fn get_archived_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error> {
+        Ok(self
+            .archived
+            .values()
+            .find(|invoice| invoice.reference() == Some(reference))
+            .cloned())
+    }
+
+    fn try_for_each_archived<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.archived
+            .values()
+            .try_for_each(move |invoice| f(Ok(invoice.clone())))
+    }
+
+    fn prune_archive(&mut self, height: u64) -> Result<(), Self::Error> {
+        self.archived
+            .retain(|_, invoice| invoice.current_height() >= height);
+        Ok(())
+    }
+}
+
 impl Storage for InMemory {
     type Error = InMemoryStorageError;
 }
@@ -128,6 +280,9 @@ pub enum InMemoryStorageError {
     /// Attempted to insert an invoice which already exists
     #[error("attempted to insert an invoice which already exists")]
     DuplicateInvoice,
+    /// Attempted to insert an invoice with the same reference as another
+    #[error("attempted to insert an invoice with the same reference as another")]
+    DuplicateReference,
     /// Attempted to insert an output public key which already exists
     #[error("attempted to insert an output public key which already exists")]
     DuplicateOutputKey,
@@ -1,15 +1,70 @@
 //! Built-in implementors of [`Storage`](super::Storage).
 
+#[cfg(feature = "encryption")]
+mod encrypted;
 #[cfg(feature = "in-memory")]
 mod in_memory;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "redb")]
+mod redb;
 #[cfg(feature = "sled")]
 mod sled;
 #[cfg(feature = "sqlite")]
 mod sqlite;
 
+#[cfg(feature = "encryption")]
+pub use super::stores::encrypted::{Encrypted, EncryptedStorageError, EncryptionKey};
 #[cfg(feature = "in-memory")]
 pub use super::stores::in_memory::{InMemory, InMemoryStorageError};
+#[cfg(feature = "postgres")]
+pub use super::stores::postgres::{Postgres, PostgresStorageError};
+#[cfg(feature = "redb")]
+pub use super::stores::redb::{Redb, RedbStorageError};
 #[cfg(feature = "sled")]
 pub use super::stores::sled::{Sled, SledStorageError};
 #[cfg(feature = "sqlite")]
 pub use super::stores::sqlite::{Sqlite, SqliteStorageError};
+
+/// Names of the tables (or trees) a database-backed store keeps its data in.
+///
+/// The defaults suit a database used only by `AcceptXMR`. Override individual
+/// names to share a database with other applications:
+///
+/// ```
+/// use acceptxmr::storage::stores::TableNames;
+///
+/// let tables = TableNames {
+///     invoices: "shop invoices".to_string(),
+///     ..TableNames::default()
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableNames {
+    /// Pending invoices. Also prefixes the names of the invoice reference
+    /// index and the schema version.
+    pub invoices: String,
+    /// Public keys of received outputs.
+    pub output_keys: String,
+    /// The last scanned blockchain height.
+    pub height: String,
+    /// Subaddresses which have received funds.
+    pub subaddresses: String,
+    /// The invoice event log.
+    pub events: String,
+    /// Archived invoices.
+    pub archive: String,
+}
+
+impl Default for TableNames {
+    fn default() -> TableNames {
+        TableNames {
+            invoices: "invoices".to_string(),
+            output_keys: "output keys".to_string(),
+            height: "height".to_string(),
+            subaddresses: "subaddresses".to_string(),
+            events: "events".to_string(),
+            archive: "archived invoices".to_string(),
+        }
+    }
+}
@@ -0,0 +1,1008 @@
+use log::debug;
+use redb::{
+    CommitError, CompactionError, Database, DatabaseError, ReadTransaction, ReadableTable,
+    ReadableTableMetadata, TableDefinition, TableError, TransactionError, WriteTransaction,
+};
+use thiserror::Error;
+
+#[cfg(feature = "sled")]
+use super::{Sled, SledStorageError};
+use crate::{
+    storage::{
+        schema, stores::TableNames, ArchiveStorage, EventStorage, HeightStorage, InvoicePage,
+        InvoiceQuery, InvoiceStorage, OutputId, OutputKeyStorage, OutputPubKey, SchemaError,
+        Storage, SubaddressStorage, SCHEMA_VERSION,
+    },
+    Invoice, InvoiceEvent, InvoiceId, SubIndex,
+};
+
+/// Invoice table key: major subaddress index, minor subaddress index, and
+/// creation height.
+type InvoiceKey = (u32, u32, u64);
+/// Major and minor subaddress index.
+type SubIndexKey = (u32, u32);
+/// Funded subaddress table key: wallet primary address, and major and minor
+/// subaddress index.
+type FundedKey<'a> = (&'a str, u32, u32);
+/// Number of events read from sled at a time while migrating from it.
+#[cfg(feature = "sled")]
+const MIGRATION_PAGE_LEN: usize = 1024;
+
+/// [Redb](redb) database. All data is kept in a single file, and every change
+/// is made in an ACID transaction.
+pub struct Redb {
+    db: Database,
+    invoices: String,
+    references: String,
+    output_keys: String,
+    height: String,
+    funded_subaddresses: String,
+    events: String,
+    last_sequence: String,
+    archived: String,
+    archived_references: String,
+}
+
+impl Redb {
+    /// Open a [Redb](redb) database file at the specified location, and use
+    /// the specified tables. Creates a new database if one does not exist.
+    ///
+    /// Invoices written with an older storage schema are upgraded to
+    /// [`SCHEMA_VERSION`](crate::storage::SCHEMA_VERSION).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database could not be opened at the specified
+    /// path, if the tables could not be created, or if it was written with a
+    /// newer schema than this version of `AcceptXMR` supports.
+    pub fn new(path: &str, tables: &TableNames) -> Result<Redb, RedbStorageError> {
+        let TableNames {
+            invoices: invoice_table,
+            output_keys: output_key_table,
+            height: height_table,
+            subaddresses: subaddress_table,
+            events: event_table,
+            archive: archive_table,
+        } = tables;
+        let db = Database::create(path)?;
+
+        let redb = Redb {
+            db,
+            invoices: invoice_table.clone(),
+            references: format!("{invoice_table} references"),
+            output_keys: output_key_table.clone(),
+            height: height_table.clone(),
+            funded_subaddresses: subaddress_table.clone(),
+            events: event_table.clone(),
+            last_sequence: format!("{event_table} last sequence"),
+            archived: archive_table.clone(),
+            archived_references: format!("{archive_table} references"),
+        };
+        let schema_version = format!("{invoice_table} schema version");
+
+        // Tables can't be opened for reading until they exist, so create them
+        // all up front.
+        redb.write(|txn| {
+            redb.upgrade_funded_subaddresses(txn)?;
+            txn.open_table(redb.invoice_table())?;
+            txn.open_table(redb.reference_table())?;
+            txn.open_table(redb.output_key_table())?;
+            txn.open_table(redb.height_table())?;
+            txn.open_table(redb.subaddress_table())?;
+            txn.open_table(redb.event_table())?;
+            txn.open_table(redb.last_sequence_table())?;
+            txn.open_table(redb.archive_table())?;
+            txn.open_table(redb.archived_reference_table())?;
+            redb.upgrade_schema(txn, TableDefinition::new(&schema_version))
+        })?;
+
+        Ok(redb)
+    }
+
+    /// Upgrade invoices, archived invoices, logged events and output keys
+    /// written with an older schema to [`SCHEMA_VERSION`], and record the
+    /// version. The index of archived invoice references is rebuilt from the
+    /// upgraded invoices.
+    fn upgrade_schema(
+        &self,
+        txn: &WriteTransaction,
+        schema_version: TableDefinition<'_, (), u32>,
+    ) -> Result<(), RedbStorageError> {
+        let mut version_table = txn.open_table(schema_version)?;
+        let is_empty = txn.open_table(self.invoice_table())?.is_empty()?
+            && txn.open_table(self.output_key_table())?.is_empty()?;
+        let recorded = version_table.get(())?.map(|v| v.value());
+        let version = schema::stored_version(recorded, is_empty)?;
+        if recorded == Some(SCHEMA_VERSION) {
+            return Ok(());
+        }
+
+        if version < SCHEMA_VERSION {
+            debug!("Upgrading invoices from schema version {version} to {SCHEMA_VERSION}");
+            for table in [self.invoice_table(), self.archive_table()] {
+                let mut invoices = txn.open_table(table)?;
+                let upgraded = invoices
+                    .iter()?
+                    .map(|row| {
+                        let (key, value) = row?;
+                        Ok((
+                            key.value(),
+                            schema::upgrade_invoice(value.value(), version)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<(InvoiceKey, Vec<u8>)>, RedbStorageError>>()?;
+                for (key, invoice) in upgraded {
+                    invoices.insert(key, invoice.as_slice())?;
+                }
+            }
+
+            let mut events = txn.open_table(self.event_table())?;
+            let upgraded = events
+                .iter()?
+                .map(|row| {
+                    let (sequence, value) = row?;
+                    Ok((
+                        sequence.value(),
+                        schema::upgrade_event(value.value(), version)?,
+                    ))
+                })
+                .collect::<Result<Vec<(u64, Vec<u8>)>, RedbStorageError>>()?;
+            for (sequence, event) in upgraded {
+                events.insert(sequence, event.as_slice())?;
+            }
+
+            let mut archived_references = txn.open_table(self.archived_reference_table())?;
+            for row in txn.open_table(self.archive_table())?.iter()? {
+                let (key, value) = row?;
+                let invoice: Invoice = decode(value.value())?;
+                if let Some(reference) = invoice.reference() {
+                    archived_references.insert((reference, key.value()), ())?;
+                }
+            }
+        }
+
+        if version < schema::OUTPUT_KEY_HEIGHT_VERSION {
+            // Older keys are recorded at the scan height, so that they are kept
+            // for the full retention depth.
+            let key_height = txn
+                .open_table(self.height_table())?
+                .get(())?
+                .map_or(0, |v| v.value());
+            let mut output_keys = txn.open_table(self.output_key_table())?;
+            let upgraded = output_keys
+                .iter()?
+                .map(|row| {
+                    let (key, value) = row?;
+                    let output_id: OutputId = decode(value.value())?;
+                    Ok((
+                        *key.value(),
+                        bincode::encode_to_vec(
+                            (output_id, key_height),
+                            bincode::config::standard(),
+                        )?,
+                    ))
+                })
+                .collect::<Result<Vec<([u8; 32], Vec<u8>)>, RedbStorageError>>()?;
+            for (key, value) in upgraded {
+                output_keys.insert(&key, value.as_slice())?;
+            }
+        }
+
+        version_table.insert((), SCHEMA_VERSION)?;
+        Ok(())
+    }
+
+    /// Rebuild a funded subaddress table created before schema version 6,
+    /// which is keyed by subaddress index alone, with the wallet in its key.
+    /// Older subaddresses are given an empty wallet. The table's key type
+    /// changed, so this must be done before it is opened.
+    fn upgrade_funded_subaddresses(&self, txn: &WriteTransaction) -> Result<(), RedbStorageError> {
+        match txn.open_table(self.subaddress_table()) {
+            Err(TableError::TableTypeMismatch { .. }) => {}
+            result => {
+                result?;
+                return Ok(());
+            }
+        }
+
+        debug!("Rebuilding funded subaddress table with wallets");
+        let old_table: TableDefinition<'_, SubIndexKey, ()> =
+            TableDefinition::new(&self.funded_subaddresses);
+        let funded = txn
+            .open_table(old_table)?
+            .iter()?
+            .map(|row| Ok(row?.0.value()))
+            .collect::<Result<Vec<SubIndexKey>, RedbStorageError>>()?;
+        txn.delete_table(old_table)?;
+        let mut funded_subaddresses = txn.open_table(self.subaddress_table())?;
+        for (major, minor) in funded {
+            funded_subaddresses.insert(("", major, minor), ())?;
+        }
+        Ok(())
+    }
+
+    /// Compact the database file, returning space freed by removed invoices,
+    /// pruned events and pruned output keys to the file system. Returns `true` if any space was
+    /// freed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if compaction fails.
+    pub fn compact(&mut self) -> Result<bool, RedbStorageError> {
+        Ok(self.db.compact()?)
+    }
+
+    fn invoice_table(&self) -> TableDefinition<'_, InvoiceKey, &'static [u8]> {
+        TableDefinition::new(&self.invoices)
+    }
+
+    /// Maps each invoice reference to the key of its invoice.
+    fn reference_table(&self) -> TableDefinition<'_, &'static str, InvoiceKey> {
+        TableDefinition::new(&self.references)
+    }
+
+    fn output_key_table(&self) -> TableDefinition<'_, &'static [u8; 32], &'static [u8]> {
+        TableDefinition::new(&self.output_keys)
+    }
+
+    fn height_table(&self) -> TableDefinition<'_, (), u64> {
+        TableDefinition::new(&self.height)
+    }
+
+    fn subaddress_table(&self) -> TableDefinition<'_, FundedKey<'static>, ()> {
+        TableDefinition::new(&self.funded_subaddresses)
+    }
+
+    fn event_table(&self) -> TableDefinition<'_, u64, &'static [u8]> {
+        TableDefinition::new(&self.events)
+    }
+
+    /// Holds the last event sequence number handed out, so that numbers are
+    /// not reused after the newest events are pruned.
+    fn last_sequence_table(&self) -> TableDefinition<'_, (), u64> {
+        TableDefinition::new(&self.last_sequence)
+    }
+
+    fn archive_table(&self) -> TableDefinition<'_, InvoiceKey, &'static [u8]> {
+        TableDefinition::new(&self.archived)
+    }
+
+    /// Indexes archived invoices by reference. Several archived invoices may
+    /// share a reference, so the key of each one is part of its entry.
+    fn archived_reference_table(&self) -> TableDefinition<'_, (&'static str, InvoiceKey), ()> {
+        TableDefinition::new(&self.archived_references)
+    }
+
+    /// Insert an invoice and its reference in `txn`.
+    fn insert_invoice(
+        &self,
+        txn: &WriteTransaction,
+        invoice: &Invoice,
+    ) -> Result<(), RedbStorageError> {
+        let key = invoice_key(invoice.id());
+        let value = bincode::encode_to_vec(invoice, bincode::config::standard())?;
+
+        let mut invoices = txn.open_table(self.invoice_table())?;
+        if invoices.get(key)?.is_some() {
+            return Err(RedbStorageError::DuplicateInvoice);
+        }
+        if let Some(reference) = invoice.reference() {
+            let mut references = txn.open_table(self.reference_table())?;
+            if references.get(reference)?.is_some() {
+                return Err(RedbStorageError::DuplicateReference);
+            }
+            references.insert(reference, key)?;
+        }
+        invoices.insert(key, value.as_slice())?;
+        Ok(())
+    }
+
+    /// Remove an invoice and its reference in `txn`, returning the encoded
+    /// invoice and the invoice if it existed.
+    fn remove_invoice(
+        &self,
+        txn: &WriteTransaction,
+        key: InvoiceKey,
+    ) -> Result<Option<(Vec<u8>, Invoice)>, RedbStorageError> {
+        let mut invoices = txn.open_table(self.invoice_table())?;
+        let Some(value) = invoices.remove(key)? else {
+            return Ok(None);
+        };
+        let value = value.value().to_vec();
+        let invoice: Invoice = decode(&value)?;
+        if let Some(reference) = invoice.reference() {
+            txn.open_table(self.reference_table())?.remove(reference)?;
+        }
+        Ok(Some((value, invoice)))
+    }
+
+    /// Insert an encoded invoice into the archive in `txn`, replacing any
+    /// archived invoice with the same ID, and index its reference.
+    fn insert_archived(
+        &self,
+        txn: &WriteTransaction,
+        invoice: &Invoice,
+        value: &[u8],
+    ) -> Result<(), RedbStorageError> {
+        let key = invoice_key(invoice.id());
+        let mut archived_references = txn.open_table(self.archived_reference_table())?;
+        let replaced = txn
+            .open_table(self.archive_table())?
+            .insert(key, value)?
+            .map(|v| decode::<Invoice>(v.value()))
+            .transpose()?;
+        if let Some(reference) = replaced.as_ref().and_then(Invoice::reference) {
+            archived_references.remove((reference, key))?;
+        }
+        if let Some(reference) = invoice.reference() {
+            archived_references.insert((reference, key), ())?;
+        }
+        Ok(())
+    }
+
+    fn read<T, F>(&self, f: F) -> Result<T, RedbStorageError>
+    where
+        F: FnOnce(&ReadTransaction) -> Result<T, RedbStorageError>,
+    {
+        let txn = self.db.begin_read()?;
+        f(&txn)
+    }
+
+    /// Run `f` in a write transaction, committing if it succeeds. If `f`
+    /// returns an error, none of its changes are kept.
+    fn write<T, F>(&self, f: F) -> Result<T, RedbStorageError>
+    where
+        F: FnOnce(&WriteTransaction) -> Result<T, RedbStorageError>,
+    {
+        let txn = self.db.begin_write()?;
+        let value = f(&txn)?;
+        txn.commit()?;
+        Ok(value)
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Redb {
+    /// Copy the contents of a [`Sled`] database into this one, so that it can
+    /// take the sled database's place. Invoices, output keys (for burning bug
+    /// mitigation), the scanned height, funded subaddresses, the event log, and
+    /// archived invoices are all copied, and event sequence numbers are
+    /// preserved.
+    ///
+    /// The copy is made in a single transaction, so if it fails, nothing is
+    /// copied. The sled database is left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sled database could not be read, if this
+    /// database could not be written to, or if an invoice, invoice reference
+    /// or output key being copied already exists in this database.
+    pub fn migrate_from_sled(&mut self, sled: &Sled) -> Result<(), RedbStorageError> {
+        self.write(|txn| {
+            for invoice_id in InvoiceStorage::get_ids(sled)? {
+                let Some(invoice) = InvoiceStorage::get(sled, invoice_id)? else {
+                    continue;
+                };
+                self.insert_invoice(txn, &invoice)?;
+            }
+
+            let mut output_keys = txn.open_table(self.output_key_table())?;
+            for row in sled.output_keys() {
+                let (key, output_id, height) = row?;
+                if output_keys.get(&key.0)?.is_some() {
+                    return Err(RedbStorageError::DuplicateOutputKey);
+                }
+                let value =
+                    bincode::encode_to_vec((output_id, height), bincode::config::standard())?;
+                output_keys.insert(&key.0, value.as_slice())?;
+            }
+
+            if let Some(height) = HeightStorage::get(sled)? {
+                txn.open_table(self.height_table())?.insert((), height)?;
+            }
+
+            let mut funded_subaddresses = txn.open_table(self.subaddress_table())?;
+            for (wallet, sub_index) in sled.funded()? {
+                funded_subaddresses.insert(funded_key(&wallet, sub_index), ())?;
+            }
+
+            let mut events = txn.open_table(self.event_table())?;
+            let mut next_sequence = 0;
+            loop {
+                let page = sled.events_from(next_sequence, MIGRATION_PAGE_LEN)?;
+                let Some(&(last, _)) = page.last() else {
+                    break;
+                };
+                for (sequence, event) in page {
+                    let value = bincode::encode_to_vec(event, bincode::config::standard())?;
+                    events.insert(sequence, value.as_slice())?;
+                }
+                next_sequence = last + 1;
+            }
+            let mut last_sequence = txn.open_table(self.last_sequence_table())?;
+            let current = last_sequence.get(())?.map_or(0, |v| v.value());
+            last_sequence.insert((), current.max(sled.last_event_sequence()?))?;
+
+            let mut archived_invoices = Vec::new();
+            sled.try_for_each_archived(|invoice| {
+                archived_invoices.push(invoice?);
+                Ok(())
+            })?;
+            for invoice in archived_invoices {
+                let value = bincode::encode_to_vec(&invoice, bincode::config::standard())?;
+                self.insert_archived(txn, &invoice, &value)?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl InvoiceStorage for Redb {
+    type Error = RedbStorageError;
+
+    fn insert(&mut self, invoice: Invoice) -> Result<(), RedbStorageError> {
+        self.write(|txn| self.insert_invoice(txn, &invoice))
+    }
+
+    fn remove(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, RedbStorageError> {
+        let key = invoice_key(invoice_id);
+
+        self.write(|txn| {
+            let old = self.remove_invoice(txn, key)?;
+            Ok(old.map(|(_, invoice)| invoice))
+        })
+    }
+
+    fn update(&mut self, invoice: Invoice) -> Result<Option<Invoice>, RedbStorageError> {
+        let key = invoice_key(invoice.id());
+        let value = bincode::encode_to_vec(invoice, bincode::config::standard())?;
+
+        self.write(|txn| {
+            let mut table = txn.open_table(self.invoice_table())?;
+            if table.get(key)?.is_none() {
+                return Ok(None);
+            }
+            let old = table
+                .insert(key, value.as_slice())?
+                .map(|v| decode(v.value()))
+                .transpose()?;
+            Ok(old)
+        })
+    }
+
+    fn get(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, RedbStorageError> {
+        let key = invoice_key(invoice_id);
+
+        self.read(|txn| {
+            let table = txn.open_table(self.invoice_table())?;
+            let invoice = table.get(key)?.map(|v| decode(v.value())).transpose()?;
+            Ok(invoice)
+        })
+    }
+
+    fn get_by_reference(&self, reference: &str) -> Result<Option<Invoice>, RedbStorageError> {
+        self.read(|txn| {
+            let Some(key) = txn.open_table(self.reference_table())?.get(reference)? else {
+                return Ok(None);
+            };
+            let table = txn.open_table(self.invoice_table())?;
+            let invoice = table
+                .get(key.value())?
+                .map(|v| decode(v.value()))
+                .transpose()?;
+            Ok(invoice)
+        })
+    }
+
+    fn get_ids(&self) -> Result<Vec<InvoiceId>, RedbStorageError> {
+        self.read(|txn| {
+            let table = txn.open_table(self.invoice_table())?;
+            let ids = table
+                .iter()?
+                .map(|row| {
+                    let (major, minor, creation_height) = row?.0.value();
+                    Ok(InvoiceId::new(SubIndex::new(major, minor), creation_height))
+                })
+                .collect();
+            ids
+        })
+    }
+
+    fn contains_sub_index(&self, sub_index: SubIndex) -> Result<bool, RedbStorageError> {
+        let (major, minor) = sub_index_key(sub_index);
+
+        self.read(|txn| {
+            let table = txn.open_table(self.invoice_table())?;
+            let contains = table
+                .range((major, minor, 0)..=(major, minor, u64::MAX))?
+                .next()
+                .is_some();
+            Ok(contains)
+        })
+    }
+
+    fn try_for_each<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.read(|txn| {
+            let table = txn.open_table(self.invoice_table())?;
+            for row in table.iter()? {
+                let invoice_or_err = row
+                    .map_err(RedbStorageError::from)
+                    .and_then(|(_, v)| decode(v.value()));
+                f(invoice_or_err)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn is_empty(&self) -> Result<bool, RedbStorageError> {
+        self.read(|txn| Ok(txn.open_table(self.invoice_table())?.is_empty()?))
+    }
+
+    fn query(&self, query: &InvoiceQuery) -> Result<InvoicePage, RedbStorageError> {
+        // Keys are ordered the same way as invoice IDs, so reading can start at
+        // the cursor. The cursor itself is skipped by `collect_page`.
+        let start = query.get_after().map_or((0, 0, 0), invoice_key);
+
+        self.read(|txn| {
+            let table = txn.open_table(self.invoice_table())?;
+            let rows = table.range(start..)?;
+            query.collect_page(rows.map(|row| {
+                row.map_err(RedbStorageError::from)
+                    .and_then(|(_, v)| decode(v.value()))
+            }))
+        })
+    }
+}
+
+impl OutputKeyStorage for Redb {
+    type Error = RedbStorageError;
+
+    fn insert(
+        &mut self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> Result<(), Self::Error> {
+        let value = bincode::encode_to_vec((output_id, height), bincode::config::standard())?;
+
+        self.write(|txn| {
+            let mut table = txn.open_table(self.output_key_table())?;
+            if table.get(&key.0)?.is_some() {
+                return Err(RedbStorageError::DuplicateOutputKey);
+            }
+            table.insert(&key.0, value.as_slice())?;
+            Ok(())
+        })
+    }
+
+    fn get(&self, key: OutputPubKey) -> Result<Option<OutputId>, Self::Error> {
+        self.read(|txn| {
+            let table = txn.open_table(self.output_key_table())?;
+            let output_id = table
+                .get(&key.0)?
+                .map(|v| decode::<(OutputId, u64)>(v.value()))
+                .transpose()?
+                .map(|(output_id, _)| output_id);
+            Ok(output_id)
+        })
+    }
+    fn try_for_each_key<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<(OutputPubKey, OutputId, u64), Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.read(|txn| {
+            let table = txn.open_table(self.output_key_table())?;
+            for row in table.iter()? {
+                let key_or_err = row.map_err(RedbStorageError::from).and_then(|(k, v)| {
+                    let (output_id, height) = decode(v.value())?;
+                    Ok((OutputPubKey(*k.value()), output_id, height))
+                });
+                f(key_or_err)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn prune_keys(&mut self, height: u64) -> Result<u64, Self::Error> {
+        self.write(|txn| {
+            let mut table = txn.open_table(self.output_key_table())?;
+            let before = table.len()?;
+            let mut decode_error = None;
+            table.retain(|_, v| match decode::<(OutputId, u64)>(v) {
+                Ok((_, key_height)) => key_height >= height,
+                Err(e) => {
+                    decode_error.get_or_insert(e);
+                    true
+                }
+            })?;
+            if let Some(e) = decode_error {
+                return Err(e);
+            }
+            Ok(before - table.len()?)
+        })
+    }
+}
+
+impl HeightStorage for Redb {
+    type Error = RedbStorageError;
+
+    fn upsert(&mut self, height: u64) -> Result<Option<u64>, Self::Error> {
+        self.write(|txn| {
+            let mut table = txn.open_table(self.height_table())?;
+            let old_height = table.insert((), height)?.map(|v| v.value());
+            Ok(old_height)
+        })
+    }
+
+    fn get(&self) -> Result<Option<u64>, Self::Error> {
+        self.read(|txn| {
+            let table = txn.open_table(self.height_table())?;
+            let height = table.get(())?.map(|v| v.value());
+            Ok(height)
+        })
+    }
+}
+
+impl SubaddressStorage for Redb {
+    type Error = RedbStorageError;
+
+    fn insert_funded(&mut self, wallet: &str, sub_index: SubIndex) -> Result<(), Self::Error> {
+        self.write(|txn| {
+            txn.open_table(self.subaddress_table())?
+                .insert(funded_key(wallet, sub_index), ())?;
+            Ok(())
+        })
+    }
+
+    fn is_funded(&self, wallet: &str, sub_index: SubIndex) -> Result<bool, Self::Error> {
+        self.read(|txn| {
+            let table = txn.open_table(self.subaddress_table())?;
+            let funded = table.get(funded_key(wallet, sub_index))?.is_some();
+            Ok(funded)
+        })
+    }
+
+    fn funded(&self) -> Result<Vec<(String, SubIndex)>, Self::Error> {
+        self.read(|txn| {
+            let table = txn.open_table(self.subaddress_table())?;
+            let funded = table
+                .iter()?
+                .map(|row| {
+                    let (key, _) = row?;
+                    let (wallet, major, minor) = key.value();
+                    Ok((wallet.to_string(), SubIndex::new(major, minor)))
+                })
+                .collect();
+            funded
+        })
+    }
+}
+
+impl EventStorage for Redb {
+    type Error = RedbStorageError;
+
+    fn append_event(&mut self, event: InvoiceEvent) -> Result<u64, Self::Error> {
+        let value = bincode::encode_to_vec(event, bincode::config::standard())?;
+
+        self.write(|txn| {
+            let mut last_sequence = txn.open_table(self.last_sequence_table())?;
+            let sequence = last_sequence.get(())?.map_or(0, |v| v.value()) + 1;
+            last_sequence.insert((), sequence)?;
+            txn.open_table(self.event_table())?
+                .insert(sequence, value.as_slice())?;
+            Ok(sequence)
+        })
+    }
+
+    fn events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, Self::Error> {
+        self.read(|txn| {
+            let table = txn.open_table(self.event_table())?;
+            let events = table
+                .range(sequence..)?
+                .take(limit)
+                .map(|row| {
+                    let (key, value) = row?;
+                    Ok((key.value(), decode(value.value())?))
+                })
+                .collect();
+            events
+        })
+    }
+
+    fn prune_events(&mut self, sequence: u64) -> Result<(), Self::Error> {
+        self.write(|txn| {
+            txn.open_table(self.event_table())?
+                .retain_in(..sequence, |_, _| false)?;
+            Ok(())
+        })
+    }
+}
+
+impl ArchiveStorage for Redb {
+    type Error = RedbStorageError;
+
+    fn archive(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, RedbStorageError> {
+        let key = invoice_key(invoice_id);
+
+        self.write(|txn| {
+            let Some((value, invoice)) = self.remove_invoice(txn, key)? else {
+                return Ok(None);
+            };
+            self.insert_archived(txn, &invoice, &value)?;
+            Ok(Some(invoice))
+        })
+    }
+
+    fn get_archived(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, RedbStorageError> {
+        let key = invoice_key(invoice_id);
+
+        self.read(|txn| {
+            let table = txn.open_table(self.archive_table())?;
+            let invoice = table.get(key)?.map(|v| decode(v.value())).transpose()?;
+            Ok(invoice)
+        })
+    }
+
+    fn get_archived_by_reference(
+        &self,
+        reference: &str,
+    ) -> Result<Option<Invoice>, RedbStorageError> {
+        self.read(|txn| {
+            let references = txn.open_table(self.archived_reference_table())?;
+            let first = (reference, (0, 0, 0));
+            let last = (reference, (u32::MAX, u32::MAX, u64::MAX));
+            let Some(row) = references.range(first..=last)?.next() else {
+                return Ok(None);
+            };
+            let key = row?.0.value().1;
+            let table = txn.open_table(self.archive_table())?;
+            let invoice = table.get(key)?.map(|v| decode(v.value())).transpose()?;
+            Ok(invoice)
+        })
+    }
+
+    fn try_for_each_archived<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.read(|txn| {
+            let table = txn.open_table(self.archive_table())?;
+            for row in table.iter()? {
+                let invoice_or_err = row
+                    .map_err(RedbStorageError::from)
+                    .and_then(|(_, v)| decode(v.value()));
+                f(invoice_or_err)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn prune_archive(&mut self, height: u64) -> Result<(), RedbStorageError> {
+        self.write(|txn| {
+            let mut table = txn.open_table(self.archive_table())?;
+            let mut archived_references = txn.open_table(self.archived_reference_table())?;
+            let mut pruned = Vec::new();
+            for row in table.iter()? {
+                let (key, value) = row?;
+                let invoice: Invoice = decode(value.value())?;
+                if invoice.current_height() < height {
+                    pruned.push((key.value(), invoice));
+                }
+            }
+            for (key, invoice) in pruned {
+                table.remove(key)?;
+                if let Some(reference) = invoice.reference() {
+                    archived_references.remove((reference, key))?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Storage for Redb {
+    // Every change is committed durably as it is made, so there is nothing to
+    // flush.
+    type Error = RedbStorageError;
+}
+
+fn invoice_key(invoice_id: InvoiceId) -> InvoiceKey {
+    (
+        invoice_id.sub_index.major,
+        invoice_id.sub_index.minor,
+        invoice_id.creation_height,
+    )
+}
+
+fn sub_index_key(sub_index: SubIndex) -> SubIndexKey {
+    (sub_index.major, sub_index.minor)
+}
+
+fn funded_key(wallet: &str, sub_index: SubIndex) -> FundedKey<'_> {
+    (wallet, sub_index.major, sub_index.minor)
+}
+
+fn decode<T: bincode::Decode>(bytes: &[u8]) -> Result<T, RedbStorageError> {
+    Ok(bincode::decode_from_slice(bytes, bincode::config::standard())?.0)
+}
+
+/// An error occurring while storing or retrieving values from a `redb`
+/// database.
+#[derive(Error, Debug)]
+pub enum RedbStorageError {
+    /// An error caused by the database, or some interaction with it.
+    #[error("database error: {0}")]
+    Database(Box<redb::Error>),
+    /// Attempted to insert an invoice which already exists
+    #[error("attempted to insert an invoice which already exists")]
+    DuplicateInvoice,
+    /// Attempted to insert an invoice with the same reference as another
+    #[error("attempted to insert an invoice with the same reference as another")]
+    DuplicateReference,
+    /// Attempted to insert an output key which already exists
+    #[error("attempted to insert an output public key which already exists")]
+    DuplicateOutputKey,
+    /// Failed to serialize an [`Invoice`] or [`OutputPubKey`].
+    #[error("serialization error: {0}")]
+    Serialize(#[from] bincode::error::EncodeError),
+    /// Failed to deserialize an [`Invoice`] or [`OutputPubKey`].
+    #[error("deserialization error: {0}")]
+    Deserialize(#[from] bincode::error::DecodeError),
+    /// The database's schema could not be read or upgraded.
+    #[error("schema error: {0}")]
+    Schema(#[from] SchemaError),
+    /// Failed to read from the sled database being migrated.
+    #[cfg(feature = "sled")]
+    #[error("failed to read sled database: {0}")]
+    Sled(#[from] SledStorageError),
+}
+
+impl From<redb::Error> for RedbStorageError {
+    fn from(e: redb::Error) -> Self {
+        RedbStorageError::Database(Box::new(e))
+    }
+}
+
+impl From<DatabaseError> for RedbStorageError {
+    fn from(e: DatabaseError) -> Self {
+        RedbStorageError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<redb::StorageError> for RedbStorageError {
+    fn from(e: redb::StorageError) -> Self {
+        RedbStorageError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<TableError> for RedbStorageError {
+    fn from(e: TableError) -> Self {
+        RedbStorageError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<TransactionError> for RedbStorageError {
+    fn from(e: TransactionError) -> Self {
+        RedbStorageError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<CommitError> for RedbStorageError {
+    fn from(e: CommitError) -> Self {
+        RedbStorageError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<CompactionError> for RedbStorageError {
+    fn from(e: CompactionError) -> Self {
+        RedbStorageError::Database(Box::new(e.into()))
+    }
+}
+
+#[cfg(all(test, feature = "sled"))]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use testing_utils::new_temp_dir;
+
+    use super::Redb;
+    use crate::{
+        storage::{
+            stores::{Sled, TableNames},
+            ArchiveStorage, EventStorage, HeightStorage, InvoiceStorage, OutputId,
+            OutputKeyStorage, OutputPubKey, SubaddressStorage,
+        },
+        Amount, Invoice, InvoiceEvent, SubIndex,
+    };
+
+    fn dummy_invoice(height: u64) -> Invoice {
+        Invoice::new(
+            "testAddress".to_string(),
+            "testWallet".to_string(),
+            SubIndex::new(0, 1),
+            height,
+            Amount::from_pico(1),
+            5,
+            10,
+            "test description".to_string(),
+        )
+    }
+
+    #[test]
+    fn migrate_from_sled() {
+        let mut sled = Sled::new(&new_temp_dir(), &TableNames::default()).unwrap();
+        let output_key = OutputPubKey([1; 32]);
+        let output_id = OutputId {
+            tx_hash: [2; 32],
+            index: 3,
+        };
+        InvoiceStorage::insert(&mut sled, dummy_invoice(10)).unwrap();
+        InvoiceStorage::insert(&mut sled, dummy_invoice(20)).unwrap();
+        InvoiceStorage::insert(&mut sled, dummy_invoice(40)).unwrap();
+        sled.archive(dummy_invoice(40).id()).unwrap();
+        OutputKeyStorage::insert(&mut sled, output_key, output_id, 25).unwrap();
+        sled.upsert(30).unwrap();
+        sled.insert_funded("wallet", SubIndex::new(0, 1)).unwrap();
+        let created = sled
+            .append_event(InvoiceEvent::Created {
+                invoice: dummy_invoice(10),
+            })
+            .unwrap();
+        let pruned = sled
+            .append_event(InvoiceEvent::Created {
+                invoice: dummy_invoice(20),
+            })
+            .unwrap();
+        sled.prune_events(pruned + 1).unwrap();
+
+        let mut redb = Redb::new(&new_temp_dir(), &TableNames::default()).unwrap();
+        redb.migrate_from_sled(&sled).unwrap();
+
+        assert_eq!(
+            InvoiceStorage::get(&redb, dummy_invoice(10).id()).unwrap(),
+            Some(dummy_invoice(10))
+        );
+        assert_eq!(
+            InvoiceStorage::get(&redb, dummy_invoice(20).id()).unwrap(),
+            Some(dummy_invoice(20))
+        );
+        assert_eq!(
+            OutputKeyStorage::get(&redb, output_key).unwrap(),
+            Some(output_id)
+        );
+        assert_eq!(redb.key_stats().unwrap().lowest_height, Some(25));
+        assert_eq!(HeightStorage::get(&redb).unwrap(), Some(30));
+        assert_eq!(
+            redb.funded().unwrap(),
+            [("wallet".to_string(), SubIndex::new(0, 1))]
+        );
+        assert!(redb.events_from(0, usize::MAX).unwrap().is_empty());
+        assert_eq!(
+            redb.get_archived(dummy_invoice(40).id()).unwrap(),
+            Some(dummy_invoice(40))
+        );
+        assert_eq!(
+            InvoiceStorage::get(&redb, dummy_invoice(40).id()).unwrap(),
+            None
+        );
+        // Sequence numbers of events pruned before the migration are not
+        // reused.
+        let next = redb
+            .append_event(InvoiceEvent::Created {
+                invoice: dummy_invoice(30),
+            })
+            .unwrap();
+        assert!(next > pruned && pruned > created);
+
+        // Migrating twice would duplicate invoices.
+        redb.migrate_from_sled(&sled)
+            .expect_err("invoices should not be migrated twice");
+    }
+}
@@ -1,54 +1,269 @@
+use log::debug;
 use sled::{
-    transaction::{ConflictableTransactionError, TransactionError},
+    transaction::{
+        ConflictableTransactionError, TransactionError, Transactional, TransactionalTree,
+    },
     IVec,
 };
 use thiserror::Error;
 
 use crate::{
-    storage::{HeightStorage, InvoiceStorage, OutputId, OutputKeyStorage, OutputPubKey, Storage},
-    Invoice, InvoiceId, SubIndex,
+    storage::{
+        schema, stores::TableNames, ArchiveStorage, EventStorage, HeightStorage, InvoiceStorage,
+        OutputId, OutputKeyStorage, OutputPubKey, SchemaError, Storage, SubaddressStorage,
+        SCHEMA_VERSION,
+    },
+    Invoice, InvoiceEvent, InvoiceId, SubIndex,
 };
 
 /// Sled database. Note that [sled](sled) is still in beta.
 pub struct Sled {
+    db: sled::Db,
     invoices: sled::Tree,
+    references: sled::Tree,
     output_keys: sled::Tree,
     height: sled::Tree,
+    funded_subaddresses: sled::Tree,
+    events: sled::Tree,
+    archived: sled::Tree,
+    archived_references: sled::Tree,
 }
 
 impl Sled {
     /// Open a [Sled](sled) database at the specified location, and use the
-    /// specified tree. Creates a new database if one does not exist.
+    /// specified trees. Creates a new database if one does not exist.
+    ///
+    /// Invoices written with an older storage schema are upgraded to
+    /// [`SCHEMA_VERSION`](crate::storage::SCHEMA_VERSION).
     ///
     /// # Errors
     ///
     /// Returns an error if the database could not be opened at the specified
-    /// path.
-    pub fn new(
-        path: &str,
-        invoice_tree: &str,
-        output_key_tree: &str,
-        height_tree: &str,
-    ) -> Result<Sled, SledStorageError> {
+    /// path, or if it was written with a newer schema than this version of
+    /// `AcceptXMR` supports.
+    pub fn new(path: &str, tables: &TableNames) -> Result<Sled, SledStorageError> {
+        let TableNames {
+            invoices: invoice_tree,
+            output_keys: output_key_tree,
+            height: height_tree,
+            subaddresses: subaddress_tree,
+            events: event_tree,
+            archive: archive_tree,
+        } = tables;
         let db = sled::Config::default()
             .path(path)
             .flush_every_ms(None)
             .open()
             .map_err(DatabaseError::from)?;
         let invoices = db.open_tree(invoice_tree).map_err(DatabaseError::from)?;
+        let references = db
+            .open_tree(format!("{invoice_tree} references"))
+            .map_err(DatabaseError::from)?;
         let output_keys = db.open_tree(output_key_tree).map_err(DatabaseError::from)?;
         let height = db.open_tree(height_tree).map_err(DatabaseError::from)?;
+        let funded_subaddresses = db.open_tree(subaddress_tree).map_err(DatabaseError::from)?;
+        let events = db.open_tree(event_tree).map_err(DatabaseError::from)?;
+        let archived = db.open_tree(archive_tree).map_err(DatabaseError::from)?;
+        let archived_references = db
+            .open_tree(format!("{archive_tree} references"))
+            .map_err(DatabaseError::from)?;
+        let schema_version = db
+            .open_tree(format!("{invoice_tree} schema version"))
+            .map_err(DatabaseError::from)?;
+
+        Sled::upgrade_schema(
+            &invoices,
+            (&archived, &archived_references),
+            &events,
+            &output_keys,
+            &height,
+            &schema_version,
+        )?;
 
         // Set merge operator to act as an update().
         invoices.set_merge_operator(Sled::update_merge);
 
         Ok(Sled {
+            db,
             invoices,
+            references,
             output_keys,
             height,
+            funded_subaddresses,
+            events,
+            archived,
+            archived_references,
+        })
+    }
+
+    /// Upgrade invoices, archived invoices, logged events and output keys
+    /// written with an older schema to [`SCHEMA_VERSION`], and record the
+    /// version. The index of archived invoice references is rebuilt from the
+    /// upgraded invoices.
+    fn upgrade_schema(
+        invoices: &sled::Tree,
+        (archived, archived_references): (&sled::Tree, &sled::Tree),
+        events: &sled::Tree,
+        output_keys: &sled::Tree,
+        height: &sled::Tree,
+        schema_version: &sled::Tree,
+    ) -> Result<(), SledStorageError> {
+        let recorded = schema_version
+            .get("version")
+            .map_err(DatabaseError::from)?
+            .map(|ivec| bincode::decode_from_slice(&ivec, bincode::config::standard()))
+            .transpose()?
+            .map(|(v, _)| v);
+        let version =
+            schema::stored_version(recorded, invoices.is_empty() && output_keys.is_empty())?;
+        if recorded == Some(SCHEMA_VERSION) {
+            return Ok(());
+        }
+
+        let mut batch = sled::Batch::default();
+        let mut archive_batch = sled::Batch::default();
+        let mut archived_reference_batch = sled::Batch::default();
+        let mut event_batch = sled::Batch::default();
+        if version < SCHEMA_VERSION {
+            for row in invoices {
+                let (key, ivec) = row.map_err(DatabaseError::from)?;
+                batch.insert(key, schema::upgrade_invoice(&ivec, version)?);
+            }
+            for row in archived {
+                let (key, ivec) = row.map_err(DatabaseError::from)?;
+                let upgraded = schema::upgrade_invoice(&ivec, version)?;
+                let invoice: Invoice =
+                    bincode::decode_from_slice(&upgraded, bincode::config::standard())?.0;
+                if let Some(reference) = invoice.reference() {
+                    archived_reference_batch.insert(archived_reference_key(reference, &key)?, &[]);
+                }
+                archive_batch.insert(key, upgraded);
+            }
+            for row in events {
+                let (key, ivec) = row.map_err(DatabaseError::from)?;
+                event_batch.insert(key, schema::upgrade_event(&ivec, version)?);
+            }
+            debug!("Upgrading invoices from schema version {version} to {SCHEMA_VERSION}");
+        }
+        let mut output_key_batch = sled::Batch::default();
+        if version < schema::OUTPUT_KEY_HEIGHT_VERSION {
+            // Older keys are recorded at the scan height, so that they are kept
+            // for the full retention depth.
+            let key_height: u64 = height
+                .get("height")
+                .map_err(DatabaseError::from)?
+                .map(|ivec| bincode::decode_from_slice(&ivec, bincode::config::standard()))
+                .transpose()?
+                .map_or(0, |(h, _)| h);
+            for row in output_keys {
+                let (key, ivec) = row.map_err(DatabaseError::from)?;
+                let (output_id, _): (OutputId, _) =
+                    bincode::decode_from_slice(&ivec, bincode::config::standard())?;
+                output_key_batch.insert(
+                    key,
+                    bincode::encode_to_vec((output_id, key_height), bincode::config::standard())?,
+                );
+            }
+        }
+        let encoded_version = bincode::encode_to_vec(SCHEMA_VERSION, bincode::config::standard())?;
+
+        // Upgrade the invoices and events and record the new version
+        // atomically, so that nothing is upgraded twice.
+        (
+            invoices,
+            archived,
+            archived_references,
+            events,
+            output_keys,
+            schema_version,
+        )
+            .transaction(
+                |(
+                    tx_invoices,
+                    tx_archived,
+                    tx_archived_references,
+                    tx_events,
+                    tx_output_keys,
+                    tx_schema_version,
+                )| {
+                    tx_invoices.apply_batch(&batch)?;
+                    tx_archived.apply_batch(&archive_batch)?;
+                    tx_archived_references.apply_batch(&archived_reference_batch)?;
+                    tx_events.apply_batch(&event_batch)?;
+                    tx_output_keys.apply_batch(&output_key_batch)?;
+                    tx_schema_version.insert("version", encoded_version.as_slice())?;
+                    Ok::<_, ConflictableTransactionError<Box<SledStorageError>>>(())
+                },
+            )
+            .map_err(DatabaseError::from)?;
+        Ok(())
+    }
+
+    /// Iterate over all stored output keys, along with their output IDs and
+    /// the heights they were recorded at.
+    pub(crate) fn output_keys(
+        &self,
+    ) -> impl Iterator<Item = Result<(OutputPubKey, OutputId, u64), SledStorageError>> + '_ {
+        self.output_keys.iter().map(|row| {
+            let (key, ivec) = row.map_err(DatabaseError::from)?;
+            let key = OutputPubKey(
+                key.as_ref()
+                    .try_into()
+                    .map_err(|_| SledStorageError::InvalidOutputKey)?,
+            );
+            let (output_id, height): (OutputId, u64) =
+                bincode::decode_from_slice(&ivec, bincode::config::standard())?.0;
+            Ok((key, output_id, height))
         })
     }
 
+    /// Returns a sequence number at least as large as every event sequence
+    /// number this database has handed out, including those of pruned events.
+    #[cfg(feature = "redb")]
+    pub(crate) fn last_event_sequence(&self) -> Result<u64, SledStorageError> {
+        // Sequence numbers are one more than an ID generated earlier, so a new
+        // ID is never smaller than any of them.
+        Ok(self.db.generate_id().map_err(DatabaseError::from)?)
+    }
+
+    /// Remove an invoice and its reference in a transaction, returning the
+    /// invoice if it existed.
+    fn remove_invoice(
+        tx_invoices: &TransactionalTree,
+        tx_references: &TransactionalTree,
+        key: &[u8],
+    ) -> Result<Option<(IVec, Invoice)>, ConflictableTransactionError<Box<SledStorageError>>> {
+        let Some(ivec) = tx_invoices.remove(key)? else {
+            return Ok(None);
+        };
+        let invoice: Invoice = bincode::decode_from_slice(&ivec, bincode::config::standard())
+            .map_err(|e| ConflictableTransactionError::Abort(Box::new(e.into())))?
+            .0;
+        if let Some(reference) = invoice.reference() {
+            tx_references.remove(reference.as_bytes())?;
+        }
+        Ok(Some((ivec, invoice)))
+    }
+
+    /// Remove the archived reference index entry of a replaced archived
+    /// invoice in a transaction.
+    fn remove_archived_reference(
+        tx_archived_references: &TransactionalTree,
+        replaced: &[u8],
+        key: &[u8],
+    ) -> Result<(), ConflictableTransactionError<Box<SledStorageError>>> {
+        let abort = |e: SledStorageError| ConflictableTransactionError::Abort(Box::new(e));
+        let replaced: Invoice = bincode::decode_from_slice(replaced, bincode::config::standard())
+            .map_err(|e| abort(e.into()))?
+            .0;
+        if let Some(reference) = replaced.reference() {
+            let reference_key =
+                archived_reference_key(reference, key).map_err(|e| abort(e.into()))?;
+            tx_archived_references.remove(reference_key)?;
+        }
+        Ok(())
+    }
+
     fn update_merge(_key: &[u8], old_value: Option<&[u8]>, new_value: &[u8]) -> Option<Vec<u8>> {
         if old_value.is_some() {
             Some(new_value.to_vec())
@@ -66,17 +281,35 @@ impl InvoiceStorage for Sled {
         let invoice_id = invoice.id();
         let key = bincode::encode_to_vec(invoice_id, bincode::config::standard())?;
 
+        let reference = invoice.reference().map(str::to_owned);
+
         // Prepare value (invoice).
         let value = bincode::encode_to_vec(invoice, bincode::config::standard())?;
 
-        // Insert the invoice into the database.
-        match self
-            .invoices
-            .compare_and_swap(key, None::<IVec>, Some(value))
-            .map_err(DatabaseError::from)?
-        {
+        // Insert the invoice and its reference into the database together.
+        let result =
+            (&self.invoices, &self.references).transaction(|(tx_invoices, tx_references)| {
+                if tx_invoices.get(key.as_slice())?.is_some() {
+                    return Err(ConflictableTransactionError::Abort(Box::new(
+                        SledStorageError::DuplicateInvoiceId,
+                    )));
+                }
+                if let Some(reference) = &reference {
+                    if tx_references.get(reference.as_bytes())?.is_some() {
+                        return Err(ConflictableTransactionError::Abort(Box::new(
+                            SledStorageError::DuplicateReference,
+                        )));
+                    }
+                    tx_references.insert(reference.as_bytes(), key.as_slice())?;
+                }
+                tx_invoices.insert(key.as_slice(), value.as_slice())?;
+                Ok(())
+            });
+
+        match result {
             Ok(()) => Ok(()),
-            Err(_) => Err(SledStorageError::DuplicateInvoiceId),
+            Err(TransactionError::Abort(e)) => Err(*e),
+            Err(e) => Err(DatabaseError::from(e).into()),
         }
     }
 
@@ -84,15 +317,12 @@ impl InvoiceStorage for Sled {
         // Prepare key (invoice id).
         let key = bincode::encode_to_vec(invoice_id, bincode::config::standard())?;
 
-        let old = self.invoices.remove(key).transpose();
-        old.map(|ivec_or_err| {
-            Ok(bincode::decode_from_slice(
-                &ivec_or_err.map_err(DatabaseError::from)?,
-                bincode::config::standard(),
-            )?
-            .0)
-        })
-        .transpose()
+        let old = (&self.invoices, &self.references)
+            .transaction(|(tx_invoices, tx_references)| {
+                Sled::remove_invoice(tx_invoices, tx_references, &key)
+            })
+            .map_err(DatabaseError::from)?;
+        Ok(old.map(|(_, invoice)| invoice))
     }
 
     fn update(&mut self, invoice: Invoice) -> Result<Option<Invoice>, SledStorageError> {
@@ -139,6 +369,21 @@ impl InvoiceStorage for Sled {
             .transpose()
     }
 
+    fn get_by_reference(&self, reference: &str) -> Result<Option<Invoice>, SledStorageError> {
+        let Some(key) = self
+            .references
+            .get(reference.as_bytes())
+            .map_err(DatabaseError::from)?
+        else {
+            return Ok(None);
+        };
+
+        let current = self.invoices.get(key).map_err(DatabaseError::from)?;
+        current
+            .map(|ivec| Ok(bincode::decode_from_slice(&ivec, bincode::config::standard())?.0))
+            .transpose()
+    }
+
     fn get_ids(&self) -> Result<Vec<InvoiceId>, SledStorageError> {
         let current = self
             .invoices
@@ -183,10 +428,15 @@ impl InvoiceStorage for Sled {
 impl OutputKeyStorage for Sled {
     type Error = SledStorageError;
 
-    fn insert(&mut self, key: OutputPubKey, output_id: OutputId) -> Result<(), Self::Error> {
+    fn insert(
+        &mut self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> Result<(), Self::Error> {
         let result = self.output_keys.transaction(move |tx| {
-            let value =
-                bincode::encode_to_vec(output_id, bincode::config::standard()).map_err(|e| {
+            let value = bincode::encode_to_vec((output_id, height), bincode::config::standard())
+                .map_err(|e| {
                     ConflictableTransactionError::Abort(Box::new(SledStorageError::Serialize(e)))
                 })?;
             match tx.insert(&key.0, value) {
@@ -205,14 +455,38 @@ impl OutputKeyStorage for Sled {
         let current = self.output_keys.get(key).transpose();
         current
             .map(|ivec_or_err| {
-                Ok(bincode::decode_from_slice(
+                let (output_id, _): (OutputId, u64) = bincode::decode_from_slice(
                     &ivec_or_err.map_err(DatabaseError::from)?,
                     bincode::config::standard(),
                 )?
-                .0)
+                .0;
+                Ok(output_id)
             })
             .transpose()
     }
+
+    fn try_for_each_key<F>(&self, f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<(OutputPubKey, OutputId, u64), Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.output_keys().try_for_each(f)
+    }
+
+    fn prune_keys(&mut self, height: u64) -> Result<u64, Self::Error> {
+        let mut batch = sled::Batch::default();
+        let mut pruned = 0;
+        for key_or_err in self.output_keys() {
+            let (key, _, key_height) = key_or_err?;
+            if key_height < height {
+                batch.remove(&key.0);
+                pruned += 1;
+            }
+        }
+        self.output_keys
+            .apply_batch(batch)
+            .map_err(DatabaseError::from)?;
+        Ok(pruned)
+    }
 }
 
 impl HeightStorage for Sled {
@@ -244,6 +518,204 @@ impl HeightStorage for Sled {
     }
 }
 
+impl SubaddressStorage for Sled {
+    type Error = SledStorageError;
+
+    fn insert_funded(&mut self, wallet: &str, sub_index: SubIndex) -> Result<(), Self::Error> {
+        self.funded_subaddresses
+            .insert(funded_key(wallet, sub_index)?, &[])
+            .map_err(DatabaseError::from)?;
+        Ok(())
+    }
+
+    fn is_funded(&self, wallet: &str, sub_index: SubIndex) -> Result<bool, Self::Error> {
+        Ok(self
+            .funded_subaddresses
+            .contains_key(funded_key(wallet, sub_index)?)
+            .map_err(DatabaseError::from)?)
+    }
+
+    fn funded(&self) -> Result<Vec<(String, SubIndex)>, Self::Error> {
+        self.funded_subaddresses
+            .iter()
+            .keys()
+            .map(|key_or_err| {
+                let key = key_or_err.map_err(DatabaseError::from)?;
+                let (sub_index, len) =
+                    bincode::decode_from_slice(&key, bincode::config::standard())?;
+                let wallet = String::from_utf8(key[len..].to_vec())
+                    .map_err(|_| SledStorageError::InvalidFundedKey)?;
+                Ok((wallet, sub_index))
+            })
+            .collect()
+    }
+}
+
+impl EventStorage for Sled {
+    type Error = SledStorageError;
+
+    fn append_event(&mut self, event: InvoiceEvent) -> Result<u64, Self::Error> {
+        // Sled's IDs are unique and monotonic, even across restarts, but start at 0.
+        let sequence = self.db.generate_id().map_err(DatabaseError::from)? + 1;
+        let value = bincode::encode_to_vec(event, bincode::config::standard())?;
+        // Big endian keys keep the events sorted by sequence number.
+        self.events
+            .insert(sequence.to_be_bytes(), value)
+            .map_err(DatabaseError::from)?;
+        Ok(sequence)
+    }
+
+    fn events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, Self::Error> {
+        self.events
+            .range(sequence.to_be_bytes()..)
+            .take(limit)
+            .map(|row| {
+                let (key, ivec) = row.map_err(DatabaseError::from)?;
+                let sequence = u64::from_be_bytes(
+                    key.as_ref()
+                        .try_into()
+                        .map_err(|_| SledStorageError::InvalidEventKey)?,
+                );
+                let event = bincode::decode_from_slice(&ivec, bincode::config::standard())?.0;
+                Ok((sequence, event))
+            })
+            .collect()
+    }
+
+    fn prune_events(&mut self, sequence: u64) -> Result<(), Self::Error> {
+        let mut batch = sled::Batch::default();
+        for key in self.events.range(..sequence.to_be_bytes()).keys() {
+            batch.remove(key.map_err(DatabaseError::from)?);
+        }
+        self.events
+            .apply_batch(batch)
+            .map_err(DatabaseError::from)?;
+        Ok(())
+    }
+}
+
+impl ArchiveStorage for Sled {
+    type Error = SledStorageError;
+
+    fn archive(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, SledStorageError> {
+        // Prepare key (invoice id).
+        let key = bincode::encode_to_vec(invoice_id, bincode::config::standard())?;
+
+        // Move the invoice in a single transaction, so it is never in both trees
+        // or neither.
+        let old = (
+            &self.invoices,
+            &self.references,
+            &self.archived,
+            &self.archived_references,
+        )
+            .transaction(
+                |(tx_invoices, tx_references, tx_archived, tx_archived_references)| {
+                    let old = Sled::remove_invoice(tx_invoices, tx_references, &key)?;
+                    let Some((ivec, invoice)) = &old else {
+                        return Ok(old);
+                    };
+                    // The invoice may replace an archived one with another
+                    // reference.
+                    if let Some(replaced) = tx_archived.insert(key.as_slice(), ivec)? {
+                        Sled::remove_archived_reference(tx_archived_references, &replaced, &key)?;
+                    }
+                    if let Some(reference) = invoice.reference() {
+                        let reference_key = archived_reference_key(reference, &key)
+                            .map_err(|e| ConflictableTransactionError::Abort(Box::new(e.into())))?;
+                        tx_archived_references.insert(reference_key, &[])?;
+                    }
+                    Ok(old)
+                },
+            )
+            .map_err(DatabaseError::from)?;
+
+        Ok(old.map(|(_, invoice)| invoice))
+    }
+
+    fn get_archived(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, SledStorageError> {
+        // Prepare key (invoice id).
+        let key = bincode::encode_to_vec(invoice_id, bincode::config::standard())?;
+
+        let current = self.archived.get(key).transpose();
+        current
+            .map(|ivec_or_err| {
+                Ok(bincode::decode_from_slice(
+                    &ivec_or_err.map_err(DatabaseError::from)?,
+                    bincode::config::standard(),
+                )?
+                .0)
+            })
+            .transpose()
+    }
+
+    fn get_archived_by_reference(
+        &self,
+        reference: &str,
+    ) -> Result<Option<Invoice>, SledStorageError> {
+        let prefix = bincode::encode_to_vec(reference, bincode::config::standard())?;
+        let Some((reference_key, _)) = self
+            .archived_references
+            .scan_prefix(&prefix)
+            .next()
+            .transpose()
+            .map_err(DatabaseError::from)?
+        else {
+            return Ok(None);
+        };
+
+        self.archived
+            .get(&reference_key[prefix.len()..])
+            .map_err(DatabaseError::from)?
+            .map(|ivec| Ok(bincode::decode_from_slice(&ivec, bincode::config::standard())?.0))
+            .transpose()
+    }
+
+    fn try_for_each_archived<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.archived.iter().try_for_each(move |row| {
+            let invoice_or_err = match row {
+                Ok((_id, ivec)) => bincode::decode_from_slice(&ivec, bincode::config::standard())
+                    .map(|v| v.0)
+                    .map_err(SledStorageError::Deserialize),
+                Err(e) => Err(SledStorageError::Database(e.into())),
+            };
+
+            f(invoice_or_err)
+        })
+    }
+
+    fn prune_archive(&mut self, height: u64) -> Result<(), SledStorageError> {
+        let mut batch = sled::Batch::default();
+        let mut reference_batch = sled::Batch::default();
+        for row in &self.archived {
+            let (key, ivec) = row.map_err(DatabaseError::from)?;
+            let invoice: Invoice =
+                bincode::decode_from_slice(&ivec, bincode::config::standard())?.0;
+            if invoice.current_height() < height {
+                if let Some(reference) = invoice.reference() {
+                    reference_batch.remove(archived_reference_key(reference, &key)?);
+                }
+                batch.remove(key);
+            }
+        }
+        (&self.archived, &self.archived_references)
+            .transaction(|(tx_archived, tx_archived_references)| {
+                tx_archived.apply_batch(&batch)?;
+                tx_archived_references.apply_batch(&reference_batch)?;
+                Ok::<_, ConflictableTransactionError<Box<SledStorageError>>>(())
+            })
+            .map_err(DatabaseError::from)?;
+        Ok(())
+    }
+}
+
 impl Storage for Sled {
     type Error = SledStorageError;
 
@@ -254,12 +726,42 @@ impl Storage for Sled {
     /// Returns an error if flush does not succeed.
     fn flush(&self) -> Result<(), SledStorageError> {
         self.invoices.flush().map_err(DatabaseError::from)?;
+        self.references.flush().map_err(DatabaseError::from)?;
         self.output_keys.flush().map_err(DatabaseError::from)?;
         self.height.flush().map_err(DatabaseError::from)?;
+        self.funded_subaddresses
+            .flush()
+            .map_err(DatabaseError::from)?;
+        self.events.flush().map_err(DatabaseError::from)?;
+        self.archived.flush().map_err(DatabaseError::from)?;
+        self.archived_references
+            .flush()
+            .map_err(DatabaseError::from)?;
         Ok(())
     }
 }
 
+/// Key of an entry in the archived invoice reference index: the encoded
+/// reference followed by the key of the archived invoice. The reference is
+/// length-prefixed, so entries for one reference can be found by prefix.
+fn archived_reference_key(
+    reference: &str,
+    key: &[u8],
+) -> Result<Vec<u8>, bincode::error::EncodeError> {
+    let mut reference_key = bincode::encode_to_vec(reference, bincode::config::standard())?;
+    reference_key.extend_from_slice(key);
+    Ok(reference_key)
+}
+
+/// Key of a funded subaddress: the encoded subaddress index followed by the
+/// primary address of its wallet. Subaddresses recorded before schema version
+/// 6 have only the index, which is read as an empty wallet.
+fn funded_key(wallet: &str, sub_index: SubIndex) -> Result<Vec<u8>, bincode::error::EncodeError> {
+    let mut key = bincode::encode_to_vec(sub_index, bincode::config::standard())?;
+    key.extend_from_slice(wallet.as_bytes());
+    Ok(key)
+}
+
 /// An error occurring while storing or retrieving values from a
 /// `sled` database.
 #[derive(Error, Debug)]
@@ -271,10 +773,25 @@ pub enum SledStorageError {
     /// exists.
     #[error("duplicate invoice ID")]
     DuplicateInvoiceId,
+    /// Failed to insert an [`Invoice`] because another has the same reference.
+    #[error("duplicate invoice reference")]
+    DuplicateReference,
     /// Failed to insert an [`OutputPubKey`] because an identical one already
     /// exists.
     #[error("duplicate output public key")]
     DuplicateOutputKey,
+    /// The database's schema could not be read or upgraded.
+    #[error("schema error: {0}")]
+    Schema(#[from] SchemaError),
+    /// An output key in the database was not 32 bytes long.
+    #[error("invalid output key")]
+    InvalidOutputKey,
+    /// An event log key was not a valid sequence number.
+    #[error("invalid event log key")]
+    InvalidEventKey,
+    /// A funded subaddress key's wallet was not valid UTF-8.
+    #[error("invalid funded subaddress key")]
+    InvalidFundedKey,
     /// Failed to serialize an [`Invoice`] or [`OutputPubKey`].
     #[error("serialization error: {0}")]
     Serialize(#[from] bincode::error::EncodeError),
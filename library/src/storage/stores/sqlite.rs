@@ -5,8 +5,12 @@ use sqlite::{version, Connection, ConnectionThreadSafe, Row, State, Value};
 use thiserror::Error;
 
 use crate::{
-    storage::{HeightStorage, InvoiceStorage, OutputId, OutputKeyStorage, OutputPubKey, Storage},
-    Invoice, InvoiceId, SubIndex,
+    storage::{
+        schema, stores::TableNames, ArchiveStorage, EventStorage, HeightStorage, InvoicePage,
+        InvoiceQuery, InvoiceStorage, OutputId, OutputKeyStats, OutputKeyStorage, OutputPubKey,
+        SchemaError, Storage, SubaddressStorage, SCHEMA_VERSION,
+    },
+    Invoice, InvoiceEvent, InvoiceId, SubIndex,
 };
 
 /// `SQLite` database.
@@ -15,28 +19,33 @@ pub struct Sqlite {
     invoices: TableName,
     output_keys: TableName,
     height: TableName,
+    funded_subaddresses: TableName,
+    events: TableName,
+    archived: TableName,
 }
 
 impl Sqlite {
     /// Open a [`SQLite`](sqlite) database at the specified location, and use
     /// the specified tables. Creates a new database if one does not exist.
     ///
+    /// Invoices written with an older storage schema are upgraded to
+    /// [`SCHEMA_VERSION`](crate::storage::SCHEMA_VERSION).
+    ///
     /// # Errors
     ///
     /// Returns an error if the database could not be opened at the specified
-    /// path.
-    pub fn new(
-        path: &str,
-        invoice_table: &str,
-        output_key_table: &str,
-        height_table: &str,
-    ) -> Result<Sqlite, SqliteStorageError> {
+    /// path, or if it was written with a newer schema than this version of
+    /// `AcceptXMR` supports.
+    pub fn new(path: &str, tables: &TableNames) -> Result<Sqlite, SqliteStorageError> {
         let db = Connection::open_thread_safe(path)?;
         debug!("Connection to SQLite v{} database established", version());
 
-        let invoices = TableName::new(invoice_table);
-        let output_keys = TableName::new(output_key_table);
-        let height = TableName::new(height_table);
+        let invoices = TableName::new(&tables.invoices);
+        let output_keys = TableName::new(&tables.output_keys);
+        let height = TableName::new(&tables.height);
+        let funded_subaddresses = TableName::new(&tables.subaddresses);
+        let events = TableName::new(&tables.events);
+        let archived = TableName::new(&tables.archive);
 
         db.execute(format!(
             "CREATE TABLE IF NOT EXISTS {invoices} (
@@ -44,16 +53,26 @@ impl Sqlite {
                 minor_subindex  INTEGER NOT NULL,
                 creation_height BLOB NOT NULL,
                 invoice         BLOB NOT NULL,
+                reference       TEXT,
                 PRIMARY KEY (major_subindex, minor_subindex, creation_height)
             );"
         ))?;
 
+        // Tables created before schema version 2 have no reference column.
+        add_reference_column(&db, &tables.invoices)?;
+        let reference_index = TableName::new(&format!("{} references", tables.invoices));
+        db.execute(format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {reference_index} ON {invoices} (reference);"
+        ))?;
+
+        // Without a rowid, each output key is only stored once.
         db.execute(format!(
             "CREATE TABLE IF NOT EXISTS {output_keys} (
                 output_key BLOB NOT NULL,
                 output_id  BLOB NOT NULL,
+                height     INTEGER NOT NULL,
                 PRIMARY KEY (output_key)
-            );"
+            ) WITHOUT ROWID;"
         ))?;
 
         db.execute(format!(
@@ -64,12 +83,272 @@ impl Sqlite {
             );"
         ))?;
 
-        Ok(Sqlite {
+        db.execute(format!(
+            "CREATE TABLE IF NOT EXISTS {funded_subaddresses} (
+                wallet         TEXT NOT NULL,
+                major_subindex INTEGER NOT NULL,
+                minor_subindex INTEGER NOT NULL,
+                PRIMARY KEY (wallet, major_subindex, minor_subindex)
+            );"
+        ))?;
+
+        // AUTOINCREMENT prevents sequence numbers from being reused after pruning.
+        db.execute(format!(
+            "CREATE TABLE IF NOT EXISTS {events} (
+                sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+                event    BLOB NOT NULL
+            );"
+        ))?;
+
+        db.execute(format!(
+            "CREATE TABLE IF NOT EXISTS {archived} (
+                major_subindex  INTEGER NOT NULL,
+                minor_subindex  INTEGER NOT NULL,
+                creation_height BLOB NOT NULL,
+                current_height  BLOB NOT NULL,
+                invoice         BLOB NOT NULL,
+                reference       TEXT,
+                PRIMARY KEY (major_subindex, minor_subindex, creation_height)
+            );"
+        ))?;
+        // Archive tables created before schema version 4 have no reference
+        // column. It is filled in when the schema is upgraded.
+        add_reference_column(&db, &tables.archive)?;
+        let archived_reference_index = TableName::new(&format!("{} references", tables.archive));
+        db.execute(format!(
+            "CREATE INDEX IF NOT EXISTS {archived_reference_index} ON {archived} (reference);"
+        ))?;
+
+        let schema_version = TableName::new(&format!("{} schema version", tables.invoices));
+        db.execute(format!(
+            "CREATE TABLE IF NOT EXISTS {schema_version} (
+                id      INTEGER NOT NULL PRIMARY KEY,
+                version INTEGER NOT NULL,
+                CHECK (id = 0)
+            );"
+        ))?;
+
+        let sqlite = Sqlite {
             db,
             invoices,
             output_keys,
             height,
-        })
+            funded_subaddresses,
+            events,
+            archived,
+        };
+
+        sqlite.db.execute("BEGIN")?;
+        match sqlite.upgrade_schema(&schema_version, tables) {
+            Ok(()) => sqlite.db.execute("COMMIT")?,
+            Err(e) => {
+                sqlite.db.execute("ROLLBACK")?;
+                return Err(e);
+            }
+        }
+
+        Ok(sqlite)
+    }
+
+    /// Upgrade invoices, archived invoices, logged events and output keys
+    /// written with an older schema to [`SCHEMA_VERSION`], and record the
+    /// version. The references of archived invoices are copied out of the
+    /// upgraded invoices. Must be run in a transaction, so that nothing is
+    /// upgraded twice.
+    fn upgrade_schema(
+        &self,
+        schema_version: &TableName,
+        tables: &TableNames,
+    ) -> Result<(), SqliteStorageError> {
+        self.upgrade_output_keys(&tables.output_keys)?;
+        self.upgrade_funded_subaddresses(&tables.subaddresses)?;
+        let height_index = TableName::new(&format!("{} height", tables.output_keys));
+        self.db.execute(format!(
+            "CREATE INDEX IF NOT EXISTS {height_index} ON {} (height);",
+            self.output_keys
+        ))?;
+
+        let mut select_stmt = self.db.prepare(format!(
+            "SELECT version FROM {schema_version}
+            WHERE id = 0"
+        ))?;
+        let recorded = if select_stmt.next()? == State::Done {
+            None
+        } else {
+            let version = select_stmt.read::<i64, _>("version")?;
+            Some(
+                u32::try_from(version)
+                    .map_err(|_| SqliteStorageError::InvalidSchemaVersion(version))?,
+            )
+        };
+        let is_empty = InvoiceStorage::is_empty(self)? && self.key_stats()?.count == 0;
+        let version = schema::stored_version(recorded, is_empty)?;
+        if recorded == Some(SCHEMA_VERSION) {
+            return Ok(());
+        }
+
+        if version < SCHEMA_VERSION {
+            debug!("Upgrading invoices from schema version {version} to {SCHEMA_VERSION}");
+            for table in [&self.invoices, &self.archived] {
+                let select_stmt = self
+                    .db
+                    .prepare(format!("SELECT rowid, invoice FROM {table}"))?;
+                let upgraded = select_stmt
+                    .into_iter()
+                    .map(|row| {
+                        let row = row?;
+                        let rowid = row.try_read::<i64, _>("rowid")?;
+                        let invoice = row.try_read::<&[u8], _>("invoice")?;
+                        Ok((rowid, schema::upgrade_invoice(invoice, version)?))
+                    })
+                    .collect::<Result<Vec<(i64, Vec<u8>)>, SqliteStorageError>>()?;
+
+                for (rowid, invoice) in upgraded {
+                    let (decoded, _): (Invoice, _) =
+                        bincode::decode_from_slice(&invoice, bincode::config::standard())?;
+                    let mut update_stmt = self.db.prepare(format!(
+                        "UPDATE {table} SET invoice = :invoice, reference = :reference
+                        WHERE rowid = :rowid"
+                    ))?;
+                    update_stmt.bind::<&[(_, Value)]>(
+                        &[
+                            (":invoice", invoice.into()),
+                            (":reference", decoded.reference.into()),
+                            (":rowid", rowid.into()),
+                        ][..],
+                    )?;
+                    update_stmt.next()?;
+                }
+            }
+            self.upgrade_events(version)?;
+        }
+
+        let mut upsert_stmt = self.db.prepare(format!(
+            "INSERT INTO {schema_version} (id, version)
+            VALUES (0, :version)
+            ON CONFLICT (id) DO UPDATE SET version = :version;"
+        ))?;
+        upsert_stmt.bind::<&[(_, Value)]>(&[(":version", i64::from(SCHEMA_VERSION).into())][..])?;
+        upsert_stmt.next()?;
+
+        Ok(())
+    }
+
+    /// Upgrade events logged with an older schema to [`SCHEMA_VERSION`].
+    fn upgrade_events(&self, version: u32) -> Result<(), SqliteStorageError> {
+        let select_stmt = self
+            .db
+            .prepare(format!("SELECT sequence, event FROM {}", self.events))?;
+        let upgraded = select_stmt
+            .into_iter()
+            .map(|row| {
+                let row = row?;
+                let sequence = row.try_read::<i64, _>("sequence")?;
+                let event = row.try_read::<&[u8], _>("event")?;
+                Ok((sequence, schema::upgrade_event(event, version)?))
+            })
+            .collect::<Result<Vec<(i64, Vec<u8>)>, SqliteStorageError>>()?;
+
+        for (sequence, event) in upgraded {
+            let mut update_stmt = self.db.prepare(format!(
+                "UPDATE {} SET event = :event
+                WHERE sequence = :sequence",
+                self.events
+            ))?;
+            update_stmt.bind::<&[(_, Value)]>(
+                &[(":event", event.into()), (":sequence", sequence.into())][..],
+            )?;
+            update_stmt.next()?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild an output key table created before schema version 3, which has
+    /// no height column, in the current layout. Older keys are recorded at the
+    /// scan height, so that they are kept for the full retention depth.
+    fn upgrade_output_keys(&self, output_key_table: &str) -> Result<(), SqliteStorageError> {
+        let output_keys = &self.output_keys;
+        let mut column_stmt = self.db.prepare(
+            "SELECT COUNT(*) FROM pragma_table_info(:table)
+            WHERE name = 'height'",
+        )?;
+        column_stmt.bind::<&[(_, Value)]>(&[(":table", output_key_table.into())][..])?;
+        column_stmt.next()?;
+        let has_height = column_stmt.read::<i64, _>(0)? != 0;
+        drop(column_stmt);
+        if has_height {
+            return Ok(());
+        }
+
+        debug!("Rebuilding output key table with key heights");
+        let key_height = HeightStorage::get(self)?.unwrap_or_default();
+        let old_output_keys = TableName::new(&format!("{output_key_table} old"));
+        self.db.execute(format!(
+            "ALTER TABLE {output_keys} RENAME TO {old_output_keys};
+            CREATE TABLE {output_keys} (
+                output_key BLOB NOT NULL,
+                output_id  BLOB NOT NULL,
+                height     INTEGER NOT NULL,
+                PRIMARY KEY (output_key)
+            ) WITHOUT ROWID;"
+        ))?;
+        let mut copy_stmt = self.db.prepare(format!(
+            "INSERT INTO {output_keys} (output_key, output_id, height)
+            SELECT output_key, output_id, :height FROM {old_output_keys};"
+        ))?;
+        copy_stmt.bind::<&[(_, Value)]>(&[(":height", height_param(key_height)?.into())][..])?;
+        copy_stmt.next()?;
+        drop(copy_stmt);
+        self.db.execute(format!("DROP TABLE {old_output_keys};"))?;
+        Ok(())
+    }
+
+    /// Rebuild a funded subaddress table created before schema version 6,
+    /// which has no wallet column, in the current layout. Older subaddresses
+    /// are given an empty wallet.
+    fn upgrade_funded_subaddresses(
+        &self,
+        subaddress_table: &str,
+    ) -> Result<(), SqliteStorageError> {
+        let funded_subaddresses = &self.funded_subaddresses;
+        let mut column_stmt = self.db.prepare(
+            "SELECT COUNT(*) FROM pragma_table_info(:table)
+            WHERE name = 'wallet'",
+        )?;
+        column_stmt.bind::<&[(_, Value)]>(&[(":table", subaddress_table.into())][..])?;
+        column_stmt.next()?;
+        let has_wallet = column_stmt.read::<i64, _>(0)? != 0;
+        drop(column_stmt);
+        if has_wallet {
+            return Ok(());
+        }
+
+        debug!("Rebuilding funded subaddress table with wallets");
+        let old_funded_subaddresses = TableName::new(&format!("{subaddress_table} old"));
+        self.db.execute(format!(
+            "ALTER TABLE {funded_subaddresses} RENAME TO {old_funded_subaddresses};
+            CREATE TABLE {funded_subaddresses} (
+                wallet         TEXT NOT NULL,
+                major_subindex INTEGER NOT NULL,
+                minor_subindex INTEGER NOT NULL,
+                PRIMARY KEY (wallet, major_subindex, minor_subindex)
+            );
+            INSERT INTO {funded_subaddresses} (wallet, major_subindex, minor_subindex)
+            SELECT '', major_subindex, minor_subindex FROM {old_funded_subaddresses};
+            DROP TABLE {old_funded_subaddresses};"
+        ))?;
+        Ok(())
+    }
+
+    /// Rebuild the database file, returning space freed by removed invoices,
+    /// pruned events and pruned output keys to the file system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database could not be rebuilt.
+    pub fn compact(&mut self) -> Result<(), SqliteStorageError> {
+        self.db.execute("VACUUM")?;
+        Ok(())
     }
 }
 
@@ -78,13 +357,19 @@ impl InvoiceStorage for Sqlite {
 
     fn insert(&mut self, invoice: Invoice) -> Result<(), SqliteStorageError> {
         let invoice_id = invoice.id();
+        let reference = invoice.reference().map(str::to_owned);
+        if let Some(reference) = &reference {
+            if InvoiceStorage::get_by_reference(self, reference)?.is_some() {
+                return Err(SqliteStorageError::DuplicateReference);
+            }
+        }
 
         // Prepare value (invoice).
         let value = bincode::encode_to_vec(invoice, bincode::config::standard())?;
 
         let mut statement = self.db.prepare(format!(
-            "INSERT INTO {} (major_subindex, minor_subindex, creation_height, invoice) 
-            VALUES (:major, :minor, :height, :invoice);",
+            "INSERT INTO {} (major_subindex, minor_subindex, creation_height, invoice, reference)
+            VALUES (:major, :minor, :height, :invoice, :reference);",
             self.invoices
         ))?;
         statement.bind::<&[(_, Value)]>(
@@ -97,6 +382,7 @@ impl InvoiceStorage for Sqlite {
                     invoice_id.creation_height.to_be_bytes()[..].into(),
                 ),
                 (":invoice", value.into()),
+                (":reference", reference.into()),
             ][..],
         )?;
 
@@ -239,6 +525,24 @@ impl InvoiceStorage for Sqlite {
         ))
     }
 
+    fn get_by_reference(&self, reference: &str) -> Result<Option<Invoice>, SqliteStorageError> {
+        let mut select_stmt = self.db.prepare(format!(
+            "SELECT invoice FROM {}
+            WHERE reference = :reference",
+            self.invoices
+        ))?;
+        select_stmt.bind::<&[(_, Value)]>(&[(":reference", reference.into())][..])?;
+
+        if select_stmt.next()? == State::Done {
+            return Ok(None);
+        }
+        let invoice_bytes = select_stmt.read::<Vec<u8>, _>("invoice")?;
+
+        Ok(Some(
+            bincode::decode_from_slice(&invoice_bytes, bincode::config::standard())?.0,
+        ))
+    }
+
     fn get_ids(&self) -> Result<Vec<InvoiceId>, SqliteStorageError> {
         // Check get the existing value.
         let select_stmt = self.db.prepare(format!(
@@ -325,21 +629,76 @@ impl InvoiceStorage for Sqlite {
         }
         Ok(is_empty == 0)
     }
+
+    fn query(&self, query: &InvoiceQuery) -> Result<InvoicePage, Self::Error> {
+        // The cursor and creation heights are filtered in the database, and the
+        // rest as invoices are read.
+        let mut conditions = Vec::new();
+        let mut params: Vec<(&str, Value)> = Vec::new();
+        if let Some(after) = query.get_after() {
+            conditions.push(
+                "(major_subindex, minor_subindex, creation_height) > (:major, :minor, :after_height)",
+            );
+            // Cast to i64 is needed because `Value` doesn't support u32.
+            params.push((":major", i64::from(after.sub_index.major).into()));
+            params.push((":minor", i64::from(after.sub_index.minor).into()));
+            // Cast to byte array is needed because `Value` doesn't support u64.
+            params.push((
+                ":after_height",
+                after.creation_height.to_be_bytes()[..].into(),
+            ));
+        }
+        if let Some(height) = query.get_min_creation_height() {
+            conditions.push("creation_height >= :min_height");
+            params.push((":min_height", height.to_be_bytes()[..].into()));
+        }
+        if let Some(height) = query.get_max_creation_height() {
+            conditions.push("creation_height <= :max_height");
+            params.push((":max_height", height.to_be_bytes()[..].into()));
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let mut statement = self.db.prepare(format!(
+            "SELECT invoice FROM {} {where_clause}
+            ORDER BY major_subindex, minor_subindex, creation_height",
+            self.invoices
+        ))?;
+        statement.bind::<&[(_, Value)]>(&params[..])?;
+
+        query.collect_page(statement.into_iter().map(|row| {
+            let row = row?;
+            let invoice = row.try_read::<&[u8], _>("invoice")?;
+            Ok(bincode::decode_from_slice(invoice, bincode::config::standard())?.0)
+        }))
+    }
 }
 
 impl OutputKeyStorage for Sqlite {
     type Error = SqliteStorageError;
 
-    fn insert(&mut self, key: OutputPubKey, output_id: OutputId) -> Result<(), Self::Error> {
+    fn insert(
+        &mut self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> Result<(), Self::Error> {
         let value = bincode::encode_to_vec(output_id, bincode::config::standard())?;
 
         let mut statement = self.db.prepare(format!(
-            "INSERT INTO {} (output_key, output_id) 
-            VALUES (:output_key, :output_id);",
+            "INSERT INTO {} (output_key, output_id, height) 
+            VALUES (:output_key, :output_id, :height);",
             self.output_keys
         ))?;
         statement.bind::<&[(_, Value)]>(
-            &[(":output_key", key.into()), (":output_id", value.into())][..],
+            &[
+                (":output_key", key.into()),
+                (":output_id", value.into()),
+                (":height", height_param(height)?.into()),
+            ][..],
         )?;
 
         while let Ok(State::Row) = statement.next() {
@@ -379,6 +738,72 @@ impl OutputKeyStorage for Sqlite {
             bincode::decode_from_slice(&output_id_bytes, bincode::config::standard())?.0,
         ))
     }
+    fn try_for_each_key<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<(OutputPubKey, OutputId, u64), Self::Error>) -> Result<(), Self::Error>,
+    {
+        let statement = self.db.prepare(format!(
+            "SELECT output_key, output_id, height FROM {}",
+            self.output_keys
+        ))?;
+
+        statement.into_iter().try_for_each(move |item| {
+            let key_or_err = item.map_err(SqliteStorageError::from).and_then(|row| {
+                let key = row
+                    .try_read::<&[u8], _>("output_key")?
+                    .try_into()
+                    .map_err(|_| SqliteStorageError::InvalidOutputKey)?;
+                let output_id = bincode::decode_from_slice(
+                    row.try_read::<&[u8], _>("output_id")?,
+                    bincode::config::standard(),
+                )?
+                .0;
+                let height = row.try_read::<i64, _>("height")?;
+                let height =
+                    u64::try_from(height).map_err(|_| SqliteStorageError::InvalidHeight(height))?;
+                Ok((OutputPubKey(key), output_id, height))
+            });
+
+            f(key_or_err)
+        })
+    }
+
+    fn prune_keys(&mut self, height: u64) -> Result<u64, Self::Error> {
+        let mut statement = self.db.prepare(format!(
+            "DELETE FROM {} WHERE height < :height",
+            self.output_keys
+        ))?;
+        // Heights beyond what can be stored are above every stored key.
+        let height = i64::try_from(height).unwrap_or(i64::MAX);
+        statement.bind::<&[(_, Value)]>(&[(":height", height.into())][..])?;
+        statement.next()?;
+        Ok(self.db.change_count() as u64)
+    }
+
+    fn key_stats(&self) -> Result<OutputKeyStats, Self::Error> {
+        let mut statement = self.db.prepare(format!(
+            "SELECT COUNT(*) AS count, MIN(height) AS lowest, MAX(height) AS highest FROM {}",
+            self.output_keys
+        ))?;
+        statement.next()?;
+        let to_height = |h: i64| u64::try_from(h).map_err(|_| SqliteStorageError::InvalidHeight(h));
+        Ok(OutputKeyStats {
+            count: statement.read::<i64, _>("count")?.unsigned_abs(),
+            lowest_height: statement
+                .read::<Option<i64>, _>("lowest")?
+                .map(to_height)
+                .transpose()?,
+            highest_height: statement
+                .read::<Option<i64>, _>("highest")?
+                .map(to_height)
+                .transpose()?,
+        })
+    }
+}
+
+/// `SQLite` integers are signed, so heights are stored as `i64`.
+fn height_param(height: u64) -> Result<i64, SqliteStorageError> {
+    i64::try_from(height).map_err(|_| SqliteStorageError::HeightOutOfRange(height))
 }
 
 impl From<OutputPubKey> for Value {
@@ -452,10 +877,328 @@ impl HeightStorage for Sqlite {
     }
 }
 
+impl SubaddressStorage for Sqlite {
+    type Error = SqliteStorageError;
+
+    fn insert_funded(&mut self, wallet: &str, sub_index: SubIndex) -> Result<(), Self::Error> {
+        let mut statement = self.db.prepare(format!(
+            "INSERT OR IGNORE INTO {} (wallet, major_subindex, minor_subindex)
+            VALUES (:wallet, :major, :minor);",
+            self.funded_subaddresses
+        ))?;
+        statement.bind::<&[(_, Value)]>(
+            &[
+                (":wallet", wallet.into()),
+                // Cast to i64 is needed because `Value` doesn't support u32.
+                (":major", i64::from(sub_index.major).into()),
+                (":minor", i64::from(sub_index.minor).into()),
+            ][..],
+        )?;
+
+        while let Ok(State::Row) = statement.next() {
+            warn!(
+                "Funded subaddress insertion returned an unexpected row: {:?}",
+                statement.read::<Value, _>(0)?
+            );
+        }
+        Ok(())
+    }
+
+    fn is_funded(&self, wallet: &str, sub_index: SubIndex) -> Result<bool, Self::Error> {
+        let mut select_stmt = self.db.prepare(format!(
+            "SELECT COUNT(*) FROM {}
+            WHERE wallet = :wallet AND major_subindex = :major AND minor_subindex = :minor",
+            self.funded_subaddresses
+        ))?;
+        select_stmt.bind::<&[(_, Value)]>(
+            &[
+                (":wallet", wallet.into()),
+                // Cast to i64 is needed because `Value` doesn't support u32.
+                (":major", i64::from(sub_index.major).into()),
+                (":minor", i64::from(sub_index.minor).into()),
+            ][..],
+        )?;
+        if select_stmt.next()? == State::Done {
+            return Ok(false);
+        }
+        let count = select_stmt.read::<i64, _>(0)?;
+
+        Ok(count > 0)
+    }
+
+    fn funded(&self) -> Result<Vec<(String, SubIndex)>, Self::Error> {
+        let select_stmt = self.db.prepare(format!(
+            "SELECT wallet, major_subindex, minor_subindex FROM {}",
+            self.funded_subaddresses
+        ))?;
+
+        select_stmt
+            .into_iter()
+            .map(|row| {
+                let row = row?;
+                let wallet = row.try_read::<&str, _>("wallet")?.to_string();
+                let major_subindex = row.try_read::<i64, _>("major_subindex")?;
+                let minor_subindex = row.try_read::<i64, _>("minor_subindex")?;
+                let sub_index = SubIndex::new(
+                    u32::try_from(major_subindex)
+                        .map_err(|_| SqliteStorageError::InvalidSubIndex(major_subindex))?,
+                    u32::try_from(minor_subindex)
+                        .map_err(|_| SqliteStorageError::InvalidSubIndex(minor_subindex))?,
+                );
+                Ok((wallet, sub_index))
+            })
+            .collect()
+    }
+}
+
+impl EventStorage for Sqlite {
+    type Error = SqliteStorageError;
+
+    fn append_event(&mut self, event: InvoiceEvent) -> Result<u64, Self::Error> {
+        let value = bincode::encode_to_vec(event, bincode::config::standard())?;
+
+        let mut statement = self.db.prepare(format!(
+            "INSERT INTO {} (event) VALUES (:event) RETURNING sequence;",
+            self.events
+        ))?;
+        statement.bind::<&[(_, Value)]>(&[(":event", value.into())][..])?;
+
+        if statement.next()? == State::Done {
+            return Err(SqliteStorageError::Database(sqlite::Error {
+                code: None,
+                message: Some("event insertion returned no sequence number".to_string()),
+            }));
+        }
+        let sequence = statement.read::<i64, _>("sequence")?;
+        while let Ok(State::Row) = statement.next() {
+            warn!(
+                "Event insertion returned an unexpected row: {:?}",
+                statement.read::<Value, _>(0)?
+            );
+        }
+
+        u64::try_from(sequence).map_err(|_| SqliteStorageError::InvalidSequence(sequence))
+    }
+
+    fn events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, Self::Error> {
+        let mut select_stmt = self.db.prepare(format!(
+            "SELECT sequence, event FROM {}
+            WHERE sequence >= :sequence
+            ORDER BY sequence
+            LIMIT :limit",
+            self.events
+        ))?;
+        // Sequence numbers past `i64::MAX` can't exist in the table.
+        select_stmt.bind::<&[(_, Value)]>(
+            &[
+                (
+                    ":sequence",
+                    i64::try_from(sequence).unwrap_or(i64::MAX).into(),
+                ),
+                (":limit", i64::try_from(limit).unwrap_or(i64::MAX).into()),
+            ][..],
+        )?;
+
+        select_stmt
+            .into_iter()
+            .map(|row| {
+                let row = row?;
+                let sequence = row.try_read::<i64, _>("sequence")?;
+                let event_bytes = row.try_read::<&[u8], _>("event")?;
+                Ok((
+                    u64::try_from(sequence)
+                        .map_err(|_| SqliteStorageError::InvalidSequence(sequence))?,
+                    bincode::decode_from_slice(event_bytes, bincode::config::standard())?.0,
+                ))
+            })
+            .collect()
+    }
+
+    fn prune_events(&mut self, sequence: u64) -> Result<(), Self::Error> {
+        let mut statement = self.db.prepare(format!(
+            "DELETE FROM {} WHERE sequence < :sequence",
+            self.events
+        ))?;
+        statement.bind::<&[(_, Value)]>(
+            &[(
+                ":sequence",
+                i64::try_from(sequence).unwrap_or(i64::MAX).into(),
+            )][..],
+        )?;
+
+        while let Ok(State::Row) = statement.next() {
+            warn!(
+                "Event pruning returned an unexpected row: {:?}",
+                statement.read::<Value, _>(0)?
+            );
+        }
+        Ok(())
+    }
+}
+
+impl ArchiveStorage for Sqlite {
+    type Error = SqliteStorageError;
+
+    fn archive(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, SqliteStorageError> {
+        self.db.execute("BEGIN")?;
+
+        let transaction = (|| {
+            let Some(invoice) = InvoiceStorage::remove(self, invoice_id)? else {
+                return Ok(None);
+            };
+            let value = bincode::encode_to_vec(&invoice, bincode::config::standard())?;
+
+            let mut insert_stmt = self.db.prepare(format!(
+                "INSERT OR REPLACE INTO {}
+                (major_subindex, minor_subindex, creation_height, current_height, invoice, reference)
+                VALUES (:major, :minor, :height, :current_height, :invoice, :reference);",
+                self.archived
+            ))?;
+            insert_stmt.bind::<&[(_, Value)]>(
+                &[
+                    // Cast to i64 is needed because `Value` doesn't support u32.
+                    (":major", i64::from(invoice_id.sub_index.major).into()),
+                    (":minor", i64::from(invoice_id.sub_index.minor).into()),
+                    // Cast to byte array is needed because `Value` doesn't support u64.
+                    (
+                        ":height",
+                        invoice_id.creation_height.to_be_bytes()[..].into(),
+                    ),
+                    (
+                        ":current_height",
+                        invoice.current_height().to_be_bytes()[..].into(),
+                    ),
+                    (":invoice", value.into()),
+                    (":reference", invoice.reference.clone().into()),
+                ][..],
+            )?;
+            insert_stmt.next()?;
+
+            Ok(Some(invoice))
+        })();
+
+        match transaction {
+            Ok(invoice) => {
+                self.db.execute("COMMIT")?;
+                Ok(invoice)
+            }
+            Err(e) => {
+                self.db.execute("ROLLBACK")?;
+                Err(e)
+            }
+        }
+    }
+
+    fn get_archived(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, SqliteStorageError> {
+        let mut select_stmt = self.db.prepare(format!(
+            "SELECT invoice FROM {}
+            WHERE major_subindex = :major AND minor_subindex = :minor AND creation_height = :height",
+            self.archived
+        ))?;
+        select_stmt.bind::<&[(_, Value)]>(
+            &[
+                // Cast to i64 is needed because `Value` doesn't support u32.
+                (":major", i64::from(invoice_id.sub_index.major).into()),
+                (":minor", i64::from(invoice_id.sub_index.minor).into()),
+                // Cast to byte array is needed because `Value` doesn't support u64.
+                (
+                    ":height",
+                    invoice_id.creation_height.to_be_bytes()[..].into(),
+                ),
+            ][..],
+        )?;
+
+        if select_stmt.next()? == State::Done {
+            return Ok(None);
+        }
+        let invoice_bytes = select_stmt.read::<Vec<u8>, _>("invoice")?;
+
+        Ok(Some(
+            bincode::decode_from_slice(&invoice_bytes, bincode::config::standard())?.0,
+        ))
+    }
+
+    fn get_archived_by_reference(
+        &self,
+        reference: &str,
+    ) -> Result<Option<Invoice>, SqliteStorageError> {
+        let mut select_stmt = self.db.prepare(format!(
+            "SELECT invoice FROM {}
+            WHERE reference = :reference",
+            self.archived
+        ))?;
+        select_stmt.bind::<&[(_, Value)]>(&[(":reference", reference.into())][..])?;
+
+        if select_stmt.next()? == State::Done {
+            return Ok(None);
+        }
+        let invoice_bytes = select_stmt.read::<Vec<u8>, _>("invoice")?;
+
+        Ok(Some(
+            bincode::decode_from_slice(&invoice_bytes, bincode::config::standard())?.0,
+        ))
+    }
+
+    fn try_for_each_archived<F>(&self, mut f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        let statement = self
+            .db
+            .prepare(format!("SELECT invoice FROM {}", self.archived))?;
+
+        statement.into_iter().try_for_each(move |item| {
+            let invoice_or_err = match item {
+                Ok(row) => match row.try_read("invoice") {
+                    Ok(value) => bincode::decode_from_slice(value, bincode::config::standard())
+                        .map(|v| v.0)
+                        .map_err(SqliteStorageError::Deserialize),
+                    Err(e) => Err(SqliteStorageError::from(e)),
+                },
+                Err(e) => Err(SqliteStorageError::Database(e)),
+            };
+
+            f(invoice_or_err)
+        })
+    }
+
+    fn prune_archive(&mut self, height: u64) -> Result<(), SqliteStorageError> {
+        // Heights are stored big-endian, so they compare in numeric order.
+        let mut statement = self.db.prepare(format!(
+            "DELETE FROM {} WHERE current_height < :height",
+            self.archived
+        ))?;
+        statement.bind::<&[(_, Value)]>(&[(":height", height.to_be_bytes()[..].into())][..])?;
+        statement.next()?;
+        Ok(())
+    }
+}
+
 impl Storage for Sqlite {
     type Error = SqliteStorageError;
 }
 
+/// Add a nullable reference column to a table created without one.
+fn add_reference_column(db: &Connection, table: &str) -> Result<(), SqliteStorageError> {
+    let mut column_stmt = db.prepare(
+        "SELECT COUNT(*) FROM pragma_table_info(:table)
+        WHERE name = 'reference'",
+    )?;
+    column_stmt.bind::<&[(_, Value)]>(&[(":table", table.into())][..])?;
+    column_stmt.next()?;
+    if column_stmt.read::<i64, _>(0)? == 0 {
+        db.execute(format!(
+            "ALTER TABLE {} ADD COLUMN reference TEXT",
+            TableName::new(table)
+        ))?;
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Ord, PartialOrd)]
 struct TableName(String);
 
@@ -513,6 +1256,9 @@ pub enum SqliteStorageError {
     /// Attempted to insert an invoice which already exists
     #[error("attempted to insert an invoice which already exists")]
     DuplicateInvoice,
+    /// Attempted to insert an invoice with the same reference as another
+    #[error("attempted to insert an invoice with the same reference as another")]
+    DuplicateReference,
     /// Attempted to insert an output key which already exists
     #[error("attempted to insert an output public key which already exists")]
     DuplicateOutputKey,
@@ -522,9 +1268,27 @@ pub enum SqliteStorageError {
     /// Failed to deserialize an [`Invoice`] or [`OutputPubKey`].
     #[error("deserialization error: {0}")]
     Deserialize(#[from] bincode::error::DecodeError),
+    /// The database's schema could not be read or upgraded.
+    #[error("schema error: {0}")]
+    Schema(#[from] SchemaError),
+    /// An output key in the database was not 32 bytes long.
+    #[error("invalid output key in database")]
+    InvalidOutputKey,
     /// Invalid subaddress index in DB.
     #[error("invalid subaddress index in database: {0}")]
     InvalidSubIndex(i64),
+    /// Invalid event sequence number in DB.
+    #[error("invalid event sequence number in database: {0}")]
+    InvalidSequence(i64),
+    /// Invalid schema version in DB.
+    #[error("invalid schema version in database: {0}")]
+    InvalidSchemaVersion(i64),
+    /// A height too large to be stored.
+    #[error("height too large to store: {0}")]
+    HeightOutOfRange(u64),
+    /// Invalid height in DB.
+    #[error("invalid height in database: {0}")]
+    InvalidHeight(i64),
 }
 
 #[cfg(test)]
@@ -0,0 +1,123 @@
+use crate::SubIndex;
+
+/// The [`SubaddressStorage`] trait describes the subaddress history storage
+/// layer for `AcceptXMR`. This layer records which subaddresses have received
+/// funds, so that a [`SubaddressAllocator`](crate::SubaddressAllocator) can
+/// refuse to reuse them, even after a restart.
+///
+/// Subaddresses are identified by the primary address of their wallet and
+/// their index. Subaddresses recorded before [schema
+/// version](crate::storage::SCHEMA_VERSION) 6 have an empty wallet.
+pub trait SubaddressStorage: Send + Sync {
+    /// Error type for the storage layer.
+    type Error: std::error::Error + Send + 'static;
+
+    /// Record that the subaddress of the given wallet with the given index has
+    /// received funds. Recording the same subaddress more than once has no
+    /// effect.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn insert_funded(&mut self, wallet: &str, sub_index: SubIndex) -> Result<(), Self::Error>;
+
+    /// Returns `true` if the subaddress of the given wallet with the given
+    /// index has received funds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn is_funded(&self, wallet: &str, sub_index: SubIndex) -> Result<bool, Self::Error>;
+
+    /// Returns the wallet and index of every subaddress which has received
+    /// funds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was an underlying issue with the database.
+    fn funded(&self) -> Result<Vec<(String, SubIndex)>, Self::Error>;
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use std::fmt::{Debug, Display};
+
+    use test_case::test_case;
+    use testing_utils::new_temp_dir;
+
+    #[cfg(feature = "postgres")]
+    use crate::storage::stores::Postgres;
+    use crate::{
+        storage::{
+            stores::{InMemory, Redb, Sled, Sqlite, TableNames},
+            SubaddressStorage,
+        },
+        SubIndex,
+    };
+    #[cfg(feature = "postgres")]
+    use testing_utils::new_postgres_db;
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn insert_and_check<S, E>(mut store: S)
+    where
+        S: SubaddressStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        store.insert_funded("a", SubIndex::new(0, 12)).unwrap();
+        store.insert_funded("a", SubIndex::new(1, 3)).unwrap();
+        store.insert_funded("b", SubIndex::new(0, 12)).unwrap();
+
+        assert!(store.is_funded("a", SubIndex::new(0, 12)).unwrap());
+        assert!(store.is_funded("b", SubIndex::new(0, 12)).unwrap());
+        assert!(!store.is_funded("a", SubIndex::new(1, 12)).unwrap());
+        assert!(!store.is_funded("b", SubIndex::new(1, 3)).unwrap());
+        let mut funded = store.funded().unwrap();
+        funded.sort();
+        assert_eq!(
+            funded,
+            [
+                ("a".to_string(), SubIndex::new(0, 12)),
+                ("a".to_string(), SubIndex::new(1, 3)),
+                ("b".to_string(), SubIndex::new(0, 12)),
+            ]
+        );
+    }
+
+    #[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(InMemory::new(); "in-memory")]
+    #[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn insert_existing<S, E>(mut store: S)
+    where
+        S: SubaddressStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        store.insert_funded("a", SubIndex::new(0, 12)).unwrap();
+        store.insert_funded("a", SubIndex::new(0, 12)).unwrap();
+
+        assert_eq!(
+            store.funded().unwrap(),
+            [("a".to_string(), SubIndex::new(0, 12))]
+        );
+    }
+
+    #[test_case(&Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
+    #[test_case(&InMemory::new(); "in-memory")]
+    #[test_case(&Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+    #[test_case(&Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+    #[cfg_attr(feature = "postgres", test_case(&Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
+    fn empty<S, E>(store: &S)
+    where
+        S: SubaddressStorage<Error = E> + 'static,
+        E: Debug + Display + Send,
+    {
+        assert!(!store.is_funded("a", SubIndex::new(0, 0)).unwrap());
+        assert!(store.funded().unwrap().is_empty());
+    }
+}
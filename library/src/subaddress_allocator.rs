@@ -0,0 +1,190 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Decides which subaddress new invoices are paid to, and whether subaddresses
+/// are reused once their invoice has been removed.
+///
+/// The payment gateway keeps a pool of available subaddresses for each
+/// account. When an invoice is created, the allocator chooses one of them.
+/// Implement this trait to customize allocation, or use one of the built-in
+/// strategies: [`RandomAllocator`] (the default), [`SequentialAllocator`] or
+/// [`NeverReuseFunded`].
+pub trait SubaddressAllocator: Send {
+    /// Choose one of an account's `available` subaddresses, ordered by
+    /// ascending minor index, and return its position. `available` is never
+    /// zero. Positions past the end are treated as the last subaddress.
+    fn select(&mut self, available: usize) -> usize;
+
+    /// Whether a subaddress which has received funds may be allocated to a
+    /// new invoice once its invoice has been removed. Defaults to `true`.
+    ///
+    /// Funded subaddresses are recorded in
+    /// [`SubaddressStorage`](crate::storage::SubaddressStorage), so this also
+    /// applies to subaddresses funded before a restart.
+    fn reuse_funded(&self) -> bool {
+        true
+    }
+}
+
+/// Allocates the available subaddress with the lowest minor index, so that
+/// subaddresses are used in order.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SequentialAllocator;
+
+impl SubaddressAllocator for SequentialAllocator {
+    fn select(&mut self, _available: usize) -> usize {
+        0
+    }
+}
+
+/// Allocates a random available subaddress. This makes it harder for an
+/// observer to guess how many invoices have been created.
+#[derive(Debug, Clone)]
+pub struct RandomAllocator {
+    rng: ChaCha12Rng,
+}
+
+impl RandomAllocator {
+    /// Create a random allocator seeded from the operating system's source of
+    /// entropy.
+    #[must_use]
+    pub fn new() -> RandomAllocator {
+        RandomAllocator {
+            rng: ChaCha12Rng::from_entropy(),
+        }
+    }
+
+    /// Create a random allocator with the given seed. Use only for
+    /// reproducible testing.
+    #[must_use]
+    pub fn from_seed(seed: u64) -> RandomAllocator {
+        RandomAllocator {
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Default for RandomAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SubaddressAllocator for RandomAllocator {
+    fn select(&mut self, available: usize) -> usize {
+        self.rng.gen_range(0..available)
+    }
+}
+
+/// Never allocates a subaddress which has ever received funds, so that a late
+/// payment to a removed invoice can't be credited to a new customer's invoice.
+/// Wraps another allocator, which chooses among the remaining subaddresses.
+///
+/// # Examples
+///
+/// ```
+/// use acceptxmr::{NeverReuseFunded, SequentialAllocator};
+///
+/// let allocator = NeverReuseFunded::new(SequentialAllocator);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct NeverReuseFunded<A = RandomAllocator> {
+    inner: A,
+}
+
+impl<A: SubaddressAllocator> NeverReuseFunded<A> {
+    /// Wrap the given allocator.
+    #[must_use]
+    pub fn new(inner: A) -> NeverReuseFunded<A> {
+        NeverReuseFunded { inner }
+    }
+}
+
+impl<A: SubaddressAllocator> SubaddressAllocator for NeverReuseFunded<A> {
+    fn select(&mut self, available: usize) -> usize {
+        self.inner.select(available)
+    }
+
+    fn reuse_funded(&self) -> bool {
+        false
+    }
+}
+
+/// The highest subaddress indices a payment gateway has used, for each account.
+///
+/// A wallet restored from seed only looks a limited number of subaddresses
+/// ahead, so it may miss funds sent to subaddresses the payment gateway used.
+/// Export this report to tell the wallet how far to look ahead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SubaddressReport {
+    /// Usage of each account the payment gateway allocates invoices from.
+    pub accounts: Vec<SubaddressUsage>,
+}
+
+impl SubaddressReport {
+    /// Returns the subaddress lookahead, as `(major, minor)`, a wallet needs to
+    /// discover every subaddress in this report. Pass it to `monero-wallet-cli`
+    /// as `--subaddress-lookahead major:minor`.
+    #[must_use]
+    pub fn lookahead(&self) -> (u32, u32) {
+        let major = self
+            .accounts
+            .iter()
+            .map(|account| account.account_index.saturating_add(1))
+            .max()
+            .unwrap_or(1);
+        let minor = self
+            .accounts
+            .iter()
+            .filter_map(|account| account.highest_used_minor_index)
+            .map(|minor| minor.saturating_add(1))
+            .max()
+            .unwrap_or(1);
+        (major, minor)
+    }
+}
+
+/// The highest subaddress indices used in an account.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SubaddressUsage {
+    /// Account index (i.e. subaddress major index).
+    pub account_index: u32,
+    /// Highest minor index allocated to an invoice or known to have received
+    /// funds, if any.
+    pub highest_used_minor_index: Option<u32>,
+    /// Highest minor index known to have received funds, if any.
+    pub highest_funded_minor_index: Option<u32>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SubaddressReport, SubaddressUsage};
+
+    #[test]
+    fn lookahead() {
+        let report = SubaddressReport {
+            accounts: vec![
+                SubaddressUsage {
+                    account_index: 0,
+                    highest_used_minor_index: Some(250),
+                    highest_funded_minor_index: Some(12),
+                },
+                SubaddressUsage {
+                    account_index: 3,
+                    highest_used_minor_index: None,
+                    highest_funded_minor_index: None,
+                },
+            ],
+        };
+        assert_eq!(report.lookahead(), (4, 251));
+
+        let empty = SubaddressReport {
+            accounts: Vec::new(),
+        };
+        assert_eq!(empty.lookahead(), (1, 1));
+    }
+}
@@ -0,0 +1,229 @@
+mod scanner;
+
+use std::any;
+
+use log::trace;
+pub(crate) use scanner::WalletRpcScanner;
+use serde_json::{json, Value};
+
+use crate::{
+    monerod_client::{Client as _, RpcClient, RpcError},
+    SubIndex,
+};
+
+/// A `monero-wallet-rpc` client. Wraps an [`RpcClient`], so authentication,
+/// timeouts, retries and rate limiting behave the same as for the monero
+/// daemon.
+#[derive(Debug, Clone)]
+pub(crate) struct WalletRpcClient(RpcClient);
+
+impl WalletRpcClient {
+    pub(crate) fn new(rpc_client: RpcClient) -> WalletRpcClient {
+        WalletRpcClient(rpc_client)
+    }
+
+    /// Whether calls to the wallet are currently suspended because it has
+    /// been failing repeatedly.
+    pub(crate) fn is_degraded(&self) -> bool {
+        self.0.is_degraded()
+    }
+
+    /// Call a JSON-RPC method, returning the contents of the response's
+    /// `result` field.
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let request_body = json!({
+            "jsonrpc": "2.0",
+            "id": "0",
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+
+        let mut res = self.0.request(&request_body, "json_rpc").await?;
+
+        if let Some(error) = res.get("error") {
+            return Err(RpcError::JsonRpc {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+        match res.get_mut("result") {
+            Some(result) => Ok(result.take()),
+            None => Err(RpcError::MissingData("{{ result: {{ ... }} }}".to_string())),
+        }
+    }
+
+    /// Fetch the wallet's address for the given account.
+    pub(crate) async fn address(&self, account_index: u32) -> Result<String, RpcError> {
+        trace!("Requesting wallet address for account {}", account_index);
+        let res = self
+            .call("get_address", json!({ "account_index": account_index }))
+            .await?;
+
+        let address = res["address"].as_str().ok_or_else(|| {
+            RpcError::MissingData("{{ result: {{ address: \"...\" }} }}".to_string())
+        })?;
+
+        Ok(address.to_string())
+    }
+
+    /// Create a new subaddress in the given account, returning its index and
+    /// base 58 encoding. The subaddress is left unlabeled, so that no invoice
+    /// data is written to the wallet file.
+    pub(crate) async fn create_address(
+        &self,
+        account_index: u32,
+    ) -> Result<(SubIndex, String), RpcError> {
+        trace!("Requesting new subaddress in account {}", account_index);
+        let res = self
+            .call("create_address", json!({ "account_index": account_index }))
+            .await?;
+
+        let address = res["address"].as_str().ok_or_else(|| {
+            RpcError::MissingData("{{ result: {{ address: \"...\" }} }}".to_string())
+        })?;
+        let minor_index = u32_field(&res, "address_index")?;
+
+        Ok((
+            SubIndex::new(account_index, minor_index),
+            address.to_string(),
+        ))
+    }
+
+    /// Fetch the wallet's height, i.e. the height of the next block it will
+    /// scan.
+    pub(crate) async fn height(&self) -> Result<u64, RpcError> {
+        let res = self.call("get_height", json!({})).await?;
+
+        res["height"]
+            .as_u64()
+            .ok_or_else(|| RpcError::MissingData("{{ result: {{ height: \"...\" }} }}".to_string()))
+    }
+
+    /// Fetch incoming transfers to the given account, both confirmed and in the
+    /// txpool. Only transfers in blocks above `min_height` are included.
+    pub(crate) async fn incoming_transfers(
+        &self,
+        account_index: u32,
+        min_height: u64,
+    ) -> Result<Vec<WalletTransfer>, RpcError> {
+        trace!(
+            "Requesting incoming transfers to account {} above height {}",
+            account_index,
+            min_height
+        );
+        let res = self
+            .call(
+                "get_transfers",
+                json!({
+                    "in": true,
+                    "pool": true,
+                    "account_index": account_index,
+                    "filter_by_height": true,
+                    "min_height": min_height,
+                }),
+            )
+            .await?;
+
+        // The wallet omits empty lists entirely.
+        res["in"]
+            .as_array()
+            .into_iter()
+            .chain(res["pool"].as_array())
+            .flatten()
+            .map(WalletTransfer::from_json)
+            .collect()
+    }
+}
+
+/// An incoming transfer, as reported by the wallet's `get_transfers` RPC
+/// method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WalletTransfer {
+    pub(crate) sub_index: SubIndex,
+    /// Amount received in piconeros.
+    pub(crate) amount: u64,
+    /// Height of the block containing the transfer, or `None` if it is in the
+    /// txpool.
+    pub(crate) height: Option<u64>,
+    pub(crate) unlock_time: u64,
+    pub(crate) txid: String,
+}
+
+impl WalletTransfer {
+    fn from_json(transfer: &Value) -> Result<WalletTransfer, RpcError> {
+        let sub_index = SubIndex::new(
+            u32_field(&transfer["subaddr_index"], "major")?,
+            u32_field(&transfer["subaddr_index"], "minor")?,
+        );
+        let amount = transfer["amount"].as_u64().ok_or_else(|| {
+            RpcError::MissingData("{{ result: {{ in: [ {{ amount: \"...\" }} ] }} }}".to_string())
+        })?;
+        // Transfers in the txpool are reported at height 0.
+        let height = transfer["height"].as_u64().filter(|&h| h != 0);
+        let unlock_time = transfer["unlock_time"].as_u64().unwrap_or_default();
+        let txid = transfer["txid"].as_str().unwrap_or_default().to_string();
+
+        Ok(WalletTransfer {
+            sub_index,
+            amount,
+            height,
+            unlock_time,
+            txid,
+        })
+    }
+}
+
+fn u32_field(json: &Value, field: &str) -> Result<u32, RpcError> {
+    let value = json[field]
+        .as_u64()
+        .ok_or_else(|| RpcError::MissingData(format!("{{ {field}: \"...\" }}")))?;
+    u32::try_from(value).map_err(|_| RpcError::DataType {
+        found: json[field].clone(),
+        expected: any::type_name::<u32>(),
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::WalletTransfer;
+    use crate::SubIndex;
+
+    #[test]
+    fn transfer_from_json() {
+        let transfer = WalletTransfer::from_json(&json!({
+            "amount": 1_000_000,
+            "height": 2_477_658,
+            "subaddr_index": { "major": 0, "minor": 12 },
+            "txid": "abcd",
+            "type": "in",
+            "unlock_time": 0,
+        }))
+        .unwrap();
+        assert_eq!(
+            transfer,
+            WalletTransfer {
+                sub_index: SubIndex::new(0, 12),
+                amount: 1_000_000,
+                height: Some(2_477_658),
+                unlock_time: 0,
+                txid: "abcd".to_string(),
+            }
+        );
+
+        // Transfers in the txpool have height 0.
+        let transfer = WalletTransfer::from_json(&json!({
+            "amount": 5,
+            "height": 0,
+            "subaddr_index": { "major": 1, "minor": 2 },
+            "type": "pool",
+        }))
+        .unwrap();
+        assert_eq!(transfer.height, None);
+
+        assert!(WalletTransfer::from_json(&json!({ "amount": 5 })).is_err());
+    }
+}
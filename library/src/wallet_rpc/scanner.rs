@@ -0,0 +1,122 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use futures_util::future::join_all;
+use log::{debug, trace};
+
+use super::WalletRpcClient;
+use crate::{
+    invoice::Transfer,
+    pubsub::Publisher,
+    scanner::{rebuild_invoices, save_and_publish, tracked_invoices, ScannerError},
+    storage::AsyncStorage,
+    Invoice, SubIndex,
+};
+
+/// Tracks payments using transfers reported by `monero-wallet-rpc`, rather
+/// than by scanning blocks itself. Reorgs and txpool changes are handled by
+/// the wallet, so every scan rebuilds each invoice's transfers from scratch.
+pub(crate) struct WalletRpcScanner<S: AsyncStorage> {
+    wallet: WalletRpcClient,
+    store: S,
+    account_indices: Vec<u32>,
+    atomic_cache_height: Arc<AtomicU64>,
+    atomic_daemon_height: Arc<AtomicU64>,
+    publisher: Arc<Publisher>,
+}
+
+impl<S: AsyncStorage> WalletRpcScanner<S> {
+    pub(crate) async fn new(
+        wallet: WalletRpcClient,
+        store: S,
+        account_indices: Vec<u32>,
+        atomic_cache_height: Arc<AtomicU64>,
+        atomic_daemon_height: Arc<AtomicU64>,
+        publisher: Arc<Publisher>,
+    ) -> Result<WalletRpcScanner<S>, ScannerError> {
+        trace!("Retrieving wallet height for scanner setup.");
+        let wallet_height = wallet.height().await?;
+        atomic_cache_height.store(wallet_height.saturating_sub(1), Ordering::Relaxed);
+        atomic_daemon_height.store(wallet_height, Ordering::Relaxed);
+
+        // Initialize the publisher with all currently-tracked invoices.
+        store
+            .get_invoice_ids()
+            .await?
+            .iter()
+            .for_each(|&id| publisher.insert_invoice(id));
+
+        Ok(WalletRpcScanner {
+            wallet,
+            store,
+            account_indices,
+            atomic_cache_height,
+            atomic_daemon_height,
+            publisher,
+        })
+    }
+
+    /// Scan for invoice updates.
+    pub(crate) async fn scan(&self) -> Result<(), ScannerError> {
+        let wallet_height = self.wallet.height().await?;
+        let top_height = wallet_height.saturating_sub(1);
+        self.atomic_cache_height
+            .store(top_height, Ordering::Relaxed);
+        self.atomic_daemon_height
+            .store(wallet_height, Ordering::Relaxed);
+
+        let invoices = tracked_invoices(&self.store).await?;
+
+        // Only request transfers recent enough to belong to a tracked invoice.
+        let transfers = match invoices.iter().map(Invoice::creation_height).min() {
+            Some(lowest) => join_all(self.account_indices.iter().map(|&account_index| {
+                self.wallet
+                    .incoming_transfers(account_index, lowest.saturating_sub(1))
+            }))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+            None => Vec::new(),
+        };
+        let transfers: Vec<(SubIndex, Transfer)> = transfers
+            .into_iter()
+            .filter(|transfer| {
+                // Ensure the time lock is zero.
+                if transfer.unlock_time == 0 {
+                    true
+                } else {
+                    debug!("Saw time locked transaction with hash {}", transfer.txid);
+                    false
+                }
+            })
+            .map(|transfer| {
+                (
+                    transfer.sub_index,
+                    Transfer::in_hex_tx(&transfer.txid, transfer.amount, transfer.height),
+                )
+            })
+            .collect();
+        trace!(
+            "Wallet reported {} transfers to accounts {:?}",
+            transfers.len(),
+            self.account_indices
+        );
+
+        let updated_invoices = rebuild_invoices(invoices, &transfers, wallet_height);
+
+        save_and_publish(&self.store, &self.publisher, updated_invoices).await;
+
+        // Update last scanned height in the database.
+        self.store.upsert_height(top_height).await?;
+
+        // Flush changes to the database.
+        self.store.flush().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use indexmap::IndexMap;
+use log::debug;
+use monero::ViewPair;
+
+use crate::{storage::AsyncStorage, AcceptXmrError, SubIndex};
+
+/// The wallets a payment gateway tracks payments to, and the wallet each
+/// subaddress index in use belongs to. Shared between the payment gateway and
+/// its scanner.
+///
+/// Each wallet allocates its own subaddresses, but an index in use by one
+/// wallet's invoice is not allocated to another, so each index in use belongs
+/// to exactly one wallet, and invoice IDs stay unique.
+pub(crate) struct Wallets(RwLock<WalletsInner>);
+
+struct WalletsInner {
+    /// View pairs by primary address. The first is the default wallet.
+    view_pairs: IndexMap<String, ViewPair>,
+    /// Primary address of the wallet each subaddress index in use belongs to.
+    owners: HashMap<SubIndex, String>,
+    /// Incremented whenever a wallet is added or removed.
+    version: u64,
+}
+
+impl Wallets {
+    /// Create a registry of the given wallets, the first of which is the
+    /// default wallet, recording the wallet of each invoice currently in
+    /// storage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AcceptXmrError::UnknownWallet`] error if an invoice in
+    /// storage belongs to none of the given wallets, since payments to it
+    /// would otherwise go unnoticed.
+    pub(crate) async fn init<S: AsyncStorage>(
+        storage: &S,
+        wallets: IndexMap<String, ViewPair>,
+    ) -> Result<Wallets, AcceptXmrError> {
+        let owners = Arc::new(Mutex::new(HashMap::new()));
+        let cloned_owners = owners.clone();
+        let default_wallet = wallets
+            .first()
+            .map(|(wallet, _)| wallet.clone())
+            .unwrap_or_default();
+        storage
+            .try_for_each_invoice(move |invoice_or_err| {
+                let invoice = invoice_or_err?;
+                // Invoices upgraded from before wallets were recorded have no
+                // wallet, and belong to the default wallet.
+                let wallet = if invoice.wallet().is_empty() {
+                    default_wallet.clone()
+                } else {
+                    invoice.wallet().to_string()
+                };
+                cloned_owners
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(invoice.index(), wallet);
+                Ok(())
+            })
+            .await?;
+        let owners = owners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if let Some(wallet) = owners
+            .values()
+            .find(|wallet| !wallets.contains_key(*wallet))
+        {
+            return Err(AcceptXmrError::UnknownWallet(wallet.clone()));
+        }
+
+        Ok(Wallets(RwLock::new(WalletsInner {
+            view_pairs: wallets,
+            owners,
+            version: 0,
+        })))
+    }
+
+    /// Primary address of the default wallet.
+    pub(crate) fn default_wallet(&self) -> String {
+        self.read()
+            .view_pairs
+            .first()
+            .map(|(wallet, _)| wallet.clone())
+            .unwrap_or_default()
+    }
+
+    /// Primary addresses of all wallets, starting with the default wallet.
+    pub(crate) fn primary_addresses(&self) -> Vec<String> {
+        self.read().view_pairs.keys().cloned().collect()
+    }
+
+    pub(crate) fn view_pair(&self, wallet: &str) -> Option<ViewPair> {
+        self.read().view_pairs.get(wallet).copied()
+    }
+
+    /// Returns every wallet's primary address and view pair, along with the
+    /// current [version](Self::version).
+    pub(crate) fn snapshot(&self) -> (u64, Vec<(String, ViewPair)>) {
+        let inner = self.read();
+        let view_pairs = inner
+            .view_pairs
+            .iter()
+            .map(|(wallet, viewpair)| (wallet.clone(), *viewpair))
+            .collect();
+        (inner.version, view_pairs)
+    }
+
+    /// Changes whenever a wallet is added or removed.
+    pub(crate) fn version(&self) -> u64 {
+        self.read().version
+    }
+
+    /// Add a wallet. Returns `false` if it was already present.
+    pub(crate) fn insert(&self, wallet: String, viewpair: ViewPair) -> bool {
+        let mut inner = self.write();
+        if inner.view_pairs.contains_key(&wallet) {
+            return false;
+        }
+        debug!("Now tracking payments to wallet {}", wallet);
+        inner.view_pairs.insert(wallet, viewpair);
+        inner.version += 1;
+        true
+    }
+
+    /// Remove a wallet. Returns `false` if it was not present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AcceptXmrError::WalletInUse`] error if the wallet is the
+    /// default wallet, or if any subaddress index in use belongs to it.
+    pub(crate) fn remove(&self, wallet: &str) -> Result<bool, AcceptXmrError> {
+        let mut inner = self.write();
+        match inner.view_pairs.get_index_of(wallet) {
+            None => Ok(false),
+            Some(0) => Err(AcceptXmrError::WalletInUse(wallet.to_string())),
+            Some(_) if inner.owners.values().any(|owner| owner == wallet) => {
+                Err(AcceptXmrError::WalletInUse(wallet.to_string()))
+            }
+            Some(i) => {
+                debug!("Stopped tracking payments to wallet {}", wallet);
+                inner.view_pairs.shift_remove_index(i);
+                inner.version += 1;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Record that the subaddress index is in use by the given wallet.
+    pub(crate) fn set_owner(&self, sub_index: SubIndex, wallet: String) {
+        self.write().owners.insert(sub_index, wallet);
+    }
+
+    /// Record that the subaddress index is no longer in use.
+    pub(crate) fn clear_owner(&self, sub_index: SubIndex) {
+        self.write().owners.remove(&sub_index);
+    }
+
+    /// Returns `true` if the subaddress index is in use by the given wallet.
+    pub(crate) fn is_owner(&self, sub_index: SubIndex, wallet: &str) -> bool {
+        self.read()
+            .owners
+            .get(&sub_index)
+            .is_some_and(|owner| owner == wallet)
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, WalletsInner> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, WalletsInner> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
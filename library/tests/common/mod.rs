@@ -1,18 +1,24 @@
 use std::time::Duration;
 
+#[cfg(feature = "postgres")]
+use acceptxmr::storage::stores::Postgres;
 use acceptxmr::{
     storage::{
-        stores::{InMemory, Sled, Sqlite},
+        stores::{InMemory, Redb, Sled, Sqlite, TableNames},
         Storage,
     },
     PaymentGatewayBuilder, SubIndex,
 };
 use test_case::test_case;
+#[cfg(feature = "postgres")]
+use testing_utils::new_postgres_db;
 use testing_utils::{init_logger, new_temp_dir, MockDaemon, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY};
 
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test]
 async fn reproducible_rand<S>(store: S)
 where
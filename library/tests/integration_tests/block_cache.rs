@@ -1,20 +1,26 @@
 use std::time::Duration;
 
+#[cfg(feature = "postgres")]
+use acceptxmr::storage::stores::Postgres;
 use acceptxmr::{
     storage::{
-        stores::{InMemory, Sled, Sqlite},
+        stores::{InMemory, Redb, Sled, Sqlite, TableNames},
         Storage,
     },
     PaymentGatewayBuilder, SubIndex,
 };
 use test_case::test_case;
+#[cfg(feature = "postgres")]
+use testing_utils::new_postgres_db;
 use testing_utils::{
     init_logger, new_temp_dir, MockDaemon, MockInvoice, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY,
 };
 
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test]
 async fn fix_reorg<S>(store: S)
 where
@@ -96,7 +102,7 @@ where
         .await
         .expect_err("should not have received an update, but did");
 
-    mock_daemon.mock_daemon_height(24_776_659);
+    mock_daemon.mock_daemon_height(2_477_659);
 
     let update = subscriber
         .recv_timeout(Duration::from_secs(120))
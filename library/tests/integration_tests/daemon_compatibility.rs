@@ -0,0 +1,104 @@
+use acceptxmr::{storage::stores::InMemory, AcceptXmrError, PaymentGatewayBuilder};
+use testing_utils::{init_logger, MockDaemon, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY};
+
+#[tokio::test]
+async fn network_mismatch() {
+    // Setup.
+    init_logger();
+    let mock_daemon = MockDaemon::new_mock_daemon().await;
+    mock_daemon.mock_daemon_info("stagenet", false);
+
+    // Build a payment gateway with a mainnet address pointing at a stagenet daemon.
+    let result = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .daemon_url(mock_daemon.url(""))
+    .build()
+    .await;
+
+    assert!(
+        matches!(
+            result,
+            Err(AcceptXmrError::NetworkMismatch {
+                daemon: monero::Network::Stagenet,
+                address: monero::Network::Mainnet,
+            })
+        ),
+        "payment gateway was built against a daemon on the wrong network"
+    );
+}
+
+#[tokio::test]
+async fn unsupported_rpc_version() {
+    // Setup.
+    init_logger();
+    let mock_daemon = MockDaemon::new_mock_daemon().await;
+    mock_daemon.mock_rpc_version(2, 9);
+
+    let result = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .daemon_url(mock_daemon.url(""))
+    .build()
+    .await;
+
+    match result {
+        Err(AcceptXmrError::UnsupportedRpcVersion {
+            found,
+            expected_major,
+        }) => {
+            assert_eq!((found.major, found.minor), (2, 9));
+            assert_eq!(expected_major, 3);
+        }
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("payment gateway was built against an unsupported daemon"),
+    }
+}
+
+#[tokio::test]
+async fn restricted_rpc() {
+    // Setup.
+    init_logger();
+    let mock_daemon = MockDaemon::new_mock_daemon().await;
+    mock_daemon.mock_daemon_info("mainnet", true);
+
+    // A restricted daemon which still serves every endpoint we need is fine.
+    PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .daemon_url(mock_daemon.url(""))
+    .build()
+    .await
+    .expect("failed to build payment gateway against restricted daemon");
+
+    // Remove the txpool hashes endpoint.
+    mock_daemon
+        .mock_txpool_hashes("../testing-utils/rpc_resources/txpools/hashes.json")
+        .delete();
+
+    let result = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .daemon_url(mock_daemon.url(""))
+    .build()
+    .await;
+
+    assert!(
+        matches!(
+            result,
+            Err(AcceptXmrError::RestrictedRpc {
+                endpoint: "get_transaction_pool_hashes",
+                ..
+            })
+        ),
+        "payment gateway was built against a restricted daemon missing a required endpoint"
+    );
+}
@@ -0,0 +1,269 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use acceptxmr::{
+    storage::{
+        stores::{InMemory, InMemoryStorageError},
+        ArchiveStorage, EventStorage, HeightStorage, InvoicePage, InvoiceQuery, InvoiceStorage,
+        OutputId, OutputKeyStats, OutputKeyStorage, OutputPubKey, Storage, SubaddressStorage,
+    },
+    Invoice, InvoiceEvent, InvoiceId, PaymentGatewayBuilder, SubIndex,
+};
+use monero::cryptonote::subaddress::Index;
+use testing_utils::{
+    init_logger, view_pair, MockDaemon, SyntheticChain, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY,
+};
+
+/// An in-memory store whose event log can be made to fail.
+struct FailingEventLog {
+    store: InMemory,
+    /// Number of upcoming calls to `append_event` which should fail.
+    failing_appends: Arc<AtomicUsize>,
+}
+
+impl InvoiceStorage for FailingEventLog {
+    type Error = InMemoryStorageError;
+
+    fn insert(&mut self, invoice: Invoice) -> Result<(), InMemoryStorageError> {
+        InvoiceStorage::insert(&mut self.store, invoice)
+    }
+
+    fn remove(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, InMemoryStorageError> {
+        InvoiceStorage::remove(&mut self.store, invoice_id)
+    }
+
+    fn update(&mut self, invoice: Invoice) -> Result<Option<Invoice>, InMemoryStorageError> {
+        InvoiceStorage::update(&mut self.store, invoice)
+    }
+
+    fn get(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, InMemoryStorageError> {
+        InvoiceStorage::get(&self.store, invoice_id)
+    }
+
+    fn get_by_reference(&self, reference: &str) -> Result<Option<Invoice>, InMemoryStorageError> {
+        self.store.get_by_reference(reference)
+    }
+
+    fn get_ids(&self) -> Result<Vec<InvoiceId>, InMemoryStorageError> {
+        self.store.get_ids()
+    }
+
+    fn contains_sub_index(&self, sub_index: SubIndex) -> Result<bool, InMemoryStorageError> {
+        self.store.contains_sub_index(sub_index)
+    }
+
+    fn try_for_each<F>(&self, f: F) -> Result<(), InMemoryStorageError>
+    where
+        F: FnMut(Result<Invoice, InMemoryStorageError>) -> Result<(), InMemoryStorageError>,
+    {
+        self.store.try_for_each(f)
+    }
+
+    fn is_empty(&self) -> Result<bool, InMemoryStorageError> {
+        InvoiceStorage::is_empty(&self.store)
+    }
+
+    fn lowest_height(&self) -> Result<Option<u64>, InMemoryStorageError> {
+        self.store.lowest_height()
+    }
+
+    fn query(&self, query: &InvoiceQuery) -> Result<InvoicePage, InMemoryStorageError> {
+        self.store.query(query)
+    }
+}
+
+impl OutputKeyStorage for FailingEventLog {
+    type Error = InMemoryStorageError;
+
+    fn insert(
+        &mut self,
+        key: OutputPubKey,
+        output_id: OutputId,
+        height: u64,
+    ) -> Result<(), InMemoryStorageError> {
+        OutputKeyStorage::insert(&mut self.store, key, output_id, height)
+    }
+
+    fn get(&self, key: OutputPubKey) -> Result<Option<OutputId>, InMemoryStorageError> {
+        OutputKeyStorage::get(&self.store, key)
+    }
+
+    fn try_for_each_key<F>(&self, f: F) -> Result<(), InMemoryStorageError>
+    where
+        F: FnMut(
+            Result<(OutputPubKey, OutputId, u64), InMemoryStorageError>,
+        ) -> Result<(), InMemoryStorageError>,
+    {
+        self.store.try_for_each_key(f)
+    }
+
+    fn prune_keys(&mut self, height: u64) -> Result<u64, InMemoryStorageError> {
+        self.store.prune_keys(height)
+    }
+
+    fn key_stats(&self) -> Result<OutputKeyStats, InMemoryStorageError> {
+        self.store.key_stats()
+    }
+}
+
+impl HeightStorage for FailingEventLog {
+    type Error = InMemoryStorageError;
+
+    fn upsert(&mut self, height: u64) -> Result<Option<u64>, InMemoryStorageError> {
+        self.store.upsert(height)
+    }
+
+    fn get(&self) -> Result<Option<u64>, InMemoryStorageError> {
+        HeightStorage::get(&self.store)
+    }
+}
+
+impl SubaddressStorage for FailingEventLog {
+    type Error = InMemoryStorageError;
+
+    fn insert_funded(&mut self, wallet: &str, sub_index: SubIndex) -> Result<(), Self::Error> {
+        self.store.insert_funded(wallet, sub_index)
+    }
+
+    fn is_funded(&self, wallet: &str, sub_index: SubIndex) -> Result<bool, Self::Error> {
+        self.store.is_funded(wallet, sub_index)
+    }
+
+    fn funded(&self) -> Result<Vec<(String, SubIndex)>, Self::Error> {
+        self.store.funded()
+    }
+}
+
+impl EventStorage for FailingEventLog {
+    type Error = io::Error;
+
+    fn append_event(&mut self, event: InvoiceEvent) -> Result<u64, io::Error> {
+        if self
+            .failing_appends
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+        {
+            return Err(io::Error::other("injected event log failure"));
+        }
+        self.store.append_event(event).map_err(io::Error::other)
+    }
+
+    fn events_from(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, InvoiceEvent)>, io::Error> {
+        self.store
+            .events_from(sequence, limit)
+            .map_err(io::Error::other)
+    }
+
+    fn prune_events(&mut self, sequence: u64) -> Result<(), io::Error> {
+        self.store.prune_events(sequence).map_err(io::Error::other)
+    }
+}
+
+impl ArchiveStorage for FailingEventLog {
+    type Error = InMemoryStorageError;
+
+    fn archive(&mut self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        self.store.archive(invoice_id)
+    }
+
+    fn get_archived(&self, invoice_id: InvoiceId) -> Result<Option<Invoice>, Self::Error> {
+        self.store.get_archived(invoice_id)
+    }
+
+    fn get_archived_by_reference(&self, reference: &str) -> Result<Option<Invoice>, Self::Error> {
+        self.store.get_archived_by_reference(reference)
+    }
+
+    fn try_for_each_archived<F>(&self, f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(Result<Invoice, Self::Error>) -> Result<(), Self::Error>,
+    {
+        self.store.try_for_each_archived(f)
+    }
+
+    fn prune_archive(&mut self, height: u64) -> Result<(), Self::Error> {
+        self.store.prune_archive(height)
+    }
+}
+
+impl Storage for FailingEventLog {
+    type Error = InMemoryStorageError;
+}
+
+#[tokio::test]
+async fn retry_unlogged_update() {
+    init_logger();
+    let mut chain = SyntheticChain::new(3_000_000, 1);
+    chain.mine_empty_blocks(10);
+    let mock_daemon = MockDaemon::new_synthetic_daemon(&chain).await;
+
+    let failing_appends = Arc::new(AtomicUsize::new(0));
+    let store = FailingEventLog {
+        store: InMemory::new(),
+        failing_appends: failing_appends.clone(),
+    };
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        store,
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .seed(1)
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    let invoice_id = payment_gateway
+        .new_invoice(1_000, 1, 10, "test invoice".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+
+    // Logging the update which finds the payment fails once. The payment's
+    // output key has been seen by then, so only a retry can record it.
+    failing_appends.store(1, Ordering::SeqCst);
+    let tx = chain
+        .new_transaction()
+        .pay(&view_pair(), Index::from(invoice_id.sub_index), 1_000)
+        .build();
+    chain.add_to_txpool(tx);
+    chain.mine_txpool();
+    mock_daemon.mock_chain(&chain);
+    let update = loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.is_confirmed() {
+            break update;
+        }
+    };
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(failing_appends.load(Ordering::SeqCst), 0);
+
+    let stored = payment_gateway
+        .get_invoice(invoice_id)
+        .await
+        .expect("failed to get invoice")
+        .expect("invoice does not exist");
+    assert_eq!(stored.amount_paid(), 1_000);
+    assert!(stored.is_confirmed());
+}
@@ -1,21 +1,27 @@
 use std::time::Duration;
 
+#[cfg(feature = "postgres")]
+use acceptxmr::storage::stores::Postgres;
 use acceptxmr::{
     storage::{
-        stores::{InMemory, Sled, Sqlite},
+        stores::{InMemory, Redb, Sled, Sqlite, TableNames},
         OutputId, OutputKeyStorage, OutputPubKey, Storage,
     },
     PaymentGatewayBuilder, SubIndex,
 };
 use monero::consensus::deserialize;
 use test_case::test_case;
+#[cfg(feature = "postgres")]
+use testing_utils::new_postgres_db;
 use testing_utils::{
     init_logger, new_temp_dir, MockDaemon, MockInvoice, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY,
 };
 
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test]
 async fn new_invoice<S>(store: S)
 where
@@ -79,9 +85,11 @@ where
     );
 }
 
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test]
 async fn default_account_index<S>(store: S)
 where
@@ -170,9 +178,11 @@ where
     expected.assert_eq(&update);
 }
 
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test]
 async fn zero_conf_invoice<S>(store: S)
 where
@@ -249,9 +259,11 @@ where
     expected.assert_eq(&update);
 }
 
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test]
 async fn timelock_rejection<S>(store: S)
 where
@@ -326,9 +338,11 @@ where
         .expect_err("timeout waiting for invoice update");
 }
 
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test]
 async fn burning_bug<S>(mut store: S)
 where
@@ -352,7 +366,7 @@ where
         index: 1,
     };
     // Insert the key with a different ID so it looks like a re-used key.
-    OutputKeyStorage::insert(&mut store, output_key, output_id).unwrap();
+    OutputKeyStorage::insert(&mut store, output_key, output_id, 2_477_650).unwrap();
 
     // Create payment gateway pointing at temp directory and mock daemon.
     let payment_gateway = PaymentGatewayBuilder::new(
@@ -420,9 +434,11 @@ where
 }
 
 #[allow(clippy::too_many_lines)]
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test]
 async fn track_parallel_invoices<S>(store: S)
 where
@@ -681,9 +697,11 @@ where
 }
 
 #[allow(clippy::too_many_lines)]
-#[test_case(Sled::new(&new_temp_dir(), "invoices", "output keys", "height").unwrap(); "sled")]
+#[test_case(Sled::new(&new_temp_dir(), &TableNames::default()).unwrap(); "sled")]
 #[test_case(InMemory::new(); "in-memory")]
-#[test_case(Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap(); "sqlite")]
+#[test_case(Sqlite::new(":memory:", &TableNames::default()).unwrap(); "sqlite")]
+#[test_case(Redb::new(&new_temp_dir(), &TableNames::default()).unwrap(); "redb")]
+#[cfg_attr(feature = "postgres", test_case(Postgres::new(&new_postgres_db(), &TableNames::default()).unwrap() => ignore; "postgres"))]
 #[tokio::test(flavor = "multi_thread")]
 async fn set_initial_height<S>(mut store: S)
 where
@@ -710,7 +728,7 @@ where
         index: 1,
     };
     // Insert the key with a different ID so it looks like a re-used key.
-    OutputKeyStorage::insert(&mut store, output_key, output_id).unwrap();
+    OutputKeyStorage::insert(&mut store, output_key, output_id, 2_477_650).unwrap();
 
     // Create payment gateway pointing at temp directory and mock daemon.
     let payment_gateway_with_height = PaymentGatewayBuilder::new(
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use acceptxmr::{storage::stores::InMemory, PaymentGatewayBuilder, SubIndex};
+use testing_utils::{
+    init_logger, MockLightWalletServer, MockTransfer, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY,
+};
+
+#[tokio::test]
+async fn track_payment() {
+    // Setup.
+    init_logger();
+    let mock_server = MockLightWalletServer::new(2_477_656).await;
+    let upsert_subaddrs = mock_server.mock_upsert_subaddrs();
+
+    // No monero daemon is needed.
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url("http://localhost:1".to_string())
+    .light_wallet_server_url(mock_server.url(""))
+    .seed(1)
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    assert_eq!(payment_gateway.daemon_url(), mock_server.url("/"));
+    assert_eq!(
+        payment_gateway
+            .daemon_height()
+            .await
+            .expect("failed to get daemon height"),
+        2_477_657
+    );
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    let invoice_id = payment_gateway
+        .new_invoice(1_000, 1, 10, "test invoice".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(invoice_id.creation_height, 2_477_657);
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+    let update = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    assert_eq!(update.current_height(), 2_477_657);
+    assert_eq!(update.amount_paid(), 0);
+    // The server was asked to scan for the payment gateway's subaddresses.
+    upsert_subaddrs.assert_hits(1);
+
+    // Pay the invoice in the txpool. A time locked output, and outputs to other
+    // subaddresses or from before the invoice was created are ignored.
+    let time_locked = MockTransfer {
+        unlock_time: 2_500_000,
+        ..MockTransfer::new(invoice_id.sub_index, 500, None)
+    };
+    let other_sub_index = SubIndex::new(0, invoice_id.sub_index.minor + 1);
+    mock_server.mock_outputs(&[
+        MockTransfer::new(invoice_id.sub_index, 600, None),
+        MockTransfer::new(invoice_id.sub_index, 400, None),
+        time_locked,
+        MockTransfer::new(other_sub_index, 2_000, None),
+        MockTransfer::new(invoice_id.sub_index, 3_000, Some(2_477_650)),
+    ]);
+    let update = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(update.confirmations(), Some(0));
+
+    // Confirm the payment.
+    mock_server.mock_outputs(&[
+        MockTransfer::new(invoice_id.sub_index, 600, Some(2_477_657)),
+        MockTransfer::new(invoice_id.sub_index, 400, Some(2_477_657)),
+    ]);
+    mock_server.mock_height(2_477_657, 2_477_657);
+    let update = loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.current_height() == 2_477_658 {
+            break update;
+        }
+    };
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(update.confirmations(), Some(1));
+    assert!(update.is_confirmed());
+}
@@ -1,3 +1,9 @@
 mod block_cache;
+mod daemon_compatibility;
+mod event_log;
 mod invoice_tracking;
+mod light_wallet;
+mod record_replay;
 mod scanning_thread_management;
+mod synthetic_chain;
+mod wallet_rpc;
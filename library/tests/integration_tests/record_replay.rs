@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use acceptxmr::{storage::stores::InMemory, PaymentGatewayBuilder, SubIndex};
+use testing_utils::{
+    init_logger, new_temp_dir, MockDaemon, MockInvoice, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY,
+};
+
+#[tokio::test]
+async fn record_and_replay() {
+    // Setup.
+    init_logger();
+    let fixture_dir = new_temp_dir();
+    let mock_daemon = MockDaemon::new_mock_daemon().await;
+
+    // Record a payment from the mock daemon.
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .seed(1)
+    .build_recording(&fixture_dir)
+    .await
+    .expect("failed to build recording payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    let invoice_id = payment_gateway
+        .new_invoice(1, 5, 10, "test invoice".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+    subscriber
+        .recv_timeout(Duration::from_secs(120))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+
+    // Add transfer to txpool.
+    mock_daemon.mock_txpool_hashes(
+        "../testing-utils/rpc_resources/txpools/hashes_with_payment_account_0.json",
+    );
+    mock_daemon.mock_txpool_transactions(
+        "../testing-utils/rpc_resources/transactions/hashes_with_payment_account_0.json",
+        "../testing-utils/rpc_resources/transactions/txs_with_payment_account_0.json",
+    );
+    let recorded = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    payment_gateway
+        .stop()
+        .await
+        .expect("failed to stop payment gateway");
+
+    // Replay the recording without the mock daemon.
+    drop(mock_daemon);
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .seed(1)
+    .build_with_replay(&fixture_dir)
+    .await
+    .expect("failed to build replaying payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    let invoice_id = payment_gateway
+        .new_invoice(1, 5, 10, "test invoice".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+
+    let mut expected = MockInvoice::new(
+        Some(recorded.address().to_string()),
+        SubIndex::new(0, 97),
+        2_477_657,
+        1,
+        5,
+        10,
+        "test invoice".to_string(),
+    );
+    expected.amount_paid = 1_468_383_460;
+    expected.confirmations = Some(0);
+
+    // The recorded payment is eventually replayed.
+    loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.amount_paid() > 0 {
+            expected.assert_eq(&update);
+            break;
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use std::time::Duration;
+
 use acceptxmr::{
-    storage::stores::Sled, AcceptXmrError, PaymentGatewayBuilder, PaymentGatewayStatus,
+    storage::stores::{InMemory, Sled, TableNames},
+    AcceptXmrError, PaymentGatewayBuilder, PaymentGatewayStatus, RpcPolicy,
 };
 use testing_utils::{init_logger, new_temp_dir, MockDaemon, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY};
 
@@ -10,8 +13,8 @@ async fn run_payment_gateway() {
     let temp_dir = new_temp_dir();
     let mock_daemon = MockDaemon::new_mock_daemon().await;
 
-    let store = Sled::new(&temp_dir, "invoices", "output keys", "height")
-        .expect("failed to create sled storage layer.");
+    let store =
+        Sled::new(&temp_dir, &TableNames::default()).expect("failed to create sled storage layer.");
 
     // Create payment gateway pointing at temp directory and mock daemon.
     let payment_gateway = PaymentGatewayBuilder::new(
@@ -38,8 +41,8 @@ async fn cannot_run_payment_gateway_twice() {
     let temp_dir = new_temp_dir();
     let mock_daemon = MockDaemon::new_mock_daemon().await;
 
-    let store = Sled::new(&temp_dir, "invoices", "output keys", "height")
-        .expect("failed to create sled storage layer.");
+    let store =
+        Sled::new(&temp_dir, &TableNames::default()).expect("failed to create sled storage layer.");
 
     // Create payment gateway pointing at temp directory and mock daemon.
     let payment_gateway = PaymentGatewayBuilder::new(
@@ -74,8 +77,8 @@ async fn stop_payment_gateway() {
     let temp_dir = new_temp_dir();
     let mock_daemon = MockDaemon::new_mock_daemon().await;
 
-    let store = Sled::new(&temp_dir, "invoices", "output keys", "height")
-        .expect("failed to create sled storage layer.");
+    let store =
+        Sled::new(&temp_dir, &TableNames::default()).expect("failed to create sled storage layer.");
 
     // Create payment gateway pointing at temp directory and mock daemon.
     let payment_gateway = PaymentGatewayBuilder::new(
@@ -101,3 +104,55 @@ async fn stop_payment_gateway() {
 
     assert!(payment_gateway.stop().await.is_ok());
 }
+
+#[tokio::test]
+async fn degraded_payment_gateway() {
+    // Setup.
+    init_logger();
+    let mock_daemon = MockDaemon::new_mock_daemon().await;
+
+    // Create payment gateway which gives up on the daemon after a single failure.
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .rpc_policy(
+        RpcPolicy::default()
+            .max_retries(0)
+            .failure_threshold(1)
+            .cooldown(Duration::from_millis(500)),
+    )
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    // Make the daemon unavailable.
+    mock_daemon.mock_daemon_height_unavailable();
+    let mut status = payment_gateway.status().await;
+    for _ in 0..50 {
+        if matches!(status, PaymentGatewayStatus::Degraded) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        status = payment_gateway.status().await;
+    }
+    assert!(matches!(status, PaymentGatewayStatus::Degraded));
+
+    // Bring it back.
+    mock_daemon.mock_daemon_height(2_477_657);
+    for _ in 0..50 {
+        if matches!(status, PaymentGatewayStatus::Running) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        status = payment_gateway.status().await;
+    }
+    assert!(matches!(status, PaymentGatewayStatus::Running));
+}
@@ -0,0 +1,1077 @@
+use std::time::Duration;
+
+use acceptxmr::{
+    storage::stores::{InMemory, Sqlite, TableNames},
+    AcceptXmrError, InvoiceEvent, InvoiceEventKind, InvoiceId, MonerodRpcClient, NeverReuseFunded,
+    PaymentGateway, PaymentGatewayBuilder, SequencedEvent, SequentialAllocator, SubIndex,
+    Subscriber, SubscriptionFilter,
+};
+use monero::{
+    cryptonote::subaddress::{self, Index},
+    Transaction,
+};
+use testing_utils::{
+    init_logger, new_temp_dir, other_view_pair, view_pair, MockDaemon, SyntheticChain,
+    OTHER_PRIMARY_ADDRESS, OTHER_PRIVATE_VIEW_KEY, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY,
+};
+
+async fn setup(
+    chain: &SyntheticChain,
+    expiration_in: u64,
+) -> (
+    MockDaemon,
+    PaymentGateway<InMemory, MonerodRpcClient>,
+    InvoiceId,
+    Subscriber,
+) {
+    init_logger();
+    let mock_daemon = MockDaemon::new_synthetic_daemon(chain).await;
+
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .seed(1)
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    let invoice_id = payment_gateway
+        .new_invoice(1_000, 2, expiration_in, "test invoice".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+    subscriber
+        .recv_timeout(Duration::from_secs(120))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+
+    (mock_daemon, payment_gateway, invoice_id, subscriber)
+}
+
+fn payment(chain: &mut SyntheticChain, invoice_id: InvoiceId, amount: u64) -> Transaction {
+    chain
+        .new_transaction()
+        .pay(&view_pair(), Index::from(invoice_id.sub_index), amount)
+        .pay_random(5_000)
+        .build()
+}
+
+#[tokio::test]
+async fn payment_confirmed() {
+    let mut chain = SyntheticChain::new(3_000_000, 1);
+    chain.mine_empty_blocks(10);
+    let (mock_daemon, _payment_gateway, invoice_id, mut subscriber) = setup(&chain, 10).await;
+
+    // Pay the invoice in the txpool.
+    let tx = payment(&mut chain, invoice_id, 1_000);
+    chain.add_to_txpool(tx);
+    mock_daemon.mock_chain(&chain);
+    let update = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(update.confirmations(), Some(0));
+
+    // Mine it, and confirm it.
+    chain.mine_txpool();
+    chain.mine_empty_blocks(1);
+    mock_daemon.mock_chain(&chain);
+    let update = loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.current_height() == chain.height() {
+            break update;
+        }
+    };
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(update.confirmations(), Some(2));
+    assert!(update.is_confirmed());
+}
+
+/// Receive the next event.
+async fn next_event(subscriber: &mut Subscriber<InvoiceEvent>) -> InvoiceEvent {
+    subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice event")
+        .expect("subscription channel is closed")
+}
+
+#[tokio::test]
+async fn invoice_events() {
+    init_logger();
+    let mut chain = SyntheticChain::new(3_000_000, 7);
+    chain.mine_empty_blocks(10);
+    let mock_daemon = MockDaemon::new_synthetic_daemon(&chain).await;
+
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .seed(1)
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+    let mut subscriber = payment_gateway.subscribe_all_events();
+
+    let invoice_id = payment_gateway
+        .new_invoice(1_000, 2, 10, "test invoice".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    match next_event(&mut subscriber).await {
+        InvoiceEvent::Created { invoice } => assert_eq!(invoice.id(), invoice_id),
+        other => panic!("expected created event, got {other:?}"),
+    }
+
+    // Pay the invoice in the txpool.
+    let tx = payment(&mut chain, invoice_id, 1_000);
+    chain.add_to_txpool(tx);
+    mock_daemon.mock_chain(&chain);
+    match next_event(&mut subscriber).await {
+        InvoiceEvent::TransferDetected { amount, height, .. } => {
+            assert_eq!(amount, 1_000);
+            assert_eq!(height, None);
+        }
+        other => panic!("expected transfer detected event, got {other:?}"),
+    }
+    match next_event(&mut subscriber).await {
+        InvoiceEvent::Paid { invoice } => assert_eq!(invoice.amount_paid(), 1_000),
+        other => panic!("expected paid event, got {other:?}"),
+    }
+
+    // Mine it, and confirm it. The scanner may briefly see the transaction in
+    // neither the txpool nor a block, so skip transfer events.
+    chain.mine_txpool();
+    chain.mine_empty_blocks(1);
+    mock_daemon.mock_chain(&chain);
+    let invoice = loop {
+        match next_event(&mut subscriber).await {
+            InvoiceEvent::Confirmed { invoice } => break invoice,
+            InvoiceEvent::TransferConfirmed { .. }
+            | InvoiceEvent::TransferDetected { .. }
+            | InvoiceEvent::Reorged { .. }
+            | InvoiceEvent::Paid { .. } => {}
+            other => panic!("expected confirmed event, got {other:?}"),
+        }
+    };
+    assert!(invoice.is_confirmed());
+    assert_eq!(invoice.amount_paid(), 1_000);
+
+    payment_gateway
+        .remove_invoice(invoice_id)
+        .await
+        .expect("failed to remove invoice");
+    match next_event(&mut subscriber).await {
+        InvoiceEvent::Removed { invoice } => assert_eq!(invoice.id(), invoice_id),
+        other => panic!("expected removed event, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn filtered_subscription() {
+    let mut chain = SyntheticChain::new(3_000_000, 8);
+    chain.mine_empty_blocks(10);
+    let (mock_daemon, payment_gateway, invoice_id, _subscriber) = setup(&chain, 10).await;
+    let mut subscriber = payment_gateway.subscribe_filtered(
+        SubscriptionFilter::new()
+            .event_kinds([InvoiceEventKind::Paid])
+            .min_amount_paid(1_000),
+    );
+    let other_invoice_id = payment_gateway
+        .new_invoice(1_000, 2, 10, "other invoice".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+
+    // Underpay one invoice, and pay the other in full.
+    let underpayment = payment(&mut chain, invoice_id, 500);
+    let payment = payment(&mut chain, other_invoice_id, 1_000);
+    chain.mine_block(vec![underpayment, payment]);
+    mock_daemon.mock_chain(&chain);
+
+    let event = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice event")
+        .expect("subscription channel is closed");
+    assert_eq!(event.kind(), InvoiceEventKind::Paid);
+    assert_eq!(event.invoice().id(), other_invoice_id);
+    assert!(subscriber
+        .recv_timeout(Duration::from_secs(1))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn replayed_subscription() {
+    let mut chain = SyntheticChain::new(3_000_000, 9);
+    chain.mine_empty_blocks(10);
+    let (mock_daemon, payment_gateway, invoice_id, _subscriber) = setup(&chain, 10).await;
+
+    // The creation of the invoice was logged before subscribing.
+    let mut subscriber = payment_gateway
+        .subscribe_from(0)
+        .await
+        .expect("failed to read event log");
+    let created = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice event")
+        .expect("subscription channel is closed");
+    assert_eq!(created.sequence, 1);
+    assert_eq!(created.event.kind(), InvoiceEventKind::Created);
+    assert_eq!(created.event.invoice().id(), invoice_id);
+
+    // New events are delivered live, in order.
+    let tx = payment(&mut chain, invoice_id, 1_000);
+    chain.add_to_txpool(tx);
+    mock_daemon.mock_chain(&chain);
+    let mut live = Vec::new();
+    while live.last().map(|e: &SequencedEvent| e.event.kind()) != Some(InvoiceEventKind::Paid) {
+        let event = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice event")
+            .expect("subscription channel is closed");
+        assert!(event.sequence > live.last().map_or(created.sequence, |e| e.sequence));
+        live.push(event);
+    }
+    drop(subscriber);
+
+    // Resubscribing replays everything after the last processed event.
+    let mut subscriber = payment_gateway
+        .subscribe_from(created.sequence + 1)
+        .await
+        .expect("failed to read event log");
+    for expected in live {
+        let replayed = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice event")
+            .expect("subscription channel is closed");
+        assert_eq!(replayed, expected);
+    }
+
+    // Pruned events are not replayed.
+    payment_gateway
+        .prune_event_log(created.sequence + 1)
+        .await
+        .expect("failed to prune event log");
+    let mut subscriber = payment_gateway
+        .subscribe_from(0)
+        .await
+        .expect("failed to read event log");
+    let first = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice event")
+        .expect("subscription channel is closed");
+    assert_eq!(first.sequence, created.sequence + 1);
+
+    // Events logged below a height are pruned.
+    payment_gateway
+        .prune_event_log_below_height(chain.height() + 2)
+        .await
+        .expect("failed to prune event log");
+    let mut subscriber = payment_gateway
+        .subscribe_from(0)
+        .await
+        .expect("failed to read event log");
+    assert!(subscriber
+        .recv_timeout(Duration::from_secs(1))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn timelocked_payment_ignored() {
+    let mut chain = SyntheticChain::new(3_000_000, 2);
+    chain.mine_empty_blocks(10);
+    let (mock_daemon, _payment_gateway, invoice_id, mut subscriber) = setup(&chain, 10).await;
+
+    // Pay the invoice with a timelocked transaction and mine it.
+    let tx = chain
+        .new_transaction()
+        .pay(&view_pair(), Index::from(invoice_id.sub_index), 1_000)
+        .unlock_time(chain.height() + 100)
+        .build();
+    chain.mine_block(vec![tx]);
+    mock_daemon.mock_chain(&chain);
+
+    let update = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    assert_eq!(update.current_height(), chain.height());
+    assert_eq!(update.amount_paid(), 0);
+}
+
+#[tokio::test]
+async fn reorg_double_spend() {
+    let mut chain = SyntheticChain::new(3_000_000, 3);
+    chain.mine_empty_blocks(10);
+    let (mock_daemon, _payment_gateway, invoice_id, mut subscriber) = setup(&chain, 10).await;
+
+    // Pay the invoice and mine the payment.
+    let key_image = [7; 32];
+    let tx = chain
+        .new_transaction()
+        .pay(&view_pair(), Index::from(invoice_id.sub_index), 1_000)
+        .key_image(key_image)
+        .build();
+    chain.mine_block(vec![tx]);
+    mock_daemon.mock_chain(&chain);
+    let update = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(update.confirmations(), Some(1));
+
+    // Reorg the payment out in favour of a double spend paying someone else.
+    chain.pop_blocks(1);
+    let double_spend = chain
+        .new_transaction()
+        .pay_random(1_000)
+        .key_image(key_image)
+        .build();
+    chain.mine_block(vec![double_spend]);
+    chain.mine_empty_blocks(1);
+    mock_daemon.mock_chain(&chain);
+    let update = loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.amount_paid() == 0 {
+            break update;
+        }
+    };
+    assert_eq!(update.confirmations(), None);
+}
+
+#[tokio::test]
+async fn catch_up() {
+    let mut chain = SyntheticChain::new(3_000_000, 4);
+    chain.mine_empty_blocks(10);
+    let (mock_daemon, payment_gateway, invoice_id, mut subscriber) = setup(&chain, 100).await;
+
+    // Fall far behind, with a payment in one of the missed blocks.
+    chain.mine_empty_blocks(5);
+    let tx = payment(&mut chain, invoice_id, 1_000);
+    chain.mine_block(vec![tx]);
+    chain.mine_empty_blocks(34);
+    mock_daemon.mock_chain(&chain);
+
+    let update = loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.current_height() == chain.height() {
+            break update;
+        }
+    };
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(update.confirmations(), Some(35));
+    assert_eq!(payment_gateway.cache_height(), chain.height() - 1);
+}
+
+#[tokio::test]
+async fn multiple_accounts() {
+    init_logger();
+    let mut chain = SyntheticChain::new(3_000_000, 5);
+    chain.mine_empty_blocks(10);
+    let mock_daemon = MockDaemon::new_synthetic_daemon(&chain).await;
+
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .account_indices([2, 7])
+    .seed(1)
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    assert_eq!(payment_gateway.account_indices(), [2, 7]);
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    // Invoices are allocated from the default account unless one is chosen.
+    let shop_invoice_id = payment_gateway
+        .new_invoice(1_000, 1, 10, "shop".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(shop_invoice_id.sub_index.major, 2);
+    let donation_invoice_id = payment_gateway
+        .new_invoice_for_account(7, 2_000, 1, 10, "donation".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(donation_invoice_id.sub_index.major, 7);
+    assert!(matches!(
+        payment_gateway
+            .new_invoice_for_account(3, 2_000, 1, 10, "unknown".to_string())
+            .await,
+        Err(AcceptXmrError::UnknownAccount(3))
+    ));
+
+    // Pay both invoices in one transaction, and confirm it.
+    let mut shop_subscriber = payment_gateway
+        .subscribe(shop_invoice_id)
+        .expect("invoice does not exist");
+    let mut donation_subscriber = payment_gateway
+        .subscribe(donation_invoice_id)
+        .expect("invoice does not exist");
+    let tx = chain
+        .new_transaction()
+        .pay(&view_pair(), Index::from(shop_invoice_id.sub_index), 1_000)
+        .pay(
+            &view_pair(),
+            Index::from(donation_invoice_id.sub_index),
+            2_000,
+        )
+        .build();
+    chain.mine_block(vec![tx]);
+    mock_daemon.mock_chain(&chain);
+
+    for subscriber in [&mut shop_subscriber, &mut donation_subscriber] {
+        let update = loop {
+            let update = subscriber
+                .recv_timeout(Duration::from_secs(5))
+                .await
+                .expect("timeout waiting for invoice update")
+                .expect("subscription channel is closed");
+            if update.is_confirmed() {
+                break update;
+            }
+        };
+        assert_eq!(update.amount_paid(), update.amount_requested());
+    }
+    let donation = payment_gateway
+        .get_invoice(donation_invoice_id)
+        .await
+        .expect("failed to get invoice")
+        .expect("invoice does not exist");
+    assert_eq!(donation.account_index(), 7);
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn multiple_wallets() {
+    init_logger();
+    let mut chain = SyntheticChain::new(3_000_000, 5);
+    chain.mine_empty_blocks(10);
+    let mock_daemon = MockDaemon::new_synthetic_daemon(&chain).await;
+
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .seed(1)
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    // Wallets can be added while the payment gateway is running.
+    assert!(payment_gateway
+        .add_wallet(OTHER_PRIMARY_ADDRESS, OTHER_PRIVATE_VIEW_KEY)
+        .expect("failed to add wallet"));
+    assert!(!payment_gateway
+        .add_wallet(OTHER_PRIMARY_ADDRESS, OTHER_PRIVATE_VIEW_KEY)
+        .expect("failed to add wallet"));
+    assert_eq!(
+        payment_gateway.wallets(),
+        [PRIMARY_ADDRESS, OTHER_PRIMARY_ADDRESS]
+    );
+
+    let invoice_id = payment_gateway
+        .new_invoice(1_000, 1, 10, "default wallet".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    let other_invoice_id = payment_gateway
+        .new_invoice_for_wallet(
+            OTHER_PRIMARY_ADDRESS,
+            0,
+            2_000,
+            1,
+            10,
+            "other wallet".to_string(),
+        )
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    let other_invoice = payment_gateway
+        .get_invoice(other_invoice_id)
+        .await
+        .expect("failed to get invoice")
+        .expect("invoice does not exist");
+    assert_eq!(other_invoice.wallet(), OTHER_PRIMARY_ADDRESS);
+    assert_eq!(
+        other_invoice.address(),
+        subaddress::get_subaddress(
+            &other_view_pair(),
+            Index::from(other_invoice_id.sub_index),
+            None
+        )
+        .to_string()
+    );
+    assert!(matches!(
+        payment_gateway
+            .new_invoice_for_wallet("unknown", 0, 1_000, 1, 10, "unknown".to_string())
+            .await,
+        Err(AcceptXmrError::UnknownWallet(_))
+    ));
+
+    // Pay both invoices in one transaction. A payment to the other wallet's
+    // subaddress with the default invoice's index is ignored.
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+    let mut other_subscriber = payment_gateway
+        .subscribe(other_invoice_id)
+        .expect("invoice does not exist");
+    let tx = chain
+        .new_transaction()
+        .pay(&view_pair(), Index::from(invoice_id.sub_index), 1_000)
+        .pay(
+            &other_view_pair(),
+            Index::from(other_invoice_id.sub_index),
+            2_000,
+        )
+        .pay(&other_view_pair(), Index::from(invoice_id.sub_index), 5_000)
+        .build();
+    chain.mine_block(vec![tx]);
+    mock_daemon.mock_chain(&chain);
+
+    for subscriber in [&mut subscriber, &mut other_subscriber] {
+        let update = loop {
+            let update = subscriber
+                .recv_timeout(Duration::from_secs(5))
+                .await
+                .expect("timeout waiting for invoice update")
+                .expect("subscription channel is closed");
+            if update.is_confirmed() {
+                break update;
+            }
+        };
+        assert_eq!(update.amount_paid(), update.amount_requested());
+    }
+
+    // Wallets can only be removed once they have no invoices.
+    assert!(matches!(
+        payment_gateway.remove_wallet(OTHER_PRIMARY_ADDRESS),
+        Err(AcceptXmrError::WalletInUse(_))
+    ));
+    payment_gateway
+        .remove_invoice(other_invoice_id)
+        .await
+        .expect("failed to remove invoice");
+    assert!(payment_gateway
+        .remove_wallet(OTHER_PRIMARY_ADDRESS)
+        .expect("failed to remove wallet"));
+    assert!(matches!(
+        payment_gateway.remove_wallet(PRIMARY_ADDRESS),
+        Err(AcceptXmrError::WalletInUse(_))
+    ));
+    assert_eq!(payment_gateway.wallets(), [PRIMARY_ADDRESS]);
+}
+
+#[tokio::test]
+async fn funded_subaddress_not_reused() {
+    init_logger();
+    let mut chain = SyntheticChain::new(3_000_000, 5);
+    chain.mine_empty_blocks(10);
+    let mock_daemon = MockDaemon::new_synthetic_daemon(&chain).await;
+    let db_dir = new_temp_dir();
+    std::fs::create_dir_all(&db_dir).expect("failed to create database directory");
+    let db_path = format!("{db_dir}/database");
+    let build_gateway = || async {
+        let store = Sqlite::new(&db_path, &TableNames::default()).expect("failed to open database");
+        PaymentGatewayBuilder::new(
+            PRIVATE_VIEW_KEY.to_string(),
+            PRIMARY_ADDRESS.to_string(),
+            store,
+        )
+        .scan_interval(Duration::from_millis(100))
+        .daemon_url(mock_daemon.url(""))
+        .account_index(1)
+        .subaddress_allocator(NeverReuseFunded::new(SequentialAllocator))
+        .build()
+        .await
+        .expect("failed to build payment gateway")
+    };
+
+    let payment_gateway = build_gateway().await;
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    // Subaddresses are allocated in order.
+    let invoice_id = payment_gateway
+        .new_invoice(1_000, 1, 10, "funded".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(invoice_id.sub_index, SubIndex::new(1, 0));
+    let unfunded_invoice_id = payment_gateway
+        .new_invoice(1_000, 1, 10, "unfunded".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(unfunded_invoice_id.sub_index, SubIndex::new(1, 1));
+
+    // Pay the first invoice.
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+    let tx = chain
+        .new_transaction()
+        .pay(&view_pair(), Index::from(invoice_id.sub_index), 1_000)
+        .build();
+    chain.mine_block(vec![tx]);
+    mock_daemon.mock_chain(&chain);
+    loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.is_confirmed() {
+            break;
+        }
+    }
+
+    // Only the unfunded subaddress is reused once both invoices are removed.
+    for id in [invoice_id, unfunded_invoice_id] {
+        payment_gateway
+            .remove_invoice(id)
+            .await
+            .expect("failed to remove invoice");
+    }
+    let invoice_id = payment_gateway
+        .new_invoice(1_000, 1, 10, "reused".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(invoice_id.sub_index, SubIndex::new(1, 1));
+
+    // The funded subaddress is remembered after a restart.
+    let restarted_gateway = build_gateway().await;
+    let invoice_id = restarted_gateway
+        .new_invoice(1_000, 1, 10, "after restart".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(invoice_id.sub_index, SubIndex::new(1, 2));
+}
+
+#[tokio::test]
+async fn wallets_registered_at_startup() {
+    init_logger();
+    let mut chain = SyntheticChain::new(3_000_000, 5);
+    chain.mine_empty_blocks(10);
+    let mock_daemon = MockDaemon::new_synthetic_daemon(&chain).await;
+    let db_dir = new_temp_dir();
+    std::fs::create_dir_all(&db_dir).expect("failed to create database directory");
+    let db_path = format!("{db_dir}/database");
+    let builder = || {
+        let store = Sqlite::new(&db_path, &TableNames::default()).expect("failed to open database");
+        PaymentGatewayBuilder::new(
+            PRIVATE_VIEW_KEY.to_string(),
+            PRIMARY_ADDRESS.to_string(),
+            store,
+        )
+        .scan_interval(Duration::from_millis(100))
+        .daemon_url(mock_daemon.url(""))
+        .subaddress_allocator(SequentialAllocator)
+    };
+
+    let payment_gateway = builder()
+        .build()
+        .await
+        .expect("failed to build payment gateway");
+    payment_gateway
+        .add_wallet(OTHER_PRIMARY_ADDRESS, OTHER_PRIVATE_VIEW_KEY)
+        .expect("failed to add wallet");
+    let other_invoice_id = payment_gateway
+        .new_invoice_for_wallet(
+            OTHER_PRIMARY_ADDRESS,
+            0,
+            1_000,
+            1,
+            10,
+            "other wallet".to_string(),
+        )
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(other_invoice_id.sub_index, SubIndex::new(0, 0));
+
+    // Added wallets aren't persisted, so the payment gateway refuses to start
+    // without the wallet its stored invoice belongs to.
+    assert!(matches!(
+        builder().build().await,
+        Err(AcceptXmrError::UnknownWallet(wallet)) if wallet == OTHER_PRIMARY_ADDRESS
+    ));
+
+    let restarted_gateway = builder()
+        .additional_wallet(
+            OTHER_PRIMARY_ADDRESS.to_string(),
+            OTHER_PRIVATE_VIEW_KEY.to_string(),
+        )
+        .build()
+        .await
+        .expect("failed to build payment gateway");
+    assert_eq!(
+        restarted_gateway.wallets(),
+        [PRIMARY_ADDRESS, OTHER_PRIMARY_ADDRESS]
+    );
+    assert_eq!(
+        restarted_gateway
+            .subaddress_report_for_wallet(OTHER_PRIMARY_ADDRESS)
+            .expect("wallet is not tracked")
+            .lookahead(),
+        (1, 1)
+    );
+
+    // The other wallet's index stays in use, so the default wallet skips it.
+    let invoice_id = restarted_gateway
+        .new_invoice(1_000, 1, 10, "default wallet".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(invoice_id.sub_index, SubIndex::new(0, 1));
+}
+
+#[tokio::test]
+async fn subaddress_gap_limit() {
+    init_logger();
+    let mut chain = SyntheticChain::new(3_000_000, 5);
+    chain.mine_empty_blocks(10);
+    let mock_daemon = MockDaemon::new_synthetic_daemon(&chain).await;
+
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .account_index(1)
+    .subaddress_allocator(SequentialAllocator)
+    .subaddress_gap_limit(2)
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    let mut invoice_ids = Vec::new();
+    for _ in 0..2 {
+        invoice_ids.push(
+            payment_gateway
+                .new_invoice(1_000, 0, 10, "within gap limit".to_string())
+                .await
+                .expect("failed to add new invoice to payment gateway for tracking"),
+        );
+    }
+    assert_eq!(invoice_ids[1].sub_index, SubIndex::new(1, 1));
+    assert!(matches!(
+        payment_gateway
+            .new_invoice(1_000, 0, 10, "past gap limit".to_string())
+            .await,
+        Err(AcceptXmrError::GapLimitReached(1))
+    ));
+
+    // Paying the second invoice moves the gap limit.
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_ids[1])
+        .expect("invoice does not exist");
+    let tx = chain
+        .new_transaction()
+        .pay(&view_pair(), Index::from(invoice_ids[1].sub_index), 1_000)
+        .build();
+    chain.mine_block(vec![tx]);
+    mock_daemon.mock_chain(&chain);
+    loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.is_confirmed() {
+            break;
+        }
+    }
+    for minor in 2..4 {
+        let invoice_id = payment_gateway
+            .new_invoice(1_000, 0, 10, "within gap limit".to_string())
+            .await
+            .expect("failed to add new invoice to payment gateway for tracking");
+        assert_eq!(invoice_id.sub_index, SubIndex::new(1, minor));
+    }
+    assert!(payment_gateway
+        .new_invoice(1_000, 0, 10, "past gap limit".to_string())
+        .await
+        .is_err());
+
+    assert_eq!(payment_gateway.subaddress_report().lookahead(), (2, 4));
+}
+
+#[tokio::test]
+async fn archive_invoice() {
+    let mut chain = SyntheticChain::new(3_000_000, 10);
+    chain.mine_empty_blocks(10);
+    let (mock_daemon, payment_gateway, invoice_id, _subscriber) = setup(&chain, 1).await;
+
+    // Let the invoice expire.
+    chain.mine_empty_blocks(2);
+    mock_daemon.mock_chain(&chain);
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+    let invoice = loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.is_expired() {
+            break update;
+        }
+    };
+
+    assert_eq!(
+        payment_gateway
+            .archive_invoice(invoice_id)
+            .await
+            .expect("failed to archive invoice"),
+        Some(invoice.clone())
+    );
+    assert!(payment_gateway
+        .get_invoice(invoice_id)
+        .await
+        .expect("failed to get invoice")
+        .is_none());
+    assert!(payment_gateway.subscribe(invoice_id).is_none());
+    assert_eq!(
+        payment_gateway
+            .get_archived_invoice(invoice_id)
+            .await
+            .expect("failed to get archived invoice"),
+        Some(invoice.clone())
+    );
+
+    // Archived invoices are kept until pruned.
+    payment_gateway
+        .prune_archive(invoice.current_height())
+        .await
+        .expect("failed to prune archive");
+    assert!(payment_gateway
+        .get_archived_invoice(invoice_id)
+        .await
+        .expect("failed to get archived invoice")
+        .is_some());
+    payment_gateway
+        .prune_archive(invoice.current_height() + 1)
+        .await
+        .expect("failed to prune archive");
+    assert!(payment_gateway
+        .get_archived_invoice(invoice_id)
+        .await
+        .expect("failed to get archived invoice")
+        .is_none());
+}
+
+#[tokio::test]
+async fn new_invoice_with_reference() {
+    let mut chain = SyntheticChain::new(3_000_000, 10);
+    chain.mine_empty_blocks(10);
+    let (_mock_daemon, payment_gateway, _invoice_id, _subscriber) = setup(&chain, 10).await;
+    let account_index = payment_gateway.account_indices()[0];
+
+    let invoice_id = payment_gateway
+        .new_invoice_with_reference(
+            "order 1".to_string(),
+            account_index,
+            1_000,
+            2,
+            10,
+            "referenced invoice".to_string(),
+        )
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    let invoice = payment_gateway
+        .get_invoice_by_reference("order 1")
+        .await
+        .expect("failed to get invoice by reference")
+        .expect("invoice does not exist");
+    assert_eq!(invoice.id(), invoice_id);
+    assert_eq!(invoice.reference(), Some("order 1"));
+
+    // Creating an invoice with the same reference returns the existing one.
+    let ids_before = payment_gateway
+        .get_invoice_ids()
+        .await
+        .expect("failed to get invoice IDs");
+    assert_eq!(
+        payment_gateway
+            .new_invoice_with_reference(
+                "order 1".to_string(),
+                account_index,
+                2_000,
+                2,
+                10,
+                "retried invoice".to_string(),
+            )
+            .await
+            .expect("failed to retry invoice creation"),
+        invoice_id
+    );
+    assert_eq!(
+        payment_gateway
+            .get_invoice_ids()
+            .await
+            .expect("failed to get invoice IDs"),
+        ids_before
+    );
+
+    assert!(payment_gateway
+        .get_invoice_by_reference("order 2")
+        .await
+        .expect("failed to get invoice by reference")
+        .is_none());
+
+    // Retrying still returns the existing invoice once it has been archived.
+    payment_gateway
+        .archive_invoice(invoice_id)
+        .await
+        .expect("failed to archive invoice");
+    assert_eq!(
+        payment_gateway
+            .get_archived_invoice_by_reference("order 1")
+            .await
+            .expect("failed to get archived invoice by reference")
+            .map(|invoice| invoice.id()),
+        Some(invoice_id)
+    );
+    assert_eq!(
+        payment_gateway
+            .new_invoice_with_reference(
+                "order 1".to_string(),
+                account_index,
+                2_000,
+                2,
+                10,
+                "retried invoice".to_string(),
+            )
+            .await
+            .expect("failed to retry invoice creation"),
+        invoice_id
+    );
+    assert!(payment_gateway
+        .get_invoice_by_reference("order 1")
+        .await
+        .expect("failed to get invoice by reference")
+        .is_none());
+}
+
+#[tokio::test]
+async fn new_invoice_for_wallet_with_reference() {
+    let mut chain = SyntheticChain::new(3_000_000, 10);
+    chain.mine_empty_blocks(10);
+    let (_mock_daemon, payment_gateway, _invoice_id, _subscriber) = setup(&chain, 10).await;
+    payment_gateway
+        .add_wallet(OTHER_PRIMARY_ADDRESS, OTHER_PRIVATE_VIEW_KEY)
+        .expect("failed to add wallet");
+
+    let invoice_id = payment_gateway
+        .new_invoice_for_wallet_with_reference(
+            "order 1".to_string(),
+            OTHER_PRIMARY_ADDRESS,
+            0,
+            1_000,
+            2,
+            10,
+            "referenced invoice".to_string(),
+        )
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    let invoice = payment_gateway
+        .get_invoice_by_reference("order 1")
+        .await
+        .expect("failed to get invoice by reference")
+        .expect("invoice does not exist");
+    assert_eq!(invoice.id(), invoice_id);
+    assert_eq!(invoice.wallet(), OTHER_PRIMARY_ADDRESS);
+    assert_eq!(
+        invoice.address(),
+        subaddress::get_subaddress(&other_view_pair(), Index::from(invoice_id.sub_index), None)
+            .to_string()
+    );
+
+    // References are unique across wallets.
+    assert_eq!(
+        payment_gateway
+            .new_invoice_with_reference(
+                "order 1".to_string(),
+                0,
+                2_000,
+                2,
+                10,
+                "retried invoice".to_string(),
+            )
+            .await
+            .expect("failed to retry invoice creation"),
+        invoice_id
+    );
+
+    assert!(matches!(
+        payment_gateway
+            .new_invoice_for_wallet_with_reference(
+                "order 2".to_string(),
+                "unknown",
+                0,
+                1_000,
+                2,
+                10,
+                "unknown".to_string(),
+            )
+            .await,
+        Err(AcceptXmrError::UnknownWallet(_))
+    ));
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use acceptxmr::{storage::stores::InMemory, AcceptXmrError, PaymentGatewayBuilder, SubIndex};
+use testing_utils::{
+    init_logger, MockDaemon, MockTransfer, MockWalletRpc, PRIMARY_ADDRESS, PRIVATE_VIEW_KEY,
+};
+
+#[tokio::test]
+async fn track_payment() {
+    // Setup.
+    init_logger();
+    let mock_daemon = MockDaemon::new_mock_daemon().await;
+    let mock_wallet = MockWalletRpc::new(2_477_657).await;
+    mock_wallet.mock_create_address(7);
+
+    let payment_gateway = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .scan_interval(Duration::from_millis(100))
+    .daemon_url(mock_daemon.url(""))
+    .wallet_rpc_url(mock_wallet.url(""))
+    .build()
+    .await
+    .expect("failed to build payment gateway");
+    payment_gateway
+        .run()
+        .await
+        .expect("failed to run payment gateway");
+
+    // The subaddress is created by the wallet.
+    let invoice_id = payment_gateway
+        .new_invoice(1_000, 1, 10, "test invoice".to_string())
+        .await
+        .expect("failed to add new invoice to payment gateway for tracking");
+    assert_eq!(invoice_id.sub_index, SubIndex::new(0, 7));
+    assert_eq!(invoice_id.creation_height, 2_477_657);
+    let mut subscriber = payment_gateway
+        .subscribe(invoice_id)
+        .expect("invoice does not exist");
+    let update = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    assert_eq!(update.current_height(), 2_477_657);
+    assert_eq!(update.amount_paid(), 0);
+
+    // Pay the invoice in the txpool. A time locked transfer, and transfers to
+    // other subaddresses or from before the invoice was created are ignored.
+    let payment = MockTransfer::new(invoice_id.sub_index, 1_000, None);
+    let time_locked = MockTransfer {
+        unlock_time: 2_500_000,
+        ..MockTransfer::new(invoice_id.sub_index, 500, None)
+    };
+    mock_wallet.mock_transfers(&[
+        payment,
+        time_locked,
+        MockTransfer::new(SubIndex::new(0, 8), 2_000, None),
+        MockTransfer::new(invoice_id.sub_index, 3_000, Some(2_477_656)),
+    ]);
+    let update = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(update.confirmations(), Some(0));
+
+    // Confirm the payment.
+    mock_wallet.mock_transfers(&[MockTransfer::new(
+        invoice_id.sub_index,
+        1_000,
+        Some(2_477_657),
+    )]);
+    mock_wallet.mock_height(2_477_658);
+    let update = loop {
+        let update = subscriber
+            .recv_timeout(Duration::from_secs(5))
+            .await
+            .expect("timeout waiting for invoice update")
+            .expect("subscription channel is closed");
+        if update.current_height() == 2_477_658 {
+            break update;
+        }
+    };
+    assert_eq!(update.amount_paid(), 1_000);
+    assert_eq!(update.confirmations(), Some(1));
+    assert!(update.is_confirmed());
+
+    // Reorg the payment away.
+    mock_wallet.mock_transfers(&[]);
+    let update = subscriber
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("timeout waiting for invoice update")
+        .expect("subscription channel is closed");
+    assert_eq!(update.amount_paid(), 0);
+    assert_eq!(update.confirmations(), None);
+}
+
+#[tokio::test]
+async fn wallet_mismatch() {
+    // Setup.
+    init_logger();
+    let mock_daemon = MockDaemon::new_mock_daemon().await;
+    let mock_wallet = MockWalletRpc::new(2_477_657).await;
+    let other_address = "44AFFq5kSiGBoZ4NMDwYtN18obc8AemS33DBLWs3H7otXft3XjrpDtQGv7SqSsaBYBb98uNbr2VBBEt7f2wfn3RVGQBEP3A";
+    mock_wallet.mock_address(other_address);
+
+    let result = PaymentGatewayBuilder::new(
+        PRIVATE_VIEW_KEY.to_string(),
+        PRIMARY_ADDRESS.to_string(),
+        InMemory::new(),
+    )
+    .daemon_url(mock_daemon.url(""))
+    .wallet_rpc_url(mock_wallet.url(""))
+    .build()
+    .await;
+
+    match result {
+        Err(AcceptXmrError::WalletMismatch { wallet, expected }) => {
+            assert_eq!(wallet, other_address);
+            assert_eq!(expected, PRIMARY_ADDRESS);
+        }
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("payment gateway was built against the wrong wallet"),
+    }
+}
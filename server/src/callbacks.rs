@@ -215,7 +215,7 @@ pub(crate) enum CallbackCommand {
     #[allow(unused)]
     Shutdown,
     Call {
-        invoice: Invoice,
+        invoice: Box<Invoice>,
         retry_count: usize,
         delay: Duration,
     },
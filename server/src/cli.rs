@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::Config;
+
+/// Command line arguments of AcceptXMR-Server.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Cli {
+    /// Path to the config file.
+    pub(crate) config_path: PathBuf,
+    /// What to do once the config has been loaded.
+    pub(crate) command: CliCommand,
+}
+
+/// Subcommand given on the command line.
+#[derive(Debug, PartialEq)]
+pub(crate) enum CliCommand {
+    /// Run the payment gateway and serve its APIs. This is the default.
+    Serve,
+    /// Write a dump of the configured database to a file.
+    Export(PathBuf),
+    /// Restore a dump from a file into the configured database, which must be
+    /// empty.
+    Import(PathBuf),
+}
+
+impl Cli {
+    /// Parse command line arguments, reading the config file path from the
+    /// `CONFIG_FILE` environment variable if it is not given.
+    ///
+    /// Exits with a usage message if the arguments are invalid.
+    #[must_use]
+    pub(crate) fn parse() -> Self {
+        Self::from_matches(&Self::command().get_matches())
+    }
+
+    fn command() -> Command {
+        Command::new("AcceptXMR-Server")
+            .arg(
+                Arg::new("config-file")
+                    .short('f')
+                    .long("config-file")
+                    .action(ArgAction::Set)
+                    .value_name("FILE")
+                    .env("CONFIG_FILE")
+                    .default_value(Config::DEFAULT_PATH)
+                    .global(true)
+                    .help("Specifies the config file to use. Defaults to ./acceptxmr.yaml"),
+            )
+            .subcommand(
+                Command::new("export")
+                    .about("Writes a dump of the configured database to FILE, for backup or migration to another database")
+                    .arg(Arg::new("file").value_name("FILE").required(true)),
+            )
+            .subcommand(
+                Command::new("import")
+                    .about("Restores a dump from FILE into the configured database, which must be empty")
+                    .arg(Arg::new("file").value_name("FILE").required(true)),
+            )
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Self {
+        // These `unwrap`s are safe because args with a default or which are
+        // required never return `None`.
+        let config_path = PathBuf::from(matches.get_one::<String>("config-file").unwrap());
+        let command = match matches.subcommand() {
+            Some(("export", args)) => {
+                CliCommand::Export(PathBuf::from(args.get_one::<String>("file").unwrap()))
+            }
+            Some(("import", args)) => {
+                CliCommand::Import(PathBuf::from(args.get_one::<String>("file").unwrap()))
+            }
+            _ => CliCommand::Serve,
+        };
+
+        Cli {
+            config_path,
+            command,
+        }
+    }
+
+    #[cfg(test)]
+    fn parse_from<I: IntoIterator<Item = T>, T: Into<std::ffi::OsString> + Clone>(args: I) -> Self {
+        Self::from_matches(&Self::command().get_matches_from(args))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{Cli, CliCommand};
+
+    #[test]
+    fn serve_by_default() {
+        let cli = Cli::parse_from(["acceptxmr-server"]);
+        assert_eq!(cli.command, CliCommand::Serve);
+    }
+
+    #[test]
+    fn export() {
+        let cli = Cli::parse_from([
+            "acceptxmr-server",
+            "-f",
+            "config.yaml",
+            "export",
+            "dump.jsonl",
+        ]);
+        assert_eq!(cli.command, CliCommand::Export(PathBuf::from("dump.jsonl")));
+        assert_eq!(cli.config_path, PathBuf::from("config.yaml"));
+    }
+
+    #[test]
+    fn import() {
+        let cli = Cli::parse_from([
+            "acceptxmr-server",
+            "import",
+            "dump.jsonl",
+            "--config-file",
+            "config.yaml",
+        ]);
+        assert_eq!(cli.command, CliCommand::Import(PathBuf::from("dump.jsonl")));
+        assert_eq!(cli.config_path, PathBuf::from("config.yaml"));
+    }
+}
@@ -1,23 +1,270 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    env::{self, VarError},
+    path::PathBuf,
+    str::FromStr,
+};
 
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 
+use super::ConfigError;
+
 /// Default invoice storage database directory.
 const DEFAULT_DB_DIR: &str = "AcceptXMR_DB/";
+/// Default number of days archived invoices are kept for.
+const DEFAULT_ARCHIVE_RETENTION_DAYS: u64 = 30;
+const DEFAULT_EVENT_RETENTION_DAYS: u64 = 30;
 
-#[derive(Clone, Deserialize, PartialEq, Eq, Debug, Serialize)]
+#[derive(Clone, Deserialize, Debug, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct DatabaseConfig {
+    /// Storage backend to use. Defaults to `SQLite`.
+    #[serde(default)]
+    pub backend: DatabaseBackend,
+    /// Directory of the `SQLite` database.
     pub path: PathBuf,
-    /// Automatically delete expired invoices that aren't pending confirmation.
-    pub delete_expired: bool,
+    /// `PostgreSQL` connection string, used by the `postgres` backend. For
+    /// best security, this should be set via the `POSTGRES_URL` environment
+    /// variable.
+    #[serde(default, skip_serializing)]
+    pub postgres_url: Option<Secret<String>>,
+    /// Number of days to keep archived invoices for, after which they are
+    /// deleted. Expired invoices that aren't pending confirmation are archived
+    /// automatically. If `None`, archived invoices are kept forever.
+    #[serde(default = "default_archive_retention_days")]
+    pub archive_retention_days: Option<u64>,
+    /// Number of days to keep logged invoice events for, after which they are
+    /// deleted. If `None`, events are kept forever.
+    #[serde(default = "default_event_retention_days")]
+    pub event_retention_days: Option<u64>,
+    /// Removed, and replaced by `archive-retention-days`. It is still read so
+    /// that configs setting it are rejected with an explanation, rather than
+    /// having expired invoices silently archived instead of deleted (or kept).
+    #[serde(default, skip_serializing)]
+    pub delete_expired: Option<bool>,
+    /// Number of blocks to keep output keys for. Output keys are used to
+    /// detect the burning bug, so payments reusing the key of an older output
+    /// will be credited again. If `None`, output keys are kept forever.
+    #[serde(default)]
+    pub output_key_retention_depth: Option<u64>,
+    /// Encrypt invoice addresses, descriptions and references before storing
+    /// them. Defaults to `false`.
+    #[serde(default)]
+    pub encrypt: bool,
+    /// Secret the storage encryption key is derived from. This should be set
+    /// via the `STORAGE_ENCRYPTION_KEY` environment variable.
+    #[serde(default, skip_serializing)]
+    pub encryption_key: Option<Secret<String>>,
+    /// Secrets of keys previously used for storage encryption, which are still
+    /// needed to read values encrypted with them. These should be set via the
+    /// `STORAGE_ENCRYPTION_OLD_KEYS` environment variable, separated by commas.
+    #[serde(default, skip_serializing)]
+    pub old_encryption_keys: Vec<Secret<String>>,
+}
+
+impl DatabaseConfig {
+    pub(super) fn apply_env_overrides(mut self) -> Result<Self, ConfigError> {
+        match env::var("POSTGRES_URL") {
+            Ok(url) => {
+                self.postgres_url = Some(Secret::new(url));
+            }
+            Err(VarError::NotPresent) => {}
+            Err(e) => return Err(e)?,
+        }
+        match env::var("STORAGE_ENCRYPTION_KEY") {
+            Ok(key) => {
+                self.encryption_key = Some(Secret::new(key));
+            }
+            Err(VarError::NotPresent) => {}
+            Err(e) => return Err(e)?,
+        }
+        match env::var("STORAGE_ENCRYPTION_OLD_KEYS") {
+            Ok(keys) => {
+                self.old_encryption_keys = keys
+                    .split(',')
+                    .filter(|key| !key.is_empty())
+                    .map(|key| Secret::new(key.to_string()))
+                    .collect();
+            }
+            Err(VarError::NotPresent) => {}
+            Err(e) => return Err(e)?,
+        }
+        Ok(self)
+    }
+
+    pub(super) fn validate(&self) {
+        if let Some(delete_expired) = self.delete_expired {
+            let replacement = if delete_expired { "0" } else { "null" };
+            panic!(
+                "the `delete-expired` database option has been removed. Expired invoices are now archived, and deleted after `archive-retention-days` (30 by default). Replace `delete-expired: {delete_expired}` with `archive-retention-days: {replacement}` to keep the old behavior."
+            );
+        }
+        if self.backend == DatabaseBackend::Postgres {
+            assert!(
+                self.postgres_url.is_some(),
+                "the postgres database backend is configured, but a connection string was not set. For best security, set it using the POSTGRES_URL environment variable."
+            );
+        }
+        if self.encrypt {
+            assert!(
+                self.encryption_key.is_some(),
+                "database encryption is enabled, but a key was not set. For best security, set it using the STORAGE_ENCRYPTION_KEY environment variable."
+            );
+        }
+    }
 }
 
 impl Default for DatabaseConfig {
     fn default() -> Self {
         Self {
+            backend: DatabaseBackend::default(),
             path: PathBuf::from_str(DEFAULT_DB_DIR).unwrap(),
-            delete_expired: true,
+            postgres_url: None,
+            archive_retention_days: default_archive_retention_days(),
+            event_retention_days: default_event_retention_days(),
+            delete_expired: None,
+            output_key_retention_depth: None,
+            encrypt: false,
+            encryption_key: None,
+            old_encryption_keys: Vec::new(),
         }
     }
 }
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_archive_retention_days() -> Option<u64> {
+    Some(DEFAULT_ARCHIVE_RETENTION_DAYS)
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_event_retention_days() -> Option<u64> {
+    Some(DEFAULT_EVENT_RETENTION_DAYS)
+}
+
+impl PartialEq for DatabaseConfig {
+    fn eq(&self, other: &Self) -> bool {
+        let urls_match = match (self.postgres_url.as_ref(), other.postgres_url.as_ref()) {
+            (Some(url), Some(other_url)) => url.expose_secret() == other_url.expose_secret(),
+            (None, None) => true,
+            _ => false,
+        };
+        let keys_match = match (self.encryption_key.as_ref(), other.encryption_key.as_ref()) {
+            (Some(key), Some(other_key)) => key.expose_secret() == other_key.expose_secret(),
+            (None, None) => true,
+            _ => false,
+        };
+        let old_keys_match = self.old_encryption_keys.len() == other.old_encryption_keys.len()
+            && self
+                .old_encryption_keys
+                .iter()
+                .zip(&other.old_encryption_keys)
+                .all(|(key, other_key)| key.expose_secret() == other_key.expose_secret());
+
+        self.backend == other.backend
+            && self.path == other.path
+            && urls_match
+            && self.archive_retention_days == other.archive_retention_days
+            && self.event_retention_days == other.event_retention_days
+            && self.delete_expired == other.delete_expired
+            && self.output_key_retention_depth == other.output_key_retention_depth
+            && self.encrypt == other.encrypt
+            && keys_match
+            && old_keys_match
+    }
+}
+
+/// Storage backend of AcceptXMR-Server.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DatabaseBackend {
+    /// An `SQLite` database in the configured directory.
+    #[default]
+    Sqlite,
+    /// A `PostgreSQL` database at the configured connection string.
+    Postgres,
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic::catch_unwind;
+
+    use secrecy::Secret;
+
+    use super::{DatabaseBackend, DatabaseConfig};
+
+    #[test]
+    fn postgres_requires_url() {
+        let mut config = DatabaseConfig {
+            backend: DatabaseBackend::Postgres,
+            ..Default::default()
+        };
+        catch_unwind(|| config.validate())
+            .expect_err("postgres config without a connection string should be invalid");
+
+        config.postgres_url = Some(Secret::new("host=localhost user=postgres".to_string()));
+        config.validate();
+    }
+
+    #[test]
+    fn encryption_requires_key() {
+        let mut config = DatabaseConfig {
+            encrypt: true,
+            ..Default::default()
+        };
+        catch_unwind(|| config.validate())
+            .expect_err("encrypted database config without a key should be invalid");
+
+        config.encryption_key = Some(Secret::new("supersecretkey".to_string()));
+        config.validate();
+    }
+
+    #[test]
+    fn delete_expired_is_rejected() {
+        for delete_expired in [true, false] {
+            let config: DatabaseConfig = serde_yaml::from_str(&format!(
+                "path: AcceptXMR_DB/\ndelete-expired: {delete_expired}"
+            ))
+            .unwrap();
+            catch_unwind(|| config.validate())
+                .expect_err("config setting the removed delete-expired option should be invalid");
+        }
+    }
+
+    #[test]
+    fn backend_from_yaml() {
+        let config: DatabaseConfig =
+            serde_yaml::from_str("backend: postgres\npath: AcceptXMR_DB/").unwrap();
+        assert_eq!(config.backend, DatabaseBackend::Postgres);
+
+        let config: DatabaseConfig = serde_yaml::from_str("path: AcceptXMR_DB/").unwrap();
+        assert_eq!(config.backend, DatabaseBackend::Sqlite);
+    }
+
+    #[test]
+    fn archive_retention_from_yaml() {
+        let config: DatabaseConfig = serde_yaml::from_str("path: AcceptXMR_DB/").unwrap();
+        assert_eq!(config.archive_retention_days, Some(30));
+
+        let config: DatabaseConfig =
+            serde_yaml::from_str("path: AcceptXMR_DB/\narchive-retention-days: 7").unwrap();
+        assert_eq!(config.archive_retention_days, Some(7));
+
+        let config: DatabaseConfig =
+            serde_yaml::from_str("path: AcceptXMR_DB/\narchive-retention-days: null").unwrap();
+        assert_eq!(config.archive_retention_days, None);
+    }
+
+    #[test]
+    fn event_retention_from_yaml() {
+        let config: DatabaseConfig = serde_yaml::from_str("path: AcceptXMR_DB/").unwrap();
+        assert_eq!(config.event_retention_days, Some(30));
+
+        let config: DatabaseConfig =
+            serde_yaml::from_str("path: AcceptXMR_DB/\nevent-retention-days: 7").unwrap();
+        assert_eq!(config.event_retention_days, Some(7));
+
+        let config: DatabaseConfig =
+            serde_yaml::from_str("path: AcceptXMR_DB/\nevent-retention-days: null").unwrap();
+        assert_eq!(config.event_retention_days, None);
+    }
+}
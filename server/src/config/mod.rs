@@ -14,9 +14,8 @@ use std::{
 };
 
 pub(crate) use callback::CallbackConfig;
-use clap::{Arg, ArgAction, Command};
 pub(crate) use daemon::DaemonConfig;
-pub(crate) use database::DatabaseConfig;
+pub(crate) use database::{DatabaseBackend, DatabaseConfig};
 use dotenv::dotenv;
 use log::info;
 pub(crate) use logging::LoggingConfig;
@@ -27,6 +26,8 @@ pub(crate) use server::{ServerConfig, TlsConfig};
 use thiserror::Error;
 pub(crate) use wallet::WalletConfig;
 
+use crate::cli::Cli;
+
 /// AcceptXMR-Server configuration.
 #[derive(Deserialize, PartialEq, Debug, Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -56,21 +57,7 @@ impl Config {
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
     pub fn get_path() -> PathBuf {
-        let cli_matches = Command::new("AcceptXMR-Server")
-            .arg(
-                Arg::new("config-file")
-                    .short('f')
-                    .long("config-file")
-                    .action(ArgAction::Set)
-                    .value_name("FILE")
-                    .env("CONFIG_FILE")
-                    .default_value(Self::DEFAULT_PATH)
-                    .help("Specifies the config file to use. Defaults to ./acceptxmr.yaml"),
-            )
-            .get_matches();
-
-        // This `unwrap` is safe because args with a default never return `None`.
-        PathBuf::from(cli_matches.get_one::<String>("config-file").unwrap())
+        Cli::parse().config_path
     }
 
     /// Creates config from file. If the file is not found, creates it
@@ -100,6 +87,7 @@ impl Config {
 
         self.wallet = self.wallet.apply_env_overrides()?;
         self.daemon = self.daemon.apply_env_overrides()?;
+        self.database = self.database.apply_env_overrides()?;
 
         match env::var("INTERNAL_API_TOKEN") {
             Ok(token) => {
@@ -124,6 +112,7 @@ impl Config {
     pub fn validate(&self) {
         self.wallet.validate();
         self.daemon.validate();
+        self.database.validate();
         self.internal_api.validate();
         self.external_api.validate();
     }
@@ -183,7 +172,9 @@ mod test {
     use secrecy::Secret;
 
     use super::{Config, DaemonConfig, LoggingConfig, ServerConfig, TlsConfig, WalletConfig};
-    use crate::config::{daemon::DaemonLoginConfig, CallbackConfig, DatabaseConfig};
+    use crate::config::{
+        daemon::DaemonLoginConfig, CallbackConfig, DatabaseBackend, DatabaseConfig,
+    };
 
     #[test]
     fn default() {
@@ -217,6 +208,7 @@ mod test {
                 primary_address: None,
                 private_viewkey: None,
                 account_index: 0,
+                additional_account_indices: Vec::new(),
                 restore_height: None,
             },
             daemon: DaemonConfig {
@@ -226,8 +218,16 @@ mod test {
                 connection_timeout: Duration::from_secs(20),
             },
             database: DatabaseConfig {
+                backend: DatabaseBackend::Sqlite,
                 path: PathBuf::from_str("AcceptXMR_DB/").unwrap(),
-                delete_expired: true,
+                postgres_url: None,
+                archive_retention_days: Some(30),
+                event_retention_days: Some(30),
+                delete_expired: None,
+                output_key_retention_depth: None,
+                encrypt: false,
+                encryption_key: None,
+                old_encryption_keys: Vec::new(),
             },
             logging: LoggingConfig {
                 verbosity: LevelFilter::Info,
@@ -295,6 +295,7 @@ mod test {
                 primary_address: Some(Address::from_str("4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf").unwrap()),
                 private_viewkey: Some(Secret::new(PrivateKey::from_str("ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03").unwrap().to_string())),
                 account_index: 0,
+                additional_account_indices: Vec::new(),
                 restore_height: Some(2_947_000),
             },
             daemon: DaemonConfig {
@@ -307,8 +308,16 @@ mod test {
                 connection_timeout: Duration::from_secs(10),
             },
             database: DatabaseConfig {
+                backend: DatabaseBackend::Sqlite,
                 path: PathBuf::from_str("server/tests/AcceptXMR_DB/").unwrap(),
-                delete_expired: true,
+                postgres_url: None,
+                archive_retention_days: Some(30),
+                event_retention_days: Some(30),
+                delete_expired: None,
+                output_key_retention_depth: None,
+                encrypt: false,
+                encryption_key: None,
+                old_encryption_keys: Vec::new(),
             },
             logging: LoggingConfig {
                 verbosity: LevelFilter::Debug,
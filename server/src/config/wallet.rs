@@ -21,6 +21,10 @@ pub struct WalletConfig {
     /// The account index to be used. Defaults to 0.
     #[serde(default)]
     pub account_index: u32,
+    /// Further account indices invoices may be allocated from. The account
+    /// index above is used when an invoice doesn't specify one.
+    #[serde(default)]
+    pub additional_account_indices: Vec<u32>,
     /// The restore height of the wallet. Defaults to the current blockchain
     /// tip.
     #[serde(default)]
@@ -67,7 +71,8 @@ impl PartialEq for WalletConfig {
             (None, None) => true,
             _ => false,
         };
-        let accounts_match = self.account_index == other.account_index;
+        let accounts_match = self.account_index == other.account_index
+            && self.additional_account_indices == other.additional_account_indices;
         let restore_heights_match = self.restore_height == other.restore_height;
 
         addresses_match && viewkeys_match && accounts_match && restore_heights_match
@@ -119,6 +124,7 @@ mod test {
                 primary_address: address.map(|addr| Address::from_str(addr).unwrap()),
                 private_viewkey: viewkey.map(|key| Secret::new(key.to_string())),
                 account_index: 123,
+                additional_account_indices: vec![1, 2],
                 restore_height: Some(12345),
             };
             config.validate();
@@ -148,6 +154,7 @@ mod test {
             primary_address: address1.map(|addr| Address::from_str(addr).unwrap()),
             private_viewkey: viewkey1.map(|key| Secret::new(key.to_string())),
             account_index: account_index1,
+            additional_account_indices: Vec::new(),
             restore_height: restore_height1,
         };
 
@@ -155,6 +162,7 @@ mod test {
             primary_address: address2.map(|addr| Address::from_str(addr).unwrap()),
             private_viewkey: viewkey2.map(|key| Secret::new(key.to_string())),
             account_index: account_index2,
+            additional_account_indices: Vec::new(),
             restore_height: restore_height2,
         };
 
@@ -6,20 +6,37 @@
 //! intended to be used on its own.
 
 mod callbacks;
+mod cli;
 mod config;
 pub mod logging;
 mod server;
 
-use std::{io::Error as IoError, net::SocketAddr, path::PathBuf, time::Duration};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Error as IoError, Write},
+    iter,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use acceptxmr::{storage::stores::Sqlite, PaymentGateway, PaymentGatewayBuilder};
-use log::{debug, error, info};
+use acceptxmr::{
+    storage::{
+        dump::{self, DumpError},
+        stores::{Encrypted, EncryptionKey, Postgres, Sqlite, TableNames},
+        InvoiceStorage, Storage,
+    },
+    PaymentGateway, PaymentGatewayBuilder,
+};
+use log::{debug, error, info, warn};
 use secrecy::ExposeSecret;
 use server::Server;
 use tokio::{join, try_join};
 
 use crate::{
     callbacks::{CallbackClient, CallbackCommand, CallbackQueue},
+    cli::{Cli, CliCommand},
+    config::{DatabaseBackend, DatabaseConfig},
     logging::{init_logger, set_verbosity},
     server::{
         api::{external, internal},
@@ -28,20 +45,118 @@ use crate::{
 };
 pub use crate::{config::Config, server::api};
 
-/// Start a standalone payment gateway.
+/// Monero blocks are mined every two minutes on average.
+const BLOCKS_PER_DAY: u64 = 720;
+/// How often archived invoices and logged events past their retention period
+/// are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Start a standalone payment gateway, or export or import its database if
+/// instructed to on the command line.
 pub async fn entrypoint() {
     init_logger();
 
-    let config_path = Config::get_path();
-    let config = load_config(&config_path);
+    let cli = Cli::parse();
+    let config = load_config(&cli.config_path);
     set_verbosity(config.logging);
 
-    let payment_gateway = build_gateway(&config).await;
+    match cli.command {
+        CliCommand::Serve => serve(&config).await,
+        CliCommand::Export(path) => export_database(&config, &path),
+        CliCommand::Import(path) => import_database(&config, &path),
+    }
+}
+
+/// Open the configured database, and run the payment gateway and its APIs.
+async fn serve(config: &Config) {
+    match config.database.backend {
+        DatabaseBackend::Sqlite => serve_store(config, open_sqlite(&config.database)).await,
+        DatabaseBackend::Postgres => serve_store(config, open_postgres(&config.database)).await,
+    }
+}
+
+/// Run the payment gateway and its APIs using the provided store, encrypting it
+/// if configured to.
+async fn serve_store<S: Storage + 'static>(config: &Config, store: S) {
+    if config.database.encrypt {
+        let store = encrypt_store(&config.database, store);
+        run(build_gateway(config, store).await, config).await;
+    } else {
+        run(build_gateway(config, store).await, config).await;
+    }
+}
+
+/// Write a dump of the configured database to the file at `path`.
+///
+/// # Panics
+///
+/// Panics if the database could not be opened or read, or if the file could
+/// not be written.
+fn export_database(config: &Config, path: &Path) {
+    let file = File::create(path).expect("failed to create dump file");
+    let writer = BufWriter::new(file);
+
+    match config.database.backend {
+        DatabaseBackend::Sqlite => export_store(config, open_sqlite(&config.database), writer),
+        DatabaseBackend::Postgres => export_store(config, open_postgres(&config.database), writer),
+    }
+    .expect("failed to export database");
+    info!("Exported database to {}", path.display());
+}
+
+/// Write a dump of the provided store, decrypting it if configured to.
+fn export_store<S: Storage>(
+    config: &Config,
+    store: S,
+    writer: impl Write,
+) -> Result<(), DumpError> {
+    if config.database.encrypt {
+        dump::export(&encrypt_store(&config.database, store), writer)
+    } else {
+        dump::export(&store, writer)
+    }
+}
+
+/// Restore a dump from the file at `path` into the configured database.
+///
+/// # Panics
+///
+/// Panics if the database could not be opened or is not empty, or if the file
+/// could not be read or is not a valid dump. If writing to the database fails
+/// partway through, it will hold part of the dump and should be deleted before
+/// trying again.
+fn import_database(config: &Config, path: &Path) {
+    let file = File::open(path).expect("failed to open dump file");
+    let reader = BufReader::new(file);
+
+    match config.database.backend {
+        DatabaseBackend::Sqlite => import_store(config, open_sqlite(&config.database), reader),
+        DatabaseBackend::Postgres => import_store(config, open_postgres(&config.database), reader),
+    }
+    .expect("failed to import database");
+    info!("Imported database from {}", path.display());
+}
+
+/// Restore a dump into the provided store, encrypting it if configured to.
+fn import_store<S: Storage>(
+    config: &Config,
+    mut store: S,
+    reader: impl BufRead,
+) -> Result<(), DumpError> {
+    if config.database.encrypt {
+        dump::import(&mut encrypt_store(&config.database, store), reader)
+    } else {
+        dump::import(&mut store, reader)
+    }
+}
+
+/// Run the payment gateway and serve the APIs until they stop.
+async fn run<S: Storage + 'static>(payment_gateway: PaymentGateway<S>, config: &Config) {
     info!("Payment gateway created.");
 
-    let gateway_clone = spawn_gateway(payment_gateway, &config).await;
+    let gateway_clone = spawn_gateway(payment_gateway, config).await;
 
-    let server = build_server(&config, gateway_clone).await;
+    let server = build_server(config, gateway_clone).await;
     debug!("Built AcceptXMR-Server");
     run_server(server).await;
 }
@@ -59,21 +174,71 @@ pub fn load_config(path: &PathBuf) -> Config {
     config
 }
 
-/// Build a payment gateway from provided config.
+/// Open the `SQLite` database in the configured directory.
 ///
 /// # Panics
 ///
-/// Panics if the payment gateway could not be built.
-pub async fn build_gateway(config: &Config) -> PaymentGateway<Sqlite> {
-    std::fs::create_dir_all(&config.database.path).expect("failed to create DB dir");
+/// Panics if the database could not be opened.
+fn open_sqlite(config: &DatabaseConfig) -> Sqlite {
+    std::fs::create_dir_all(&config.path).expect("failed to create DB dir");
     let db_path = config
-        .database
         .path
         .canonicalize()
         .expect("could not determine absolute database path")
         .join("database");
     let db_path_str = db_path.to_str().expect("failed to cast DB path to string");
 
+    Sqlite::new(db_path_str, &TableNames::default()).expect("failed to open invoice store")
+}
+
+/// Connect to the configured `PostgreSQL` database.
+///
+/// # Panics
+///
+/// Panics if the database could not be connected to.
+fn open_postgres(config: &DatabaseConfig) -> Postgres {
+    let url = config
+        .postgres_url
+        .as_ref()
+        .expect("PostgreSQL connection string must be configured");
+
+    Postgres::new(url.expose_secret(), &TableNames::default())
+        .expect("failed to connect to invoice store")
+}
+
+/// Wrap the provided store to encrypt it with the configured key. If old keys
+/// are configured, tracked invoices are re-encrypted with the new key.
+///
+/// # Panics
+///
+/// Panics if no key is configured, or if invoices could not be re-encrypted.
+fn encrypt_store<S: InvoiceStorage>(config: &DatabaseConfig, store: S) -> Encrypted<S> {
+    let key = config
+        .encryption_key
+        .as_ref()
+        .expect("storage encryption key must be configured");
+    let old_keys = config
+        .old_encryption_keys
+        .iter()
+        .map(|key| EncryptionKey::from_secret(key.expose_secret()));
+
+    let mut store =
+        Encrypted::new(store, EncryptionKey::from_secret(key.expose_secret())).old_keys(old_keys);
+    if !config.old_encryption_keys.is_empty() {
+        store
+            .reencrypt()
+            .expect("failed to re-encrypt invoices with new storage encryption key");
+        info!("Re-encrypted invoices with new storage encryption key");
+    }
+    store
+}
+
+/// Build a payment gateway from provided config, using the provided store.
+///
+/// # Panics
+///
+/// Panics if the payment gateway could not be built.
+pub async fn build_gateway<S: Storage + 'static>(config: &Config, store: S) -> PaymentGateway<S> {
     let private_view_key = config
         .wallet
         .private_viewkey
@@ -84,14 +249,15 @@ pub async fn build_gateway(config: &Config) -> PaymentGateway<Sqlite> {
         .primary_address
         .expect("primary address must be configured");
 
-    let store = Sqlite::new(db_path_str, "invoices", "output keys", "height")
-        .expect("failed to open invoice store");
     let mut payment_gateway_builder = PaymentGatewayBuilder::new(
         private_view_key.expose_secret().clone(),
         primary_address.to_string(),
         store,
     )
-    .account_index(config.wallet.account_index)
+    .account_indices(
+        iter::once(config.wallet.account_index)
+            .chain(config.wallet.additional_account_indices.iter().copied()),
+    )
     .daemon_url(config.daemon.url.to_string());
 
     // Use daemon login if one was configured.
@@ -111,6 +277,11 @@ pub async fn build_gateway(config: &Config) -> PaymentGateway<Sqlite> {
         payment_gateway_builder = payment_gateway_builder.initial_height(restore_height);
     }
 
+    // Drop old output keys if a retention depth was configured.
+    if let Some(depth) = config.database.output_key_retention_depth {
+        payment_gateway_builder = payment_gateway_builder.output_key_retention(depth);
+    }
+
     payment_gateway_builder
         .build()
         .await
@@ -123,10 +294,10 @@ pub async fn build_gateway(config: &Config) -> PaymentGateway<Sqlite> {
 /// # Panics
 ///
 /// Panics if the payment gateway could not be run.
-pub async fn spawn_gateway(
-    payment_gateway: PaymentGateway<Sqlite>,
+pub async fn spawn_gateway<S: Storage + 'static>(
+    payment_gateway: PaymentGateway<S>,
     config: &Config,
-) -> PaymentGateway<Sqlite> {
+) -> PaymentGateway<S> {
     payment_gateway
         .run()
         .await
@@ -136,7 +307,8 @@ pub async fn spawn_gateway(
     let gateway_clone = payment_gateway.clone();
     let callback_queue_size = config.callback.queue_size;
     let callback_max_retries = config.callback.max_retries;
-    let delete_expired = config.database.delete_expired;
+    let archive_retention_days = config.database.archive_retention_days;
+    let event_retention_days = config.database.event_retention_days;
 
     // Watch for invoice updates and deal with them accordingly.
     tokio::spawn(async move {
@@ -153,15 +325,16 @@ pub async fn spawn_gateway(
 
         loop {
             let Some(invoice) = subscriber.recv().await else {
-                // TODO: Should this attempt to restart instead?
-                panic!("Blockchain scanner crashed!")
+                warn!("Subscription to invoice updates closed unexpectedly; resubscribing.");
+                subscriber = payment_gateway.subscribe_all();
+                continue;
             };
             debug!("Update for invoice with ID {}:\n{}", invoice.id(), &invoice);
 
             // Call the callback, if applicable.
             if let Err(e) = callback_queue
                 .send(CallbackCommand::Call {
-                    invoice: invoice.clone(),
+                    invoice: Box::new(invoice.clone()),
                     delay: Duration::ZERO,
                     retry_count: 0,
                 })
@@ -171,22 +344,66 @@ pub async fn spawn_gateway(
             };
 
             // If it's expired and not pending confirmation then we probably
-            // shouldn't bother tracking it anymore. But only if configured to do so.
-            if invoice.is_expired()
-                && (invoice.is_confirmed() || !invoice.is_paid())
-                && delete_expired
-            {
+            // shouldn't bother tracking it anymore, so move it to the archive.
+            // Archiving waits for updates to be published, so it mustn't hold
+            // up receiving them.
+            if invoice.is_expired() && (invoice.is_confirmed() || !invoice.is_paid()) {
                 debug!(
-                    "Invoice to index {} is expired. Removing invoice now",
+                    "Invoice to index {} is expired. Archiving invoice now",
                     invoice.index()
                 );
-                if let Err(e) = payment_gateway.remove_invoice(invoice.id()).await {
-                    error!("Failed to remove expired invoice: {}", e);
-                };
+                let payment_gateway = payment_gateway.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = payment_gateway.archive_invoice(invoice.id()).await {
+                        error!("Failed to archive expired invoice: {}", e);
+                    }
+                });
             }
         }
     });
 
+    // Periodically delete archived invoices and logged events older than their
+    // retention periods.
+    if archive_retention_days.is_some() || event_retention_days.is_some() {
+        let payment_gateway = gateway_clone.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let height = match payment_gateway.daemon_height().await {
+                    Ok(height) => height,
+                    Err(e) => {
+                        error!(
+                            "Failed to get daemon height for pruning invoice archive and event log: {}",
+                            e
+                        );
+                        continue;
+                    }
+                };
+                if let Some(days) = archive_retention_days {
+                    let retention = days.saturating_mul(BLOCKS_PER_DAY);
+                    debug!("Pruning archived invoices older than {days} days");
+                    if let Err(e) = payment_gateway
+                        .prune_archive(height.saturating_sub(retention))
+                        .await
+                    {
+                        error!("Failed to prune invoice archive: {}", e);
+                    }
+                }
+                if let Some(days) = event_retention_days {
+                    let retention = days.saturating_mul(BLOCKS_PER_DAY);
+                    debug!("Pruning logged events older than {days} days");
+                    if let Err(e) = payment_gateway
+                        .prune_event_log_below_height(height.saturating_sub(retention))
+                        .await
+                    {
+                        error!("Failed to prune event log: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
     gateway_clone
 }
 
@@ -196,9 +413,9 @@ pub async fn spawn_gateway(
 ///
 /// Panics if the external or internal API servers could not be created (for
 /// example, if the specified port could not be bound).
-pub async fn build_server(
+pub async fn build_server<S: Storage + 'static>(
     config: &Config,
-    payment_gateway: PaymentGateway<Sqlite>,
+    payment_gateway: PaymentGateway<S>,
 ) -> AcceptXmrServer {
     let (external_server, internal_server) = try_join!(
         new_server(
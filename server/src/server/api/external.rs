@@ -1,6 +1,8 @@
 use std::borrow::Cow;
 
-use acceptxmr::{storage::Storage, InvoiceId, MonerodClient, Subscriber};
+use acceptxmr::{
+    storage::Storage, AcceptXmrError, Invoice, InvoiceId, MonerodClient, PaymentGateway, Subscriber,
+};
 use axum::{
     extract::{
         ws::{close_code, CloseFrame, Message, WebSocket},
@@ -58,7 +60,7 @@ pub(crate) fn external<S: Storage + 'static, M: MonerodClient + 'static>(
 
 /// Get invoice status.
 ///
-/// Get invoice status by ID with query param.
+/// Get invoice status by ID with query param. Archived invoices are included.
 #[utoipa::path(
     get,
     path = "/invoice",
@@ -74,7 +76,7 @@ async fn get_invoice_status<S: Storage + 'static, M: MonerodClient + 'static>(
     AxumState(state): AxumState<State<S, M>>,
 ) -> Result<impl IntoResponse, ApiError> {
     let invoice_id = InvoiceId::try_from(query.0)?;
-    match state.payment_gateway.get_invoice(invoice_id).await {
+    match get_tracked_or_archived(&state.payment_gateway, invoice_id).await {
         Ok(Some(invoice)) => Ok((
             [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
             Json(InvoiceUpdate::from(invoice)),
@@ -87,6 +89,17 @@ async fn get_invoice_status<S: Storage + 'static, M: MonerodClient + 'static>(
     }
 }
 
+/// Get an invoice, falling back to the archive if it is no longer tracked.
+async fn get_tracked_or_archived<S: Storage + 'static, M: MonerodClient + 'static>(
+    payment_gateway: &PaymentGateway<S, M>,
+    invoice_id: InvoiceId,
+) -> Result<Option<Invoice>, AcceptXmrError> {
+    match payment_gateway.get_invoice(invoice_id).await? {
+        Some(invoice) => Ok(Some(invoice)),
+        None => payment_gateway.get_archived_invoice(invoice_id).await,
+    }
+}
+
 /// Subscribe to an invoice.
 ///
 /// Subscribe to an invoice's updates via websocket. Invoice updates sent over
@@ -212,7 +225,7 @@ async fn pay<S: Storage + 'static, M: MonerodClient + 'static>(
             .to_string_lossy()
             .as_ref(),
     );
-    match state.payment_gateway.get_invoice(invoice_id).await {
+    match get_tracked_or_archived(&state.payment_gateway, invoice_id).await {
         Ok(Some(invoice)) => Ok((
             StatusCode::OK,
             [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
@@ -340,17 +353,60 @@ mod test {
                 {
                     "id":"AAAAAAAAAEkAAAAAACXOWQ",
                     "address":"84Gv7pf9wJhUS1pK7Kn7Fw2UScnKjdVnxRQfQMC3tsuZbMZkVKiUBrrJ8UPsztJQUXiFdEb1kcsD33bJy98gUB2g4pvirxc",
+                    "account_index":0,
                     "uri": r"monero:84Gv7pf9wJhUS1pK7Kn7Fw2UScnKjdVnxRQfQMC3tsuZbMZkVKiUBrrJ8UPsztJQUXiFdEb1kcsD33bJy98gUB2g4pvirxc?tx_amount=0.000001",
                     "amount_requested":1_000_000,
                     "amount_paid":0,
+                    "xmr_requested":"0.000001",
+                    "xmr_paid":"0",
                     "confirmations_required":2,
                     "confirmations":None::<u64>,
                     "expiration_in":10,
                     "current_height":0,
                     "order":"large pizza",
-                    "callback":r"https://example.com/success?=largepizza"
+                    "callback":r"https://example.com/success?=largepizza",
+                    "reference":None::<String>
                 }
             )
         );
     }
+
+    #[tokio::test]
+    async fn get_archived_invoice_status() {
+        init_logger();
+
+        let payment_gateway = PaymentGatewayBuilder::new(
+            PRIVATE_VIEW_KEY.to_string(),
+            PRIMARY_ADDRESS.to_string(),
+            InMemory::new(),
+        )
+        .seed(0)
+        .build_with_mock_daemon()
+        .await
+        .unwrap();
+        let invoice_id = payment_gateway
+            .new_invoice(1_000_000, 2, 10, "large pizza".to_string())
+            .await
+            .unwrap();
+        payment_gateway.archive_invoice(invoice_id).await.unwrap();
+        let (external_api, _) = external(State::<InMemory, MonerodMockClient>::new(
+            payment_gateway,
+            ServerConfig::default(),
+        ));
+
+        let response = external_api
+            .oneshot(
+                (Request::get("/invoice?id=AAAAAAAAAEkAAAAAACXOWQ"))
+                    .body(Empty::new())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let status = serde_json::from_slice::<serde_json::Value>(&body[..]).unwrap();
+        assert_eq!(status["id"], "AAAAAAAAAEkAAAAAACXOWQ");
+        assert_eq!(status["order"], "large pizza");
+    }
 }
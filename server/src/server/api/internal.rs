@@ -1,6 +1,9 @@
 use std::str::FromStr;
 
-use acceptxmr::{storage::Storage, MonerodClient};
+use acceptxmr::{
+    storage::{InvoiceQuery, InvoiceStatus, Storage},
+    Amount, InvoiceId, MonerodClient,
+};
 use axum::{
     extract::{Query, State as AxumState},
     http::HeaderValue,
@@ -13,21 +16,33 @@ use hyper::{
     StatusCode,
 };
 use log::debug;
-use serde::Deserialize;
-use utoipa::{openapi::OpenApi, OpenApi as _, ToSchema};
+use serde::{Deserialize, Serialize};
+use utoipa::{openapi::OpenApi, IntoParams, OpenApi as _, ToSchema};
 
 use crate::server::{
     api::{
         types::invoice_id::{Base64InvoiceId, InvoiceIdPayload, InvoiceIdQuery},
-        ApiError, InvoiceDescription,
+        ApiError, InvoiceDescription, InvoiceUpdate,
     },
     State,
 };
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(new_invoice, delete_invoice, invoice_ids),
-    components(schemas(InvoiceIdPayload, NewInvoiceParams, Base64InvoiceId)),
+    paths(
+        new_invoice,
+        delete_invoice,
+        invoice_by_reference,
+        invoice_ids,
+        invoices
+    ),
+    components(schemas(
+        InvoiceIdPayload,
+        NewInvoiceParams,
+        Base64InvoiceId,
+        InvoicePageResponse,
+        InvoiceUpdate
+    )),
     info(
         title = "AcceptXMR Server (Internal)",
         description = "AcceptXMR Server's non user-facing API."
@@ -42,7 +57,9 @@ pub(crate) fn internal<S: Storage + 'static, M: MonerodClient + 'static>(
         Router::new()
             .route("/invoice", post(new_invoice))
             .route("/invoice", delete(delete_invoice))
+            .route("/invoice/by-reference", get(invoice_by_reference))
             .route("/invoice/ids", get(invoice_ids))
+            .route("/invoices", get(invoices))
             //.route("/status", get(status))
             .with_state(state),
         ApiDoc::openapi(),
@@ -51,8 +68,13 @@ pub(crate) fn internal<S: Storage + 'static, M: MonerodClient + 'static>(
 
 #[derive(Deserialize, ToSchema)]
 struct NewInvoiceParams {
+    /// Amount due in piconeros. Provide either this or `xmr_due`.
     #[schema(example = "1000000")]
-    piconeros_due: u64,
+    piconeros_due: Option<u64>,
+    /// Amount due in XMR, as a decimal string. Provide either this or
+    /// `piconeros_due`.
+    #[schema(example = "0.000001")]
+    xmr_due: Option<String>,
     #[schema(example = "1")]
     confirmations_required: u64,
     #[schema(example = "30")]
@@ -61,12 +83,21 @@ struct NewInvoiceParams {
     order: String,
     #[schema(example = "https://example.com/paid")]
     callback: Option<String>,
+    /// Account to allocate the invoice from. Defaults to the configured
+    /// `account-index`.
+    #[schema(example = "0")]
+    account_index: Option<u32>,
+    /// Your own unique reference for the invoice, such as an order ID. If a
+    /// tracked invoice with this reference already exists, no invoice is
+    /// created, and the existing invoice's ID is returned instead.
+    #[schema(example = "order-1234")]
+    reference: Option<String>,
 }
 
 /// Create a new invoice.
 ///
 /// Create a new invoice with the provided details. Returns the ID of the new
-/// invoice.
+/// invoice, or of the existing invoice with the same reference.
 #[utoipa::path(
     post,
     path = "/invoice",
@@ -85,19 +116,50 @@ async fn new_invoice<S: Storage + 'static, M: MonerodClient + 'static>(
         let _uri = Uri::from_str(callback).map_err(ApiError::InvalidCallback)?;
     }
 
-    let invoice_id = state
-        .payment_gateway
-        .new_invoice(
-            payload.piconeros_due,
-            payload.confirmations_required,
-            payload.expiration_in,
-            serde_json::to_string(&InvoiceDescription {
-                order: payload.order.clone(),
-                callback: payload.callback.clone(),
-            })
-            .map_err(ApiError::DescriptionSerialization)?,
-        )
-        .await?;
+    let amount_due = match (payload.piconeros_due, &payload.xmr_due) {
+        (Some(piconeros), None) => Amount::from_pico(piconeros),
+        (None, Some(xmr)) => xmr.parse()?,
+        _ => {
+            return Err(ApiError::InvalidAmount(
+                "exactly one of piconeros_due and xmr_due must be provided".to_string(),
+            ))
+        }
+    };
+    let account_index = payload
+        .account_index
+        .unwrap_or(state.payment_gateway.account_indices()[0]);
+    let description = serde_json::to_string(&InvoiceDescription {
+        order: payload.order.clone(),
+        callback: payload.callback.clone(),
+    })
+    .map_err(ApiError::DescriptionSerialization)?;
+    let invoice_id = match payload.reference {
+        Some(reference) => {
+            state
+                .payment_gateway
+                .new_invoice_with_reference(
+                    reference,
+                    account_index,
+                    amount_due,
+                    payload.confirmations_required,
+                    payload.expiration_in,
+                    description,
+                )
+                .await?
+        }
+        None => {
+            state
+                .payment_gateway
+                .new_invoice_for_account(
+                    account_index,
+                    amount_due,
+                    payload.confirmations_required,
+                    payload.expiration_in,
+                    description,
+                )
+                .await?
+        }
+    };
     debug!(
         "Created new invoice successfully. Invoice ID: {}",
         invoice_id
@@ -140,6 +202,46 @@ async fn delete_invoice<S: Storage + 'static, M: MonerodClient + 'static>(
     ))
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReferenceQuery {
+    /// The reference the invoice was created with.
+    #[param(example = "order-1234")]
+    reference: String,
+}
+
+/// Get an invoice by reference.
+///
+/// Get the currently tracked invoice created with the provided reference.
+#[utoipa::path(
+    get,
+    path = "/invoice/by-reference",
+    tag = "invoice",
+    params(
+        ReferenceQuery
+    ),
+    responses(
+        (status = 200, description = "The invoice", body = InvoiceUpdate),
+        (status = 404, description = "No tracked invoice has the reference")
+    )
+)]
+async fn invoice_by_reference<S: Storage + 'static, M: MonerodClient + 'static>(
+    AxumState(state): AxumState<State<S, M>>,
+    Query(query): Query<ReferenceQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    match state
+        .payment_gateway
+        .get_invoice_by_reference(&query.reference)
+        .await?
+    {
+        Some(invoice) => Ok((
+            [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+            Json(InvoiceUpdate::from(invoice)),
+        )),
+        None => Err(ApiError::ReferenceNotFound(query.reference)),
+    }
+}
+
 /// List all invoice IDs.
 ///
 /// List invoice IDs of all currently tracked invoices.
@@ -168,6 +270,127 @@ async fn invoice_ids<S: Storage + 'static, M: MonerodClient + 'static>(
     ))
 }
 
+/// Most invoices returned in a single page.
+const MAX_QUERY_LIMIT: usize = 1_000;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct InvoiceQueryParams {
+    /// Comma-separated statuses to select, out of `unpaid`, `paid`,
+    /// `confirmed` and `expired`.
+    #[param(example = "unpaid,paid")]
+    status: Option<String>,
+    /// Lowest creation height to select.
+    min_height: Option<u64>,
+    /// Highest creation height to select.
+    max_height: Option<u64>,
+    /// Lowest amount requested to select, in piconeros.
+    min_piconeros: Option<u64>,
+    /// Highest amount requested to select, in piconeros.
+    max_piconeros: Option<u64>,
+    /// Only select invoices for this order.
+    order: Option<String>,
+    /// Only select invoices with this metadata key, such as `callback`.
+    metadata_key: Option<String>,
+    /// Cursor returned with the previous page, as `next`.
+    after: Option<Base64InvoiceId>,
+    /// Most invoices to return. Defaults to 100, and may be at most 1000.
+    limit: Option<usize>,
+}
+
+impl TryFrom<InvoiceQueryParams> for InvoiceQuery {
+    type Error = ApiError;
+
+    fn try_from(params: InvoiceQueryParams) -> Result<Self, Self::Error> {
+        let mut query = InvoiceQuery::new();
+        if let Some(statuses) = params.status {
+            let statuses = statuses
+                .split(',')
+                .map(|status| match status.trim() {
+                    "unpaid" => Ok(InvoiceStatus::Unpaid),
+                    "paid" => Ok(InvoiceStatus::Paid),
+                    "confirmed" => Ok(InvoiceStatus::Confirmed),
+                    "expired" => Ok(InvoiceStatus::Expired),
+                    other => Err(ApiError::InvalidQuery(format!("unknown status {other:?}"))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            query = query.statuses(statuses);
+        }
+        if let Some(height) = params.min_height {
+            query = query.min_creation_height(height);
+        }
+        if let Some(height) = params.max_height {
+            query = query.max_creation_height(height);
+        }
+        if let Some(piconeros) = params.min_piconeros {
+            query = query.min_amount_requested(piconeros);
+        }
+        if let Some(piconeros) = params.max_piconeros {
+            query = query.max_amount_requested(piconeros);
+        }
+        if let Some(order) = params.order {
+            query = query.metadata("order", order);
+        }
+        if let Some(key) = params.metadata_key {
+            query = query.metadata_key(key);
+        }
+        if let Some(after) = params.after {
+            query = query.after(InvoiceId::try_from(after)?);
+        }
+        if let Some(limit) = params.limit {
+            if limit > MAX_QUERY_LIMIT {
+                return Err(ApiError::InvalidQuery(format!(
+                    "limit may be at most {MAX_QUERY_LIMIT}"
+                )));
+            }
+            query = query.limit(limit);
+        }
+        Ok(query)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct InvoicePageResponse {
+    /// The invoices selected, in ascending order of ID.
+    invoices: Vec<InvoiceUpdate>,
+    /// Cursor to pass as `after` to get the next page, or `null` if this is
+    /// the last page.
+    next: Option<Base64InvoiceId>,
+}
+
+/// Query invoices.
+///
+/// List currently tracked invoices matching the provided filters, a page at a
+/// time.
+#[utoipa::path(
+    get,
+    path = "/invoices",
+    tag = "invoice",
+    params(
+        InvoiceQueryParams
+    ),
+    responses(
+        (status = 200, description = "A page of invoices", body = InvoicePageResponse)
+    )
+)]
+async fn invoices<S: Storage + 'static, M: MonerodClient + 'static>(
+    AxumState(state): AxumState<State<S, M>>,
+    Query(params): Query<InvoiceQueryParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let page = state
+        .payment_gateway
+        .query_invoices(params.try_into()?)
+        .await?;
+
+    Ok((
+        [(CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Json(InvoicePageResponse {
+            invoices: page.invoices.into_iter().map(InvoiceUpdate::from).collect(),
+            next: page.next.map(Base64InvoiceId::from),
+        }),
+    ))
+}
+
 #[cfg(test)]
 mod test {
     use acceptxmr::{storage::stores::InMemory, MonerodMockClient, PaymentGatewayBuilder};
@@ -234,6 +457,104 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn new_invoice_unknown_account() {
+        init_logger();
+
+        let payment_gateway = PaymentGatewayBuilder::new(
+            PRIVATE_VIEW_KEY.to_string(),
+            PRIMARY_ADDRESS.to_string(),
+            InMemory::new(),
+        )
+        .account_indices([0, 1])
+        .seed(0)
+        .build_with_mock_daemon()
+        .await
+        .unwrap();
+        let (app, _) = internal(State::<InMemory, MonerodMockClient>::new(
+            payment_gateway,
+            ServerConfig::default(),
+        ));
+
+        let response = app
+            .oneshot(
+                Request::post("/invoice")
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(
+                        serde_json::to_vec(&json!({
+                            "piconeros_due": 1_000_000,
+                            "confirmations_required": 2,
+                            "expiration_in": 10,
+                            "order": "large pizza",
+                            "account_index": 2,
+                        }))
+                        .unwrap(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn new_invoice_xmr_due() {
+        init_logger();
+
+        let payment_gateway = PaymentGatewayBuilder::new(
+            PRIVATE_VIEW_KEY.to_string(),
+            PRIMARY_ADDRESS.to_string(),
+            InMemory::new(),
+        )
+        .seed(0)
+        .build_with_mock_daemon()
+        .await
+        .unwrap();
+        let (mut app, _) = internal(State::<InMemory, MonerodMockClient>::new(
+            payment_gateway.clone(),
+            ServerConfig::default(),
+        ));
+
+        for (amount, expected_status) in [
+            (json!({ "xmr_due": "0.0015" }), StatusCode::OK),
+            (
+                json!({ "xmr_due": "0.0000000000001" }),
+                StatusCode::BAD_REQUEST,
+            ),
+            (json!({}), StatusCode::BAD_REQUEST),
+            (
+                json!({ "xmr_due": "1", "piconeros_due": 1 }),
+                StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            let mut params = json!({
+                "confirmations_required": 2,
+                "expiration_in": 10,
+                "order": "large pizza",
+            });
+            params
+                .as_object_mut()
+                .unwrap()
+                .extend(amount.as_object().unwrap().clone());
+            let response = app
+                .call(
+                    Request::post("/invoice")
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(serde_json::to_vec(&params).unwrap()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected_status, "{params}");
+        }
+
+        let ids = payment_gateway.get_invoice_ids().await.unwrap();
+        assert_eq!(ids.len(), 1);
+        let invoice = payment_gateway.get_invoice(ids[0]).await.unwrap().unwrap();
+        assert_eq!(invoice.amount_requested(), 1_500_000_000);
+    }
+
     #[tokio::test]
     async fn delete_invoice() {
         init_logger();
@@ -369,4 +690,166 @@ mod test {
         // Check the length of the invoice IDs vector
         assert_eq!(invoice_ids.len(), 5);
     }
+
+    #[tokio::test]
+    async fn query_invoices() {
+        init_logger();
+
+        let payment_gateway = PaymentGatewayBuilder::new(
+            PRIVATE_VIEW_KEY.to_string(),
+            PRIMARY_ADDRESS.to_string(),
+            InMemory::new(),
+        )
+        .seed(0)
+        .build_with_mock_daemon()
+        .await
+        .unwrap();
+
+        let (mut app, _) = internal(State::<InMemory, MonerodMockClient>::new(
+            payment_gateway,
+            ServerConfig::default(),
+        ));
+
+        for (order, piconeros) in [("pizza", 1_000), ("pasta", 2_000), ("pizza", 3_000)] {
+            let response = app
+                .call(
+                    Request::post("/invoice")
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(
+                            serde_json::to_vec(&json!({
+                                "piconeros_due": piconeros,
+                                "confirmations_required": 2,
+                                "expiration_in": 10,
+                                "order": order,
+                            }))
+                            .unwrap(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        // Follow the cursor through every page of pizza orders.
+        let mut amounts = Vec::new();
+        let mut uri = "/invoices?order=pizza&status=unpaid&limit=1".to_string();
+        loop {
+            let response = app
+                .call(Request::get(&uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            for invoice in page["invoices"].as_array().unwrap() {
+                assert_eq!(invoice["order"], "pizza");
+                amounts.push(invoice["amount_requested"].as_u64().unwrap());
+            }
+            match page["next"].as_str() {
+                Some(next) => {
+                    uri = format!("/invoices?order=pizza&status=unpaid&limit=1&after={next}");
+                }
+                None => break,
+            }
+        }
+        amounts.sort_unstable();
+        assert_eq!(amounts, [1_000, 3_000]);
+
+        let response = app
+            .call(
+                Request::get("/invoices?min_piconeros=1500&max_piconeros=2500")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page["invoices"].as_array().unwrap().len(), 1);
+        assert_eq!(page["invoices"][0]["order"], "pasta");
+
+        let response = app
+            .oneshot(
+                Request::get("/invoices?status=lost")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn invoice_by_reference() {
+        init_logger();
+
+        let payment_gateway = PaymentGatewayBuilder::new(
+            PRIVATE_VIEW_KEY.to_string(),
+            PRIMARY_ADDRESS.to_string(),
+            InMemory::new(),
+        )
+        .seed(0)
+        .build_with_mock_daemon()
+        .await
+        .unwrap();
+
+        let (mut app, _) = internal(State::<InMemory, MonerodMockClient>::new(
+            payment_gateway,
+            ServerConfig::default(),
+        ));
+
+        // Creating an invoice twice with the same reference only creates one.
+        let mut invoice_ids = Vec::new();
+        for order in ["large pizza", "large pizza again"] {
+            let response = app
+                .call(
+                    Request::post("/invoice")
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(
+                            serde_json::to_vec(&json!({
+                                "piconeros_due": 1_000_000,
+                                "confirmations_required": 2,
+                                "expiration_in": 10,
+                                "order": order,
+                                "reference": "order-1234",
+                            }))
+                            .unwrap(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            invoice_ids.push(body["invoice_id"].clone());
+        }
+        assert_eq!(invoice_ids[0], invoice_ids[1]);
+
+        let response = app
+            .call(
+                Request::get("/invoice/by-reference?reference=order-1234")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let invoice: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(invoice["id"], invoice_ids[0]);
+        assert_eq!(invoice["order"], "large pizza");
+        assert_eq!(invoice["reference"], "order-1234");
+
+        let response = app
+            .oneshot(
+                Request::get("/invoice/by-reference?reference=order-5678")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -5,7 +5,7 @@ mod internal;
 mod templating;
 pub mod types;
 
-use acceptxmr::{AcceptXmrError, Invoice, InvoiceId};
+use acceptxmr::{AcceptXmrError, Amount, Invoice, InvoiceId, ParseAmountError};
 use axum::response::{IntoResponse, Response};
 pub(crate) use external::external;
 use hyper::{
@@ -27,12 +27,22 @@ pub struct InvoiceUpdate {
     pub id: Base64InvoiceId,
     /// The XMR address.
     pub address: String,
+    /// The account index the address belongs to.
+    pub account_index: u32,
     /// The payment URI.
     pub uri: String,
     /// The amount requested in piconeros.
-    pub amount_requested: u64,
+    #[schema(value_type = u64)]
+    pub amount_requested: Amount,
     /// The amount paid in piconeros.
-    pub amount_paid: u64,
+    #[schema(value_type = u64)]
+    pub amount_paid: Amount,
+    /// The amount requested in XMR, as an exact decimal string.
+    #[schema(example = "0.0015")]
+    pub xmr_requested: String,
+    /// The amount paid in XMR, as an exact decimal string.
+    #[schema(example = "0")]
+    pub xmr_paid: String,
     /// The number of confirmations required.
     pub confirmations_required: u64,
     /// The number of confirmations received, or `None` if the invoice is not
@@ -46,6 +56,8 @@ pub struct InvoiceUpdate {
     pub order: String,
     /// The callback associated with the invoice.
     pub callback: Option<String>,
+    /// The merchant's reference for the invoice, if it was created with one.
+    pub reference: Option<String>,
 }
 
 impl From<Invoice> for InvoiceUpdate {
@@ -56,15 +68,19 @@ impl From<Invoice> for InvoiceUpdate {
         InvoiceUpdate {
             id: value.id().into(),
             address: value.address().to_string(),
+            account_index: value.account_index(),
             uri: value.uri(),
             amount_requested: value.amount_requested(),
             amount_paid: value.amount_paid(),
+            xmr_requested: value.amount_requested().to_string(),
+            xmr_paid: value.amount_paid().to_string(),
             confirmations_required: value.confirmations_required(),
             confirmations: value.confirmations(),
             expiration_in: value.expiration_in(),
             current_height: value.current_height(),
             order,
             callback,
+            reference: value.reference().map(str::to_string),
         }
     }
 }
@@ -98,12 +114,19 @@ pub enum ApiError {
     /// Invalid callback URI.
     #[error("invalid callback URI: {0}")]
     InvalidCallback(InvalidUri),
+    /// Invalid amount due. Exactly one of `piconeros_due` and `xmr_due` must be
+    /// provided.
+    #[error("invalid amount due: {0}")]
+    InvalidAmount(String),
     /// Failed to build HTTP response.
     #[error("failed to build HTTP response: {0}")]
     InvalidResponse(#[from] HttpError),
     /// Invoice not found.
     #[error("invoice with ID {0} not found")]
     InvoiceNotFound(InvoiceId),
+    /// No tracked invoice has the given reference.
+    #[error("invoice with reference {0:?} not found")]
+    ReferenceNotFound(String),
     /// Invalid invoice ID.
     #[error("invoice ID could not be parsed: {0}")]
     InvalidInvoiceId(#[from] InvoiceIdParseError),
@@ -113,35 +136,52 @@ pub enum ApiError {
     /// Templating error.
     #[error("failed to render template: {0}")]
     TemplatingError(#[from] tera::Error),
+    /// Invalid invoice query parameter.
+    #[error("invalid invoice query: {0}")]
+    InvalidQuery(String),
 }
 
 impl ApiError {
     fn status_code(&self) -> StatusCode {
         match self {
+            Self::AcceptXmr(AcceptXmrError::UnknownAccount(_))
+            | Self::InvalidInvoiceId(_)
+            | Self::DescriptionSerialization(_)
+            | Self::InvalidCallback(_)
+            | Self::InvalidAmount(_)
+            | Self::InvalidQuery(_) => StatusCode::BAD_REQUEST,
             Self::AcceptXmr(_) | Self::InvalidResponse(_) | Self::TemplatingError(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
-            Self::InvalidInvoiceId(_)
-            | Self::DescriptionSerialization(_)
-            | Self::InvalidCallback(_) => StatusCode::BAD_REQUEST,
-            Self::MissingResource(_) | Self::InvoiceNotFound(_) => StatusCode::NOT_FOUND,
+            Self::MissingResource(_) | Self::InvoiceNotFound(_) | Self::ReferenceNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
         }
     }
 
     fn message(&self) -> &'static str {
         match self {
+            Self::AcceptXmr(AcceptXmrError::UnknownAccount(_)) => "Unknown account index",
             Self::AcceptXmr(_) => "Internal payment gateway error",
             Self::DescriptionSerialization(_) => "Failed to serialize invoice description",
             Self::InvalidCallback(_) => "Callback is not a valid URI",
+            Self::InvalidAmount(_) => "Invalid amount due",
             Self::InvalidResponse(_) => "Failed to build HTTP response",
-            Self::InvoiceNotFound(_) => "Invoice not found",
+            Self::InvoiceNotFound(_) | Self::ReferenceNotFound(_) => "Invoice not found",
             Self::InvalidInvoiceId(_) => "Invalid invoice ID",
             Self::MissingResource(_) => "Missing static resource",
             Self::TemplatingError(_) => "Failed to render template",
+            Self::InvalidQuery(_) => "Invalid invoice query",
         }
     }
 }
 
+impl From<ParseAmountError> for ApiError {
+    fn from(value: ParseAmountError) -> Self {
+        ApiError::InvalidAmount(value.to_string())
+    }
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         (self.status_code(), self.message()).into_response()
@@ -9,7 +9,7 @@ use std::{
     pin::Pin,
 };
 
-use acceptxmr::{storage::stores::Sqlite, PaymentGateway};
+use acceptxmr::{storage::Storage, PaymentGateway};
 use axum::{extract::Request, Router};
 use futures_util::pin_mut;
 use hyper::body::Incoming;
@@ -34,13 +34,14 @@ use utoipa_swagger_ui::SwaggerUi;
 use super::config::ServerConfig;
 use crate::server::auth::MaybeBearer;
 
-pub(crate) async fn new_server<F>(
+pub(crate) async fn new_server<S, F>(
     server_config: ServerConfig,
     api: F,
-    payment_gateway: PaymentGateway<Sqlite>,
+    payment_gateway: PaymentGateway<S>,
 ) -> std::io::Result<Server>
 where
-    F: Fn(State) -> (Router, OpenApi),
+    S: Storage + 'static,
+    F: Fn(State<S>) -> (Router, OpenApi),
 {
     let static_dir = server_config.static_dir.clone();
 
@@ -5,12 +5,12 @@ use acceptxmr::{
 
 use crate::config::ServerConfig;
 
-pub(crate) struct State<S: Storage = Sqlite, M: MonerodClient = MonerodRpcClient> {
+pub(crate) struct State<S: Storage + 'static = Sqlite, M: MonerodClient = MonerodRpcClient> {
     pub(crate) payment_gateway: PaymentGateway<S, M>,
     pub(crate) config: ServerConfig,
 }
 
-impl<S: Storage, M: MonerodClient> State<S, M> {
+impl<S: Storage + 'static, M: MonerodClient> State<S, M> {
     pub(crate) fn new(payment_gateway: PaymentGateway<S, M>, config: ServerConfig) -> Self {
         Self {
             payment_gateway,
@@ -19,7 +19,7 @@ impl<S: Storage, M: MonerodClient> State<S, M> {
     }
 }
 
-impl<S: Storage, M: MonerodClient> Clone for State<S, M> {
+impl<S: Storage + 'static, M: MonerodClient> Clone for State<S, M> {
     fn clone(&self) -> Self {
         Self {
             payment_gateway: self.payment_gateway.clone(),
@@ -15,8 +15,8 @@ function displayInvoiceUpdate(invoiceUpdate) {
     console.log(invoiceUpdate);
 
     // Show paid/due.
-    document.getElementById("paid").innerHTML = picoToXMR(invoiceUpdate.amount_paid);
-    document.getElementById("due").innerHTML = picoToXMR(invoiceUpdate.amount_requested);
+    document.getElementById("paid").innerHTML = invoiceUpdate.xmr_paid;
+    document.getElementById("due").innerHTML = invoiceUpdate.xmr_requested;
 
     // Show instructive text depending on invoice state.
     var instructionString = "Loading...";
@@ -91,13 +91,6 @@ function newWebsocket() {
     };
 }
 
-// Convert from piconeros to monero.
-function picoToXMR(amount) {
-    const divisor = 1_000_000_000_000;
-    const xmr = Math.floor(amount / divisor) + amount % divisor / divisor;
-    return new Intl.NumberFormat(undefined, { maximumSignificantDigits: 20 }).format(xmr);
-}
-
 // Make the copy button work.
 function copyInvoiceAddress() {
     // Get the text field
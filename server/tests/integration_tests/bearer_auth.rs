@@ -1,6 +1,9 @@
 use std::{path::PathBuf, str::FromStr};
 
-use acceptxmr::{storage::stores::Sqlite, PaymentGatewayBuilder};
+use acceptxmr::{
+    storage::stores::{Sqlite, TableNames},
+    PaymentGatewayBuilder,
+};
 use acceptxmr_server::{build_server, load_config, run_server, Config};
 use hyper::{http::Uri, StatusCode};
 use log::{debug, info};
@@ -17,7 +20,7 @@ async fn bearer_auth(token: Option<&str>) -> StatusCode {
     init_logger();
     let mock_daemon = MockDaemon::new_mock_daemon().await;
 
-    let store = Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap();
+    let store = Sqlite::new(":memory:", &TableNames::default()).unwrap();
     let payment_gateway = PaymentGatewayBuilder::new(
         PRIVATE_VIEW_KEY.to_string(),
         PRIMARY_ADDRESS.to_string(),
@@ -1,6 +1,9 @@
 use std::{path::PathBuf, str::FromStr, time::Duration};
 
-use acceptxmr::{storage::stores::Sqlite, PaymentGatewayBuilder};
+use acceptxmr::{
+    storage::stores::{Sqlite, TableNames},
+    PaymentGatewayBuilder,
+};
 use acceptxmr_server::{build_server, load_config, run_server, spawn_gateway, Config};
 use hyper::http::Uri;
 use log::{debug, info};
@@ -14,7 +17,7 @@ async fn callback() {
     init_logger();
     let mock_daemon = MockDaemon::new_mock_daemon().await;
 
-    let store = Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap();
+    let store = Sqlite::new(":memory:", &TableNames::default()).unwrap();
     let payment_gateway = PaymentGatewayBuilder::new(
         PRIVATE_VIEW_KEY.to_string(),
         PRIMARY_ADDRESS.to_string(),
@@ -64,8 +67,11 @@ async fn callback() {
         json!(callback),
         json!({
             "address": "82ZZhxB2dAtGwRQSSzvc9fUfM2oFWCUBUFJUAYDsureAB57RZEXm7fyZjwVXGyDGMA3wMtZjMSzECjfbkk5jYkA1SDmWWkx",
+            "account_index": 0,
             "amount_paid": 0,
             "amount_requested": 2_234_345,
+            "xmr_paid": "0",
+            "xmr_requested": "0.000002234345",
             "callback": format!("http://127.0.0.1:{}/", callback_listener.port()),
             "confirmations": None::<u64>,
             "confirmations_required": 2,
@@ -73,6 +79,7 @@ async fn callback() {
             "expiration_in": 20,
             "id": "AAAAAAAAAGEAAAAAACXOWQ",
             "order": "I am a test order",
+            "reference": None::<String>,
             "uri": "monero:82ZZhxB2dAtGwRQSSzvc9fUfM2oFWCUBUFJUAYDsureAB57RZEXm7fyZjwVXGyDGMA3wMtZjMSzECjfbkk5jYkA1SDmWWkx?tx_amount=0.000002234345"
         })
     );
@@ -84,7 +91,7 @@ async fn callback_retry() {
     init_logger();
     let mock_daemon = MockDaemon::new_mock_daemon().await;
 
-    let store = Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap();
+    let store = Sqlite::new(":memory:", &TableNames::default()).unwrap();
     let payment_gateway = PaymentGatewayBuilder::new(
         PRIVATE_VIEW_KEY.to_string(),
         PRIMARY_ADDRESS.to_string(),
@@ -1,6 +1,9 @@
 use std::{path::PathBuf, str::FromStr, time::Duration};
 
-use acceptxmr::{storage::stores::Sqlite, PaymentGatewayBuilder};
+use acceptxmr::{
+    storage::stores::{Sqlite, TableNames},
+    PaymentGatewayBuilder,
+};
 use acceptxmr_server::{
     api::types::invoice_id::Base64InvoiceId, build_server, load_config, run_server, spawn_gateway,
     Config,
@@ -21,7 +24,7 @@ async fn websocket() {
     init_logger();
     let mock_daemon = MockDaemon::new_mock_daemon().await;
 
-    let store = Sqlite::new(":memory:", "invoices", "output keys", "height").unwrap();
+    let store = Sqlite::new(":memory:", &TableNames::default()).unwrap();
     let payment_gateway = PaymentGatewayBuilder::new(
         PRIVATE_VIEW_KEY.to_string(),
         PRIMARY_ADDRESS.to_string(),
@@ -106,8 +109,11 @@ async fn websocket() {
         Value::from_str(&msg).unwrap(),
         json!({
             "address": "82ZZhxB2dAtGwRQSSzvc9fUfM2oFWCUBUFJUAYDsureAB57RZEXm7fyZjwVXGyDGMA3wMtZjMSzECjfbkk5jYkA1SDmWWkx",
+            "account_index": 0,
             "amount_paid": 1_468_383_460,
             "amount_requested": 2_234_345,
+            "xmr_paid": "0.00146838346",
+            "xmr_requested": "0.000002234345",
             "callback": format!("http://127.0.0.1:{}/", callback_listener.port()),
             "confirmations": 0,
             "confirmations_required": 2,
@@ -115,7 +121,8 @@ async fn websocket() {
             "expiration_in": 20,
             "id": "AAAAAAAAAGEAAAAAACXOWQ",
             "order": "I am a test order",
-            "uri": "monero:82ZZhxB2dAtGwRQSSzvc9fUfM2oFWCUBUFJUAYDsureAB57RZEXm7fyZjwVXGyDGMA3wMtZjMSzECjfbkk5jYkA1SDmWWkx?tx_amount=0.0"
+            "reference": None::<String>,
+            "uri": "monero:82ZZhxB2dAtGwRQSSzvc9fUfM2oFWCUBUFJUAYDsureAB57RZEXm7fyZjwVXGyDGMA3wMtZjMSzECjfbkk5jYkA1SDmWWkx?tx_amount=0"
         })
     );
 }
@@ -0,0 +1,82 @@
+SET statement_timeout = 0;
+SET lock_timeout = 0;
+SET idle_in_transaction_session_timeout = 0;
+SET client_encoding = 'SQL_ASCII';
+SET standard_conforming_strings = on;
+SELECT pg_catalog.set_config('search_path', '', false);
+SET check_function_bodies = false;
+SET xmloption = content;
+SET client_min_messages = warning;
+SET row_security = off;
+SET default_tablespace = '';
+SET default_table_access_method = heap;
+CREATE TABLE public."archived invoices" (
+    major_subindex bigint NOT NULL,
+    minor_subindex bigint NOT NULL,
+    creation_height bigint NOT NULL,
+    current_height bigint NOT NULL,
+    invoice bytea NOT NULL
+);
+CREATE TABLE public.events (
+    sequence bigint NOT NULL,
+    event bytea NOT NULL
+);
+ALTER TABLE public.events ALTER COLUMN sequence ADD GENERATED ALWAYS AS IDENTITY (
+    SEQUENCE NAME public.events_sequence_seq
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1
+);
+CREATE TABLE public.height (
+    id integer NOT NULL,
+    height bigint NOT NULL,
+    CONSTRAINT height_id_check CHECK ((id = 0))
+);
+CREATE TABLE public.invoices (
+    major_subindex bigint NOT NULL,
+    minor_subindex bigint NOT NULL,
+    creation_height bigint NOT NULL,
+    current_height bigint NOT NULL,
+    invoice bytea NOT NULL
+);
+CREATE TABLE public."invoices schema version" (
+    id integer NOT NULL,
+    version bigint NOT NULL,
+    CONSTRAINT "invoices schema version_id_check" CHECK ((id = 0))
+);
+CREATE TABLE public."output keys" (
+    output_key bytea NOT NULL,
+    output_id bytea NOT NULL
+);
+CREATE TABLE public.subaddresses (
+    major_subindex bigint NOT NULL,
+    minor_subindex bigint NOT NULL
+);
+INSERT INTO public."archived invoices" VALUES (0, 3, 2477600, 2477610, '\x5f383445324c6f33766b4357715644716d716a327a59476e526e6e704d347a66396a315a4162694447364e6879486f536f5750514d716a465652584c324d336a77714655456d3267567a4b3146517844584e7362537467374537564c344c7a685f343631335969484c4d364a4d48347a656a4d42327a4a593554775143784c38703635756677386b4250357978583969746d75474c717031645334746b566f54786a794833615968594e7274474862517a4a51503562467573334b4856646d660003fc20ce250005000000fc2ace2500fc2ace250000086172636869766564');
+INSERT INTO public.events OVERRIDING SYSTEM VALUE VALUES (1, '\x005f3842626e356141344e7a545438577038597270537435575a3134725635596857684d544d55327154346154704b36574b44324e674b704d4a70526d474b37693546594b565039546f55586132566531686a6d6a7a737a664e43546270486e445f343631335969484c4d364a4d48347a656a4d42327a4a593554775143784c38703635756677386b4250357978583969746d75474c717031645334746b566f54786a794833615968594e7274474862517a4a51503562467573334b4856646d660002fc5ace25002a000000fc5dce2500fc64ce25000006756e70616964');
+INSERT INTO public.events OVERRIDING SYSTEM VALUE VALUES (2, '\x015f3832695034756a33745562316f503576594338676e6b5764347a636d4352674342316535634b377477446935555436787677347946717a3159396958623764444c385a4c72347673426862774351726d384779704b6b4837504a72484c4c325f343631335969484c4d364a4d48347a656a4d42327a4a593554775143784c38703635756677386b4250357978583969746d75474c717031645334746b566f54786a794833615968594e7274474862517a4a51503562467573334b4856646d660001fc59ce2500fc00ca9a3bfc00ca9a3b01fc5cce250002fc5dce2500fcbdce250001fc00ca9a3b01fc5cce25000470616964fc00ca9a3b01fc5cce2500');
+INSERT INTO public.events OVERRIDING SYSTEM VALUE VALUES (3, '\x035f3832695034756a33745562316f503576594338676e6b5764347a636d4352674342316535634b377477446935555436787677347946717a3159396958623764444c385a4c72347673426862774351726d384779704b6b4837504a72484c4c325f343631335969484c4d364a4d48347a656a4d42327a4a593554775143784c38703635756677386b4250357978583969746d75474c717031645334746b566f54786a794833615968594e7274474862517a4a51503562467573334b4856646d660001fc59ce2500fc00ca9a3bfc00ca9a3b01fc5cce250002fc5dce2500fcbdce250001fc00ca9a3b01fc5cce25000470616964');
+INSERT INTO public.height VALUES (0, 2477661);
+INSERT INTO public.invoices VALUES (0, 1, 2477657, 2477661, '\x5f3832695034756a33745562316f503576594338676e6b5764347a636d4352674342316535634b377477446935555436787677347946717a3159396958623764444c385a4c72347673426862774351726d384779704b6b4837504a72484c4c325f343631335969484c4d364a4d48347a656a4d42327a4a593554775143784c38703635756677386b4250357978583969746d75474c717031645334746b566f54786a794833615968594e7274474862517a4a51503562467573334b4856646d660001fc59ce2500fc00ca9a3bfc00ca9a3b01fc5cce250002fc5dce2500fcbdce250001fc00ca9a3b01fc5cce25000470616964');
+INSERT INTO public.invoices VALUES (0, 2, 2477658, 2477661, '\x5f3842626e356141344e7a545438577038597270537435575a3134725635596857684d544d55327154346154704b36574b44324e674b704d4a70526d474b37693546594b565039546f55586132566531686a6d6a7a737a664e43546270486e445f343631335969484c4d364a4d48347a656a4d42327a4a593554775143784c38703635756677386b4250357978583969746d75474c717031645334746b566f54786a794833615968594e7274474862517a4a51503562467573334b4856646d660002fc5ace25002a000000fc5dce2500fc64ce25000006756e70616964');
+INSERT INTO public."invoices schema version" VALUES (0, 1);
+INSERT INTO public."output keys" VALUES ('\x0707070707070707070707070707070707070707070707070707070707070707', '\x080808080808080808080808080808080808080808080808080808080808080801');
+INSERT INTO public.subaddresses VALUES (0, 1);
+SELECT pg_catalog.setval('public.events_sequence_seq', 3, true);
+ALTER TABLE ONLY public."archived invoices"
+    ADD CONSTRAINT "archived invoices_pkey" PRIMARY KEY (major_subindex, minor_subindex, creation_height);
+ALTER TABLE ONLY public.events
+    ADD CONSTRAINT events_pkey PRIMARY KEY (sequence);
+ALTER TABLE ONLY public.height
+    ADD CONSTRAINT height_pkey PRIMARY KEY (id);
+ALTER TABLE ONLY public."invoices schema version"
+    ADD CONSTRAINT "invoices schema version_pkey" PRIMARY KEY (id);
+ALTER TABLE ONLY public.invoices
+    ADD CONSTRAINT invoices_pkey PRIMARY KEY (major_subindex, minor_subindex, creation_height);
+ALTER TABLE ONLY public."output keys"
+    ADD CONSTRAINT "output keys_pkey" PRIMARY KEY (output_key);
+ALTER TABLE ONLY public.subaddresses
+    ADD CONSTRAINT subaddresses_pkey PRIMARY KEY (major_subindex, minor_subindex);
+CREATE INDEX "invoices current height" ON public.invoices USING btree (current_height);
@@ -0,0 +1,440 @@
+use monero::{
+    blockdata::transaction::{ExtraField, KeyImage, RawExtraField, SubField, TxOutTarget},
+    consensus::serialize,
+    cryptonote::{
+        hash::{Hash8, Hashable},
+        onetime_key::KeyGenerator,
+        subaddress::{self, Index},
+    },
+    util::{
+        key::H,
+        ringct::{
+            BulletproofPlus, Clsag, CtKey, EcdhInfo, Key, RctSig, RctSigBase, RctSigPrunable,
+            RctType,
+        },
+    },
+    Amount, Block, BlockHeader, Hash, PrivateKey, PublicKey, Transaction, TransactionPrefix, TxIn,
+    TxOut, VarInt, ViewPair,
+};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+/// Number of ring members used for synthetic transaction inputs.
+const RING_SIZE: usize = 16;
+/// Number of blocks before a coinbase output unlocks.
+const COINBASE_LOCK_BLOCKS: u64 = 60;
+/// Target seconds between blocks.
+const BLOCK_TIME: u64 = 120;
+/// Block version (hard fork 16).
+const BLOCK_VERSION: u64 = 16;
+/// Arbitrary timestamp of the first synthetic block.
+const FIRST_TIMESTAMP: u64 = 1_700_000_000;
+
+/// A synthetic blockchain which can be served by a
+/// [`MockDaemon`](crate::MockDaemon).
+///
+/// Blocks and transactions are structurally valid and their outputs are
+/// correctly derived, so they are detected by anything scanning with the right
+/// view pair. Signatures and range proofs are filled with random data.
+pub struct SyntheticChain {
+    first_height: u64,
+    blocks: Vec<SyntheticBlock>,
+    txpool: Vec<Transaction>,
+    rng: ChaCha12Rng,
+}
+
+/// A block in a [`SyntheticChain`], along with its transactions.
+#[derive(Debug, Clone)]
+pub struct SyntheticBlock {
+    pub hash: Hash,
+    pub block: Block,
+    pub transactions: Vec<Transaction>,
+}
+
+impl SyntheticChain {
+    /// Create an empty chain whose first block will be at `first_height`.
+    /// The `seed` makes the generated keys, hashes and proofs reproducible.
+    #[must_use]
+    pub fn new(first_height: u64, seed: u64) -> SyntheticChain {
+        SyntheticChain {
+            first_height,
+            blocks: Vec::new(),
+            txpool: Vec::new(),
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Blockchain height as reported by monerod (i.e. the height of the next
+    /// block to be mined).
+    #[must_use]
+    pub fn height(&self) -> u64 {
+        self.first_height + self.blocks.len() as u64
+    }
+
+    /// The block at `height`, if there is one.
+    #[must_use]
+    pub fn block(&self, height: u64) -> Option<&SyntheticBlock> {
+        let index = usize::try_from(height.checked_sub(self.first_height)?).ok()?;
+        self.blocks.get(index)
+    }
+
+    /// Iterate over the blocks in the chain along with their heights.
+    pub fn blocks(&self) -> impl Iterator<Item = (u64, &SyntheticBlock)> {
+        (self.first_height..).zip(self.blocks.iter())
+    }
+
+    /// Transactions currently in the txpool.
+    #[must_use]
+    pub fn txpool(&self) -> &[Transaction] {
+        &self.txpool
+    }
+
+    /// Start building a new transaction.
+    pub fn new_transaction(&mut self) -> TransactionBuilder {
+        TransactionBuilder::new(self.rng.next_u64())
+    }
+
+    /// Add a transaction to the txpool.
+    pub fn add_to_txpool(&mut self, transaction: Transaction) {
+        self.txpool.push(transaction);
+    }
+
+    /// Remove a transaction from the txpool, returning it if it was present.
+    pub fn remove_from_txpool(&mut self, hash: &Hash) -> Option<Transaction> {
+        let index = self.txpool.iter().position(|tx| tx.hash() == *hash)?;
+        Some(self.txpool.remove(index))
+    }
+
+    /// Mine `count` blocks containing only a coinbase transaction.
+    pub fn mine_empty_blocks(&mut self, count: u64) {
+        for _ in 0..count {
+            self.mine_block(Vec::new());
+        }
+    }
+
+    /// Mine a block containing `transactions`, returning its hash. The
+    /// transactions, along with any txpool transactions spending the same key
+    /// images (i.e. double spends), are removed from the txpool.
+    pub fn mine_block(&mut self, transactions: Vec<Transaction>) -> Hash {
+        let height = self.height();
+        let prev_id = self.blocks.last().map_or_else(Hash::zero, |b| b.hash);
+
+        let spent: Vec<KeyImage> = transactions.iter().flat_map(key_images).collect();
+        let hashes: Vec<Hash> = transactions.iter().map(Hashable::hash).collect();
+        self.txpool.retain(|tx| {
+            !hashes.contains(&tx.hash()) && key_images(tx).all(|ki| !spent.contains(&ki))
+        });
+
+        let miner_tx = self.coinbase(height);
+        let block = Block {
+            header: BlockHeader {
+                major_version: VarInt(BLOCK_VERSION),
+                minor_version: VarInt(BLOCK_VERSION),
+                timestamp: VarInt(FIRST_TIMESTAMP + height * BLOCK_TIME),
+                prev_id,
+                nonce: self.rng.next_u32(),
+            },
+            miner_tx,
+            tx_hashes: hashes,
+        };
+        let hash = block.id();
+        self.blocks.push(SyntheticBlock {
+            hash,
+            block,
+            transactions,
+        });
+        hash
+    }
+
+    /// Mine a block containing every transaction in the txpool, returning its
+    /// hash.
+    pub fn mine_txpool(&mut self) -> Hash {
+        let transactions = self.txpool.clone();
+        self.mine_block(transactions)
+    }
+
+    /// Remove the top `count` blocks from the chain (e.g. to simulate a
+    /// reorg), returning their non-coinbase transactions. The transactions are
+    /// not returned to the txpool.
+    pub fn pop_blocks(&mut self, count: usize) -> Vec<Transaction> {
+        let split = self.blocks.len().saturating_sub(count);
+        self.blocks
+            .split_off(split)
+            .into_iter()
+            .flat_map(|b| b.transactions)
+            .collect()
+    }
+
+    fn coinbase(&mut self, height: u64) -> Transaction {
+        let tx_key = random_private_key(&mut self.rng);
+        let mut key = [0; 32];
+        self.rng.fill_bytes(&mut key);
+        Transaction {
+            prefix: TransactionPrefix {
+                version: VarInt(2),
+                unlock_time: VarInt(height + COINBASE_LOCK_BLOCKS),
+                inputs: vec![TxIn::Gen {
+                    height: VarInt(height),
+                }],
+                outputs: vec![TxOut {
+                    amount: VarInt(600_000_000_000),
+                    target: TxOutTarget::ToTaggedKey {
+                        key,
+                        view_tag: self.rng.gen(),
+                    },
+                }],
+                extra: RawExtraField::from(ExtraField(vec![SubField::TxPublicKey(
+                    PublicKey::from_private_key(&tx_key),
+                )])),
+            },
+            signatures: Vec::new(),
+            rct_signatures: RctSig {
+                sig: Some(RctSigBase {
+                    rct_type: RctType::Null,
+                    txn_fee: Amount::ZERO,
+                    pseudo_outs: Vec::new(),
+                    ecdh_info: Vec::new(),
+                    out_pk: Vec::new(),
+                }),
+                p: None,
+            },
+        }
+    }
+}
+
+/// Builds a synthetic ring confidential transaction.
+pub struct TransactionBuilder {
+    rng: ChaCha12Rng,
+    outputs: Vec<(PublicKey, PublicKey, bool, u64)>,
+    unlock_time: u64,
+    fee: u64,
+    key_images: Vec<KeyImage>,
+}
+
+impl TransactionBuilder {
+    /// Create a transaction builder. The `seed` makes the generated keys and
+    /// proofs reproducible.
+    #[must_use]
+    pub fn new(seed: u64) -> TransactionBuilder {
+        TransactionBuilder {
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            outputs: Vec::new(),
+            unlock_time: 0,
+            fee: 30_000_000,
+            key_images: Vec::new(),
+        }
+    }
+
+    /// Add an output paying `amount` piconero to the subaddress at `index` of
+    /// `viewpair`.
+    #[must_use]
+    pub fn pay(mut self, viewpair: &ViewPair, index: Index, amount: u64) -> TransactionBuilder {
+        let (view, spend) = subaddress::get_public_keys(viewpair, index);
+        self.outputs.push((view, spend, !index.is_zero(), amount));
+        self
+    }
+
+    /// Add an output paying `amount` piconero to a random address.
+    #[must_use]
+    pub fn pay_random(mut self, amount: u64) -> TransactionBuilder {
+        let view = PublicKey::from_private_key(&random_private_key(&mut self.rng));
+        let spend = PublicKey::from_private_key(&random_private_key(&mut self.rng));
+        self.outputs.push((view, spend, true, amount));
+        self
+    }
+
+    /// Set the transaction's unlock time (a block height, or a unix timestamp
+    /// if greater than 500,000,000). Defaults to 0 (i.e. no timelock).
+    #[must_use]
+    pub fn unlock_time(mut self, unlock_time: u64) -> TransactionBuilder {
+        self.unlock_time = unlock_time;
+        self
+    }
+
+    /// Set the transaction fee in piconero.
+    #[must_use]
+    pub fn fee(mut self, fee: u64) -> TransactionBuilder {
+        self.fee = fee;
+        self
+    }
+
+    /// Add an input spending `key_image`. Two transactions sharing a key image
+    /// are double spends of each other. If no inputs are added, a single input
+    /// with a random key image is used.
+    #[must_use]
+    pub fn key_image(mut self, key_image: [u8; 32]) -> TransactionBuilder {
+        self.key_images.push(KeyImage {
+            image: Hash::from(key_image),
+        });
+        self
+    }
+
+    /// Build the transaction.
+    #[must_use]
+    pub fn build(mut self) -> Transaction {
+        if self.key_images.is_empty() {
+            let mut image = [0; 32];
+            self.rng.fill_bytes(&mut image);
+            self.key_images.push(KeyImage {
+                image: Hash::from(image),
+            });
+        }
+
+        let inputs: Vec<TxIn> = self
+            .key_images
+            .iter()
+            .map(|k_image| TxIn::ToKey {
+                amount: VarInt(0),
+                key_offsets: (0..RING_SIZE)
+                    .map(|_| VarInt(self.rng.gen_range(1..100_000)))
+                    .collect(),
+                k_image: k_image.clone(),
+            })
+            .collect();
+
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        let mut tx_pubkeys = Vec::with_capacity(self.outputs.len());
+        let mut ecdh_info = Vec::with_capacity(self.outputs.len());
+        let mut out_pk = Vec::with_capacity(self.outputs.len());
+        for (i, (view, spend, is_subaddress, amount)) in self.outputs.iter().enumerate() {
+            // Each output gets its own tx key, so that subaddresses can be paid.
+            let tx_key = random_private_key(&mut self.rng);
+            tx_pubkeys.push(if *is_subaddress {
+                tx_key * spend
+            } else {
+                PublicKey::from_private_key(&tx_key)
+            });
+            let keygen = KeyGenerator::from_random(*view, *spend, tx_key);
+
+            outputs.push(TxOut {
+                amount: VarInt(0),
+                target: TxOutTarget::ToTaggedKey {
+                    key: keygen.one_time_key(i).to_bytes(),
+                    view_tag: view_tag(&keygen.rv, i),
+                },
+            });
+
+            let (ecdh, commitment) = encrypt_amount(&keygen.get_rvn_scalar(i), *amount);
+            ecdh_info.push(ecdh);
+            out_pk.push(commitment);
+        }
+
+        let main_pubkey = PublicKey::from_private_key(&random_private_key(&mut self.rng));
+        let extra = ExtraField(vec![
+            SubField::TxPublicKey(main_pubkey),
+            SubField::AdditionalPublickKey(tx_pubkeys),
+        ]);
+
+        let input_count = inputs.len();
+        let output_count = outputs.len();
+        Transaction {
+            prefix: TransactionPrefix {
+                version: VarInt(2),
+                unlock_time: VarInt(self.unlock_time),
+                inputs,
+                outputs,
+                extra: RawExtraField::from(extra),
+            },
+            signatures: Vec::new(),
+            rct_signatures: RctSig {
+                sig: Some(RctSigBase {
+                    rct_type: RctType::BulletproofPlus,
+                    txn_fee: Amount::from_pico(self.fee),
+                    pseudo_outs: Vec::new(),
+                    ecdh_info,
+                    out_pk,
+                }),
+                p: Some(RctSigPrunable {
+                    range_sigs: Vec::new(),
+                    bulletproofs: Vec::new(),
+                    bulletproofplus: vec![random_bulletproof_plus(&mut self.rng, output_count)],
+                    MGs: Vec::new(),
+                    Clsags: (0..input_count)
+                        .map(|_| Clsag {
+                            s: (0..RING_SIZE).map(|_| random_key(&mut self.rng)).collect(),
+                            c1: random_key(&mut self.rng),
+                            D: random_key(&mut self.rng),
+                        })
+                        .collect(),
+                    pseudo_outs: (0..input_count)
+                        .map(|_| random_key(&mut self.rng))
+                        .collect(),
+                }),
+            },
+        }
+    }
+}
+
+/// Encrypt `amount` with the output's shared secret and commit to it.
+fn encrypt_amount(shared_secret: &PrivateKey, amount: u64) -> (EcdhInfo, CtKey) {
+    let amount_key = Hash::new([b"amount".as_slice(), shared_secret.as_bytes()].concat());
+    let mut encrypted_amount = amount.to_le_bytes();
+    for (byte, key) in encrypted_amount.iter_mut().zip(amount_key.as_bytes()) {
+        *byte ^= key;
+    }
+
+    let mask =
+        Hash::hash_to_scalar([b"commitment_mask".as_slice(), shared_secret.as_bytes()].concat());
+    let mut amount_scalar = [0; 32];
+    amount_scalar[..8].copy_from_slice(&amount.to_le_bytes());
+    #[allow(clippy::expect_used)]
+    let amount_scalar =
+        PrivateKey::from_slice(&amount_scalar).expect("amount is a canonical scalar");
+    let commitment = PublicKey::from_private_key(&mask) + H * &amount_scalar;
+
+    (
+        EcdhInfo::Bulletproof {
+            amount: Hash8(encrypted_amount),
+        },
+        CtKey {
+            mask: Key {
+                key: commitment.to_bytes(),
+            },
+        },
+    )
+}
+
+fn key_images(tx: &Transaction) -> impl Iterator<Item = KeyImage> + '_ {
+    tx.prefix.inputs.iter().filter_map(|input| match input {
+        TxIn::ToKey { k_image, .. } => Some(k_image.clone()),
+        TxIn::Gen { .. } => None,
+    })
+}
+
+/// Derive the view tag of output `index` from the shared secret `rv`.
+fn view_tag(rv: &PublicKey, index: usize) -> u8 {
+    let data = [
+        b"view_tag".as_slice(),
+        rv.as_bytes(),
+        &serialize(&VarInt(index as u64)),
+    ]
+    .concat();
+    Hash::new(data).as_bytes()[0]
+}
+
+fn random_private_key(rng: &mut ChaCha12Rng) -> PrivateKey {
+    let mut bytes = [0; 64];
+    rng.fill_bytes(&mut bytes);
+    Hash::hash_to_scalar(bytes)
+}
+
+fn random_key(rng: &mut ChaCha12Rng) -> Key {
+    Key {
+        key: PublicKey::from_private_key(&random_private_key(rng)).to_bytes(),
+    }
+}
+
+fn random_bulletproof_plus(rng: &mut ChaCha12Rng, outputs: usize) -> BulletproofPlus {
+    // Proof size grows logarithmically with the (padded) number of outputs.
+    let rounds = 6 + outputs.next_power_of_two().trailing_zeros() as usize;
+    BulletproofPlus {
+        A: random_key(rng),
+        A1: random_key(rng),
+        B: random_key(rng),
+        r1: random_key(rng),
+        s1: random_key(rng),
+        d1: random_key(rng),
+        L: (0..rounds).map(|_| random_key(rng)).collect(),
+        R: (0..rounds).map(|_| random_key(rng)).collect(),
+    }
+}
@@ -1,15 +1,21 @@
 use std::{collections::HashMap, fs, ops::Deref, sync::Mutex};
 
 use httpmock::{Mock, MockServer};
+use monero::{consensus::serialize, cryptonote::hash::Hashable, Transaction};
 use serde_json::{json, Value};
 
+use crate::SyntheticChain;
+
 pub struct MockDaemon {
     server: MockServer,
     daemon_height_id: Mutex<Option<usize>>,
+    daemon_info_id: Mutex<Option<usize>>,
+    rpc_version_id: Mutex<Option<usize>>,
     block_ids: Mutex<HashMap<u64, usize>>,
     txpool_id: Mutex<Option<usize>>,
     txpool_hashes_id: Mutex<Option<usize>>,
     txpool_transactions_id: Mutex<Option<usize>>,
+    chain_ids: Mutex<Vec<usize>>,
 }
 
 impl Deref for MockDaemon {
@@ -21,15 +27,26 @@ impl Deref for MockDaemon {
 }
 
 impl MockDaemon {
-    pub async fn new_mock_daemon() -> MockDaemon {
+    async fn new_empty() -> MockDaemon {
         let mock_daemon = MockDaemon {
             server: MockServer::start_async().await,
             daemon_height_id: Mutex::new(None),
+            daemon_info_id: Mutex::new(None),
+            rpc_version_id: Mutex::new(None),
             block_ids: Mutex::new(HashMap::new()),
             txpool_id: Mutex::new(None),
             txpool_hashes_id: Mutex::new(None),
             txpool_transactions_id: Mutex::new(None),
+            chain_ids: Mutex::new(Vec::new()),
         };
+        // Mock daemon info and RPC version requests.
+        mock_daemon.mock_daemon_info("mainnet", false);
+        mock_daemon.mock_rpc_version(3, 13);
+        mock_daemon
+    }
+
+    pub async fn new_mock_daemon() -> MockDaemon {
+        let mock_daemon = MockDaemon::new_empty().await;
         // Mock daemon height request.
         mock_daemon.mock_daemon_height(2_477_657);
         // Mock txpool request.
@@ -62,6 +79,107 @@ impl MockDaemon {
         mock_daemon
     }
 
+    /// Create a mock daemon serving a [`SyntheticChain`] instead of the
+    /// recorded mainnet fixtures.
+    pub async fn new_synthetic_daemon(chain: &SyntheticChain) -> MockDaemon {
+        let mock_daemon = MockDaemon::new_empty().await;
+        mock_daemon.mock_chain(chain);
+        mock_daemon
+    }
+
+    /// Serve the current state of `chain`, replacing any previously served
+    /// chain. Call this again after mining blocks, popping blocks or changing
+    /// the txpool.
+    ///
+    /// Transactions in the txpool are served individually, so a request for
+    /// several new txpool transactions at once is answered with only one of
+    /// them. The rest are picked up by subsequent requests.
+    pub fn mock_chain(&self, chain: &SyntheticChain) {
+        let mut chain_ids = self
+            .chain_ids
+            .lock()
+            .expect("PoisonError when reading chain mock IDs");
+        for id in chain_ids.drain(..) {
+            Mock::new(id, self).delete();
+        }
+
+        self.mock_daemon_height(chain.height());
+
+        for (height, block) in chain.blocks() {
+            let mock = self.mock(|when, then| {
+                when.path("/json_rpc").body(
+                    r#"{"jsonrpc":"2.0","id":"0","method":"get_block","params":{"height":"#
+                        .to_owned()
+                        + &height.to_string()
+                        + "}}",
+                );
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .json_body(json!({
+                        "id": "0",
+                        "jsonrpc": "2.0",
+                        "result": {
+                            "blob": hex::encode(serialize(&block.block)),
+                            "block_header": {
+                                "hash": hex::encode(block.hash.as_bytes()),
+                                "height": height,
+                                "num_txes": block.transactions.len(),
+                            },
+                            "status": "OK"
+                        }
+                    }));
+            });
+            chain_ids.push(mock.id);
+
+            if block.transactions.is_empty() {
+                continue;
+            }
+            let mock = self.mock(|when, then| {
+                when.path("/get_transactions")
+                    .json_body(json!({ "txs_hashes": tx_hashes(&block.transactions) }));
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .json_body(txs_response(&block.transactions));
+            });
+            chain_ids.push(mock.id);
+        }
+
+        let txpool = chain.txpool();
+        let mock = self.mock(|when, then| {
+            when.path("/get_transaction_pool").body("");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "status": "OK",
+                    "transactions": txpool
+                        .iter()
+                        .map(|tx| json!({
+                            "id_hash": hex::encode(tx.hash().as_bytes()),
+                            "tx_blob": hex::encode(serialize(tx)),
+                        }))
+                        .collect::<Vec<Value>>(),
+                }));
+        });
+        chain_ids.push(mock.id);
+        let mock = self.mock(|when, then| {
+            when.path("/get_transaction_pool_hashes").body("");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "status": "OK", "tx_hashes": tx_hashes(txpool) }));
+        });
+        chain_ids.push(mock.id);
+        for tx in txpool {
+            let mock = self.mock(|when, then| {
+                when.path("/get_transactions")
+                    .body_contains(hex::encode(tx.hash().as_bytes()));
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .json_body(txs_response(std::slice::from_ref(tx)));
+            });
+            chain_ids.push(mock.id);
+        }
+    }
+
     pub fn mock_daemon_height(&self, height: u64) -> Mock {
         // Use mock ID to delete old daemon height mock.
         if let Some(id) = *self
@@ -94,6 +212,99 @@ impl MockDaemon {
         mock
     }
 
+    /// Respond to daemon height requests with `503 Service Unavailable` until
+    /// the daemon height is mocked again.
+    pub fn mock_daemon_height_unavailable(&self) -> Mock<'_> {
+        let mut daemon_height_id = self
+            .daemon_height_id
+            .lock()
+            .expect("PoisonError when reading daemon height mock ID");
+        if let Some(id) = *daemon_height_id {
+            Mock::new(id, self).delete();
+        }
+
+        let mock = self.mock(|when, then| {
+            when.path("/json_rpc")
+                .body(r#"{"jsonrpc":"2.0","id":"0","method":"get_block_count"}"#);
+            then.status(503);
+        });
+        *daemon_height_id = Some(mock.id);
+        mock
+    }
+
+    pub fn mock_daemon_info(&self, nettype: &str, restricted: bool) -> Mock<'_> {
+        // Use mock ID to delete old daemon info mock.
+        if let Some(id) = *self
+            .daemon_info_id
+            .lock()
+            .expect("PoisonError when reading daemon info mock ID")
+        {
+            Mock::new(id, self).delete();
+        }
+
+        // Create the new daemon info mock.
+        let mock = self.mock(|when, then| {
+            when.path("/json_rpc")
+                .body(r#"{"jsonrpc":"2.0","id":"0","method":"get_info"}"#);
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "id": "0",
+                    "jsonrpc": "2.0",
+                    "result": {
+                        "height": 2_477_657,
+                        "mainnet": nettype == "mainnet",
+                        "nettype": nettype,
+                        "restricted": restricted,
+                        "stagenet": nettype == "stagenet",
+                        "status": "OK",
+                        "synchronized": true,
+                        "testnet": nettype == "testnet",
+                        "version": "0.18.3.1-release"
+                    }
+                }));
+        });
+        *self
+            .daemon_info_id
+            .lock()
+            .expect("PoisonError when writing daemon info mock ID") = Some(mock.id);
+        mock
+    }
+
+    pub fn mock_rpc_version(&self, major: u32, minor: u32) -> Mock<'_> {
+        // Use mock ID to delete old RPC version mock.
+        if let Some(id) = *self
+            .rpc_version_id
+            .lock()
+            .expect("PoisonError when reading RPC version mock ID")
+        {
+            Mock::new(id, self).delete();
+        }
+
+        // Create the new RPC version mock.
+        let mock = self.mock(|when, then| {
+            when.path("/json_rpc")
+                .body(r#"{"jsonrpc":"2.0","id":"0","method":"get_version"}"#);
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "id": "0",
+                    "jsonrpc": "2.0",
+                    "result": {
+                        "release": true,
+                        "status": "OK",
+                        "untrusted": false,
+                        "version": (major << 16) | minor
+                    }
+                }));
+        });
+        *self
+            .rpc_version_id
+            .lock()
+            .expect("PoisonError when writing RPC version mock ID") = Some(mock.id);
+        mock
+    }
+
     pub fn mock_alt_2477657(&self) {
         // Mock block requests.
         let response_path = "../testing-utils/rpc_resources/blocks/2477657_alt/block.json";
@@ -231,3 +442,20 @@ impl MockDaemon {
         mock
     }
 }
+
+fn tx_hashes(transactions: &[Transaction]) -> Vec<String> {
+    transactions
+        .iter()
+        .map(|tx| hex::encode(tx.hash().as_bytes()))
+        .collect()
+}
+
+fn txs_response(transactions: &[Transaction]) -> Value {
+    json!({
+        "status": "OK",
+        "txs_as_hex": transactions
+            .iter()
+            .map(|tx| hex::encode(serialize(tx)))
+            .collect::<Vec<String>>(),
+    })
+}
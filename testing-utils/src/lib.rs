@@ -1,19 +1,58 @@
 #![allow(missing_docs)]
 #![allow(clippy::missing_panics_doc)]
 
+mod chain;
 mod daemon;
 mod invoice;
+mod light_wallet;
+mod wallet_rpc;
 
+use std::{fs, ops::Deref, path::Path};
+
+pub use chain::{SyntheticBlock, SyntheticChain, TransactionBuilder};
 pub use daemon::MockDaemon;
 pub use invoice::MockInvoice;
+pub use light_wallet::MockLightWalletServer;
+use monero::{Address, PrivateKey, ViewPair};
+use postgres::{Client, NoTls};
 use tempfile::Builder;
 use tracing_subscriber::{filter::LevelFilter, prelude::*, EnvFilter};
+pub use wallet_rpc::{MockTransfer, MockWalletRpc};
 
 pub const PRIVATE_VIEW_KEY: &str =
     "ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03";
 pub const PRIMARY_ADDRESS: &str =
     "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
 
+/// A second test wallet, for tests involving several wallets.
+pub const OTHER_PRIVATE_VIEW_KEY: &str =
+    "0909090909090909090909090909090909090909090909090909090909090900";
+pub const OTHER_PRIMARY_ADDRESS: &str =
+    "49EoGL99szpehtkKuWCuaFduM6dopUmCfbaUTo58YgNq2VuMg6m8q3fYHS9omA66spPC54oY9T6HP6h6wRV5oy4aD6ff7y4";
+
+/// View pair of [`PRIVATE_VIEW_KEY`] and [`PRIMARY_ADDRESS`], for paying the
+/// test wallet from a [`SyntheticChain`].
+#[must_use]
+pub fn view_pair() -> ViewPair {
+    parse_view_pair(PRIMARY_ADDRESS, PRIVATE_VIEW_KEY)
+}
+
+/// View pair of [`OTHER_PRIVATE_VIEW_KEY`] and [`OTHER_PRIMARY_ADDRESS`].
+#[must_use]
+pub fn other_view_pair() -> ViewPair {
+    parse_view_pair(OTHER_PRIMARY_ADDRESS, OTHER_PRIVATE_VIEW_KEY)
+}
+
+fn parse_view_pair(primary_address: &str, private_view_key: &str) -> ViewPair {
+    let address: Address = primary_address.parse().expect("invalid primary address");
+    ViewPair {
+        view: private_view_key
+            .parse::<PrivateKey>()
+            .expect("invalid private view key"),
+        spend: address.public_spend,
+    }
+}
+
 #[must_use]
 pub fn new_temp_dir() -> String {
     Builder::new()
@@ -27,6 +66,127 @@ pub fn new_temp_dir() -> String {
         .to_string()
 }
 
+/// Copy a database written by a past version of `AcceptXMR` from
+/// `db_resources/` to a new temporary location, returning the copy's path.
+/// Used for testing storage schema upgrades.
+#[must_use]
+pub fn copy_db_fixture(fixture: &str) -> String {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("db_resources")
+        .join(fixture);
+    let destination = new_temp_dir();
+    copy_recursive(&source, Path::new(&destination));
+    destination
+}
+
+fn copy_recursive(source: &Path, destination: &Path) {
+    if source.is_dir() {
+        fs::create_dir_all(destination).expect("failed to create fixture directory");
+        for entry in fs::read_dir(source).expect("failed to read fixture directory") {
+            let entry = entry.expect("failed to read fixture directory");
+            copy_recursive(&entry.path(), &destination.join(entry.file_name()));
+        }
+    } else {
+        fs::copy(source, destination).expect("failed to copy fixture");
+    }
+}
+
+/// Connection string of the `PostgreSQL` server used in tests, unless
+/// overridden by the `TEST_POSTGRES` environment variable.
+const DEFAULT_TEST_POSTGRES: &str = "host=localhost user=postgres";
+
+/// A temporary `PostgreSQL` database, which is dropped along with this guard.
+/// Dereferences to the database's connection string.
+///
+/// Used as a test case argument, the guard lives until the test returns.
+#[must_use]
+pub struct PostgresDb {
+    server: String,
+    name: String,
+    config: String,
+}
+
+impl Deref for PostgresDb {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.config
+    }
+}
+
+impl Drop for PostgresDb {
+    fn drop(&mut self) {
+        // Stores may still be connected, for example from a storage thread which
+        // hasn't shut down yet, so close their connections too.
+        let drop = format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.name);
+        let server = self.server.clone();
+        let dropped = std::thread::spawn(move || {
+            Client::connect(&server, NoTls).and_then(|mut client| client.batch_execute(&drop))
+        })
+        .join();
+        if !matches!(dropped, Ok(Ok(()))) {
+            eprintln!("failed to drop temporary database {}", self.name);
+        }
+    }
+}
+
+/// Create an empty `PostgreSQL` database, returning a guard which drops it
+/// again.
+///
+/// Needs a running `PostgreSQL` server, so tests using it are ignored unless
+/// run with `--ignored`.
+pub fn new_postgres_db() -> PostgresDb {
+    let server =
+        std::env::var("TEST_POSTGRES").unwrap_or_else(|_| DEFAULT_TEST_POSTGRES.to_string());
+    let name = format!("temp_db_{:016x}", rand::random::<u64>());
+
+    // The synchronous client can't be used from within an async runtime, so
+    // use it from a separate thread.
+    let create = format!("CREATE DATABASE {name}");
+    let server_clone = server.clone();
+    std::thread::spawn(move || {
+        Client::connect(&server_clone, NoTls)
+            .expect("failed to connect to PostgreSQL server")
+            .batch_execute(&create)
+            .expect("failed to create temporary database");
+    })
+    .join()
+    .expect("failed to create temporary database");
+
+    PostgresDb {
+        config: format!("{server} dbname={name}"),
+        server,
+        name,
+    }
+}
+
+/// Create a `PostgreSQL` database from an SQL dump of a database written by
+/// a past version of `AcceptXMR` in `db_resources/`, returning a guard which
+/// drops it again. Used for testing storage schema upgrades.
+pub fn copy_postgres_fixture(fixture: &str) -> PostgresDb {
+    let dump = fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("db_resources")
+            .join(fixture),
+    )
+    .expect("failed to read fixture");
+    let db = new_postgres_db();
+
+    // The synchronous client can't be used from within an async runtime, so
+    // use it from a separate thread.
+    let config = db.to_string();
+    std::thread::spawn(move || {
+        Client::connect(&config, NoTls)
+            .expect("failed to connect to PostgreSQL server")
+            .batch_execute(&dump)
+            .expect("failed to load fixture");
+    })
+    .join()
+    .expect("failed to load fixture");
+
+    db
+}
+
 /// Initialize the logging implementation.
 pub fn init_logger() {
     let filter = EnvFilter::builder()
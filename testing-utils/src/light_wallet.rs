@@ -0,0 +1,169 @@
+use std::{ops::Deref, sync::Mutex};
+
+use httpmock::{Mock, MockServer};
+use serde_json::{json, Value};
+
+use crate::MockTransfer;
+
+/// A mock light wallet server, implementing the parts of the light wallet REST
+/// API used by the payment gateway. Each [`MockTransfer`] is served as a
+/// transaction with a single output.
+pub struct MockLightWalletServer {
+    server: MockServer,
+    state: Mutex<State>,
+}
+
+struct State {
+    scanned_block_height: u64,
+    blockchain_height: u64,
+    outputs: Vec<MockTransfer>,
+    upsert_subaddrs_id: Option<usize>,
+    address_txs_id: Option<usize>,
+    unspent_outs_id: Option<usize>,
+}
+
+impl Deref for MockLightWalletServer {
+    type Target = MockServer;
+
+    fn deref(&self) -> &MockServer {
+        &self.server
+    }
+}
+
+impl MockLightWalletServer {
+    /// Create a mock light wallet server which has scanned up to
+    /// `scanned_block_height`, with no outputs.
+    pub async fn new(scanned_block_height: u64) -> MockLightWalletServer {
+        let mock_server = MockLightWalletServer {
+            server: MockServer::start_async().await,
+            state: Mutex::new(State {
+                scanned_block_height,
+                blockchain_height: scanned_block_height,
+                outputs: Vec::new(),
+                upsert_subaddrs_id: None,
+                address_txs_id: None,
+                unspent_outs_id: None,
+            }),
+        };
+        mock_server.mock(|when, then| {
+            when.path("/login");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "new_address": true, "generated_locally": false }));
+        });
+        mock_server.mock_upsert_subaddrs();
+        mock_server.update();
+        mock_server
+    }
+
+    /// Replace the `upsert_subaddrs` mock, returning it so its hits can be
+    /// checked.
+    pub fn mock_upsert_subaddrs(&self) -> Mock<'_> {
+        let mut state = self.state();
+        if let Some(id) = state.upsert_subaddrs_id {
+            Mock::new(id, self).delete();
+        }
+        let mock = self.mock(|when, then| {
+            when.path("/upsert_subaddrs");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "all_subaddrs": [] }));
+        });
+        state.upsert_subaddrs_id = Some(mock.id);
+        mock
+    }
+
+    pub fn mock_height(&self, scanned_block_height: u64, blockchain_height: u64) {
+        {
+            let mut state = self.state();
+            state.scanned_block_height = scanned_block_height;
+            state.blockchain_height = blockchain_height;
+        }
+        self.update();
+    }
+
+    /// Serve the given outputs. Outputs with no height are served as being in
+    /// the txpool.
+    pub fn mock_outputs(&self, outputs: &[MockTransfer]) {
+        self.state().outputs = outputs.to_vec();
+        self.update();
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("PoisonError when reading light wallet server state")
+    }
+
+    /// Replace the `get_address_txs` and `get_unspent_outs` mocks to reflect
+    /// the current state.
+    fn update(&self) {
+        let mut state = self.state();
+        for id in [state.address_txs_id.take(), state.unspent_outs_id.take()]
+            .into_iter()
+            .flatten()
+        {
+            Mock::new(id, self).delete();
+        }
+
+        let transactions: Vec<Value> = state
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(i, output)| {
+                json!({
+                    "hash": tx_hash(i),
+                    "height": output.height.unwrap_or(0),
+                    "mempool": output.height.is_none(),
+                    "total_received": output.amount.to_string(),
+                    "unlock_time": output.unlock_time,
+                })
+            })
+            .collect();
+        let address_txs = json!({
+            "blockchain_height": state.blockchain_height,
+            "scanned_block_height": state.scanned_block_height,
+            "scanned_height": state.scanned_block_height,
+            "start_height": 0,
+            "total_received": state.outputs.iter().map(|o| o.amount).sum::<u64>().to_string(),
+            "transactions": transactions,
+        });
+        let mock = self.mock(|when, then| {
+            when.path("/get_address_txs");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(address_txs);
+        });
+        state.address_txs_id = Some(mock.id);
+
+        let outputs: Vec<Value> = state
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(i, output)| {
+                json!({
+                    "amount": output.amount.to_string(),
+                    "height": output.height.unwrap_or(0),
+                    "index": 0,
+                    "recipient": {
+                        "maj_i": output.sub_index.major,
+                        "min_i": output.sub_index.minor,
+                    },
+                    "spend_key_images": [],
+                    "tx_hash": tx_hash(i),
+                })
+            })
+            .collect();
+        let mock = self.mock(|when, then| {
+            when.path("/get_unspent_outs");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "amount": "0", "outputs": outputs }));
+        });
+        state.unspent_outs_id = Some(mock.id);
+    }
+}
+
+fn tx_hash(index: usize) -> String {
+    format!("{index:064x}")
+}
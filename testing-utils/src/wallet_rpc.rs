@@ -0,0 +1,162 @@
+use std::{ops::Deref, sync::Mutex};
+
+use acceptxmr::SubIndex;
+use httpmock::{Mock, MockServer};
+use monero::cryptonote::subaddress;
+use serde_json::{json, Value};
+
+use crate::{view_pair, PRIMARY_ADDRESS};
+
+/// An incoming transfer reported by a [`MockWalletRpc`].
+#[derive(Debug, Copy, Clone)]
+pub struct MockTransfer {
+    pub sub_index: SubIndex,
+    pub amount: u64,
+    /// Height of the block containing the transfer, or `None` if it is in the
+    /// txpool.
+    pub height: Option<u64>,
+    pub unlock_time: u64,
+}
+
+impl MockTransfer {
+    #[must_use]
+    pub fn new(sub_index: SubIndex, amount: u64, height: Option<u64>) -> MockTransfer {
+        MockTransfer {
+            sub_index,
+            amount,
+            height,
+            unlock_time: 0,
+        }
+    }
+}
+
+/// A mock `monero-wallet-rpc` serving a view-only wallet for
+/// [`PRIMARY_ADDRESS`].
+pub struct MockWalletRpc {
+    server: MockServer,
+    address_id: Mutex<Option<usize>>,
+    create_address_id: Mutex<Option<usize>>,
+    height_id: Mutex<Option<usize>>,
+    transfers_id: Mutex<Option<usize>>,
+}
+
+impl Deref for MockWalletRpc {
+    type Target = MockServer;
+
+    fn deref(&self) -> &MockServer {
+        &self.server
+    }
+}
+
+impl MockWalletRpc {
+    /// Create a mock wallet RPC at the given height, with no transfers.
+    pub async fn new(height: u64) -> MockWalletRpc {
+        let mock_wallet = MockWalletRpc {
+            server: MockServer::start_async().await,
+            address_id: Mutex::new(None),
+            create_address_id: Mutex::new(None),
+            height_id: Mutex::new(None),
+            transfers_id: Mutex::new(None),
+        };
+        mock_wallet.mock_address(PRIMARY_ADDRESS);
+        mock_wallet.mock_create_address(1);
+        mock_wallet.mock_height(height);
+        mock_wallet.mock_transfers(&[]);
+        mock_wallet
+    }
+
+    pub fn mock_address(&self, address: &str) -> Mock<'_> {
+        self.replace_mock(
+            &self.address_id,
+            "get_address",
+            &json!({ "address": address }),
+        )
+    }
+
+    /// Respond to `create_address` requests with the subaddress at the given
+    /// minor index of account 0.
+    pub fn mock_create_address(&self, minor_index: u32) -> Mock<'_> {
+        let address = subaddress::get_subaddress(
+            &view_pair(),
+            subaddress::Index {
+                major: 0,
+                minor: minor_index,
+            },
+            None,
+        );
+        self.replace_mock(
+            &self.create_address_id,
+            "create_address",
+            &json!({
+                "address": address.to_string(),
+                "address_index": minor_index,
+            }),
+        )
+    }
+
+    pub fn mock_height(&self, height: u64) -> Mock<'_> {
+        self.replace_mock(&self.height_id, "get_height", &json!({ "height": height }))
+    }
+
+    /// Respond to `get_transfers` requests with the given transfers. Like the
+    /// real wallet RPC, empty lists are omitted from the response.
+    pub fn mock_transfers(&self, transfers: &[MockTransfer]) -> Mock<'_> {
+        let to_json = |transfer: &MockTransfer| {
+            json!({
+                "amount": transfer.amount,
+                "height": transfer.height.unwrap_or(0),
+                "subaddr_index": {
+                    "major": transfer.sub_index.major,
+                    "minor": transfer.sub_index.minor,
+                },
+                "txid": format!("{:064x}", transfer.amount),
+                "type": if transfer.height.is_some() { "in" } else { "pool" },
+                "unlock_time": transfer.unlock_time,
+            })
+        };
+        let confirmed: Vec<Value> = transfers
+            .iter()
+            .filter(|t| t.height.is_some())
+            .map(to_json)
+            .collect();
+        let pool: Vec<Value> = transfers
+            .iter()
+            .filter(|t| t.height.is_none())
+            .map(to_json)
+            .collect();
+
+        let mut result = json!({});
+        if !confirmed.is_empty() {
+            result["in"] = confirmed.into();
+        }
+        if !pool.is_empty() {
+            result["pool"] = pool.into();
+        }
+        self.replace_mock(&self.transfers_id, "get_transfers", &result)
+    }
+
+    /// Replace the mock stored in `id` with one answering `method` with
+    /// `result`.
+    fn replace_mock(&self, id: &Mutex<Option<usize>>, method: &str, result: &Value) -> Mock<'_> {
+        let mut id = id
+            .lock()
+            .expect("PoisonError when reading wallet RPC mock ID");
+        if let Some(id) = *id {
+            Mock::new(id, self).delete();
+        }
+
+        let mock = self.mock(|when, then| {
+            when.path("/json_rpc")
+                .json_body_partial(json!({ "method": method }).to_string());
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "id": "0",
+                    "jsonrpc": "2.0",
+                    "result": result,
+                }));
+        });
+        *id = Some(mock.id);
+        mock
+    }
+}